@@ -0,0 +1,56 @@
+//! Lexes generated formula dumps of growing size and prints the time per
+//! byte for each, which should stay flat if lexing is linear.
+//!
+//! ```text
+//! cargo bench --bench lexer
+//! ```
+
+use std::time::{Duration, Instant};
+
+use compiler::{
+    ast::{lexer::Lexer, lib::Id, text::FileId},
+    diagnostics::DiagnosticsBag,
+};
+
+const SIZES_IN_MB: [usize; 4] = [1, 2, 4, 8];
+const RUNS: usize = 3;
+
+fn generate_input(target_len: usize) -> String {
+    let mut input = String::with_capacity(target_len + 128);
+    let mut line = 0;
+    while input.len() < target_len {
+        input.push_str(&format!(
+            "let café_{} = (200 * {} + 5) - 6 / 2 // total for période {}\n/* note */ {} * 7\n",
+            line, line, line, line
+        ));
+        line += 1;
+    }
+    input
+}
+
+fn lex(input: &str) -> (usize, Duration) {
+    let start = Instant::now();
+    let count = Lexer::new(input, FileId::new(0), DiagnosticsBag::new_cell()).count();
+    (count, start.elapsed())
+}
+
+fn main() {
+    let mut baseline_ns_per_byte = None;
+    for size in SIZES_IN_MB {
+        let input = generate_input(size * 1024 * 1024);
+        let (tokens, elapsed) = (0..RUNS)
+            .map(|_| lex(&input))
+            .min_by_key(|(_, elapsed)| *elapsed)
+            .unwrap();
+        let ns_per_byte = elapsed.as_nanos() as f64 / input.len() as f64;
+        let baseline = *baseline_ns_per_byte.get_or_insert(ns_per_byte);
+        println!(
+            "{:>2} MB: {:>9} tokens in {:>8.2?} ({:.2} ns/byte, {:.2}x the 1 MB cost per byte)",
+            size,
+            tokens,
+            elapsed,
+            ns_per_byte,
+            ns_per_byte / baseline
+        );
+    }
+}
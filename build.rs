@@ -0,0 +1,49 @@
+//! Hashes the sources of the compiler into `COMPILER_SOURCE_FINGERPRINT`,
+//! which the cache keys of `run` and `check` include: a build from other
+//! sources, even of the same version, does not take the entries of this
+//! one.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A 64-bit FNV-1a hash, as the cache keys use.
+struct Fingerprint(u64);
+
+impl Fingerprint {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+/// The files under `path`, in the order of their paths.
+fn files(path: &Path, found: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+        let mut entries: Vec<PathBuf> = fs::read_dir(path).unwrap().map(|entry| entry.unwrap().path()).collect();
+        entries.sort();
+        for entry in entries {
+            files(&entry, found);
+        }
+    } else {
+        found.push(path.to_path_buf());
+    }
+}
+
+fn main() {
+    let inputs = ["src", "Cargo.toml", "build.rs"];
+    let mut found = Vec::new();
+    for input in inputs {
+        println!("cargo:rerun-if-changed={}", input);
+        files(Path::new(input), &mut found);
+    }
+    let mut fingerprint = Fingerprint(0xcbf2_9ce4_8422_2325);
+    for path in found {
+        fingerprint.write(path.to_string_lossy().as_bytes());
+        fingerprint.write(&fs::read(&path).unwrap());
+    }
+    println!("cargo:rustc-env=COMPILER_SOURCE_FINGERPRINT={:016x}", fingerprint.0);
+}
@@ -0,0 +1,101 @@
+//! Runs programs under fuel budgets and memory limits and checks where
+//! evaluation stops and what the error reports.
+//!
+//! ```text
+//! cargo run --example budgets
+//! ```
+
+use compiler::{
+    ast::{
+        evaluator::{Budget, Evaluation, ExpressionEvaluator, RuntimeError},
+        lib::Id,
+        text::{FileId, SourceFile},
+        value::Value,
+        Ast,
+    },
+    diagnostics::DiagnosticsBag,
+};
+
+const PROGRAM: &str = "function repeat(text) {
+    let twice = text + text
+    let four = twice + twice
+    return len(four)
+}
+let a = 1 + 2
+let b = repeat(\"abcd\")
+let c = b * a
+";
+
+/// Runs `PROGRAM` with `evaluator`, returning how much fuel it used.
+fn run(source: &SourceFile, mut evaluator: ExpressionEvaluator) -> (Evaluation, usize) {
+    let diagnostics = DiagnosticsBag::new_cell();
+    let ast = Ast::parse(source, diagnostics.clone());
+    ast.type_check(diagnostics.clone());
+    assert!(!diagnostics.borrow().has_errors(), "{:?}", diagnostics.borrow().diagnostics);
+    let evaluation = ast.evaluate(&mut evaluator);
+    (evaluation, evaluator.fuel_used())
+}
+
+fn main() {
+    let source = SourceFile::new(FileId::new(0), String::from("budgets.calc"), PROGRAM.to_string());
+
+    // Every statement and expression takes one unit: four for `a`, fourteen
+    // for `b` with the call and four for `c`.
+    let (evaluation, fuel_used) = run(&source, ExpressionEvaluator::new());
+    assert_eq!(evaluation.error, None);
+    assert_eq!(fuel_used, 22);
+    let values: Vec<Value> = evaluation.results.into_iter().map(|result| result.value).collect();
+    assert_eq!(values[2], Value::Integer(48));
+
+    // Exactly enough fuel runs the whole program.
+    let (evaluation, _) = run(&source, ExpressionEvaluator::new().with_fuel(22));
+    assert_eq!(evaluation.error, None);
+
+    let cases = [
+        (0, 1, "budgets.calc:6:1"),
+        (4, 2, "budgets.calc:7:1"),
+        (10, 2, "budgets.calc:2:24"),
+        (21, 3, "budgets.calc:8:13"),
+    ];
+    for (fuel, completed, location) in cases {
+        let (evaluation, fuel_used) = run(&source, ExpressionEvaluator::new().with_fuel(fuel));
+        let error = evaluation.error.unwrap();
+        println!("--fuel {}: {} at {}", fuel, error, source.location(error.span()));
+        assert_eq!(fuel_used, fuel);
+        assert_eq!(
+            error,
+            RuntimeError::BudgetExhausted { budget: Budget::Fuel, limit: fuel, completed_items: completed, span: error.span().clone() }
+        );
+        assert_eq!(source.location(error.span()), location, "--fuel {}", fuel);
+        // The declaration of `repeat` is the first item to complete; only
+        // the statements that finished after it have a result.
+        assert_eq!(evaluation.results.len(), completed - 1);
+    }
+    let (evaluation, _) = run(&source, ExpressionEvaluator::new().with_fuel(10));
+    assert_eq!(
+        evaluation.error.unwrap().to_string(),
+        "Fuel budget of 10 steps exhausted after 2 top-level item(s)"
+    );
+
+    // The call holds `text`, `twice` and `four`, 28 bytes of text, next to
+    // `a`, and gives them back when it returns. In the end the globals are
+    // three integers.
+    let value = Value::Integer(0).size();
+    let in_call = 4 * value + 28;
+    let (evaluation, _) = run(&source, ExpressionEvaluator::new().with_memory_limit(in_call));
+    assert_eq!(evaluation.error, None);
+    let limit = in_call - 1;
+    let (evaluation, _) = run(&source, ExpressionEvaluator::new().with_memory_limit(limit));
+    let error = evaluation.error.unwrap();
+    println!("--memory-limit {}: {} at {}", limit, error, source.location(error.span()));
+    assert_eq!(error.to_string(), format!("Variables need more than {} bytes after 2 top-level item(s)", limit));
+    assert!(matches!(error, RuntimeError::BudgetExhausted { budget: Budget::Memory, completed_items: 2, .. }));
+    assert_eq!(source.location(error.span()), "budgets.calc:3:9");
+    // A limit below the size of one value stops at the first `let`.
+    let (evaluation, _) = run(&source, ExpressionEvaluator::new().with_memory_limit(value - 1));
+    let error = evaluation.error.unwrap();
+    assert!(matches!(error, RuntimeError::BudgetExhausted { budget: Budget::Memory, completed_items: 1, .. }));
+    assert_eq!(source.location(error.span()), "budgets.calc:6:5");
+    assert_eq!(evaluation.environment.len(), 1);
+    println!("budgets passed");
+}
@@ -0,0 +1,88 @@
+//! Runs recursive functions into the call depth limit and checks the stack
+//! overflow error and its call trace. The evaluator keeps its calls off the
+//! host stack, so even a limit of a million calls is reached on the main
+//! thread.
+//!
+//! ```text
+//! cargo run --example call_depth
+//! ```
+
+use compiler::{
+    ast::{
+        evaluator::{ExpressionEvaluator, RuntimeError, DEFAULT_MAX_CALL_DEPTH},
+        lib::Id,
+        text::{FileId, SourceFile},
+        value::Value,
+        Ast,
+    },
+    diagnostics::DiagnosticsBag,
+};
+
+const PROGRAM: &str = "function ping(n) {
+    return pong(n + 1)
+}
+function pong(n) {
+    return ping(n * 1)
+}
+let before = 1 + 2
+ping(0)
+let after = 3
+";
+
+/// Runs `PROGRAM` with at most `max_call_depth` calls in progress and
+/// returns the values computed with the error it stopped at.
+fn run(source: &SourceFile, max_call_depth: usize) -> (Vec<Value>, RuntimeError) {
+    let diagnostics = DiagnosticsBag::new_cell();
+    let ast = Ast::parse(source, diagnostics.clone());
+    ast.type_check(diagnostics.clone());
+    assert!(!diagnostics.borrow().has_errors(), "{:?}", diagnostics.borrow().diagnostics);
+    let mut evaluator = ExpressionEvaluator::new().with_max_call_depth(max_call_depth);
+    let evaluation = ast.evaluate(&mut evaluator);
+    let values = evaluation.results.into_iter().map(|result| result.value).collect();
+    (values, evaluation.error.unwrap())
+}
+
+fn main() {
+    let source = SourceFile::new(FileId::new(0), String::from("depth.calc"), PROGRAM.to_string());
+
+    let (values, error) = run(&source, DEFAULT_MAX_CALL_DEPTH);
+    println!("{}", error);
+    // Evaluation stops at the overflow; `after` is never bound.
+    assert_eq!(values, [Value::Integer(3)]);
+    assert_eq!(error.to_string(), "Stack overflow: more than 256 nested calls");
+    let RuntimeError::StackOverflow { max_depth, trace, span } = error else {
+        panic!("expected a stack overflow, found {:?}", error);
+    };
+    assert_eq!(max_depth, DEFAULT_MAX_CALL_DEPTH);
+    assert_eq!(source.location(&span), "depth.calc:5:12");
+    // The call that did not fit comes first, then every call in progress,
+    // innermost first, down to the one made at the top level.
+    assert_eq!(trace.len(), DEFAULT_MAX_CALL_DEPTH + 1);
+    let innermost: Vec<(&str, String)> = trace
+        .iter()
+        .take(3)
+        .map(|call_site| (call_site.function.as_str(), source.location(&call_site.span)))
+        .collect();
+    assert_eq!(
+        innermost,
+        [
+            ("ping", String::from("depth.calc:5:12")),
+            ("pong", String::from("depth.calc:2:12")),
+            ("ping", String::from("depth.calc:5:12")),
+        ]
+    );
+    let outermost = trace.last().unwrap();
+    assert_eq!((outermost.function.as_str(), source.location(&outermost.span).as_str()), ("ping", "depth.calc:8:1"));
+    assert_eq!(source.slice(&outermost.span), "ping(0)");
+
+    for max_call_depth in [0, 1, 7, 1_000_000] {
+        let (_, error) = run(&source, max_call_depth);
+        println!("--max-depth {}: {}", max_call_depth, error);
+        let RuntimeError::StackOverflow { max_depth, trace, .. } = error else {
+            panic!("expected a stack overflow, found {:?}", error);
+        };
+        assert_eq!(max_depth, max_call_depth);
+        assert_eq!(trace.len(), max_call_depth + 1);
+    }
+    println!("call depth passed");
+}
@@ -0,0 +1,169 @@
+//! Hashes the expressions of a generated formula by structure, rewrites the
+//! repeated ones into temporaries, and checks that the rewritten program
+//! computes the same values and prints the same results while the
+//! evaluator visits fewer nodes.
+//!
+//! ```text
+//! cargo run --example common_subexpressions
+//! ```
+
+use std::collections::HashMap;
+
+use compiler::{
+    ast::{
+        evaluator::{Evaluation, ExpressionEvaluator},
+        modules::{Program, SourceSnapshot},
+        subexpressions::eliminate_common_subexpressions,
+        value::Value,
+        ExpressionKind, StatementKind,
+    },
+    diagnostics::DiagnosticsBag,
+};
+
+const PROGRAM: &str = "function area(a, b, c) {
+    return (a * b + c) * (a * b + c) / 2
+}
+function grow(x) {
+    let y = x * 3
+    let y = y + x * 3
+    return y
+}
+let a = 3
+let b = 4
+let c = 5
+let scale = a * b + c
+let first = (a * b + c) * 2 + area(a, b, c)
+let second = (a * b + c) / 3 + area(a, b, c)
+let g = a * 7
+let g = grow(g)
+let h = a * 7 + g
+let d = (a * c - b) * (a * c - b)
+let e = (a * c - b) * (a * c - b) + 1
+(a * b + c) - len(\"abc\")
+";
+
+const EXPECTED: &str = "function area(a, b, c) {
+    let _cse0 = a * b + c
+    return _cse0 * _cse0 / 2
+}
+function grow(x) {
+    let _cse1 = x * 3
+    let y = _cse1
+    let y = y + _cse1
+    return y
+}
+let a = 3
+let b = 4
+let c = 5
+let scale = a * b + c
+let _cse2 = area(a, b, c)
+let first = scale * 2 + _cse2
+let second = scale / 3 + _cse2
+let _cse3 = a * 7
+let g = _cse3
+let g = grow(g)
+let h = _cse3 + g
+let _cse4 = a * c - b
+let d = _cse4 * _cse4
+let e = d + 1
+scale - len(\"abc\")
+";
+
+/// Loads `text` as `formulas.calc` and runs it, with how many nodes the
+/// evaluator visited.
+fn load(text: &str) -> (Program, Evaluation, usize) {
+    let mut files = HashMap::from([(String::from("formulas.calc"), text.to_string())]);
+    let diagnostics = DiagnosticsBag::new_cell();
+    let program = Program::load("formulas.calc", &mut files, diagnostics.clone()).unwrap();
+    program.ast.type_check(diagnostics.clone());
+    assert!(!diagnostics.borrow().has_errors(), "{:?}", diagnostics.borrow().diagnostics);
+    let mut evaluator = ExpressionEvaluator::new();
+    let evaluation = program.ast.evaluate(&mut evaluator);
+    assert_eq!(evaluation.error, None);
+    (program, evaluation, evaluator.fuel_used())
+}
+
+/// The value each global of `names` holds at the end.
+fn globals(program: &Program, evaluation: &Evaluation, names: &[&str]) -> Vec<Value> {
+    names
+        .iter()
+        .map(|name| evaluation.environment[&program.ast.global_in(program.main, name).unwrap()].clone())
+        .collect()
+}
+
+fn main() {
+    let (before, evaluation_before, nodes_before) = load(PROGRAM);
+
+    // The `(a * b + c)` of `first` is in the class of the initializer of
+    // `scale`, with or without its parentheses. The one in `area` reads the
+    // parameters rather than the globals, and is not.
+    let table = before.ast.hash_expressions();
+    let initializer = |name: &str| {
+        let variable_id = before.ast.global_in(before.main, name).unwrap();
+        before
+            .ast
+            .statements
+            .iter()
+            .find_map(|stmt| match &stmt.stmt_kind {
+                StatementKind::Let(let_stmt) if let_stmt.variable_id == variable_id => Some(let_stmt.initializer),
+                _ => None,
+            })
+            .unwrap()
+    };
+    let ExpressionKind::Binary(first) = &before.ast.query_expr(initializer("first")).kind else {
+        panic!("`first` is a sum");
+    };
+    let ExpressionKind::Binary(product) = &before.ast.query_expr(first.left).kind else {
+        panic!("`first` starts with a product");
+    };
+    assert!(table.same(product.left, initializer("scale")));
+    assert_eq!(table.size(product.left), 5);
+    let ExpressionKind::Parenthesized(parenthesized) = &before.ast.query_expr(product.left).kind else {
+        panic!("the sum is in parentheses");
+    };
+    assert!(table.same(product.left, parenthesized.expr));
+    let area = before.ast.function_by_name("area").unwrap();
+    let StatementKind::Return(return_stmt) = &before.ast.query_stmt(before.ast.query_function(area).body.statements[0]).stmt_kind else {
+        panic!("`area` returns");
+    };
+    let ExpressionKind::Binary(quotient) = &before.ast.query_expr(return_stmt.value).kind else {
+        panic!("`area` returns a quotient");
+    };
+    let ExpressionKind::Binary(square) = &before.ast.query_expr(quotient.left).kind else {
+        panic!("`area` squares the sum");
+    };
+    assert!(table.same(square.left, square.right));
+    assert!(!table.same(square.left, initializer("scale")));
+
+    let snapshot = SourceSnapshot {
+        files: vec![(String::from("formulas.calc"), Ok(PROGRAM.to_string()))],
+    };
+    let elimination = eliminate_common_subexpressions("formulas.calc", snapshot).unwrap();
+    print!("{}", elimination.text);
+    for temporary in elimination.temporaries.iter() {
+        println!("temporary: {}", temporary);
+    }
+    assert_eq!(elimination.text, EXPECTED);
+    let names: Vec<&str> = elimination.temporaries.iter().map(|temporary| temporary.name.as_str()).collect();
+    // The second `let y` reads the first `y` in its initializer, so `y` is
+    // not reused there. At the top level `scale` and `d` are reused for
+    // their own initializers, `a * 7` gets a temporary since `g` is bound
+    // again before its last occurrence, and so does the `a * c - b` left
+    // in `d` after the first round.
+    assert_eq!(names, ["_cse0", "_cse1", "scale", "_cse2", "_cse3", "d", "_cse4"]);
+    assert_eq!(elimination.temporaries[2].occurrences, 4);
+
+    let (after, evaluation_after, nodes_after) = load(&elimination.text);
+    println!("evaluated nodes: {} before, {} after", nodes_before, nodes_after);
+    assert_eq!((nodes_before, nodes_after), (137, 85));
+    let names = ["scale", "first", "second", "d", "e", "g", "h"];
+    assert_eq!(
+        globals(&before, &evaluation_before, &names),
+        globals(&after, &evaluation_after, &names)
+    );
+    // No temporary adds a line to what `run` prints: every top-level
+    // statement still has the value it had, and the `_cseN` have none.
+    let results = |evaluation: &Evaluation| -> Vec<Value> { evaluation.results.iter().map(|result| result.value.clone()).collect() };
+    assert_eq!(results(&evaluation_before), results(&evaluation_after));
+    println!("common subexpressions passed");
+}
@@ -0,0 +1,126 @@
+//! Compiles a program into a cache in a temporary directory, runs the
+//! stored program under different options, and checks that changing an
+//! imported file, the command, the compiler version or the sources it was
+//! built from makes a new key while the same inputs hit the stored entry,
+//! and that an entry compiled from other files is not taken even under the
+//! same key.
+//!
+//! ```text
+//! cargo run --example compile_cache
+//! ```
+
+use std::{collections::HashMap, env, fs};
+
+use compiler::{
+    ast::{
+        evaluator::{Budget, ExpressionEvaluator, RuntimeError},
+        image,
+        modules::{Program, SourceSnapshot},
+        value::Value,
+    },
+    cache::{Artifact, Cache, CacheKey, CacheStats, SourceHash, CACHE_FORMAT_VERSION, SOURCE_FINGERPRINT},
+    diagnostics::DiagnosticsBag,
+    json::Json,
+};
+
+fn snapshot(files: &[(&str, &str)]) -> SourceSnapshot {
+    let mut loader: HashMap<String, String> = files.iter().map(|(path, text)| (path.to_string(), text.to_string())).collect();
+    SourceSnapshot::read("main.calc", &mut loader).unwrap()
+}
+
+fn command(arguments: &[&str]) -> Vec<String> {
+    arguments.iter().map(|argument| argument.to_string()).collect()
+}
+
+fn main() {
+    let dir = env::temp_dir().join(format!("compile-cache-example-{}", std::process::id()));
+    let cache = Cache::new(&dir);
+    cache.clear().unwrap();
+
+    let version = format!("compiler 0.1.0 ({}) cache format {}", SOURCE_FINGERPRINT, CACHE_FORMAT_VERSION);
+    let program = [
+        ("main.calc", "import \"units.calc\"\nlet width = units::metre * 2\nunits::double(width) + 1\nlet half = 0.5\n"),
+        ("units.calc", "let metre = 100\nfunction double(x) {\n    return x * 2\n}\n"),
+    ];
+    let sources = snapshot(&program);
+    let names: Vec<&str> = sources.files.iter().map(|(path, _)| path.as_str()).collect();
+    assert_eq!(names, ["main.calc", "units.calc"]);
+
+    let run = command(&["run", "main.calc"]);
+    let key = CacheKey::new(&version, &run, &sources.files);
+    assert_eq!(key, CacheKey::new(&version, &run, &snapshot(&program).files), "the key is reproducible");
+
+    let hashes = SourceHash::of_files(&sources.files);
+    assert_eq!(cache.get(key, &hashes), None);
+    let diagnostics = DiagnosticsBag::new_cell();
+    let compiled = Program::load("main.calc", &mut sources.clone(), diagnostics.clone()).unwrap();
+    compiled.ast.type_check(diagnostics.clone());
+    assert!(!diagnostics.borrow().has_errors(), "{:?}", diagnostics.borrow().diagnostics);
+    let artifact = Artifact {
+        sources: hashes.clone(),
+        program: Some(image::save(&compiled)),
+        ..Artifact::default()
+    };
+    cache.put(key, &artifact).unwrap();
+    let cached = cache.get(key, &hashes).unwrap();
+    assert_eq!(cached, artifact);
+
+    // The stored program runs like the one it was saved from, with the
+    // spans and values pointing into the same sources.
+    let loaded = image::load(cached.program.as_ref().unwrap(), &sources).unwrap();
+    assert_eq!(loaded.main, compiled.main);
+    let expected = compiled.ast.evaluate(&mut ExpressionEvaluator::new());
+    let evaluation = loaded.ast.evaluate(&mut ExpressionEvaluator::new());
+    assert_eq!(evaluation, expected);
+    let values: Vec<Value> = evaluation.results.iter().map(|result| result.value.clone()).collect();
+    println!("{:?}", values);
+    assert_eq!(values, [Value::Integer(100), Value::Integer(200), Value::Integer(401), Value::Float(0.5)]);
+    assert_eq!(loaded.main_source().slice(&evaluation.results[2].span), "units::double(width) + 1");
+
+    // The options of `run` apply to the stored program when it runs.
+    let evaluation = loaded.ast.evaluate(&mut ExpressionEvaluator::new().with_fuel(10));
+    assert!(matches!(evaluation.error, Some(RuntimeError::BudgetExhausted { budget: Budget::Fuel, limit: 10, .. })));
+
+    // An image that does not fit the sources is not loaded.
+    let Json::Object(mut fields) = artifact.program.clone().unwrap() else {
+        panic!("an image is an object");
+    };
+    fields.retain(|(name, _)| name != "modules");
+    assert!(image::load(&Json::Object(fields), &sources).is_none());
+    let shortened = snapshot(&[program[0], ("units.calc", "let metre = 1\n")]);
+    assert!(image::load(artifact.program.as_ref().unwrap(), &shortened).is_none());
+
+    let edited = snapshot(&[program[0], ("units.calc", "let metre = 1000\nfunction double(x) {\n    return x * 2\n}\n")]);
+    let changes = [
+        ("an imported file", CacheKey::new(&version, &run, &edited.files)),
+        ("the command", CacheKey::new(&version, &command(&["check", "main.calc"]), &sources.files)),
+        ("the compiler", CacheKey::new("compiler 0.2.0 (0000000000000000) cache format 3", &run, &sources.files)),
+        ("the compiler sources", CacheKey::new("compiler 0.1.0 (0000000000000000) cache format 3", &run, &sources.files)),
+    ];
+    for (change, changed) in changes {
+        println!("{} {} after changing {}", key, changed, change);
+        assert_ne!(changed, key, "changing {} changes the key", change);
+        assert_eq!(cache.get(changed, &hashes), None);
+    }
+
+    // Should other files hash to the same key, the entry is not theirs.
+    let edited_hashes = SourceHash::of_files(&edited.files);
+    assert_ne!(edited_hashes, hashes);
+    assert_eq!(cache.get(key, &edited_hashes), None);
+
+    let stats = cache.stats();
+    println!("{}", stats);
+    assert_eq!(
+        stats,
+        CacheStats {
+            entries: 1,
+            bytes: fs::metadata(dir.join(format!("{}.json", key))).unwrap().len(),
+            hits: 1,
+            misses: 6,
+        }
+    );
+    assert_eq!(cache.clear().unwrap(), 1);
+    assert_eq!(cache.stats(), CacheStats::default());
+    fs::remove_dir_all(&dir).unwrap();
+    println!("compile cache passed");
+}
@@ -0,0 +1,100 @@
+//! Checks the warnings about unused variables, parameters and functions,
+//! and that dead code elimination removes their code from the IR while
+//! the code it keeps still verifies.
+//!
+//! ```text
+//! cargo run --example dead_code
+//! ```
+
+use compiler::{
+    ast::{
+        lib::Id,
+        text::{FileId, SourceFile},
+        Ast,
+    },
+    diagnostics::{DiagnosticKind, DiagnosticsBag},
+    ir,
+};
+
+const PROGRAM: &str = "function unused(a, _ignored) {
+    let doubled = a * 2
+    return 1
+}
+function helper(x, y) {
+    return x
+}
+function only_from_unused() {
+    return helper(1, 2)
+}
+let _scratch = 3
+let k = helper(4, 5 * 2)
+let label = len(\"abc\") + 1
+k
+";
+
+const EXPECTED: &str = "function @helper(%0: int) -> int {
+b0:
+    ret %0
+}
+
+function @$main() -> void {
+b0:
+    %0: int = const 4
+    %1: int = call @helper(%0)
+    output %1
+    %2: string = const \"abc\"
+    %3: int = call len(%2)
+    %4: int = const 1
+    %5: int = add %3, %4
+    output %5
+    output %1
+    ret
+}
+";
+
+fn main() {
+    let source = SourceFile::new(FileId::new(0), String::from("dead.calc"), PROGRAM.to_string());
+    let diagnostics = DiagnosticsBag::new_cell();
+    let ast = Ast::parse(&source, diagnostics.clone());
+    let checker = ast.type_check(diagnostics.clone());
+    assert_eq!(diagnostics.borrow().diagnostics, [], "the program checks");
+
+    let liveness = ast.check_liveness(diagnostics.clone());
+    let warnings: Vec<(String, String)> = diagnostics
+        .borrow()
+        .diagnostics
+        .iter()
+        .map(|diagnostic| {
+            assert_eq!(diagnostic.kind, DiagnosticKind::Warning);
+            (diagnostic.message.clone(), source.location(&diagnostic.span))
+        })
+        .collect();
+    for (message, location) in warnings.iter() {
+        println!("{}: {}", location, message);
+    }
+    // `label` is not reported: `run` prints the value of a top-level `let`.
+    let expected = [
+        ("Function `unused` is never called", "dead.calc:1:10"),
+        ("Variable `doubled` is never used", "dead.calc:2:9"),
+        ("Parameter `y` is never used", "dead.calc:5:20"),
+        ("Function `only_from_unused` is never called", "dead.calc:8:10"),
+    ];
+    let expected: Vec<(String, String)> = expected.iter().map(|(message, location)| (message.to_string(), location.to_string())).collect();
+    assert_eq!(warnings, expected);
+    let helper = ast.function_by_name("helper").unwrap();
+    assert!(liveness.is_called(helper), "`helper` is called from the top level");
+
+    let mut program = ir::lower(&ast, &checker);
+    let removed = ir::eliminate_dead_code(&mut program);
+    println!("{}", removed);
+    print!("{}", program);
+    assert_eq!(removed.functions, ["unused", "only_from_unused"]);
+    assert_eq!(removed.parameters, 1);
+    // No function reads `k`, so the top-level code that does keeps it in a
+    // register and the global goes as well. The value of every top-level
+    // statement but `_scratch` is output, so the code computing it stays.
+    assert_eq!(removed.globals, ["_scratch", "k", "label"]);
+    assert_eq!(ir::verify(&program), []);
+    assert_eq!(program.to_string(), EXPECTED);
+    println!("dead code passed");
+}
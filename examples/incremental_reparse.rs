@@ -0,0 +1,179 @@
+//! Applies random edits to formula files through `IncrementalParser` and
+//! checks after every edit that the result matches a full reparse of the
+//! new text: the same tree, the same syntax and type errors, the same
+//! name resolution, and unchanged `ItemId`s for the items that were kept.
+//! It also checks that the syntax tree is the same and holds the new text
+//! byte for byte, and that the arenas do not fill up with replaced nodes.
+//!
+//! ```text
+//! cargo run --example incremental_reparse [edits] [seed]
+//! ```
+
+use std::{env, process};
+
+use compiler::{
+    ast::{
+        incremental::{IncrementalParser, TextEdit, MIN_GARBAGE},
+        lib::Id,
+        symbols::SymbolIndex,
+        text::{FileId, SourceFile, TextSpan},
+        Ast,
+    },
+    diagnostics::{Diagnostic, DiagnosticsBag},
+    json::Json,
+};
+
+const PROGRAM: &str = "// areas of a few shapes
+import \"units.calc\"
+let width = 4
+let height = 2.5 /* metres */
+function area(w: float, h) -> float {
+  let result = w * h
+  return result
+}
+let name = \"room\"
+let total = area(1.5, height) + 2.0
+function double(x) { return x + x }
+let twice = double(width)
+units::metre * total
+";
+
+/// Pieces of code that random edits insert, chosen to open and close
+/// constructs that span items.
+const SNIPPETS: [&str; 31] = [
+    "let ", "function ", "return ", "(", ")", "{", "}", "+", "*", "=", ",", ":", "->", "\"", "/*", "*/", "//", "\n", " ",
+    "width", "height", "area(", "x", "42", "3.5", "int", "é", "double(", "import ", "use ", "::",
+];
+
+/// A xorshift generator, so runs can be replayed from their seed.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound.max(1) as u64) as usize
+    }
+}
+
+fn random_edit(random: &mut Random, text: &str) -> TextEdit {
+    let boundaries: Vec<usize> = (0..=text.len()).filter(|index| text.is_char_boundary(*index)).collect();
+    let start = random.below(boundaries.len());
+    let end = (start + random.below(6)).min(boundaries.len() - 1);
+    let replacement: String = (0..random.below(3)).map(|_| SNIPPETS[random.below(SNIPPETS.len())]).collect();
+    TextEdit::new(boundaries[start], boundaries[end], replacement)
+}
+
+/// The JSON export without arena ids, which differ between the two trees.
+fn without_ids(json: Json) -> Json {
+    match json {
+        Json::Object(fields) => Json::Object(
+            fields.into_iter().filter(|(key, _)| key != "id").map(|(key, value)| (key, without_ids(value))).collect(),
+        ),
+        Json::Array(values) => Json::Array(values.into_iter().map(without_ids).collect()),
+        json => json,
+    }
+}
+
+/// What a tree looks like from the outside.
+#[derive(Debug, PartialEq)]
+struct Observation {
+    json: Json,
+    formatted: String,
+    /// Every name with the span of its declaration.
+    names: Vec<(TextSpan, Option<TextSpan>)>,
+    type_errors: Vec<Diagnostic>,
+}
+
+fn observe(ast: &Ast) -> Observation {
+    let json = without_ids(Json::parse(&ast.to_json()).unwrap());
+    let symbols = SymbolIndex::new(ast);
+    let names = symbols
+        .references
+        .iter()
+        .map(|reference| (reference.span.clone(), symbols.declaration(reference.symbol).map(|declaration| declaration.span.clone())))
+        .collect();
+    let diagnostics = DiagnosticsBag::new_cell();
+    ast.type_check(diagnostics.clone());
+    let type_errors = diagnostics.borrow().diagnostics.clone();
+    Observation {
+        json,
+        formatted: ast.format(),
+        names,
+        type_errors,
+    }
+}
+
+/// How many nodes the arenas of `ast` hold.
+fn arena_nodes(ast: &Ast) -> usize {
+    ast.items.data.len()
+        + ast.statements.data.len()
+        + ast.expressions.data.len()
+        + ast.functions.data.len()
+        + ast.variables.data.len()
+        + ast.imports.data.len()
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let edits: usize = args.next().and_then(|arg| arg.parse().ok()).unwrap_or(2000);
+    let seed: u64 = args.next().and_then(|arg| arg.parse().ok()).unwrap_or(0x5eed);
+    let mut random = Random(seed | 1);
+    let mut parser = IncrementalParser::new(SourceFile::new(FileId::new(0), "shapes.calc".to_string(), PROGRAM.to_string()));
+    let mut reparsed_items = 0;
+    let mut total_items = 0;
+    let mut compactions = 0;
+    for index in 0..edits {
+        // Start over from time to time, so the text does not drift into noise.
+        if index % 100 == 0 {
+            let length = parser.source.text.len();
+            parser.edit(&TextEdit::new(0, length, PROGRAM.to_string()));
+        }
+        let edit = random_edit(&mut random, &parser.source.text);
+        let kept: Vec<_> = parser.ast.top_level.clone();
+        // Only compacting takes nodes out of the expressions arena.
+        let expressions_before = parser.ast.expressions.data.len();
+        let reparse = parser.edit(&edit);
+        if parser.ast.expressions.data.len() < expressions_before {
+            compactions += 1;
+        }
+
+        let diagnostics = DiagnosticsBag::new_cell();
+        let full = Ast::parse(&parser.source, diagnostics.clone());
+        let mismatch = if observe(&parser.ast) != observe(&full) {
+            Some("the trees differ")
+        } else if full.syntax().text() != parser.source.text {
+            Some("the syntax tree lost text")
+        } else if parser.ast.syntax_tree != full.syntax_tree {
+            Some("the syntax trees differ")
+        } else if parser.diagnostics() != diagnostics.borrow().diagnostics {
+            Some("the syntax errors differ")
+        } else if kept.iter().filter(|item_id| !reparse.removed.contains(item_id)).any(|item_id| !parser.ast.top_level.contains(item_id)) {
+            Some("a kept item lost its id")
+        } else if parser.ast.top_level.iter().any(|item_id| parser.ast.query_item(*item_id).id != *item_id) {
+            Some("an item is not in its slot")
+        } else if arena_nodes(&parser.ast) > 2 * arena_nodes(&full) + MIN_GARBAGE {
+            Some("the arenas kept too many replaced nodes")
+        } else {
+            None
+        };
+        if let Some(mismatch) = mismatch {
+            eprintln!("edit {} (seed {}): {} after {:?}", index, seed, mismatch, edit);
+            eprintln!("{}", parser.source.text);
+            process::exit(1);
+        }
+        reparsed_items += reparse.added.len();
+        total_items += parser.ast.top_level.len();
+    }
+    println!(
+        "{} edits matched a full reparse, reparsing {:.1}% of the items and compacting the arenas {} times",
+        edits,
+        100.0 * reparsed_items as f64 / total_items.max(1) as f64,
+        compactions
+    );
+}
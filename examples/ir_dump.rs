@@ -0,0 +1,131 @@
+//! Lowers a program to the intermediate representation and checks its text
+//! dump, then runs the verifier over a hand-built function with a branch
+//! and a phi, first as written and then broken in the ways it catches.
+//!
+//! ```text
+//! cargo run --example ir_dump
+//! ```
+
+use compiler::{
+    ast::{lib::{Id, IdVec}, text::{FileId, SourceFile}, types::Type, value::Value, Ast, BinOperatorKind},
+    diagnostics::DiagnosticsBag,
+    ir::{self, Block, BlockId, Instruction, IrFunction, IrFunctionId, IrProgram, Register, Terminator},
+};
+
+const PROGRAM: &str = "let width = 3
+function area(height: int) -> int {
+    return width * height
+}
+let tall = area(4) + len(\"ab\")
+";
+
+const EXPECTED: &str = "global @width: int
+global @tall: int
+
+function @area(%0: int) -> int {
+b0:
+    %1: int = load @width
+    %2: int = mul %1, %0
+    ret %2
+}
+
+function @$main() -> void {
+b0:
+    %0: int = const 3
+    store @width, %0
+    output %0
+    %1: int = const 4
+    %2: int = call @area(%1)
+    %3: string = const \"ab\"
+    %4: int = call len(%3)
+    %5: int = add %2, %4
+    store @tall, %5
+    output %5
+    ret
+}
+";
+
+fn register(index: usize) -> Register {
+    Register::new(index)
+}
+
+fn block(index: usize) -> BlockId {
+    BlockId::new(index)
+}
+
+/// `choose(c) = c ? 1 : 2`, as a diamond whose join picks the value with a
+/// phi; `broken` returns it with one of the rules of the IR broken.
+fn choose(broken: usize) -> IrProgram {
+    let mut function = IrFunction {
+        name: String::from("choose"),
+        parameters: vec![register(0)],
+        return_type: Type::Int,
+        registers: IdVec::new(),
+        blocks: IdVec::new(),
+    };
+    for ty in [Type::Int, Type::Int, Type::Int, Type::Int] {
+        function.registers.push(ty);
+    }
+    let constant = |dest: usize, value: i64| Instruction::Const { dest: register(dest), value: Value::Integer(value) };
+    let phi = Instruction::Phi { dest: register(3), incoming: vec![(block(1), register(1)), (block(2), register(2))] };
+    let blocks = [
+        Block { instructions: vec![], terminator: Terminator::Branch { condition: register(0), then_block: block(1), else_block: block(2) } },
+        Block { instructions: vec![constant(1, 1)], terminator: Terminator::Jump(block(3)) },
+        Block { instructions: vec![constant(2, 2)], terminator: Terminator::Jump(block(3)) },
+        Block { instructions: vec![phi], terminator: Terminator::Return(Some(register(3))) },
+    ];
+    for block in blocks {
+        function.blocks.push(block);
+    }
+    let blocks = &mut function.blocks.data;
+    match broken {
+        // The join returns the register of one arm, which does not dominate it.
+        1 => blocks[3].terminator = Terminator::Return(Some(register(1))),
+        // The phi misses the else arm.
+        2 => blocks[3].instructions[0] = Instruction::Phi { dest: register(3), incoming: vec![(block(1), register(1))] },
+        // Both arms define the same register.
+        3 => blocks[2].instructions[0] = constant(1, 2),
+        // The else arm computes a float.
+        4 => {
+            blocks[2].instructions[0] = Instruction::Const { dest: register(2), value: Value::Float(2.0) };
+        }
+        // An addition after the phi, then a phi after that.
+        5 => {
+            let sum = Instruction::Binary { dest: register(3), operator: BinOperatorKind::Plus, left: register(1), right: register(1) };
+            blocks[3].instructions.insert(0, sum);
+        }
+        _ => {}
+    }
+    let mut program = IrProgram { globals: IdVec::new(), functions: IdVec::new(), main: IrFunctionId::new(0) };
+    program.functions.push(function);
+    program
+}
+
+fn main() {
+    let source = SourceFile::new(FileId::new(0), String::from("area.calc"), PROGRAM.to_string());
+    let diagnostics = DiagnosticsBag::new_cell();
+    let ast = Ast::parse(&source, diagnostics.clone());
+    let checker = ast.type_check(diagnostics.clone());
+    assert_eq!(diagnostics.borrow().diagnostics, []);
+    let program = ir::lower(&ast, &checker);
+    print!("{}", program);
+    assert_eq!(program.to_string(), EXPECTED);
+    assert_eq!(ir::verify(&program), []);
+
+    println!();
+    print!("{}", choose(0));
+    assert_eq!(ir::verify(&choose(0)), [], "the diamond is valid");
+    let expected = [
+        "@choose b3: %1 is used where its definition does not dominate",
+        "@choose b3: phi %3 comes in from [b1], but the predecessors are [b1, b2]",
+        "@choose b2: %1 is defined more than once",
+        "@choose b2: %2 is a constant of type int, expected float",
+        "@choose b3: %3 is defined more than once",
+    ];
+    for (broken, expected) in expected.into_iter().enumerate() {
+        let errors: Vec<String> = ir::verify(&choose(broken + 1)).iter().map(ToString::to_string).collect();
+        println!("{}", errors.join("\n"));
+        assert!(errors.iter().any(|error| error == expected), "expected `{}` in {:?}", expected, errors);
+    }
+    println!("ir passed");
+}
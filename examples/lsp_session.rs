@@ -0,0 +1,167 @@
+//! Drives the `lsp` binary through a scripted editing session over stdio
+//! and checks its answers: diagnostics after opening and changing a file,
+//! both by range and in full, hover, go-to-definition, document symbols,
+//! references, rename and completion. Also checks that a message longer
+//! than the limit is refused without being stored.
+//!
+//! ```text
+//! cargo build --bin lsp && cargo run --example lsp_session [path/to/lsp]
+//! ```
+
+use std::{
+    env,
+    io::{BufReader, ErrorKind},
+    process::{Command, Stdio},
+};
+
+use compiler::{
+    json::Json,
+    lsp::protocol::{read_message, write_message, MAX_CONTENT_LENGTH},
+};
+
+const URI: &str = "file:///area.calc";
+
+const SOURCE: &str = "function area(width, height) {
+  return width * height
+}
+let side = 4
+let total = area(side, 5)
+";
+
+const BROKEN_SOURCE: &str = "let side = 4
+let total = side +
+";
+
+fn position(line: i64, character: i64) -> Json {
+    Json::object(vec![("line", Json::Number(line)), ("character", Json::Number(character))])
+}
+
+fn text_document() -> Json {
+    Json::object(vec![("uri", Json::string(URI))])
+}
+
+fn request(id: i64, method: &str, params: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::string("2.0")),
+        ("id", Json::Number(id)),
+        ("method", Json::string(method)),
+        ("params", params),
+    ])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::string("2.0")),
+        ("method", Json::string(method)),
+        ("params", params),
+    ])
+}
+
+fn main() {
+    let server_path = env::args().nth(1).unwrap_or_else(|| "target/debug/lsp".to_string());
+    let mut server = Command::new(&server_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|error| panic!("could not start {}: {}", server_path, error));
+    let mut input = server.stdin.take().unwrap();
+    let mut output = BufReader::new(server.stdout.take().unwrap());
+    let mut exchange = |message: Json| -> Option<Json> {
+        println!("--> {}", message);
+        write_message(&mut input, &message).unwrap();
+        let expects_answer = message.get("id").is_some() || message.get("method").and_then(Json::as_str).unwrap().starts_with("textDocument/did");
+        if !expects_answer {
+            return None;
+        }
+        let body = read_message(&mut output).unwrap().expect("the server closed its output");
+        println!("<-- {}", body);
+        Some(Json::parse(&body).unwrap())
+    };
+
+    let initialize = exchange(request(1, "initialize", Json::object(vec![("capabilities", Json::object(vec![]))]))).unwrap();
+    let capabilities = initialize.get("result").and_then(|result| result.get("capabilities")).unwrap();
+    assert_eq!(capabilities.get("hoverProvider"), Some(&Json::Bool(true)));
+    exchange(notification("initialized", Json::object(vec![])));
+
+    let open = Json::object(vec![
+        ("uri", Json::string(URI)),
+        ("languageId", Json::string("calc")),
+        ("version", Json::Number(1)),
+        ("text", Json::string(SOURCE)),
+    ]);
+    let published = exchange(notification("textDocument/didOpen", Json::object(vec![("textDocument", open)]))).unwrap();
+    let diagnostics = published.get("params").and_then(|params| params.get("diagnostics")).and_then(Json::as_array).unwrap();
+    let messages: Vec<(i64, &str)> = diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.get("severity").and_then(Json::as_i64).unwrap(), diagnostic.get("message").and_then(Json::as_str).unwrap()))
+        .collect();
+    assert!(messages.is_empty(), "a valid file gets no diagnostics, not even for the `let` holding its answer: {:?}", messages);
+
+    let hover = exchange(request(2, "textDocument/hover", Json::object(vec![("textDocument", text_document()), ("position", position(4, 5))]))).unwrap();
+    let contents = hover.get("result").and_then(|result| result.get("contents")).and_then(|contents| contents.get("value")).and_then(Json::as_str).unwrap();
+    assert!(contents.contains("let total: int = 20"), "hover shows the type and value of a let");
+
+    let hover = exchange(request(3, "textDocument/hover", Json::object(vec![("textDocument", text_document()), ("position", position(4, 13))]))).unwrap();
+    let contents = hover.get("result").and_then(|result| result.get("contents")).and_then(|contents| contents.get("value")).and_then(Json::as_str).unwrap();
+    assert!(contents.contains("function area(width: int, height: int) -> int"), "hover shows the signature of a function");
+
+    let definition = exchange(request(4, "textDocument/definition", Json::object(vec![("textDocument", text_document()), ("position", position(4, 18))]))).unwrap();
+    let start = definition.get("result").and_then(|result| result.get("range")).and_then(|range| range.get("start")).unwrap();
+    assert_eq!(start, &position(3, 4), "`side` is declared by the let on line 4");
+
+    let definition = exchange(request(5, "textDocument/definition", Json::object(vec![("textDocument", text_document()), ("position", position(1, 10))]))).unwrap();
+    let start = definition.get("result").and_then(|result| result.get("range")).and_then(|range| range.get("start")).unwrap();
+    assert_eq!(start, &position(0, 14), "`width` is declared as a parameter");
+
+    let symbols = exchange(request(6, "textDocument/documentSymbol", Json::object(vec![("textDocument", text_document())]))).unwrap();
+    let names: Vec<&str> = symbols.get("result").and_then(Json::as_array).unwrap().iter().filter_map(|symbol| symbol.get("name").and_then(Json::as_str)).collect();
+    assert_eq!(names, ["area", "side", "total"]);
+
+    let references = exchange(request(7, "textDocument/references", Json::object(vec![("textDocument", text_document()), ("position", position(3, 5))]))).unwrap();
+    let starts: Vec<&Json> = references.get("result").and_then(Json::as_array).unwrap().iter().filter_map(|location| location.get("range").and_then(|range| range.get("start"))).collect();
+    assert_eq!(starts, [&position(3, 4), &position(4, 17)], "`side` is declared once and used once");
+
+    let rename = |id: i64, line: i64, character: i64, new_name: &str| {
+        request(id, "textDocument/rename", Json::object(vec![("textDocument", text_document()), ("position", position(line, character)), ("newName", Json::string(new_name))]))
+    };
+    let renamed = exchange(rename(8, 0, 15, "w")).unwrap();
+    let edits = renamed.get("result").and_then(|result| result.get("changes")).and_then(|changes| changes.get(URI)).and_then(Json::as_array).unwrap();
+    assert_eq!(edits.len(), 2, "the parameter and its use are renamed");
+    assert!(edits.iter().all(|edit| edit.get("newText") == Some(&Json::string("w"))));
+
+    let refused = exchange(rename(9, 0, 15, "height")).unwrap();
+    let message = refused.get("error").and_then(|error| error.get("message")).and_then(Json::as_str).unwrap();
+    assert!(message.contains("already declared"), "a parameter cannot take the name of another one");
+
+    let completion = exchange(request(10, "textDocument/completion", Json::object(vec![("textDocument", text_document()), ("position", position(4, 18))]))).unwrap();
+    let labels: Vec<&str> = completion.get("result").and_then(Json::as_array).unwrap().iter().filter_map(|item| item.get("label").and_then(Json::as_str)).collect();
+    assert_eq!(labels, ["side"], "only `side` starts with `s` in scope");
+
+    let range = Json::object(vec![("start", position(3, 11)), ("end", position(3, 12))]);
+    let change = Json::object(vec![
+        ("textDocument", Json::object(vec![("uri", Json::string(URI)), ("version", Json::Number(2))])),
+        ("contentChanges", Json::Array(vec![Json::object(vec![("range", range), ("text", Json::string("6"))])])),
+    ]);
+    exchange(notification("textDocument/didChange", change)).unwrap();
+    let hover = exchange(request(11, "textDocument/hover", Json::object(vec![("textDocument", text_document()), ("position", position(4, 5))]))).unwrap();
+    let contents = hover.get("result").and_then(|result| result.get("contents")).and_then(|contents| contents.get("value")).and_then(Json::as_str).unwrap();
+    assert!(contents.contains("let total: int = 30"), "a ranged change edits the text");
+
+    let change = Json::object(vec![
+        ("textDocument", Json::object(vec![("uri", Json::string(URI)), ("version", Json::Number(3))])),
+        ("contentChanges", Json::Array(vec![Json::object(vec![("text", Json::string(BROKEN_SOURCE))])])),
+    ]);
+    let published = exchange(notification("textDocument/didChange", change)).unwrap();
+    let diagnostics = published.get("params").and_then(|params| params.get("diagnostics")).and_then(Json::as_array).unwrap();
+    assert_eq!(diagnostics.len(), 1, "the missing operand is reported once");
+
+    exchange(request(12, "shutdown", Json::Null)).unwrap();
+    exchange(notification("exit", Json::Null));
+    let status = server.wait().unwrap();
+    assert!(status.success(), "the server exits cleanly after shutdown");
+
+    let oversized = format!("Content-Length: {}\r\n\r\n", MAX_CONTENT_LENGTH + 1);
+    let error = read_message(&mut oversized.as_bytes()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData, "a body over the limit is refused without being stored");
+    println!("lsp session passed");
+}
@@ -0,0 +1,76 @@
+//! Loads a program of several files from memory: a main file that calls
+//! into two modules through qualified names and `use`, and a second
+//! program whose imports form a cycle, which is reported with its path.
+//!
+//! ```text
+//! cargo run --example modules
+//! ```
+
+use std::collections::HashMap;
+
+use compiler::{
+    ast::{evaluator::ExpressionEvaluator, modules::Program, value::Value},
+    diagnostics::DiagnosticsBag,
+};
+
+const FILES: [(&str, &str); 6] = [
+    (
+        "main.calc",
+        "import \"shapes/geometry.calc\"
+use units::scale
+let floor = geometry::area(4, 3)
+let round = geometry::circle(1.0)
+scale(floor) + geometry::sides
+",
+    ),
+    (
+        "shapes/geometry.calc",
+        "import \"../units.calc\"
+let sides = 4
+function area(w, h) {
+    return w * h
+}
+function circle(r: float) -> float {
+    return units::pi * r * r
+}
+",
+    ),
+    ("units.calc", "let pi = 3.5\nfunction scale(x) { return x * 100 }\n"),
+    ("a.calc", "import \"b.calc\"\nlet x = b::y\n"),
+    ("b.calc", "use c::z\nlet y = z\n"),
+    ("c.calc", "import \"a.calc\"\nlet z = 1\n"),
+];
+
+fn main() {
+    let mut files: HashMap<String, String> = FILES.iter().map(|(path, text)| (path.to_string(), text.to_string())).collect();
+
+    let diagnostics = DiagnosticsBag::new_cell();
+    let program = Program::load("main.calc", &mut files, diagnostics.clone()).unwrap();
+    program.ast.type_check(diagnostics.clone());
+    assert_eq!(diagnostics.borrow().diagnostics, [], "the program checks");
+    let names: Vec<&str> = program.sources.files.iter().map(|file| file.name.as_str()).collect();
+    assert_eq!(names, ["main.calc", "shapes/geometry.calc", "units.calc"]);
+
+    let evaluation = program.ast.evaluate(&mut ExpressionEvaluator::new());
+    assert_eq!(evaluation.error, None);
+    let main_results: Vec<&Value> = evaluation
+        .results
+        .iter()
+        .filter(|result| result.span.file_id == program.main)
+        .map(|result| &result.value)
+        .collect();
+    for value in main_results.iter() {
+        println!("{}", value);
+    }
+    assert_eq!(main_results, [&Value::Integer(12), &Value::Float(3.5), &Value::Integer(1204)]);
+
+    let diagnostics = DiagnosticsBag::new_cell();
+    let program = Program::load("a.calc", &mut files, diagnostics.clone()).unwrap();
+    let messages: Vec<String> = diagnostics.borrow().diagnostics.iter().map(|diagnostic| diagnostic.message.clone()).collect();
+    println!("{}", messages.join("\n"));
+    assert_eq!(messages, ["Import cycle: a.calc -> b.calc -> c.calc -> a.calc"]);
+    let cycle = &diagnostics.borrow().diagnostics[0];
+    assert_eq!(program.sources.get(cycle.span.file_id).name, "c.calc");
+    assert_eq!(cycle.span.literal, "\"a.calc\"");
+    println!("modules passed");
+}
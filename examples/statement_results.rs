@@ -0,0 +1,100 @@
+//! Evaluates a program statement by statement and checks the value and
+//! span reported for each top-level statement, and the global variables at
+//! the end.
+//!
+//! ```text
+//! cargo run --example statement_results
+//! ```
+
+use compiler::{
+    ast::{
+        evaluator::{ExpressionEvaluator, RuntimeError},
+        lib::Id,
+        text::{FileId, SourceFile},
+        value::Value,
+        Ast, StatementKind,
+    },
+    diagnostics::DiagnosticsBag,
+};
+
+const PROGRAM: &str = "42
+let a = 40
+let b = 30
+function note(x) {
+    let ignored = x
+}
+note(a)
+let c = a + b
+let _half = c / 2
+\"total\"
+_half / 5
+";
+
+fn main() {
+    let source = SourceFile::new(FileId::new(0), String::from("notebook.calc"), PROGRAM.to_string());
+    let diagnostics = DiagnosticsBag::new_cell();
+    let ast = Ast::parse(&source, diagnostics.clone());
+    ast.type_check(diagnostics.clone());
+    assert!(!diagnostics.borrow().has_errors(), "{:?}", diagnostics.borrow().diagnostics);
+    let evaluation = ast.evaluate(&mut ExpressionEvaluator::new());
+    assert_eq!(evaluation.error, None);
+
+    // A bare literal has a value like any other statement. Declaring
+    // `note` and calling it, which returns nothing, have none, and neither
+    // has `let _half`, whose name starts with `_`.
+    let results: Vec<(String, String, Value)> = evaluation
+        .results
+        .iter()
+        .map(|result| (source.location(&result.span), source.slice(&result.span).to_string(), result.value.clone()))
+        .collect();
+    for (location, text, value) in results.iter() {
+        println!("{}: {} => {}", location, text, value);
+    }
+    let expected = [
+        ("notebook.calc:1:1", "42", Value::Integer(42)),
+        ("notebook.calc:2:1", "let a = 40", Value::Integer(40)),
+        ("notebook.calc:3:1", "let b = 30", Value::Integer(30)),
+        ("notebook.calc:8:1", "let c = a + b", Value::Integer(70)),
+        ("notebook.calc:10:1", "\"total\"", Value::String(String::from("total"))),
+        ("notebook.calc:11:1", "_half / 5", Value::Integer(7)),
+    ];
+    let expected: Vec<(String, String, Value)> = expected
+        .into_iter()
+        .map(|(location, text, value)| (location.to_string(), text.to_string(), value))
+        .collect();
+    assert_eq!(results, expected);
+    for result in evaluation.results.iter() {
+        assert_eq!(result.span, ast.query_stmt(result.stmt_id).span(&ast));
+    }
+
+    // The environment holds the globals, not the locals of `note`.
+    let mut globals: Vec<(String, Value)> = evaluation
+        .environment
+        .iter()
+        .map(|(variable_id, value)| (ast.query_variable(*variable_id).name.clone(), value.clone()))
+        .collect();
+    globals.sort_by(|left, right| left.0.cmp(&right.0));
+    assert_eq!(
+        globals,
+        [
+            (String::from("_half"), Value::Integer(35)),
+            (String::from("a"), Value::Integer(40)),
+            (String::from("b"), Value::Integer(30)),
+            (String::from("c"), Value::Integer(70)),
+        ]
+    );
+
+    // When evaluation stops at an error, the results cover the statements
+    // before it.
+    let source = SourceFile::new(FileId::new(0), String::from("notebook.calc"), String::from("let a = 1\na / 0\nlet b = 2"));
+    let ast = Ast::parse(&source, DiagnosticsBag::new_cell());
+    let evaluation = ast.evaluate(&mut ExpressionEvaluator::new());
+    assert!(matches!(evaluation.error, Some(RuntimeError::DivisionByZero { .. })));
+    let values: Vec<Value> = evaluation.results.iter().map(|result| result.value.clone()).collect();
+    assert_eq!(values, [Value::Integer(1)]);
+    assert_eq!(evaluation.environment.len(), 1);
+    let StatementKind::Let(_) = &ast.query_stmt(evaluation.results[0].stmt_id).stmt_kind else {
+        panic!("the first result is the `let`");
+    };
+    println!("statement results passed");
+}
@@ -0,0 +1,64 @@
+//! Rewrites a file through its syntax tree: renames a variable by
+//! replacing its identifier tokens and checks that everything else,
+//! comments and spacing included, is left as it was.
+//!
+//! ```text
+//! cargo run --example syntax_rewrite
+//! ```
+
+use compiler::{
+    ast::{
+        lexer::TokenKind,
+        lib::Id,
+        syntax::{GreenToken, SyntaxKind, SyntaxNode},
+        syntax_view::{FileSyntax, StatementSyntax, SyntaxView},
+        text::{FileId, SourceFile},
+        Ast,
+    },
+    diagnostics::DiagnosticsBag,
+};
+
+const SOURCE: &str = "// sizes in metres
+let width   = 4   /* wall */
+let area = width*2.5 // rounded down
+function volume(h) {
+  return area  *  h + width
+}
+";
+
+const EXPECTED: &str = "// sizes in metres
+let breadth   = 4   /* wall */
+let area = breadth*2.5 // rounded down
+function volume(h) {
+  return area  *  h + breadth
+}
+";
+
+fn main() {
+    let diagnostics = DiagnosticsBag::new_cell();
+    let source = SourceFile::new(FileId::new(0), "sizes.calc".to_string(), SOURCE.to_string());
+    let ast = Ast::parse(&source, diagnostics.clone());
+    assert!(!diagnostics.borrow().has_errors());
+    assert_eq!(ast.syntax().text(), SOURCE, "the syntax tree holds the text byte for byte");
+
+    let file = FileSyntax::cast(ast.syntax()).unwrap();
+    let names: Vec<String> = file
+        .statements()
+        .iter()
+        .filter_map(|statement| match statement {
+            StatementSyntax::Let(let_statement) => let_statement.name().map(|name| name.text().to_string()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(names, ["width", "area"]);
+
+    // Each replacement makes a new tree, so the next token is looked up in it.
+    let mut root = ast.syntax_tree.clone();
+    let identifier = SyntaxKind::Token(TokenKind::Identifier);
+    while let Some(token) = SyntaxNode::new_root(root.clone()).tokens().into_iter().find(|token| token.kind() == identifier && token.text() == "width") {
+        root = token.replace_with(GreenToken::new(identifier, "breadth".to_string()));
+    }
+    print!("{}", root);
+    assert_eq!(root.to_string(), EXPECTED);
+    println!("syntax rewrite passed");
+}
@@ -0,0 +1,44 @@
+use super::{types::{FunctionType, Type}, value::Value};
+
+/// Functions that are always in scope without being declared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Builtin {
+    /// `len(text)`: the number of characters in a string.
+    Len,
+}
+
+impl Builtin {
+    pub const ALL: [Builtin; 1] = [Builtin::Len];
+
+    pub fn lookup(name: &str) -> Option<Builtin> {
+        Self::ALL.into_iter().find(|builtin| builtin.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Len => "len",
+        }
+    }
+
+    pub fn parameter_count(&self) -> usize {
+        match self {
+            Builtin::Len => 1,
+        }
+    }
+
+    pub fn signature(&self) -> FunctionType {
+        match self {
+            Builtin::Len => FunctionType::new(vec![Type::String], Type::Int),
+        }
+    }
+
+    /// Calls the builtin with arguments whose count has already been checked.
+    /// Returns the name of the expected argument type when an argument has
+    /// the wrong type.
+    pub fn call(&self, arguments: &[Value]) -> Result<Value, &'static str> {
+        match (self, arguments) {
+            (Builtin::Len, [Value::String(text)]) => Ok(Value::Integer(text.chars().count() as i64)),
+            (Builtin::Len, _) => Err("string"),
+        }
+    }
+}
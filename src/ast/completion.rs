@@ -0,0 +1,273 @@
+use crate::diagnostics::DiagnosticsBag;
+
+use super::{
+    builtins::Builtin,
+    lexer::{TokenKind, TriviaKind},
+    syntax::{SyntaxKind, SyntaxNode, SyntaxToken},
+    text::SourceFile,
+    type_checker::TypeChecker,
+    types::Type,
+    Ast, FunctionId, ItemKind, StatementKind, StmtId, VariableId,
+};
+
+const KEYWORDS: [(&str, TokenKind); 5] = [
+    ("let", TokenKind::Let),
+    ("function", TokenKind::Function),
+    ("return", TokenKind::Return),
+    ("import", TokenKind::Import),
+    ("use", TokenKind::Use),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Variable,
+    Function,
+    Builtin,
+    Keyword,
+    Type,
+}
+
+/// A name that can be typed at the cursor, with its type or signature as
+/// `detail`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    pub detail: String,
+}
+
+impl Completion {
+    fn new(label: &str, kind: CompletionKind, detail: String) -> Self {
+        Self {
+            label: label.to_string(),
+            kind,
+            detail,
+        }
+    }
+}
+
+/// Parses and checks `source`, which may be half typed, and lists what can
+/// be typed at `offset`.
+pub fn complete(source: &SourceFile, offset: usize) -> Vec<Completion> {
+    let diagnostics = DiagnosticsBag::new_cell();
+    let ast = Ast::parse(source, diagnostics.clone());
+    let checker = ast.type_check(diagnostics);
+    completions(&ast, &checker, offset)
+}
+
+/// What can be typed at `offset` of a checked file, starting with the word
+/// the cursor is at the end of, if any.
+///
+/// Where a name is being declared, after `let`, `function` or in a
+/// parameter list, there is nothing to complete, nor in an import or after
+/// the `::` of a module, whose names a file parsed on its own does not
+/// know. After `:` or `->` only type names are offered. Elsewhere the
+/// candidates are the variables in scope, the closest first, the
+/// functions, the built-ins and the keywords that can start a statement
+/// there. A variable is in scope once its `let` has ended before the word,
+/// and in a function body the globals declared before the function are
+/// too, as the parser resolves them.
+pub fn completions(ast: &Ast, checker: &TypeChecker, offset: usize) -> Vec<Completion> {
+    let root = ast.syntax();
+    let tokens = root.tokens();
+    // Inside a comment or a string there is nothing to complete.
+    if tokens.iter().any(|token| in_literal(token, offset)) {
+        return Vec::new();
+    }
+    let word = tokens
+        .iter()
+        .find(|token| is_word(token) && token.text_range().start < offset && offset <= token.text_range().end);
+    let (word_start, prefix) = match word {
+        Some(word) => {
+            let start = word.text_range().start;
+            (start, &word.text()[..offset - start])
+        }
+        None => (offset, ""),
+    };
+    let previous = tokens
+        .iter()
+        .rfind(|token| !token.kind().is_trivia() && token.text_range().end <= word_start);
+
+    let mut candidates = Vec::new();
+    match previous.map(|token| (token.kind(), token.parent().kind())) {
+        Some((SyntaxKind::Token(TokenKind::Let | TokenKind::Function | TokenKind::Import | TokenKind::Use | TokenKind::ColonColon), _)) => {}
+        Some((SyntaxKind::Token(TokenKind::Identifier), SyntaxKind::LetStatement | SyntaxKind::Parameter | SyntaxKind::Function)) => {}
+        Some((SyntaxKind::Token(TokenKind::OpenParen | TokenKind::Comma), SyntaxKind::Function)) => {}
+        Some((SyntaxKind::Token(TokenKind::Identifier), SyntaxKind::TypeAnnotation)) => {}
+        Some((SyntaxKind::Token(TokenKind::Colon | TokenKind::Arrow), SyntaxKind::TypeAnnotation)) => {
+            for ty in Type::NAMED {
+                candidates.push(Completion::new(&ty.to_string(), CompletionKind::Type, String::new()));
+            }
+        }
+        _ => {
+            let scope = Scope::at(ast, &root, word_start);
+            for variable_id in scope.variables.iter() {
+                let variable = ast.query_variable(*variable_id);
+                let detail = checker.variable_types.get(*variable_id).to_string();
+                candidates.push(Completion::new(&variable.name, CompletionKind::Variable, detail));
+            }
+            for item in ast.top_level_items() {
+                if let ItemKind::Function(function_id) = item.kind {
+                    let function = ast.query_function(function_id);
+                    candidates.push(Completion::new(&function.name, CompletionKind::Function, checker.function_signature(ast, function_id)));
+                }
+            }
+            for builtin in Builtin::ALL {
+                let detail = format!("function {}{}", builtin.name(), builtin.signature());
+                candidates.push(Completion::new(builtin.name(), CompletionKind::Builtin, detail));
+            }
+            if starts_statement(&root, previous, word_start) {
+                for (keyword, kind) in KEYWORDS {
+                    let allowed = match kind {
+                        TokenKind::Function | TokenKind::Import | TokenKind::Use => scope.function.is_none(),
+                        TokenKind::Return => scope.function.is_some(),
+                        _ => true,
+                    };
+                    if allowed {
+                        candidates.push(Completion::new(keyword, CompletionKind::Keyword, String::new()));
+                    }
+                }
+            }
+        }
+    }
+    let mut seen = Vec::new();
+    candidates.retain(|candidate| {
+        let keep = !candidate.label.is_empty() && candidate.label.starts_with(prefix) && !seen.contains(&candidate.label);
+        seen.push(candidate.label.clone());
+        keep
+    });
+    candidates
+}
+
+/// Identifiers and keywords, which are words being typed until the cursor
+/// moves past them.
+fn is_word(token: &SyntaxToken) -> bool {
+    match token.kind() {
+        SyntaxKind::Token(kind) => kind == TokenKind::Identifier || KEYWORDS.iter().any(|(_, keyword)| *keyword == kind),
+        _ => false,
+    }
+}
+
+/// Whether `offset` is inside a comment or a string literal, including
+/// right after one that is not closed yet.
+fn in_literal(token: &SyntaxToken, offset: usize) -> bool {
+    let range = token.text_range();
+    if offset <= range.start {
+        return false;
+    }
+    let text = token.text();
+    match token.kind() {
+        SyntaxKind::Trivia(TriviaKind::LineComment) => offset <= range.end,
+        SyntaxKind::Trivia(TriviaKind::BlockComment) => offset < range.end || !text.ends_with("*/") || text.len() < 4,
+        SyntaxKind::Token(TokenKind::String) => offset < range.end || !text.ends_with('"') || text.len() < 2,
+        _ => false,
+    }
+}
+
+/// Whether a statement can start at `offset`: at the start of the file or
+/// a body, after a `}`, or on a new line after a token that can end one.
+fn starts_statement(root: &SyntaxNode, previous: Option<&SyntaxToken>, offset: usize) -> bool {
+    let Some(previous) = previous else {
+        return true;
+    };
+    match previous.kind() {
+        SyntaxKind::Token(TokenKind::OpenBrace | TokenKind::CloseBrace) => true,
+        SyntaxKind::Token(
+            TokenKind::Plus
+            | TokenKind::Minus
+            | TokenKind::Asterisk
+            | TokenKind::Slash
+            | TokenKind::Equals
+            | TokenKind::OpenParen
+            | TokenKind::Comma
+            | TokenKind::Colon
+            | TokenKind::Arrow
+            | TokenKind::ColonColon
+            | TokenKind::Return
+            | TokenKind::Import
+            | TokenKind::Use,
+        ) => false,
+        _ => root.text()[previous.text_range().end..offset].contains('\n'),
+    }
+}
+
+/// The variables visible at an offset, innermost and latest first, and the
+/// function whose body the offset is in.
+struct Scope {
+    variables: Vec<VariableId>,
+    function: Option<FunctionId>,
+}
+
+impl Scope {
+    fn at(ast: &Ast, root: &SyntaxNode, offset: usize) -> Self {
+        let mut globals = Vec::new();
+        let mut locals = Vec::new();
+        let mut parameters = Vec::new();
+        let mut function = None;
+        // The parser wraps each item in one node, in order.
+        for (node, item) in root.children().zip(ast.top_level_items()) {
+            match item.kind {
+                ItemKind::Statement(stmt_id) => globals.push((node, stmt_id)),
+                ItemKind::Import(_) => {}
+                ItemKind::Function(function_id) => {
+                    let Some(body) = node.child_node(SyntaxKind::Body) else {
+                        continue;
+                    };
+                    let open_brace = body.child_token(TokenKind::OpenBrace);
+                    let close_brace = body.child_token(TokenKind::CloseBrace);
+                    let inside = open_brace.is_some_and(|open_brace| open_brace.text_range().end <= offset)
+                        && close_brace.is_none_or(|close_brace| offset <= close_brace.text_range().start);
+                    if !inside {
+                        continue;
+                    }
+                    function = Some(function_id);
+                    let declaration = ast.query_function(function_id);
+                    parameters.extend(declaration.parameters.iter().map(|parameter| parameter.variable_id));
+                    locals.extend(body.children().zip(declaration.body.statements.iter().copied()));
+                    break;
+                }
+            }
+        }
+        let variables = declared_before(ast, locals, offset)
+            .into_iter()
+            .rev()
+            .chain(parameters.into_iter().rev())
+            .chain(declared_before(ast, globals, offset).into_iter().rev())
+            .collect();
+        Self { variables, function }
+    }
+}
+
+/// The variables of the `let`s among `statements` that end before
+/// `offset`. The last of them is left out if its expression is missing,
+/// since that is the statement being typed.
+fn declared_before(ast: &Ast, statements: Vec<(SyntaxNode, StmtId)>, offset: usize) -> Vec<VariableId> {
+    let mut ended: Vec<(SyntaxNode, StmtId)> = statements.into_iter().filter(|(node, _)| last_token_end(node) < offset).collect();
+    if ended.last().is_some_and(|(node, _)| ends_open(node)) {
+        ended.pop();
+    }
+    ended.into_iter().filter_map(|(_, stmt_id)| let_variable(ast, stmt_id)).collect()
+}
+
+/// Whether the last expression of `node` is missing, as in `let x = 1 +`.
+fn ends_open(node: &SyntaxNode) -> bool {
+    let end = last_token_end(node);
+    node.descendants()
+        .iter()
+        .any(|descendant| descendant.kind() == SyntaxKind::ErrorExpr && descendant.text_range().is_empty() && descendant.text_range().start >= end)
+}
+
+fn let_variable(ast: &Ast, stmt_id: StmtId) -> Option<VariableId> {
+    match &ast.query_stmt(stmt_id).stmt_kind {
+        StatementKind::Let(let_stmt) => Some(let_stmt.variable_id),
+        _ => None,
+    }
+}
+
+/// The end of the last token of `node`, trivia left out.
+fn last_token_end(node: &SyntaxNode) -> usize {
+    node.tokens()
+        .iter()
+        .rfind(|token| !token.kind().is_trivia())
+        .map_or(node.text_range().start, |token| token.text_range().end)
+}
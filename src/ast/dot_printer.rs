@@ -0,0 +1,188 @@
+use super::{lib::Id, visitor::Visitor, Ast, ExprId, ExpressionKind, FunctionId, ImportId, ItemId, StatementKind, StmtId};
+
+/// Renders the `Ast` arena as a Graphviz digraph. Every item, statement and
+/// expression becomes a node named after its id (`item3`, `stmt1`, `expr7`),
+/// edges point from a node to its children and every function is drawn in
+/// its own `cluster_` subgraph.
+///
+/// ```text
+/// compiler ast --dot formulas.calc | dot -Tsvg > formulas.svg
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DotPrinter {
+    pub output: String,
+    indent: usize,
+    parents: Vec<String>,
+}
+
+impl DotPrinter {
+    pub fn new() -> Self {
+        Self {
+            output: String::new(),
+            indent: 1,
+            parents: Vec::new(),
+        }
+    }
+
+    /// Wraps the nodes and edges collected so far in a `digraph`.
+    pub fn finish(&self) -> String {
+        format!(
+            "digraph ast {{\n  node [fontname=\"monospace\"];\n{}}}\n",
+            self.output
+        )
+    }
+
+    fn escape(label: &str) -> String {
+        label.replace('\\', "\\\\").replace('"', "\\\"")
+    }
+
+    fn write_line(&mut self, line: &str) {
+        self.output.push_str(&"  ".repeat(self.indent));
+        self.output.push_str(line);
+        self.output.push('\n');
+    }
+
+    fn write_node(&mut self, name: &str, label: &str, shape: &str) {
+        self.write_line(&format!("{} [label=\"{}\", shape={}];", name, Self::escape(label), shape));
+    }
+
+    fn write_edge(&mut self, to: &str) {
+        if let Some(from) = self.parents.last().cloned() {
+            self.write_line(&format!("{} -> {};", from, to));
+        }
+    }
+
+    /// Visits a child expression with a labelled edge from the current node.
+    fn visit_child(&mut self, ast: &Ast, expr_id: ExprId, label: &str) {
+        let parent = self.parents.last().cloned();
+        self.visit_expression(ast, expr_id);
+        if let Some(parent) = parent {
+            self.write_line(&format!("{} -> {} [label=\"{}\"];", parent, Self::expr_name(expr_id), label));
+        }
+    }
+
+    fn expr_name(expr_id: ExprId) -> String {
+        format!("expr{}", expr_id.to_usize())
+    }
+
+    fn stmt_name(stmt_id: StmtId) -> String {
+        format!("stmt{}", stmt_id.to_usize())
+    }
+}
+
+impl Visitor for DotPrinter {
+    fn visit_item(&mut self, ast: &Ast, item_id: ItemId) {
+        let name = format!("item{}", item_id.to_usize());
+        self.write_node(&name, &format!("item {}", item_id.to_usize()), "box");
+        self.parents.push(name);
+        self.do_visit_item(ast, item_id);
+        self.parents.pop();
+    }
+
+    fn visit_import(&mut self, ast: &Ast, import_id: ImportId) {
+        let name = format!("import{}", import_id.to_usize());
+        self.write_node(&name, &ast.query_import(import_id).text(), "box");
+        self.write_edge(&name);
+    }
+
+    fn visit_function(&mut self, ast: &Ast, function_id: FunctionId) {
+        let function = ast.query_function(function_id);
+        let name = format!("function{}", function_id.to_usize());
+        let parameters = function
+            .parameters
+            .iter()
+            .map(|parameter| match &parameter.type_annotation {
+                Some(type_annotation) => format!("{}: {}", parameter.identifier.span.literal, type_annotation.type_name()),
+                None => parameter.identifier.span.literal.clone(),
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        let return_type = match &function.return_type {
+            Some(return_type) => format!(" -> {}", return_type.type_name()),
+            None => String::new(),
+        };
+        self.write_line(&format!("subgraph cluster_{} {{", name));
+        self.indent += 1;
+        self.write_line(&format!("label=\"{}\";", Self::escape(&function.name)));
+        self.write_node(&name, &format!("function {}({}){}", function.name, parameters, return_type), "box");
+        self.write_edge(&name);
+        self.parents.push(name);
+        for stmt_id in function.body.statements.iter() {
+            self.visit_statement(ast, *stmt_id);
+        }
+        self.parents.pop();
+        self.indent -= 1;
+        self.write_line("}");
+    }
+
+    fn visit_statement(&mut self, ast: &Ast, stmt_id: StmtId) {
+        let name = Self::stmt_name(stmt_id);
+        let label = match &ast.query_stmt(stmt_id).stmt_kind {
+            StatementKind::Let(let_stmt) => match &let_stmt.type_annotation {
+                Some(type_annotation) => format!("let {}: {}", let_stmt.identifier.span.literal, type_annotation.type_name()),
+                None => format!("let {}", let_stmt.identifier.span.literal),
+            },
+            StatementKind::Return(_) => String::from("return"),
+            StatementKind::Expression(_) => String::from("expression"),
+        };
+        self.write_node(&name, &label, "box");
+        self.write_edge(&name);
+        self.parents.push(name);
+        match &ast.query_stmt(stmt_id).stmt_kind {
+            StatementKind::Let(let_stmt) => self.visit_let_statement(ast, let_stmt),
+            StatementKind::Return(return_stmt) => self.visit_child(ast, return_stmt.value, "value"),
+            StatementKind::Expression(expr_id) => self.visit_child(ast, *expr_id, "expr"),
+        }
+        self.parents.pop();
+    }
+
+    fn visit_let_statement(&mut self, ast: &Ast, stmt: &super::LetStatement) {
+        self.visit_child(ast, stmt.initializer, "initializer");
+    }
+
+    fn visit_expression(&mut self, ast: &Ast, expr_id: ExprId) {
+        let name = Self::expr_name(expr_id);
+        let (label, shape) = match &ast.query_expr(expr_id).kind {
+            ExpressionKind::Number(number) => (number.number.to_string(), "plaintext"),
+            ExpressionKind::Float(number) => (number.token.span.literal.clone(), "plaintext"),
+            ExpressionKind::String(string) => (string.token.span.literal.clone(), "plaintext"),
+            ExpressionKind::Variable(variable_expr) => (variable_expr.qualified_name(), "plaintext"),
+            ExpressionKind::Call(call_expr) => (format!("{}()", call_expr.qualified_name()), "box"),
+            ExpressionKind::Binary(bin_expr) => (bin_expr.operator.kind.to_string(), "circle"),
+            ExpressionKind::Parenthesized(_) => (String::from("( )"), "ellipse"),
+            ExpressionKind::Assignment(_) => (String::from("="), "circle"),
+            ExpressionKind::Error(_) => (String::from("error"), "octagon"),
+        };
+        self.write_node(&name, &label, shape);
+        self.parents.push(name);
+        self.do_visit_expression(ast, expr_id);
+        self.parents.pop();
+    }
+
+    fn visit_number(&mut self, _ast: &Ast, _number: &super::NumberExpr) {}
+
+    fn visit_float(&mut self, _ast: &Ast, _number: &super::FloatExpr) {}
+
+    fn visit_string(&mut self, _ast: &Ast, _string: &super::StringExpr) {}
+
+    fn visit_variable_expression(&mut self, _ast: &Ast, _variable_expr: &super::VariableExpr) {}
+
+    fn visit_call_expression(&mut self, ast: &Ast, call_expr: &super::CallExpr) {
+        for (index, argument) in call_expr.arguments.iter().enumerate() {
+            self.visit_child(ast, *argument, &format!("arg{}", index));
+        }
+    }
+
+    fn visit_binary_expression(&mut self, ast: &Ast, bin_expr: &super::BinaryExpr) {
+        self.visit_child(ast, bin_expr.left, "left");
+        self.visit_child(ast, bin_expr.right, "right");
+    }
+
+    fn visit_parenthesized_expression(&mut self, ast: &Ast, parenthesized_expr: &super::ParenthesizedExpr) {
+        self.visit_child(ast, parenthesized_expr.expr, "expr");
+    }
+
+    fn visit_assignment_expression(&mut self, ast: &Ast, assign_expr: &super::AssignExpr) {
+        self.visit_child(ast, assign_expr.expr, "expr");
+    }
+}
@@ -1,94 +1,454 @@
-use core::panic;
+use std::{collections::HashMap, fmt::{Display, Formatter}};
 
-use super::{visitor::Visitor, BinOperatorKind};
+use super::{builtins::Builtin, text::TextSpan, value::Value, BinOperatorKind, FunctionId, StmtId, VariableId};
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeError{
+    InvalidOperands{ operator:BinOperatorKind, left:&'static str, right:&'static str, span:TextSpan },
+    DivisionByZero{ span:TextSpan },
+    IntegerOverflow{ span:TextSpan },
+    UndefinedFunction{ name:String, span:TextSpan },
+    UndefinedVariable{ name:String, span:TextSpan },
+    WrongArgumentCount{ name:String, expected:usize, found:usize, span:TextSpan },
+    InvalidArgument{ name:String, expected:&'static str, found:&'static str, span:TextSpan },
+    /// More than `max_depth` calls were in progress. `trace` lists them from
+    /// the innermost call outwards.
+    StackOverflow{ max_depth:usize, trace:Vec<CallSite>, span:TextSpan },
+    /// Evaluation ran out of `budget` at `span`, after finishing
+    /// `completed_items` top-level items.
+    BudgetExhausted{ budget:Budget, limit:usize, completed_items:usize, span:TextSpan },
+}
+
+/// A resource whose use the evaluator can cap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget{
+    /// Expressions and statements evaluated, one unit each.
+    Fuel,
+    /// Bytes held by the variables of the global scope and the calls in
+    /// progress, as measured by `Value::size`.
+    Memory,
+}
+
+/// A function call in progress: the function and where it was called from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallSite{
+    pub function:String,
+    pub span:TextSpan
+}
+
+impl RuntimeError{
+    pub fn span(&self)->&TextSpan{
+        match self{
+            RuntimeError::InvalidOperands { span, .. } => span,
+            RuntimeError::DivisionByZero { span } => span,
+            RuntimeError::IntegerOverflow { span } => span,
+            RuntimeError::UndefinedFunction { span, .. } => span,
+            RuntimeError::UndefinedVariable { span, .. } => span,
+            RuntimeError::WrongArgumentCount { span, .. } => span,
+            RuntimeError::InvalidArgument { span, .. } => span,
+            RuntimeError::StackOverflow { span, .. } => span,
+            RuntimeError::BudgetExhausted { span, .. } => span,
+        }
+    }
+}
+
+impl Display for RuntimeError{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self{
+            RuntimeError::InvalidOperands { operator, left, right, .. } => write!(f, "Cannot apply `{}` to {} and {}", operator, left, right),
+            RuntimeError::DivisionByZero { .. } => write!(f, "Division by zero"),
+            RuntimeError::IntegerOverflow { .. } => write!(f, "Integer overflow"),
+            RuntimeError::UndefinedFunction { name, .. } => write!(f, "Undefined function `{}`", name),
+            RuntimeError::UndefinedVariable { name, .. } => write!(f, "Undefined variable `{}`", name),
+            RuntimeError::WrongArgumentCount { name, expected, found, .. } => write!(f, "`{}` takes {} argument(s) but {} were given", name, expected, found),
+            RuntimeError::InvalidArgument { name, expected, found, .. } => write!(f, "`{}` expects a {} argument, found {}", name, expected, found),
+            RuntimeError::StackOverflow { max_depth, .. } => write!(f, "Stack overflow: more than {} nested calls", max_depth),
+            RuntimeError::BudgetExhausted { budget: Budget::Fuel, limit, completed_items, .. } => write!(f, "Fuel budget of {} steps exhausted after {} top-level item(s)", limit, completed_items),
+            RuntimeError::BudgetExhausted { budget: Budget::Memory, limit, completed_items, .. } => write!(f, "Variables need more than {} bytes after {} top-level item(s)", limit, completed_items),
+        }
+    }
+}
+
+/// How many calls may be in progress at once unless configured otherwise.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 256;
+
+/// The parameters and locals of one function call. `return_value` is set
+/// by `return`, after which the rest of the body is skipped.
+#[derive(Debug, Clone)]
+struct Frame{
+    call_site:CallSite,
+    variables:HashMap<VariableId,Value>,
+    return_value:Option<Value>
+}
+
+/// The value of one top-level statement: what a `let` bound or what an
+/// expression statement evaluated to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementResult{
+    pub stmt_id:StmtId,
+    pub span:TextSpan,
+    pub value:Value
+}
+
+/// The outcome of running a program: the value of every top-level
+/// statement that has one, in order, and the global variables at the end.
+/// When evaluation stopped at `error`, both cover what ran before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation{
+    pub results:Vec<StatementResult>,
+    pub environment:HashMap<VariableId,Value>,
+    pub error:Option<RuntimeError>
+}
+
+/// Evaluates the items of an `Ast` in order. `value` holds the value of the
+/// statement evaluated last and `results` the value of each top-level
+/// statement. `variables` holds the value each global `let` bound and
+/// `frames` the locals of the function calls in progress, at most
+/// `max_call_depth` of them. Evaluation stops at the first `RuntimeError`,
+/// which is kept in `error`.
+///
+/// For untrusted programs evaluation can be capped with a fuel budget, which
+/// every statement and expression uses one unit of, and a memory limit on
+/// the variables held at once. Both are unlimited by default.
  pub struct ExpressionEvaluator{
-    pub result:Option<i64>,
-    pub value:Option<i64>
+    pub results:Vec<StatementResult>,
+    pub value:Option<Value>,
+    pub variables:HashMap<VariableId,Value>,
+    frames:Vec<Frame>,
+    max_call_depth:usize,
+    fuel_limit:Option<usize>,
+    fuel_used:usize,
+    memory_limit:Option<usize>,
+    memory_used:usize,
+    completed_items:usize,
+    pub error:Option<RuntimeError>
 }
 
 
+impl Default for ExpressionEvaluator{
+    fn default()->Self{
+        Self::new()
+    }
+}
+
 impl ExpressionEvaluator{
     pub fn new()->Self{
-        Self { result: None ,value:None}
+        Self {
+            results: Vec::new(),
+            value: None,
+            variables: HashMap::new(),
+            frames: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            fuel_limit: None,
+            fuel_used: 0,
+            memory_limit: None,
+            memory_used: 0,
+            completed_items: 0,
+            error: None,
+        }
+    }
+
+    pub fn with_fuel(mut self,fuel:usize)->Self{
+        self.fuel_limit = Some(fuel);
+        self
     }
-}
 
-impl Visitor for ExpressionEvaluator{
-    fn visit_item(&mut self,ast:&super::Ast,item_id:super::ItemId){
-        self.do_visit_item(ast,item_id);
+    pub fn with_memory_limit(mut self,bytes:usize)->Self{
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Hands over the results and the global variables, leaving the
+    /// evaluator empty.
+    pub fn take_evaluation(&mut self)->Evaluation{
+        Evaluation{
+            results: std::mem::take(&mut self.results),
+            environment: std::mem::take(&mut self.variables),
+            error: self.error.take(),
+        }
     }
 
-    fn visit_statement(&mut self,ast: &super::Ast,stmt_id:super::StmtId){
-        self.do_visit_statement(ast,stmt_id);
-    
+    /// How many units of fuel evaluation has used so far.
+    pub fn fuel_used(&self)->usize{
+        self.fuel_used
     }
 
-    fn do_visit_item(&mut self,ast:&super::Ast,item_id:super::ItemId){
-        let item = ast.query_item(item_id);
-        match item.kind{
-            super::ItemKind::Statement(stmt_id)=>{
-                self.visit_statement(ast, stmt_id);
+    fn budget_exhausted(&self,budget:Budget,limit:usize,span:TextSpan)->RuntimeError{
+        RuntimeError::BudgetExhausted { budget, limit, completed_items: self.completed_items, span }
+    }
+
+    /// Uses one unit of fuel for the node at `span`. Returns `false`, with
+    /// `error` set, once the budget is spent.
+    fn consume_fuel(&mut self,span:impl FnOnce()->TextSpan)->bool{
+        if let Some(limit) = self.fuel_limit{
+            if self.fuel_used >= limit{
+                self.error = Some(self.budget_exhausted(Budget::Fuel, limit, span()));
+                return false;
             }
-            super::ItemKind::Function(function_id) => todo!(),
         }
+        self.fuel_used += 1;
+        true
     }
 
-    fn do_visit_statement(&mut self,ast: &super::Ast,stmt_id:super::StmtId){
-        let stmt = ast.query_stmt(stmt_id);
-        match &stmt.stmt_kind{
-            super::StatementKind::Expression(expr_id)=>{
-                self.visit_expression(ast,*expr_id);
-            },
-            super::StatementKind::Let(stmt)=>{
-                self.visit_let_statement(ast,stmt);
+    /// Binds `variable_id` in the innermost call in progress, or in the
+    /// global scope outside of calls, within the memory limit.
+    fn bind(&mut self,variable_id:VariableId,value:Value,span:TextSpan){
+        let size = value.size();
+        let previous = match self.frames.last_mut(){
+            Some(frame)=>frame.variables.insert(variable_id, value),
+            None=>self.variables.insert(variable_id, value),
+        };
+        self.memory_used = self.memory_used + size - previous.map_or(0, |previous| previous.size());
+        if let Some(limit) = self.memory_limit{
+            if self.memory_used > limit{
+                self.error = Some(self.budget_exhausted(Budget::Memory, limit, span));
             }
         }
     }
 
-    fn visit_expression(&mut self,ast:&super::Ast, expr_id:super::ExprId){
-        let expr = ast.query_expr(expr_id);
-        match &expr.kind{
-            super::ExpressionKind::Number(number)=>{
-                self.visit_number(ast,number);
-            },
-            super::ExpressionKind::Binary(bin_expr)=>{
-                self.visit_binary_expression(ast,bin_expr);
+    pub fn with_max_call_depth(mut self,max_call_depth:usize)->Self{
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    fn apply_binary_operator(operator:&super::BinOperator,left:Value,right:Value)->Result<Value,RuntimeError>{
+        let span = operator.token.span.clone();
+        match (operator.kind,left,right){
+            (BinOperatorKind::Plus,Value::String(left),Value::String(right))=>Ok(Value::String(left + &right)),
+            (BinOperatorKind::Divide,Value::Integer(_),Value::Integer(0))=>Err(RuntimeError::DivisionByZero { span }),
+            (kind,Value::Integer(left),Value::Integer(right))=>{
+                let result = match kind{
+                    BinOperatorKind::Minus => left.checked_sub(right),
+                    BinOperatorKind::Plus => left.checked_add(right),
+                    BinOperatorKind::Multiply => left.checked_mul(right),
+                    BinOperatorKind::Divide => left.checked_div(right),
+                };
+                result.map(Value::Integer).ok_or(RuntimeError::IntegerOverflow { span })
             }
-            super::ExpressionKind::Parenthesized(parenthesized_expr) => {
-                self.visit_parenthesized_expression(ast, parenthesized_expr);
-            },
-            super::ExpressionKind::Assignment(assign_expr) => {
-                self.visit_assignment_expression(ast, assign_expr);
+            (BinOperatorKind::Divide,Value::Float(_),Value::Float(0.0))=>Err(RuntimeError::DivisionByZero { span }),
+            (kind,Value::Float(left),Value::Float(right))=>{
+                let result = match kind{
+                    BinOperatorKind::Minus => left - right,
+                    BinOperatorKind::Plus => left + right,
+                    BinOperatorKind::Multiply => left * right,
+                    BinOperatorKind::Divide => left / right,
+                };
+                Ok(Value::Float(result))
             }
+            (kind,left,right)=>Err(RuntimeError::InvalidOperands { operator: kind, left: left.type_name(), right: right.type_name(), span }),
         }
     }
 
-    fn visit_binary_expression(&mut self,ast: &super::Ast,bin_expr:&super::BinaryExpr){
-        self.visit_expression(ast, bin_expr.left);
-        let left = self.value.unwrap();
-        self.visit_expression(ast, bin_expr.right);
-        let right = self.value.unwrap();
-       
-           self.result =Some( match bin_expr.operator.kind{
-            BinOperatorKind::Minus => left - right,
-           BinOperatorKind::Plus => left + right,
-           BinOperatorKind::Multiply =>left * right,
-           BinOperatorKind::Divide => left / right,
-          
-        });
+    /// Starts a call of `function_id`: checks the arguments and the call
+    /// depth, pushes a frame with the parameters bound and returns the body
+    /// to run in it.
+    fn enter_function<'a>(&mut self,ast:&'a super::Ast,function_id:FunctionId,call_expr:&super::CallExpr,arguments:Vec<Value>)->Result<&'a [StmtId],RuntimeError>{
+        let function = ast.query_function(function_id);
+        let span = TextSpan::combine(vec![call_expr.callee.span.clone(),call_expr.close_paren.span.clone()]);
+        if arguments.len() != function.parameters.len(){
+            return Err(RuntimeError::WrongArgumentCount { name: function.name.clone(), expected: function.parameters.len(), found: arguments.len(), span });
+        }
+        let call_site = CallSite{ function: function.name.clone(), span: span.clone() };
+        if self.frames.len() >= self.max_call_depth{
+            let trace = std::iter::once(call_site)
+                .chain(self.frames.iter().rev().map(|frame| frame.call_site.clone()))
+                .collect();
+            return Err(RuntimeError::StackOverflow { max_depth: self.max_call_depth, trace, span });
+        }
+        self.frames.push(Frame{ call_site, variables: HashMap::new(), return_value: None });
+        for (parameter, argument) in function.parameters.iter().zip(arguments){
+            self.bind(parameter.variable_id, argument, span.clone());
+        }
+        Ok(&function.body.statements)
     }
-    
-    fn visit_let_statement(&mut self,ast:&super::Ast,stmt:&super::LetStatement) {
-        self.visit_expression(ast, stmt.initializer);
 
+    /// Ends the innermost call and returns what it returned.
+    fn leave_function(&mut self)->Option<Value>{
+        let frame = self.frames.pop()?;
+        let freed:usize = frame.variables.values().map(Value::size).sum();
+        self.memory_used -= freed;
+        frame.return_value
+    }
+
+    fn call_builtin(builtin:Builtin,call_expr:&super::CallExpr,arguments:Vec<Value>)->Result<Value,RuntimeError>{
+        let span = TextSpan::combine(vec![call_expr.callee.span.clone(),call_expr.close_paren.span.clone()]);
+        if arguments.len() != builtin.parameter_count(){
+            return Err(RuntimeError::WrongArgumentCount { name: builtin.name().to_string(), expected: builtin.parameter_count(), found: arguments.len(), span });
+        }
+        let found = arguments.first().map(Value::type_name).unwrap_or("nothing");
+        builtin.call(&arguments).map_err(|expected| RuntimeError::InvalidArgument { name: builtin.name().to_string(), expected, found, span })
+    }
+}
+
+/// A step of evaluation still to be taken. Expressions are evaluated onto a
+/// stack of operands, which the steps queued after their operands take the
+/// values from. `None` stands for the missing value of a call that returned
+/// nothing.
+enum Task<'a>{
+    Statement(StmtId),
+    Expression(super::ExprId),
+    /// Applies the operator to the two operands on top.
+    Binary(&'a super::BinaryExpr),
+    /// Calls the function with the arguments on top.
+    Call(&'a super::CallExpr),
+    /// Binds the operand on top to the variable of the `let`.
+    Let(&'a super::LetStatement),
+    /// Returns the operand on top from the call in progress.
+    Return,
+    /// Takes the operand on top as the value of an expression statement.
+    Discard,
+    /// Ends the call in progress. Its statements are queued above it, so a
+    /// `return` drops whatever is left of them.
+    EndCall,
+}
+
+impl ExpressionEvaluator{
+    /// Evaluates a top-level item. Evaluation does nothing once an error
+    /// stopped it.
+    pub fn evaluate_item(&mut self,ast:&super::Ast,item_id:super::ItemId){
+        self.value = None;
+        if self.error.is_some(){
+            return;
+        }
+        match ast.query_item(item_id).kind{
+            super::ItemKind::Statement(stmt_id)=>self.evaluate_statement(ast, stmt_id),
+            // Declaring a function does not evaluate its body, and the
+            // imported modules have run before.
+            super::ItemKind::Function(_) | super::ItemKind::Import(_) => {}
+        }
+        if self.error.is_some(){
+            return;
+        }
+        self.completed_items += 1;
+        if let (super::ItemKind::Statement(stmt_id),Some(value)) = (ast.query_item(item_id).kind,self.value.take()){
+            if !ast.is_result(stmt_id){
+                return;
+            }
+            let span = ast.query_stmt(stmt_id).span(ast);
+            self.results.push(StatementResult{ stmt_id, span, value });
+        }
     }
-    
-    fn visit_number(&mut self,_ast: &super::Ast,number:&super::NumberExpr) {
-        self.value= Some(number.number);
-        
+
+    /// Evaluates a top-level statement, and the calls it makes, without
+    /// recursing: what is left to do is kept on `tasks` and the calls in
+    /// progress on `frames`, so deep recursion in the program only ever
+    /// runs into `max_call_depth`.
+    fn evaluate_statement(&mut self,ast:&super::Ast,stmt_id:StmtId){
+        let mut tasks = vec![Task::Statement(stmt_id)];
+        let mut operands:Vec<Option<Value>> = Vec::new();
+        while let Some(task) = tasks.pop(){
+            match task{
+                Task::Statement(stmt_id)=>{
+                    let stmt = ast.query_stmt(stmt_id);
+                    if !self.consume_fuel(|| stmt.span(ast)){
+                        break;
+                    }
+                    match &stmt.stmt_kind{
+                        super::StatementKind::Expression(expr_id)=>tasks.extend([Task::Discard,Task::Expression(*expr_id)]),
+                        super::StatementKind::Let(let_stmt)=>tasks.extend([Task::Let(let_stmt),Task::Expression(let_stmt.initializer)]),
+                        super::StatementKind::Return(return_stmt)=>tasks.extend([Task::Return,Task::Expression(return_stmt.value)]),
+                    }
+                }
+                Task::Expression(expr_id)=>{
+                    let expr = ast.query_expr(expr_id);
+                    if !self.consume_fuel(|| expr.span(ast)){
+                        break;
+                    }
+                    match &expr.kind{
+                        super::ExpressionKind::Number(number)=>operands.push(Some(Value::Integer(number.number))),
+                        super::ExpressionKind::Float(number)=>operands.push(Some(Value::Float(number.number))),
+                        super::ExpressionKind::String(string)=>operands.push(Some(Value::String(string.value.clone()))),
+                        super::ExpressionKind::Variable(variable_expr)=>match self.lookup(variable_expr){
+                            Ok(value)=>operands.push(Some(value)),
+                            Err(error)=>self.error = Some(error),
+                        },
+                        super::ExpressionKind::Binary(bin_expr)=>{
+                            tasks.extend([Task::Binary(bin_expr),Task::Expression(bin_expr.right),Task::Expression(bin_expr.left)]);
+                        }
+                        super::ExpressionKind::Call(call_expr)=>{
+                            tasks.push(Task::Call(call_expr));
+                            tasks.extend(call_expr.arguments.iter().rev().map(|argument| Task::Expression(*argument)));
+                        }
+                        super::ExpressionKind::Parenthesized(parenthesized_expr)=>tasks.push(Task::Expression(parenthesized_expr.expr)),
+                        super::ExpressionKind::Assignment(assign_expr)=>tasks.push(Task::Expression(assign_expr.expr)),
+                        // Programs with syntax errors are not run.
+                        super::ExpressionKind::Error(_)=>operands.push(None),
+                    }
+                }
+                Task::Binary(bin_expr)=>{
+                    let right = operands.pop().flatten();
+                    let left = operands.pop().flatten();
+                    let (Some(left),Some(right)) = (left,right) else {
+                        operands.push(None);
+                        continue;
+                    };
+                    match Self::apply_binary_operator(&bin_expr.operator, left, right){
+                        Ok(value)=>operands.push(Some(value)),
+                        Err(error)=>self.error = Some(error),
+                    }
+                }
+                Task::Call(call_expr)=>{
+                    let arguments = operands.split_off(operands.len() - call_expr.arguments.len());
+                    let Some(arguments) = arguments.into_iter().collect::<Option<Vec<Value>>>() else {
+                        operands.push(None);
+                        continue;
+                    };
+                    let builtin = call_expr.qualifier.is_none().then(|| Builtin::lookup(call_expr.name())).flatten();
+                    match (ast.resolve_call(call_expr), builtin){
+                        (Some(function_id),_)=>match self.enter_function(ast, function_id, call_expr, arguments){
+                            Ok(body)=>{
+                                tasks.push(Task::EndCall);
+                                tasks.extend(body.iter().rev().map(|stmt_id| Task::Statement(*stmt_id)));
+                            }
+                            Err(error)=>self.error = Some(error),
+                        },
+                        (None,Some(builtin))=>match Self::call_builtin(builtin, call_expr, arguments){
+                            Ok(value)=>operands.push(Some(value)),
+                            Err(error)=>self.error = Some(error),
+                        },
+                        (None,None)=>self.error = Some(RuntimeError::UndefinedFunction { name: call_expr.name().to_string(), span: call_expr.callee.span.clone() }),
+                    }
+                }
+                Task::Let(let_stmt)=>{
+                    self.value = operands.pop().flatten();
+                    if let Some(value) = self.value.clone(){
+                        self.bind(let_stmt.variable_id, value, let_stmt.identifier.span.clone());
+                    }
+                }
+                Task::Return=>{
+                    self.value = operands.pop().flatten();
+                    if let Some(frame) = self.frames.last_mut(){
+                        frame.return_value = self.value.clone();
+                        while !matches!(tasks.last(), Some(Task::EndCall)){
+                            tasks.pop();
+                        }
+                    }
+                }
+                Task::Discard=>self.value = operands.pop().flatten(),
+                Task::EndCall=>{
+                    let return_value = self.leave_function();
+                    operands.push(return_value);
+                }
+            }
+            if self.error.is_some(){
+                break;
+            }
+        }
+        // An error leaves the calls it happened in.
+        while !self.frames.is_empty(){
+            self.leave_function();
+        }
     }
-    fn visit_parenthesized_expression(&mut self,ast:&super::Ast,parenthesized_expr:&super::ParenthesizedExpr) {
-        self.visit_expression(ast, parenthesized_expr.expr);
+
+    /// The value of a variable in the innermost call in progress, or in the
+    /// global scope.
+    fn lookup(&self,variable_expr:&super::VariableExpr)->Result<Value,RuntimeError>{
+        let value = variable_expr.variable_id.and_then(|variable_id| {
+            self.frames
+                .last()
+                .and_then(|frame| frame.variables.get(&variable_id))
+                .or_else(|| self.variables.get(&variable_id))
+        });
+        value.cloned().ok_or_else(|| RuntimeError::UndefinedVariable { name: variable_expr.name().to_string(), span: variable_expr.identifier.span.clone() })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,365 @@
+use super::{
+    lexer::{Token, TriviaKind},
+    visitor::Visitor,
+    Ast, BinOperatorAssiciativity, ExprId, ExpressionKind, ImportKind, ItemKind, Qualifier,
+};
+
+pub const INDENT_WIDTH: usize = 4;
+
+/// Prints an `Ast` back as source code in the canonical house style:
+/// one statement per line, single spaces around binary operators,
+/// parentheses only where precedence or associativity requires them and
+/// function bodies indented by `INDENT_WIDTH` spaces.
+///
+/// Comments are kept next to the token they were attached to by the lexer.
+/// A line comment in the middle of a statement ends the line and the rest of
+/// the statement continues one level deeper.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFormatter {
+    pub output: String,
+    pub indent: usize,
+    previous_item_is_function: Option<bool>,
+    line_start: bool,
+    statement_started: bool,
+    continuation: bool,
+}
+
+impl Default for SourceFormatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SourceFormatter {
+    pub fn new() -> Self {
+        Self {
+            output: String::new(),
+            indent: 0,
+            previous_item_is_function: None,
+            line_start: true,
+            statement_started: false,
+            continuation: false,
+        }
+    }
+
+    /// Writes the comments that follow the last item.
+    pub fn finish(&mut self, ast: &Ast) {
+        if let Some(eof) = &ast.eof {
+            self.newline();
+            self.write_leading_comments(eof);
+            self.newline();
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        if self.line_start {
+            let indent = if self.continuation { self.indent + INDENT_WIDTH } else { self.indent };
+            self.output.push_str(&" ".repeat(indent));
+            self.line_start = false;
+        }
+        self.output.push_str(text);
+    }
+
+    fn write_space(&mut self) {
+        if !self.line_start && !self.output.ends_with(' ') {
+            self.output.push(' ');
+        }
+    }
+
+    fn newline(&mut self) {
+        if self.line_start {
+            return;
+        }
+        self.output.truncate(self.output.trim_end_matches(' ').len());
+        self.output.push('\n');
+        self.line_start = true;
+        if self.statement_started {
+            self.continuation = true;
+        }
+    }
+
+    fn end_statement(&mut self) {
+        self.newline();
+        self.statement_started = false;
+        self.continuation = false;
+    }
+
+    fn write_leading_comments(&mut self, token: &Token) {
+        for (index, trivia) in token.leading_trivia.iter().enumerate() {
+            if trivia.kind == TriviaKind::Whitespace {
+                continue;
+            }
+            self.write_space();
+            self.write(&trivia.span.literal);
+            let followed_by_line_break = trivia.kind == TriviaKind::LineComment
+                || token.leading_trivia.get(index + 1).is_some_and(|next| {
+                    next.kind == TriviaKind::Whitespace && next.span.literal.contains('\n')
+                });
+            if followed_by_line_break {
+                self.newline();
+            }
+        }
+    }
+
+    fn write_trailing_comments(&mut self, token: &Token) {
+        for trivia in token.trailing_trivia.iter() {
+            if trivia.kind == TriviaKind::Whitespace {
+                continue;
+            }
+            self.write_space();
+            self.write(&trivia.span.literal);
+            if trivia.kind == TriviaKind::LineComment {
+                self.newline();
+            }
+        }
+    }
+
+    fn write_token(&mut self, token: &Token, text: &str) {
+        self.write_leading_comments(token);
+        self.write(text);
+        self.statement_started = true;
+        self.write_trailing_comments(token);
+    }
+
+    /// Writes only the comments of a token that is left out of the output,
+    /// such as a redundant parenthesis.
+    fn write_comments(&mut self, token: &Token) {
+        self.write_leading_comments(token);
+        self.write_trailing_comments(token);
+    }
+
+    fn enter_scope(&mut self) {
+        self.indent += INDENT_WIDTH;
+    }
+
+    fn exit_scope(&mut self) {
+        if self.indent >= INDENT_WIDTH {
+            self.indent -= INDENT_WIDTH;
+        }
+    }
+
+    /// Visits an operand of a binary expression, wrapping it in parentheses
+    /// when printing it bare would change how it parses.
+    fn visit_operand(&mut self, ast: &Ast, expr_id: ExprId, parent_precedence: u8, is_right_operand: bool) {
+        let needs_parentheses = match &ast.query_expr(Self::strip_parentheses(ast, expr_id)).kind {
+            ExpressionKind::Binary(bin_expr) => {
+                let precedence = bin_expr.operator.precedence();
+                let binds_to_other_side = match bin_expr.operator.assicativity() {
+                    BinOperatorAssiciativity::Left => is_right_operand,
+                    BinOperatorAssiciativity::Right => !is_right_operand,
+                };
+                precedence < parent_precedence || (precedence == parent_precedence && binds_to_other_side)
+            }
+            _ => false,
+        };
+        if needs_parentheses {
+            self.visit_with_innermost_parentheses(ast, expr_id);
+        } else {
+            self.visit_expression(ast, expr_id);
+        }
+    }
+
+    /// Keeps the innermost pair of a run of nested parentheses, so the
+    /// comments attached to it stay in place, and drops the others.
+    fn visit_with_innermost_parentheses(&mut self, ast: &Ast, expr_id: ExprId) {
+        match &ast.query_expr(expr_id).kind {
+            ExpressionKind::Parenthesized(parenthesized_expr) => {
+                if Self::strip_parentheses(ast, parenthesized_expr.expr) == parenthesized_expr.expr {
+                    self.write_token(&parenthesized_expr.open_paren, "(");
+                    self.visit_expression(ast, parenthesized_expr.expr);
+                    self.write_token(&parenthesized_expr.close_paren, ")");
+                } else {
+                    self.write_comments(&parenthesized_expr.open_paren);
+                    self.visit_with_innermost_parentheses(ast, parenthesized_expr.expr);
+                    self.write_comments(&parenthesized_expr.close_paren);
+                }
+            }
+            _ => {
+                self.write("(");
+                self.visit_expression(ast, expr_id);
+                self.write(")");
+            }
+        }
+    }
+
+    fn write_qualifier(&mut self, qualifier: &Option<Qualifier>) {
+        if let Some(qualifier) = qualifier {
+            self.write_token(&qualifier.module, qualifier.name());
+            self.write_token(&qualifier.colons, "::");
+        }
+    }
+
+    fn strip_parentheses(ast: &Ast, mut expr_id: ExprId) -> ExprId {
+        while let ExpressionKind::Parenthesized(parenthesized_expr) = &ast.query_expr(expr_id).kind {
+            expr_id = parenthesized_expr.expr;
+        }
+        expr_id
+    }
+}
+
+impl Visitor for SourceFormatter {
+    fn visit_item(&mut self, ast: &Ast, item_id: super::ItemId) {
+        let is_function = matches!(ast.query_item(item_id).kind, ItemKind::Function(_));
+        if let Some(previous_item_is_function) = self.previous_item_is_function {
+            if is_function || previous_item_is_function {
+                self.output.push('\n');
+            }
+        }
+        self.do_visit_item(ast, item_id);
+        self.previous_item_is_function = Some(is_function);
+    }
+
+    fn visit_import(&mut self, ast: &Ast, import_id: super::ImportId) {
+        let import = ast.query_import(import_id);
+        match &import.kind {
+            ImportKind::File { path } => {
+                self.write_token(&import.keyword, "import");
+                self.write_space();
+                self.write_token(path, &path.span.literal);
+            }
+            ImportKind::Name { module, colons, name } => {
+                self.write_token(&import.keyword, "use");
+                self.write_space();
+                self.write_token(module, &module.span.literal);
+                self.write_token(colons, "::");
+                self.write_token(name, &name.span.literal);
+            }
+        }
+        self.end_statement();
+    }
+
+    fn visit_function(&mut self, ast: &Ast, function_id: super::FunctionId) {
+        let function = ast.query_function(function_id);
+        self.write_token(&function.function_keyword, "function");
+        self.write_space();
+        self.write_token(&function.identifier, &function.name);
+        self.write_token(&function.open_paren, "(");
+        for (index, parameter) in function.parameters.iter().enumerate() {
+            // The comma goes before the comments the parser moved onto the
+            // parameter from the comma.
+            if index > 0 {
+                self.write_space();
+            }
+            self.write_leading_comments(&parameter.identifier);
+            self.write(&parameter.identifier.span.literal);
+            let mut last_token = &parameter.identifier;
+            if let Some(type_annotation) = &parameter.type_annotation {
+                self.write_trailing_comments(&parameter.identifier);
+                self.write_token(&type_annotation.separator, ":");
+                self.write_space();
+                self.write_leading_comments(&type_annotation.name);
+                self.write(type_annotation.type_name());
+                last_token = &type_annotation.name;
+            }
+            if index + 1 < function.parameters.len() {
+                self.write(",");
+            }
+            self.write_trailing_comments(last_token);
+        }
+        self.write_token(&function.close_paren, ")");
+        self.write_space();
+        if let Some(return_type) = &function.return_type {
+            self.write_token(&return_type.separator, "->");
+            self.write_space();
+            self.write_token(&return_type.name, return_type.type_name());
+            self.write_space();
+        }
+        let body = &function.body;
+        let close_brace_has_comments = body.close_brace.comments().next().is_some();
+        if body.statements.is_empty() && !close_brace_has_comments {
+            self.write_token(&body.open_brace, "{");
+            self.write_token(&body.close_brace, "}");
+            self.end_statement();
+            return;
+        }
+        self.write_token(&body.open_brace, "{");
+        self.end_statement();
+        self.enter_scope();
+        for stmt_id in body.statements.iter() {
+            self.visit_statement(ast, *stmt_id);
+        }
+        self.write_leading_comments(&body.close_brace);
+        self.newline();
+        self.exit_scope();
+        self.write("}");
+        self.write_trailing_comments(&body.close_brace);
+        self.end_statement();
+    }
+
+    fn visit_statement(&mut self, ast: &Ast, stmt_id: super::StmtId) {
+        self.do_visit_statement(ast, stmt_id);
+        self.end_statement();
+    }
+
+    fn visit_let_statement(&mut self, ast: &Ast, stmt: &super::LetStatement) {
+        self.write_token(&stmt.let_keyword, "let");
+        self.write_space();
+        self.write_token(&stmt.identifier, &stmt.identifier.span.literal);
+        if let Some(type_annotation) = &stmt.type_annotation {
+            self.write_token(&type_annotation.separator, ":");
+            self.write_space();
+            self.write_token(&type_annotation.name, type_annotation.type_name());
+        }
+        self.write_space();
+        self.write_token(&stmt.equals, "=");
+        self.write_space();
+        self.visit_expression(ast, stmt.initializer);
+    }
+
+    fn visit_return_statement(&mut self, ast: &Ast, stmt: &super::ReturnStatement) {
+        self.write_token(&stmt.return_keyword, "return");
+        self.write_space();
+        self.visit_expression(ast, stmt.value);
+    }
+
+    fn visit_number(&mut self, _ast: &Ast, number: &super::NumberExpr) {
+        self.write_token(&number.token, &number.token.span.literal);
+    }
+
+    fn visit_float(&mut self, _ast: &Ast, number: &super::FloatExpr) {
+        self.write_token(&number.token, &number.token.span.literal);
+    }
+
+    fn visit_string(&mut self, _ast: &Ast, string: &super::StringExpr) {
+        self.write_token(&string.token, &string.token.span.literal);
+    }
+
+    fn visit_variable_expression(&mut self, _ast: &Ast, variable_expr: &super::VariableExpr) {
+        self.write_qualifier(&variable_expr.qualifier);
+        self.write_token(&variable_expr.identifier, variable_expr.name());
+    }
+
+    fn visit_call_expression(&mut self, ast: &Ast, call_expr: &super::CallExpr) {
+        self.write_qualifier(&call_expr.qualifier);
+        self.write_token(&call_expr.callee, call_expr.name());
+        self.write_token(&call_expr.open_paren, "(");
+        for (index, argument) in call_expr.arguments.iter().enumerate() {
+            if index > 0 {
+                self.write_space();
+            }
+            self.visit_expression(ast, *argument);
+            match call_expr.commas.get(index) {
+                Some(comma) if index + 1 < call_expr.arguments.len() => self.write_token(comma, ","),
+                // A trailing comma is dropped but its comments are kept.
+                Some(comma) => self.write_comments(comma),
+                None => {}
+            }
+        }
+        self.write_token(&call_expr.close_paren, ")");
+    }
+
+    fn visit_binary_expression(&mut self, ast: &Ast, bin_expr: &super::BinaryExpr) {
+        let precedence = bin_expr.operator.precedence();
+        self.visit_operand(ast, bin_expr.left, precedence, false);
+        self.write_space();
+        self.write_token(&bin_expr.operator.token, &bin_expr.operator.kind.to_string());
+        self.write_space();
+        self.visit_operand(ast, bin_expr.right, precedence, true);
+    }
+
+    fn visit_parenthesized_expression(&mut self, ast: &Ast, parenthesized_expr: &super::ParenthesizedExpr) {
+        self.write_comments(&parenthesized_expr.open_paren);
+        self.visit_expression(ast, parenthesized_expr.expr);
+        self.write_comments(&parenthesized_expr.close_paren);
+    }
+}
@@ -1,55 +1,49 @@
 use std::collections::HashMap;
 
+use super::VariableId;
+
+/// Resolves variable names while parsing. `variables` holds the global
+/// `let`s; a function body pushes a local scope on top of it, which sees the
+/// globals declared before the function. Declaring a name that is already
+/// visible shadows it from then on.
 #[derive(Debug,Clone,PartialEq, Eq)]
 pub struct GlobalScope{
- pub variables: HashMap<String,String>
+ pub variables: HashMap<String,VariableId>,
+ local_scopes: Vec<HashMap<String,VariableId>>
 
 }
 
-#[derive(Debug,Clone,PartialEq, Eq)]
-pub struct Variable{
-    pub name :String, 
-    pub value: String
-}
-impl Variable{
-    pub fn new(name:String,value:String)->Self{
-        Self{name,value}
+impl Default for GlobalScope{
+    fn default()->Self{
+        Self::new()
     }
 }
+
 impl GlobalScope{
     pub fn new()->Self{
-        Self { variables: HashMap::new()}
+        Self { variables: HashMap::new(), local_scopes: Vec::new()}
 
     }
-    pub fn add_global_variable(&mut self,variable_name:String,variable_value:String)->String{
-         let existing_variable = self.variables.get(&variable_name);
-         if existing_variable.is_some() {
-              return String::from("Variable already exist use another variable name");
-         }else{
-
-             self.variables.insert(variable_name, variable_value);
-              return String::from("Variable saved");
-
-         }
-
+    pub fn enter_scope(&mut self){
+        self.local_scopes.push(HashMap::new());
     }
-    pub fn get_global_variable(&mut self,variable_name:String)->Option<Variable>{
-         let existing_variable = self.variables.get(&variable_name);
-         let variable =  self.variables.get_key_value(&variable_name);
-         if existing_variable.is_some() {
-             return Some(Variable::new(variable.unwrap().0.clone(), variable.unwrap().1.clone()));
-         }else{
-
-        return  None;
-
-         }
+    pub fn exit_scope(&mut self){
+        self.local_scopes.pop();
     }
-    pub fn get_all_global_variables(&self)->Option<HashMap<String,String>>{
-        if self.variables.is_empty(){
-            return None;
-        }else{
-            return Some(self.variables.clone());
-            
-        }
+    pub fn is_global(&self)->bool{
+        self.local_scopes.is_empty()
     }
-}
\ No newline at end of file
+    pub fn declare_variable(&mut self,variable_name:String,variable_id:VariableId){
+        match self.local_scopes.last_mut(){
+            Some(scope)=>scope.insert(variable_name, variable_id),
+            None=>self.variables.insert(variable_name, variable_id),
+        };
+    }
+    pub fn lookup_variable(&self,variable_name:&str)->Option<VariableId>{
+        self.local_scopes
+            .iter()
+            .rev()
+            .chain(std::iter::once(&self.variables))
+            .find_map(|scope| scope.get(variable_name).copied())
+    }
+}
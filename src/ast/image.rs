@@ -0,0 +1,533 @@
+//! Saves a checked `Program` as JSON and loads it back, so a compiled
+//! program can be kept in the cache and run later without parsing and
+//! checking it again. An image holds every node with the spans of its
+//! tokens, not their text: loading takes the text from the same sources the
+//! program was compiled from. Trivia and the syntax tree are left out, as
+//! running a program does not use them.
+
+use crate::json::Json;
+
+use super::{
+    lexer::{Token, TokenKind},
+    lib::Id,
+    modules::{Program, SourceSnapshot},
+    text::{FileId, SourceMap, TextSpan},
+    AssignExpr, Ast, BinOperator, BinOperatorKind, BinaryExpr, Body, CallExpr, ExprId, Expression, ExpressionKind, FloatExpr, Function,
+    FunctionId, Import, ImportKind, Item, ItemId, ItemKind, LetStatement, Module, ModuleImport, NumberExpr, Parameter,
+    ParenthesizedExpr, Qualifier, ReturnStatement, Statement, StatementKind, StmtId, StringExpr, TypeAnnotation, Variable,
+    VariableExpr, VariableId, VariableKind,
+};
+
+/// Saves `program`: the names of its files in the order of their ids, the
+/// main file and the nodes of every arena.
+pub fn save(program: &Program) -> Json {
+    let ast = &program.ast;
+    let files = program.sources.files.iter().map(|file| Json::string(&file.name)).collect();
+    Json::object(vec![
+        ("files", Json::Array(files)),
+        ("main", id(program.main)),
+        ("items", Json::Array(ast.items.iter().map(save_item).collect())),
+        ("top_level", Json::Array(ast.top_level.iter().map(|item_id| id(*item_id)).collect())),
+        ("statements", Json::Array(ast.statements.iter().map(save_statement).collect())),
+        ("expressions", Json::Array(ast.expressions.iter().map(save_expression).collect())),
+        ("functions", Json::Array(ast.functions.iter().map(save_function).collect())),
+        ("variables", Json::Array(ast.variables.iter().map(save_variable).collect())),
+        ("imports", Json::Array(ast.imports.iter().map(save_import).collect())),
+        ("modules", Json::Array(ast.modules.iter().map(save_module).collect())),
+        ("eof", ast.eof.as_ref().map_or(Json::Null, token)),
+    ])
+}
+
+/// Loads an image saved by `save`, taking the text of its files from
+/// `snapshot`. Returns `None` if the image is malformed, names a file the
+/// snapshot does not have, or has a node that refers to one that is not
+/// there.
+pub fn load(image: &Json, snapshot: &SourceSnapshot) -> Option<Program> {
+    let mut sources = SourceMap::new();
+    for name in image.get("files")?.as_array()? {
+        let name = name.as_str()?;
+        let (_, text) = snapshot.files.iter().find(|(path, _)| path == name)?;
+        sources.add_file(name.to_string(), text.clone().ok()?);
+    }
+    let arena = |name| image.get(name).and_then(Json::as_array);
+    let loader = Loader {
+        sources: &sources,
+        items: arena("items")?.len(),
+        statements: arena("statements")?.len(),
+        expressions: arena("expressions")?.len(),
+        functions: arena("functions")?.len(),
+        variables: arena("variables")?.len(),
+        imports: arena("imports")?.len(),
+    };
+    let mut ast = Ast::new();
+    for (index, item) in arena("items")?.iter().enumerate() {
+        ast.items.push(loader.item(item, ItemId::new(index))?);
+    }
+    for item_id in arena("top_level")? {
+        ast.top_level.push(loader.id(item_id, loader.items)?);
+    }
+    for (index, statement) in arena("statements")?.iter().enumerate() {
+        ast.statements.push(loader.statement(statement, StmtId::new(index))?);
+    }
+    for (index, expression) in arena("expressions")?.iter().enumerate() {
+        ast.expressions.push(loader.expression(expression, ExprId::new(index))?);
+    }
+    for function in arena("functions")? {
+        ast.functions.push(loader.function(function)?);
+    }
+    for (index, variable) in arena("variables")?.iter().enumerate() {
+        ast.variables.push(loader.variable(variable, VariableId::new(index))?);
+    }
+    for import in arena("imports")? {
+        ast.imports.push(loader.import(import)?);
+    }
+    for module in arena("modules")? {
+        ast.modules.push(loader.module(module)?);
+    }
+    ast.eof = match image.get("eof")? {
+        Json::Null => None,
+        eof => Some(loader.token(eof, TokenKind::Eof)?),
+    };
+    let main = loader.file_id(image.get("main")?)?;
+    Some(Program { sources, ast, main })
+}
+
+fn id(id: impl Id) -> Json {
+    Json::Number(id.to_usize() as i64)
+}
+
+fn optional<T>(value: &Option<T>, save: impl Fn(&T) -> Json) -> Json {
+    value.as_ref().map_or(Json::Null, save)
+}
+
+/// A token as `[file, start, end]`. Its kind follows from where it is.
+fn token(token: &Token) -> Json {
+    let span = &token.span;
+    Json::Array(vec![id(span.file_id), Json::Number(span.start as i64), Json::Number(span.end as i64)])
+}
+
+fn tokens(tokens: &[Token]) -> Json {
+    Json::Array(tokens.iter().map(token).collect())
+}
+
+fn ids<T: Id>(ids: &[T]) -> Json {
+    Json::Array(ids.iter().map(|item_id| id(*item_id)).collect())
+}
+
+fn type_annotation(type_annotation: &TypeAnnotation) -> Json {
+    Json::object(vec![("separator", token(&type_annotation.separator)), ("name", token(&type_annotation.name))])
+}
+
+fn qualifier(qualifier: &Qualifier) -> Json {
+    Json::object(vec![("module", token(&qualifier.module)), ("colons", token(&qualifier.colons))])
+}
+
+fn save_item(item: &Item) -> Json {
+    let (kind, node) = match item.kind {
+        ItemKind::Statement(stmt_id) => ("statement", id(stmt_id)),
+        ItemKind::Function(function_id) => ("function", id(function_id)),
+        ItemKind::Import(import_id) => ("import", id(import_id)),
+    };
+    Json::object(vec![("kind", Json::string(kind)), ("id", node)])
+}
+
+fn save_statement(statement: &Statement) -> Json {
+    match &statement.stmt_kind {
+        StatementKind::Let(let_stmt) => Json::object(vec![
+            ("kind", Json::string("let")),
+            ("let", token(&let_stmt.let_keyword)),
+            ("identifier", token(&let_stmt.identifier)),
+            ("type", optional(&let_stmt.type_annotation, type_annotation)),
+            ("equals", token(&let_stmt.equals)),
+            ("initializer", id(let_stmt.initializer)),
+            ("variable", id(let_stmt.variable_id)),
+        ]),
+        StatementKind::Return(return_stmt) => Json::object(vec![
+            ("kind", Json::string("return")),
+            ("return", token(&return_stmt.return_keyword)),
+            ("value", id(return_stmt.value)),
+        ]),
+        StatementKind::Expression(expr_id) => Json::object(vec![("kind", Json::string("expression")), ("expression", id(*expr_id))]),
+    }
+}
+
+fn save_expression(expression: &Expression) -> Json {
+    match &expression.kind {
+        ExpressionKind::Number(number) => Json::object(vec![
+            ("kind", Json::string("number")),
+            ("token", token(&number.token)),
+            ("value", Json::Number(number.number)),
+        ]),
+        // The bits of the float, which JSON numbers could round.
+        ExpressionKind::Float(number) => Json::object(vec![
+            ("kind", Json::string("float")),
+            ("token", token(&number.token)),
+            ("bits", Json::Number(number.number.to_bits() as i64)),
+        ]),
+        ExpressionKind::String(string) => Json::object(vec![
+            ("kind", Json::string("string")),
+            ("token", token(&string.token)),
+            ("value", Json::string(&string.value)),
+        ]),
+        ExpressionKind::Variable(variable_expr) => Json::object(vec![
+            ("kind", Json::string("variable")),
+            ("qualifier", optional(&variable_expr.qualifier, qualifier)),
+            ("identifier", token(&variable_expr.identifier)),
+            ("variable", optional(&variable_expr.variable_id, |variable_id| id(*variable_id))),
+        ]),
+        ExpressionKind::Call(call_expr) => Json::object(vec![
+            ("kind", Json::string("call")),
+            ("qualifier", optional(&call_expr.qualifier, qualifier)),
+            ("callee", token(&call_expr.callee)),
+            ("open_paren", token(&call_expr.open_paren)),
+            ("arguments", ids(&call_expr.arguments)),
+            ("commas", tokens(&call_expr.commas)),
+            ("close_paren", token(&call_expr.close_paren)),
+        ]),
+        ExpressionKind::Binary(bin_expr) => Json::object(vec![
+            ("kind", Json::string("binary")),
+            ("left", id(bin_expr.left)),
+            ("operator", Json::String(bin_expr.operator.kind.to_string())),
+            ("token", token(&bin_expr.operator.token)),
+            ("right", id(bin_expr.right)),
+        ]),
+        ExpressionKind::Parenthesized(parenthesized_expr) => Json::object(vec![
+            ("kind", Json::string("parenthesized")),
+            ("open_paren", token(&parenthesized_expr.open_paren)),
+            ("expression", id(parenthesized_expr.expr)),
+            ("close_paren", token(&parenthesized_expr.close_paren)),
+        ]),
+        ExpressionKind::Assignment(assign_expr) => Json::object(vec![
+            ("kind", Json::string("assignment")),
+            ("let", token(&assign_expr.let_keyword)),
+            ("equals", token(&assign_expr.equals)),
+            ("expression", id(assign_expr.expr)),
+        ]),
+        ExpressionKind::Error(error) => Json::object(vec![("kind", Json::string("error")), ("token", token(error))]),
+    }
+}
+
+fn save_function(function: &Function) -> Json {
+    let parameters = function
+        .parameters
+        .iter()
+        .map(|parameter| {
+            Json::object(vec![
+                ("identifier", token(&parameter.identifier)),
+                ("type", optional(&parameter.type_annotation, type_annotation)),
+                ("variable", id(parameter.variable_id)),
+            ])
+        })
+        .collect();
+    Json::object(vec![
+        ("function", token(&function.function_keyword)),
+        ("identifier", token(&function.identifier)),
+        ("open_paren", token(&function.open_paren)),
+        ("parameters", Json::Array(parameters)),
+        ("close_paren", token(&function.close_paren)),
+        ("return_type", optional(&function.return_type, type_annotation)),
+        ("open_brace", token(&function.body.open_brace)),
+        ("statements", ids(&function.body.statements)),
+        ("close_brace", token(&function.body.close_brace)),
+    ])
+}
+
+fn save_variable(variable: &Variable) -> Json {
+    let kind = match variable.kind {
+        VariableKind::Global => "global",
+        VariableKind::Local => "local",
+        VariableKind::Parameter => "parameter",
+    };
+    Json::object(vec![("identifier", token(&variable.identifier)), ("kind", Json::string(kind))])
+}
+
+fn save_import(import: &Import) -> Json {
+    let mut fields = vec![("keyword", token(&import.keyword))];
+    match &import.kind {
+        ImportKind::File { path } => fields.push(("path", token(path))),
+        ImportKind::Name { module, colons, name } => {
+            fields.extend([("module", token(module)), ("colons", token(colons)), ("name", token(name))]);
+        }
+    }
+    fields.push(("file", optional(&import.module, |file_id| id(*file_id))));
+    Json::object(fields)
+}
+
+fn save_module(module: &Module) -> Json {
+    let imports = module
+        .imports
+        .iter()
+        .map(|module_import| {
+            Json::object(vec![
+                ("path", Json::string(&module_import.path)),
+                ("name", Json::string(&module_import.name)),
+                ("file", optional(&module_import.file_id, |file_id| id(*file_id))),
+            ])
+        })
+        .collect();
+    let used_functions = module
+        .used_functions
+        .iter()
+        .map(|(name, function_id)| Json::Array(vec![Json::string(name), id(*function_id)]))
+        .collect();
+    Json::object(vec![
+        ("name", Json::string(&module.name)),
+        ("file", id(module.file_id)),
+        ("imports", Json::Array(imports)),
+        ("used_functions", Json::Array(used_functions)),
+    ])
+}
+
+/// Reads the nodes of an image back, checking that every id it meets is
+/// within its arena.
+struct Loader<'a> {
+    sources: &'a SourceMap,
+    items: usize,
+    statements: usize,
+    expressions: usize,
+    functions: usize,
+    variables: usize,
+    imports: usize,
+}
+
+impl Loader<'_> {
+    fn id<T: Id>(&self, json: &Json, len: usize) -> Option<T> {
+        let index = usize::try_from(json.as_i64()?).ok()?;
+        (index < len).then(|| T::new(index))
+    }
+
+    fn optional_id<T: Id>(&self, json: &Json, len: usize) -> Option<Option<T>> {
+        match json {
+            Json::Null => Some(None),
+            json => Some(Some(self.id(json, len)?)),
+        }
+    }
+
+    fn ids<T: Id>(&self, json: &Json, len: usize) -> Option<Vec<T>> {
+        json.as_array()?.iter().map(|id| self.id(id, len)).collect()
+    }
+
+    fn file_id(&self, json: &Json) -> Option<FileId> {
+        self.id(json, self.sources.files.data.len())
+    }
+
+    fn optional_file_id(&self, json: &Json) -> Option<Option<FileId>> {
+        self.optional_id(json, self.sources.files.data.len())
+    }
+
+    /// A token of `kind`, with its text taken from the source.
+    fn token(&self, json: &Json, kind: TokenKind) -> Option<Token> {
+        let [file_id, start, end] = json.as_array()? else {
+            return None;
+        };
+        let file_id = self.file_id(file_id)?;
+        let start = usize::try_from(start.as_i64()?).ok()?;
+        let end = usize::try_from(end.as_i64()?).ok()?;
+        let literal = self.sources.get(file_id).text.get(start..end)?.to_string();
+        Some(Token::new(kind, TextSpan::new(file_id, start, end, literal)))
+    }
+
+    fn field_token(&self, json: &Json, name: &str, kind: TokenKind) -> Option<Token> {
+        self.token(json.get(name)?, kind)
+    }
+
+    fn tokens(&self, json: &Json, kind: TokenKind) -> Option<Vec<Token>> {
+        json.as_array()?.iter().map(|token| self.token(token, kind)).collect()
+    }
+
+    fn type_annotation(&self, json: &Json, separator: TokenKind) -> Option<Option<TypeAnnotation>> {
+        match json {
+            Json::Null => Some(None),
+            json => Some(Some(TypeAnnotation::new(
+                self.field_token(json, "separator", separator)?,
+                self.field_token(json, "name", TokenKind::Identifier)?,
+            ))),
+        }
+    }
+
+    fn qualifier(&self, json: &Json) -> Option<Option<Qualifier>> {
+        match json {
+            Json::Null => Some(None),
+            json => Some(Some(Qualifier {
+                module: self.field_token(json, "module", TokenKind::Identifier)?,
+                colons: self.field_token(json, "colons", TokenKind::ColonColon)?,
+            })),
+        }
+    }
+
+    fn item(&self, json: &Json, item_id: ItemId) -> Option<Item> {
+        let node = json.get("id")?;
+        let kind = match json.get("kind")?.as_str()? {
+            "statement" => ItemKind::Statement(self.id(node, self.statements)?),
+            "function" => ItemKind::Function(self.id(node, self.functions)?),
+            "import" => ItemKind::Import(self.id(node, self.imports)?),
+            _ => return None,
+        };
+        Some(Item::new(kind, item_id))
+    }
+
+    fn statement(&self, json: &Json, stmt_id: StmtId) -> Option<Statement> {
+        let kind = match json.get("kind")?.as_str()? {
+            "let" => StatementKind::Let(LetStatement {
+                let_keyword: self.field_token(json, "let", TokenKind::Let)?,
+                identifier: self.field_token(json, "identifier", TokenKind::Identifier)?,
+                type_annotation: self.type_annotation(json.get("type")?, TokenKind::Colon)?,
+                equals: self.field_token(json, "equals", TokenKind::Equals)?,
+                initializer: self.id(json.get("initializer")?, self.expressions)?,
+                variable_id: self.id(json.get("variable")?, self.variables)?,
+            }),
+            "return" => StatementKind::Return(ReturnStatement {
+                return_keyword: self.field_token(json, "return", TokenKind::Return)?,
+                value: self.id(json.get("value")?, self.expressions)?,
+            }),
+            "expression" => StatementKind::Expression(self.id(json.get("expression")?, self.expressions)?),
+            _ => return None,
+        };
+        Some(Statement::new(kind, stmt_id))
+    }
+
+    fn expression(&self, json: &Json, expr_id: ExprId) -> Option<Expression> {
+        let kind = match json.get("kind")?.as_str()? {
+            "number" => {
+                let number = json.get("value")?.as_i64()?;
+                ExpressionKind::Number(NumberExpr { number, token: self.field_token(json, "token", TokenKind::Number(number))? })
+            }
+            "float" => ExpressionKind::Float(FloatExpr {
+                number: f64::from_bits(json.get("bits")?.as_i64()? as u64),
+                token: self.field_token(json, "token", TokenKind::Float)?,
+            }),
+            "string" => ExpressionKind::String(StringExpr {
+                value: json.get("value")?.as_str()?.to_string(),
+                token: self.field_token(json, "token", TokenKind::String)?,
+            }),
+            "variable" => ExpressionKind::Variable(VariableExpr {
+                qualifier: self.qualifier(json.get("qualifier")?)?,
+                identifier: self.field_token(json, "identifier", TokenKind::Identifier)?,
+                variable_id: self.optional_id(json.get("variable")?, self.variables)?,
+            }),
+            "call" => ExpressionKind::Call(CallExpr {
+                qualifier: self.qualifier(json.get("qualifier")?)?,
+                callee: self.field_token(json, "callee", TokenKind::Identifier)?,
+                open_paren: self.field_token(json, "open_paren", TokenKind::OpenParen)?,
+                arguments: self.ids(json.get("arguments")?, self.expressions)?,
+                commas: self.tokens(json.get("commas")?, TokenKind::Comma)?,
+                close_paren: self.field_token(json, "close_paren", TokenKind::CloseParen)?,
+            }),
+            "binary" => {
+                let (kind, token_kind) = match json.get("operator")?.as_str()? {
+                    "+" => (BinOperatorKind::Plus, TokenKind::Plus),
+                    "-" => (BinOperatorKind::Minus, TokenKind::Minus),
+                    "*" => (BinOperatorKind::Multiply, TokenKind::Asterisk),
+                    "/" => (BinOperatorKind::Divide, TokenKind::Slash),
+                    _ => return None,
+                };
+                let operator = BinOperator::new(kind, self.field_token(json, "token", token_kind)?);
+                ExpressionKind::Binary(BinaryExpr::new(
+                    self.id(json.get("left")?, self.expressions)?,
+                    operator,
+                    self.id(json.get("right")?, self.expressions)?,
+                ))
+            }
+            "parenthesized" => ExpressionKind::Parenthesized(ParenthesizedExpr::new(
+                self.field_token(json, "open_paren", TokenKind::OpenParen)?,
+                self.id(json.get("expression")?, self.expressions)?,
+                self.field_token(json, "close_paren", TokenKind::CloseParen)?,
+            )),
+            "assignment" => ExpressionKind::Assignment(AssignExpr::new(
+                self.field_token(json, "let", TokenKind::Let)?,
+                self.id(json.get("expression")?, self.expressions)?,
+                self.field_token(json, "equals", TokenKind::Equals)?,
+            )),
+            "error" => ExpressionKind::Error(self.field_token(json, "token", TokenKind::Bad)?),
+            _ => return None,
+        };
+        Some(Expression::new(kind, expr_id))
+    }
+
+    fn function(&self, json: &Json) -> Option<Function> {
+        let parameters = json
+            .get("parameters")?
+            .as_array()?
+            .iter()
+            .map(|parameter| {
+                Some(Parameter {
+                    identifier: self.field_token(parameter, "identifier", TokenKind::Identifier)?,
+                    type_annotation: self.type_annotation(parameter.get("type")?, TokenKind::Colon)?,
+                    variable_id: self.id(parameter.get("variable")?, self.variables)?,
+                })
+            })
+            .collect::<Option<Vec<Parameter>>>()?;
+        let body = Body::new(
+            self.field_token(json, "open_brace", TokenKind::OpenBrace)?,
+            self.ids(json.get("statements")?, self.statements)?,
+            self.field_token(json, "close_brace", TokenKind::CloseBrace)?,
+        );
+        Some(Function::new(
+            self.field_token(json, "function", TokenKind::Function)?,
+            self.field_token(json, "identifier", TokenKind::Identifier)?,
+            self.field_token(json, "open_paren", TokenKind::OpenParen)?,
+            self.field_token(json, "close_paren", TokenKind::CloseParen)?,
+            parameters,
+            self.type_annotation(json.get("return_type")?, TokenKind::Arrow)?,
+            body,
+        ))
+    }
+
+    fn variable(&self, json: &Json, variable_id: VariableId) -> Option<Variable> {
+        let identifier = self.field_token(json, "identifier", TokenKind::Identifier)?;
+        let kind = match json.get("kind")?.as_str()? {
+            "global" => VariableKind::Global,
+            "local" => VariableKind::Local,
+            "parameter" => VariableKind::Parameter,
+            _ => return None,
+        };
+        Some(Variable { id: variable_id, name: identifier.span.literal.clone(), identifier, kind })
+    }
+
+    fn import(&self, json: &Json) -> Option<Import> {
+        let (keyword, kind) = match json.get("path") {
+            Some(path) => (TokenKind::Import, ImportKind::File { path: self.token(path, TokenKind::String)? }),
+            None => (
+                TokenKind::Use,
+                ImportKind::Name {
+                    module: self.field_token(json, "module", TokenKind::Identifier)?,
+                    colons: self.field_token(json, "colons", TokenKind::ColonColon)?,
+                    name: self.field_token(json, "name", TokenKind::Identifier)?,
+                },
+            ),
+        };
+        Some(Import { keyword: self.field_token(json, "keyword", keyword)?, kind, module: self.optional_file_id(json.get("file")?)? })
+    }
+
+    fn module(&self, json: &Json) -> Option<Module> {
+        let imports = json
+            .get("imports")?
+            .as_array()?
+            .iter()
+            .map(|module_import| {
+                Some(ModuleImport {
+                    path: module_import.get("path")?.as_str()?.to_string(),
+                    name: module_import.get("name")?.as_str()?.to_string(),
+                    file_id: self.optional_file_id(module_import.get("file")?)?,
+                })
+            })
+            .collect::<Option<Vec<ModuleImport>>>()?;
+        let used_functions = json
+            .get("used_functions")?
+            .as_array()?
+            .iter()
+            .map(|used| {
+                let [name, function_id] = used.as_array()? else {
+                    return None;
+                };
+                Some((name.as_str()?.to_string(), self.id(function_id, self.functions)?))
+            })
+            .collect::<Option<Vec<(String, FunctionId)>>>()?;
+        Some(Module {
+            name: json.get("name")?.as_str()?.to_string(),
+            file_id: self.file_id(json.get("file")?)?,
+            imports,
+            used_functions,
+        })
+    }
+}
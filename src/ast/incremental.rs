@@ -0,0 +1,606 @@
+use std::mem;
+
+use crate::diagnostics::{Diagnostic, DiagnosticsBag};
+
+use super::{
+    global_scope::GlobalScope,
+    lib::{Id, IdVec},
+    lexer::{Lexer, Token},
+    parser::Parser,
+    syntax::{GreenBuilder, GreenElement, SyntaxKind},
+    text::SourceFile,
+    visitor::Visitor,
+    Ast, ExprId, ExpressionKind, FunctionId, ImportId, ImportKind, ItemId, ItemKind, StatementKind, StmtId, TypeAnnotation, VariableId,
+};
+
+/// How many bytes past its end the lexer may read to make out a token, as
+/// when it tells the `1` of `1.5` from the `1` of `1.x`.
+const LEXER_LOOKAHEAD: usize = 2;
+
+/// How many nodes of replaced items the arenas hold at least before
+/// `IncrementalParser` compacts them.
+pub const MIN_GARBAGE: usize = 1024;
+
+/// Replaces the bytes `start..end` of a file by `replacement`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub start: usize,
+    pub end: usize,
+    pub replacement: String,
+}
+
+impl TextEdit {
+    pub fn new(start: usize, end: usize, replacement: String) -> Self {
+        Self { start, end, replacement }
+    }
+
+    /// How much longer the text gets.
+    fn delta(&self) -> isize {
+        self.replacement.len() as isize - (self.end - self.start) as isize
+    }
+
+    /// Where the byte at `offset` of the old text ends up. Offsets inside
+    /// the replaced range are left alone.
+    fn shift(&self, offset: usize) -> usize {
+        if offset >= self.end {
+            offset.wrapping_add_signed(self.delta())
+        } else {
+            offset
+        }
+    }
+}
+
+/// The items that an edit replaced and the ones parsed in their place. An
+/// added item takes over the `ItemId` of a removed one where it can, so the
+/// two lists may share ids.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reparse {
+    pub removed: Vec<ItemId>,
+    pub added: Vec<ItemId>,
+}
+
+/// A top-level item and the text it was parsed from: `start..end` runs from
+/// the leading trivia of its first token up to the start of the next item,
+/// so the items of a file tile its text.
+#[derive(Debug, Clone)]
+struct ParsedItem {
+    item_id: ItemId,
+    start: usize,
+    end: usize,
+    /// The syntax errors reported while the item was parsed.
+    diagnostics: Vec<Diagnostic>,
+    /// Whether the parser reported an error at the token after the item,
+    /// the only error position it still compares against.
+    error_at_end: bool,
+    /// The syntax of the item, with the trivia up to the next item.
+    syntax: Vec<GreenElement>,
+}
+
+/// Keeps the `Ast` of a file up to date as it is edited, reparsing only the
+/// items around each edit.
+///
+/// An edit is reparsed from the item before the first one with a token it
+/// can change, since that item may end differently once the token after it
+/// changes. Tokens are lexed as the parser asks for them, and parsing stops
+/// at the first item boundary past the edit where an old item starts and
+/// the parser is in the same state as before: the same global variables
+/// and functions declared and an error reported at the token it is on or
+/// not. The items from there on are kept with their `ItemId`s; only their
+/// spans are moved. A reparse that changes which global variables or
+/// functions are declared continues to the end of the file, since every
+/// later use of a name may resolve differently.
+///
+/// The items parsed in place of removed ones take over their slots in the
+/// items arena, and with them their `ItemId`s, so the arena stays about as
+/// long as the file has items. The other nodes of replaced items stay in
+/// their arenas, out of `Ast::top_level`, until they outnumber the nodes in
+/// use and `MIN_GARBAGE`; the arenas are then compacted, which renumbers
+/// the statements, expressions, functions, variables and imports but keeps
+/// every `ItemId`. The syntax tree is put together again from the green
+/// nodes of the items, so the kept items share theirs with the old tree.
+pub struct IncrementalParser {
+    pub source: SourceFile,
+    pub ast: Ast,
+    items: Vec<ParsedItem>,
+    /// Errors reported before the first item, when lexing its first token.
+    preamble: Vec<Diagnostic>,
+    /// The slots of the items arena that hold no item in use.
+    free_items: Vec<ItemId>,
+    /// How many nodes of replaced items the arenas hold, free item slots
+    /// included.
+    garbage: usize,
+}
+
+impl IncrementalParser {
+    pub fn new(source: SourceFile) -> Self {
+        let mut parser = Self {
+            source,
+            ast: Ast::new(),
+            items: Vec::new(),
+            preamble: Vec::new(),
+            free_items: Vec::new(),
+            garbage: 0,
+        };
+        parser.reparse(0, &TextEdit::new(0, 0, String::new()));
+        parser
+    }
+
+    /// The syntax errors of the whole file, in the order a full parse
+    /// reports them.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let item_diagnostics = self.items.iter().flat_map(|item| item.diagnostics.iter());
+        self.preamble.iter().chain(item_diagnostics).cloned().collect()
+    }
+
+    /// Applies `edit` to the source and reparses the items it touches.
+    ///
+    /// Panics if the range of `edit` is not within the text or does not
+    /// fall on character boundaries.
+    pub fn edit(&mut self, edit: &TextEdit) -> Reparse {
+        let text = &self.source.text;
+        assert!(
+            edit.start <= edit.end && text.is_char_boundary(edit.start) && text.is_char_boundary(edit.end),
+            "edit {}..{} is not a range of the text",
+            edit.start,
+            edit.end
+        );
+        let mut text = text.clone();
+        text.replace_range(edit.start..edit.end, &edit.replacement);
+        self.source = SourceFile::new(self.source.id, self.source.name.clone(), text);
+        // The first item with a token the edit can change.
+        let edited = self.items.partition_point(|item| item.start + LEXER_LOOKAHEAD < edit.start + 1).saturating_sub(1);
+        let first = edited.saturating_sub(1);
+        let mut reparse = self.reparse(first, edit);
+        for item_id in reparse.removed.iter() {
+            self.garbage += ItemNodes::collect(&self.ast, *item_id).len();
+        }
+        self.reuse_item_slots(&mut reparse);
+        if self.garbage >= MIN_GARBAGE && self.garbage > arena_len(&self.ast) - self.garbage {
+            self.compact();
+        }
+        reparse
+    }
+
+    /// Moves the items `reparse` added, which the parser put at the end of
+    /// the items arena, into the free slots in order, then drops the free
+    /// slots at the end of the arena.
+    fn reuse_item_slots(&mut self, reparse: &mut Reparse) {
+        self.free_items.extend(reparse.removed.iter().copied());
+        self.free_items.sort_unstable_by(|a, b| b.cmp(a));
+        let added_from = self.ast.items.data.len() - reparse.added.len();
+        let added_items = self.ast.items.data.split_off(added_from);
+        for (added, mut item) in reparse.added.iter_mut().zip(added_items) {
+            let slot = match self.free_items.pop() {
+                Some(slot) => {
+                    self.garbage -= 1;
+                    slot
+                }
+                None => ItemId::new(self.ast.items.data.len()),
+            };
+            item.id = slot;
+            if slot.to_usize() == self.ast.items.data.len() {
+                self.ast.items.data.push(item);
+            } else {
+                *self.ast.items.get_mut(slot) = item;
+            }
+            for item_id in self.ast.top_level.iter_mut().chain(self.items.iter_mut().map(|item| &mut item.item_id)) {
+                if item_id == added {
+                    *item_id = slot;
+                }
+            }
+            *added = slot;
+        }
+        while self.free_items.first().is_some_and(|free| free.to_usize() + 1 == self.ast.items.data.len()) {
+            self.free_items.remove(0);
+            self.ast.items.data.pop();
+            self.garbage -= 1;
+        }
+    }
+
+    /// Moves the nodes of the items in use into new arenas, keeping their
+    /// order, and points every id at the new place of its node. The items
+    /// stay in their slots.
+    fn compact(&mut self) {
+        let mut live = ItemNodes::default();
+        let mut live_functions = Vec::new();
+        let mut live_imports = Vec::new();
+        for item_id in self.ast.top_level.iter() {
+            let nodes = ItemNodes::collect(&self.ast, *item_id);
+            live.statements.extend(nodes.statements);
+            live.expressions.extend(nodes.expressions);
+            live.variables.extend(nodes.variables);
+            live_functions.extend(nodes.function);
+            live_imports.extend(nodes.import);
+        }
+        let ast = &mut self.ast;
+        let statements = Compacted::new(&mut ast.statements, live.statements);
+        let expressions = Compacted::new(&mut ast.expressions, live.expressions);
+        let functions = Compacted::new(&mut ast.functions, live_functions);
+        let variables = Compacted::new(&mut ast.variables, live.variables);
+        let imports = Compacted::new(&mut ast.imports, live_imports);
+        for item_id in ast.top_level.iter() {
+            let item = ast.items.get_mut(*item_id);
+            item.kind = match item.kind {
+                ItemKind::Statement(stmt_id) => ItemKind::Statement(statements.id(stmt_id)),
+                ItemKind::Function(function_id) => ItemKind::Function(functions.id(function_id)),
+                ItemKind::Import(import_id) => ItemKind::Import(imports.id(import_id)),
+            };
+        }
+        for stmt in ast.statements.data.iter_mut() {
+            stmt.id = statements.id(stmt.id);
+            match &mut stmt.stmt_kind {
+                StatementKind::Let(let_stmt) => {
+                    let_stmt.initializer = expressions.id(let_stmt.initializer);
+                    let_stmt.variable_id = variables.id(let_stmt.variable_id);
+                }
+                StatementKind::Return(return_stmt) => return_stmt.value = expressions.id(return_stmt.value),
+                StatementKind::Expression(expr_id) => *expr_id = expressions.id(*expr_id),
+            }
+        }
+        for expr in ast.expressions.data.iter_mut() {
+            expr.id = expressions.id(expr.id);
+            match &mut expr.kind {
+                ExpressionKind::Binary(bin_expr) => {
+                    bin_expr.left = expressions.id(bin_expr.left);
+                    bin_expr.right = expressions.id(bin_expr.right);
+                }
+                ExpressionKind::Parenthesized(parenthesized_expr) => parenthesized_expr.expr = expressions.id(parenthesized_expr.expr),
+                ExpressionKind::Call(call_expr) => call_expr.arguments.iter_mut().for_each(|argument| *argument = expressions.id(*argument)),
+                ExpressionKind::Assignment(assign_expr) => assign_expr.expr = expressions.id(assign_expr.expr),
+                ExpressionKind::Variable(variable_expr) => {
+                    if let Some(variable_id) = variable_expr.variable_id.as_mut() {
+                        *variable_id = variables.id(*variable_id);
+                    }
+                }
+                ExpressionKind::Number(_) | ExpressionKind::Float(_) | ExpressionKind::String(_) | ExpressionKind::Error(_) => {}
+            }
+        }
+        for function in ast.functions.data.iter_mut() {
+            function.body.statements.iter_mut().for_each(|stmt_id| *stmt_id = statements.id(*stmt_id));
+            function.parameters.iter_mut().for_each(|parameter| parameter.variable_id = variables.id(parameter.variable_id));
+        }
+        for variable in ast.variables.data.iter_mut() {
+            variable.id = variables.id(variable.id);
+        }
+        self.garbage = self.free_items.len();
+    }
+
+    /// Parses the new text from the start of item `first` until it can
+    /// reuse the old items that follow `edit`.
+    fn reparse(&mut self, first: usize, edit: &TextEdit) -> Reparse {
+        let start = self.items.get(first).map_or(0, |item| item.start);
+        let diagnostics = DiagnosticsBag::new_cell();
+        let mut scope = GlobalScope::new();
+        for item in self.items[..first].iter() {
+            if let Some((name, variable_id)) = global_let(&self.ast, item.item_id) {
+                scope.declare_variable(name.to_string(), variable_id);
+            }
+        }
+        let error_at_start = first.checked_sub(1).is_some_and(|index| self.items[index].error_at_end);
+        self.ast.top_level.truncate(first);
+
+        let lexer = Lexer::new(&self.source.text, self.source.id, diagnostics.clone()).with_start_position(start);
+        let mut parser = Parser::new(lexer, &mut self.ast, &mut scope, diagnostics.clone());
+        if error_at_start {
+            let first_token = parser.current_token().span.start;
+            parser = parser.with_last_error_at(Some(first_token));
+        }
+        // Lexing the first token reported these already, while the item
+        // before it was parsed, unless there is no such item.
+        let preamble = mem::take(&mut diagnostics.borrow_mut().diagnostics);
+        if first == 0 {
+            self.preamble = preamble;
+        }
+        let mut parsed = Vec::new();
+        let reused_from = loop {
+            if parser.is_at_end() {
+                parser.finish();
+                break self.items.len();
+            }
+            let item_start = parser.current_token().full_start();
+            let checkpoint = parser.syntax.checkpoint();
+            let item_id = parser.parse_next_item();
+            let item_end = parser.current_token().full_start();
+            let error_at_end = parser.last_error_at() == Some(parser.current_token().span.start);
+            parsed.push(ParsedItem {
+                item_id,
+                start: item_start,
+                end: item_end,
+                diagnostics: mem::take(&mut diagnostics.borrow_mut().diagnostics),
+                error_at_end,
+                syntax: parser.syntax.children_since(checkpoint),
+            });
+            let next = self.items.partition_point(|item| item.start < edit.end || edit.shift(item.start) < item_end);
+            if self.items.get(next).is_none_or(|old| edit.shift(old.start) != item_end) {
+                continue;
+            }
+            let old_error_at_end = next.checked_sub(1).is_some_and(|index| self.items[index].error_at_end);
+            if old_error_at_end == error_at_end && same_declarations(parser.ast, &self.items[first..next], &parsed) {
+                break next;
+            }
+        };
+
+        let delta = edit.delta();
+        if reused_from < self.items.len() {
+            // Later items refer to the old variables of the reparsed `let`s,
+            // so those take over the new declarations.
+            let old_lets = global_lets(&self.ast, &self.items[first..reused_from]);
+            let new_lets = global_lets(&self.ast, &parsed);
+            let renames: Vec<(VariableId, VariableId)> = new_lets.into_iter().zip(old_lets).collect();
+            for (new_id, old_id) in renames.iter() {
+                let mut variable = self.ast.variables.get(*new_id).clone();
+                variable.id = *old_id;
+                *self.ast.variables.get_mut(*old_id) = variable;
+            }
+            for item in parsed.iter() {
+                ItemNodes::collect(&self.ast, item.item_id).rename_variables(&mut self.ast, &renames);
+            }
+            for item in self.items[reused_from..].iter_mut() {
+                ItemNodes::collect(&self.ast, item.item_id).shift(&mut self.ast, delta);
+                item.start = item.start.wrapping_add_signed(delta);
+                item.end = item.end.wrapping_add_signed(delta);
+                for diagnostic in item.diagnostics.iter_mut() {
+                    diagnostic.span.shift(delta);
+                }
+                self.ast.top_level.push(item.item_id);
+            }
+            if let Some(eof) = self.ast.eof.as_mut() {
+                eof.shift(delta);
+            }
+        }
+        let added = parsed.iter().map(|item| item.item_id).collect();
+        let removed = self.items.splice(first..reused_from, parsed).map(|item| item.item_id).collect();
+        self.rebuild_syntax_tree();
+        Reparse { removed, added }
+    }
+
+    /// Puts the syntax tree of the file together from the syntax of its
+    /// items, which a reparse keeps unless it parsed them again.
+    fn rebuild_syntax_tree(&mut self) {
+        let mut builder = GreenBuilder::new();
+        builder.start_node(SyntaxKind::File);
+        for item in self.items.iter() {
+            builder.elements(item.syntax.iter().cloned());
+        }
+        if let Some(eof) = self.ast.eof.as_ref() {
+            builder.trivia(&eof.leading_trivia);
+        }
+        self.ast.syntax_tree = builder.finish();
+    }
+}
+
+/// The new ids of the nodes an arena keeps when it is compacted.
+struct Compacted<I> {
+    ids: Vec<Option<I>>,
+}
+
+impl<I: Id + Ord> Compacted<I> {
+    /// Keeps the nodes `live` of `arena`, in the order of their ids.
+    fn new<T>(arena: &mut IdVec<I, T>, mut live: Vec<I>) -> Self {
+        live.sort_unstable();
+        live.dedup();
+        let mut old: Vec<Option<T>> = mem::take(&mut arena.data).into_iter().map(Some).collect();
+        let mut ids = Vec::new();
+        ids.resize_with(old.len(), || None);
+        for id in live {
+            ids[id.to_usize()] = Some(arena.push(old[id.to_usize()].take().unwrap()));
+        }
+        Self { ids }
+    }
+
+    fn id(&self, old: I) -> I {
+        self.ids[old.to_usize()].expect("a node in use refers to a replaced one")
+    }
+}
+
+/// How many nodes the arenas of `ast` hold.
+fn arena_len(ast: &Ast) -> usize {
+    ast.items.data.len()
+        + ast.statements.data.len()
+        + ast.expressions.data.len()
+        + ast.functions.data.len()
+        + ast.variables.data.len()
+        + ast.imports.data.len()
+}
+
+/// The name and variable of a top-level `let`.
+fn global_let(ast: &Ast, item_id: ItemId) -> Option<(&str, VariableId)> {
+    let ItemKind::Statement(stmt_id) = ast.query_item(item_id).kind else {
+        return None;
+    };
+    match &ast.query_stmt(stmt_id).stmt_kind {
+        StatementKind::Let(let_stmt) => Some((&let_stmt.identifier.span.literal, let_stmt.variable_id)),
+        _ => None,
+    }
+}
+
+fn global_lets(ast: &Ast, items: &[ParsedItem]) -> Vec<VariableId> {
+    items.iter().filter_map(|item| global_let(ast, item.item_id)).map(|(_, variable_id)| variable_id).collect()
+}
+
+/// Whether `old` and `new` declare the same global variables and functions
+/// in the same order.
+fn same_declarations(ast: &Ast, old: &[ParsedItem], new: &[ParsedItem]) -> bool {
+    let declarations = |items: &[ParsedItem]| -> Vec<(bool, String)> {
+        items
+            .iter()
+            .filter_map(|item| match ast.query_item(item.item_id).kind {
+                ItemKind::Function(function_id) => Some((true, ast.query_function(function_id).name.clone())),
+                ItemKind::Statement(_) => global_let(ast, item.item_id).map(|(name, _)| (false, name.to_string())),
+                // A file parsed on its own resolves no imports.
+                ItemKind::Import(_) => None,
+            })
+            .collect()
+    };
+    declarations(old) == declarations(new)
+}
+
+/// The nodes of one item, which the incremental parser updates in place.
+/// `variables` are the variables the item declares, its parameters and
+/// `let`s.
+#[derive(Debug, Default)]
+struct ItemNodes {
+    import: Option<ImportId>,
+    function: Option<FunctionId>,
+    statements: Vec<StmtId>,
+    expressions: Vec<ExprId>,
+    variables: Vec<VariableId>,
+}
+
+impl ItemNodes {
+    fn collect(ast: &Ast, item_id: ItemId) -> Self {
+        let mut nodes = Self::default();
+        nodes.visit_item(ast, item_id);
+        nodes
+    }
+
+    /// How many nodes there are in all the arenas, the item included.
+    fn len(&self) -> usize {
+        1 + self.import.iter().count() + self.function.iter().count() + self.statements.len() + self.expressions.len() + self.variables.len()
+    }
+
+    fn shift(&self, ast: &mut Ast, delta: isize) {
+        let shift_annotation = |type_annotation: &mut Option<TypeAnnotation>| {
+            if let Some(type_annotation) = type_annotation {
+                type_annotation.separator.shift(delta);
+                type_annotation.name.shift(delta);
+            }
+        };
+        if let Some(import_id) = self.import {
+            let import = ast.imports.get_mut(import_id);
+            import.keyword.shift(delta);
+            match &mut import.kind {
+                ImportKind::File { path } => path.shift(delta),
+                ImportKind::Name { module, colons, name } => {
+                    for token in [module, colons, name] {
+                        token.shift(delta);
+                    }
+                }
+            }
+        }
+        if let Some(function_id) = self.function {
+            let function = ast.functions.get_mut(function_id);
+            for token in [
+                &mut function.function_keyword,
+                &mut function.identifier,
+                &mut function.open_paren,
+                &mut function.close_paren,
+                &mut function.body.open_brace,
+                &mut function.body.close_brace,
+            ] {
+                token.shift(delta);
+            }
+            shift_annotation(&mut function.return_type);
+            for parameter in function.parameters.iter_mut() {
+                parameter.identifier.shift(delta);
+                shift_annotation(&mut parameter.type_annotation);
+            }
+        }
+        for stmt_id in self.statements.iter() {
+            match &mut ast.statements.get_mut(*stmt_id).stmt_kind {
+                StatementKind::Let(let_stmt) => {
+                    let_stmt.let_keyword.shift(delta);
+                    let_stmt.identifier.shift(delta);
+                    shift_annotation(&mut let_stmt.type_annotation);
+                    let_stmt.equals.shift(delta);
+                }
+                StatementKind::Return(return_stmt) => return_stmt.return_keyword.shift(delta),
+                StatementKind::Expression(_) => {}
+            }
+        }
+        for expr_id in self.expressions.iter() {
+            let tokens: Vec<&mut Token> = match &mut ast.expressions.get_mut(*expr_id).kind {
+                ExpressionKind::Number(number) => vec![&mut number.token],
+                ExpressionKind::Float(number) => vec![&mut number.token],
+                ExpressionKind::String(string) => vec![&mut string.token],
+                ExpressionKind::Variable(variable_expr) => {
+                    let mut tokens = vec![&mut variable_expr.identifier];
+                    if let Some(qualifier) = variable_expr.qualifier.as_mut() {
+                        tokens.extend([&mut qualifier.module, &mut qualifier.colons]);
+                    }
+                    tokens
+                }
+                ExpressionKind::Call(call_expr) => {
+                    let mut tokens = vec![&mut call_expr.callee, &mut call_expr.open_paren, &mut call_expr.close_paren];
+                    tokens.extend(call_expr.commas.iter_mut());
+                    if let Some(qualifier) = call_expr.qualifier.as_mut() {
+                        tokens.extend([&mut qualifier.module, &mut qualifier.colons]);
+                    }
+                    tokens
+                }
+                ExpressionKind::Binary(bin_expr) => vec![&mut bin_expr.operator.token],
+                ExpressionKind::Parenthesized(parenthesized_expr) => {
+                    vec![&mut parenthesized_expr.open_paren, &mut parenthesized_expr.close_paren]
+                }
+                ExpressionKind::Assignment(assign_expr) => vec![&mut assign_expr.let_keyword, &mut assign_expr.equals],
+                ExpressionKind::Error(token) => vec![token],
+            };
+            for token in tokens {
+                token.shift(delta);
+            }
+        }
+        for variable_id in self.variables.iter() {
+            ast.variables.get_mut(*variable_id).identifier.shift(delta);
+        }
+    }
+
+    /// Points the declarations and uses of the first variable of each pair
+    /// at the second.
+    fn rename_variables(&self, ast: &mut Ast, renames: &[(VariableId, VariableId)]) {
+        let rename = |variable_id: &mut VariableId| {
+            if let Some((_, to)) = renames.iter().find(|(from, _)| from == variable_id) {
+                *variable_id = *to;
+            }
+        };
+        for stmt_id in self.statements.iter() {
+            if let StatementKind::Let(let_stmt) = &mut ast.statements.get_mut(*stmt_id).stmt_kind {
+                rename(&mut let_stmt.variable_id);
+            }
+        }
+        for expr_id in self.expressions.iter() {
+            if let ExpressionKind::Variable(variable_expr) = &mut ast.expressions.get_mut(*expr_id).kind {
+                if let Some(variable_id) = variable_expr.variable_id.as_mut() {
+                    rename(variable_id);
+                }
+            }
+        }
+    }
+}
+
+impl Visitor for ItemNodes {
+    fn visit_import(&mut self, _ast: &Ast, import_id: ImportId) {
+        self.import = Some(import_id);
+    }
+
+    fn visit_function(&mut self, ast: &Ast, function_id: FunctionId) {
+        self.function = Some(function_id);
+        let function = ast.query_function(function_id);
+        self.variables.extend(function.parameters.iter().map(|parameter| parameter.variable_id));
+        for stmt_id in function.body.statements.iter() {
+            self.visit_statement(ast, *stmt_id);
+        }
+    }
+
+    fn visit_statement(&mut self, ast: &Ast, stmt_id: StmtId) {
+        self.statements.push(stmt_id);
+        self.do_visit_statement(ast, stmt_id);
+    }
+
+    fn visit_expression(&mut self, ast: &Ast, expr_id: ExprId) {
+        self.expressions.push(expr_id);
+        self.do_visit_expression(ast, expr_id);
+    }
+
+    fn visit_let_statement(&mut self, ast: &Ast, stmt: &super::LetStatement) {
+        self.variables.push(stmt.variable_id);
+        self.visit_expression(ast, stmt.initializer);
+    }
+
+    fn visit_number(&mut self, _ast: &Ast, _number: &super::NumberExpr) {}
+
+    fn visit_float(&mut self, _ast: &Ast, _number: &super::FloatExpr) {}
+
+    fn visit_string(&mut self, _ast: &Ast, _string: &super::StringExpr) {}
+
+    fn visit_variable_expression(&mut self, _ast: &Ast, _variable_expr: &super::VariableExpr) {}
+}
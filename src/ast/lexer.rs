@@ -1,31 +1,43 @@
-use std::fmt::{write, Display, Formatter};
+use std::{
+    fmt::{Display, Formatter},
+    iter::FusedIterator,
+};
 
-use crate::ast::text::TextSpan;
+use crate::{
+    ast::text::{FileId, TextSpan},
+    diagnostics::DiagnosticsBagCell,
+};
 
 
 #[derive(Debug, Clone, Eq,PartialEq,Copy)]
 pub enum TokenKind {
     // Literals
     Number(i64),
+    Float,
+    String,
     // Operators
     Plus,
     Minus,
     Asterisk,
     Slash,
     Equals,
-
+    Arrow,
    //Keyword
     Let,
+    Return,
+    Import,
+    Use,
 
     // Other
     OpenParen,
     CloseParen,
     Bad,
-    Whitespace,
     Identifier,
     Function,
     Eof,
     Comma,
+    Colon,
+    ColonColon,
     OpenBrace,
     CloseBrace
 }
@@ -34,20 +46,27 @@ impl Display for TokenKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             TokenKind::Number(_) => write!(f, "Number"),
+            TokenKind::Float => write!(f, "Float"),
+            TokenKind::String => write!(f, "String"),
             TokenKind::Plus => write!(f, "+"),
             TokenKind::Minus => write!(f, "-"),
             TokenKind::Asterisk => write!(f, "*"),
             TokenKind::Slash => write!(f, "/"),
             TokenKind::Bad => write!(f, "Bad"),
-            TokenKind::Whitespace => write!(f, "Whitespace"),
-            TokenKind::Eof => write!(f, "Eof"),
+            TokenKind::Eof => write!(f, "end of file"),
             TokenKind::Let => write!(f, "Let"),
+            TokenKind::Return => write!(f, "Return"),
+            TokenKind::Import => write!(f, "Import"),
+            TokenKind::Use => write!(f, "Use"),
             TokenKind::Identifier => write!(f, "Identifier"),
             TokenKind::Equals => write!(f, "="),
+            TokenKind::Arrow => write!(f, "->"),
             TokenKind::OpenParen => write!(f,"Open parenthesis"),
             TokenKind::CloseParen => write!(f,"Closing parenthesis"),
             TokenKind::Function=>write!(f, "Function"),
             TokenKind::Comma=>write!(f, "Comma"),
+            TokenKind::Colon=>write!(f, "Colon"),
+            TokenKind::ColonColon=>write!(f, "::"),
             TokenKind::OpenBrace => write!(f,"Open Brace"),
             TokenKind::CloseBrace => write!(f,"Close Brace"),
           
@@ -59,68 +78,230 @@ impl Display for TokenKind {
 pub struct Token {
     pub kind: TokenKind,
     pub span: TextSpan,
+    pub leading_trivia: Vec<Trivia>,
+    pub trailing_trivia: Vec<Trivia>,
 }
 
 impl Token {
     pub fn new(kind: TokenKind, span: TextSpan) -> Self {
+        Self {
+            kind,
+            span,
+            leading_trivia: vec![],
+            trailing_trivia: vec![],
+        }
+    }
+
+    /// The comments around this token, leading ones first.
+    pub fn comments(&self) -> impl Iterator<Item = &Trivia> {
+        self.leading_trivia
+            .iter()
+            .chain(self.trailing_trivia.iter())
+            .filter(|trivia| trivia.kind != TriviaKind::Whitespace)
+    }
+
+    /// Where the token starts, counting its leading trivia.
+    pub fn full_start(&self) -> usize {
+        self.leading_trivia.first().map_or(self.span.start, |trivia| trivia.span.start)
+    }
+
+    /// Moves the token and its trivia by `delta` bytes.
+    pub fn shift(&mut self, delta: isize) {
+        self.span.shift(delta);
+        for trivia in self.leading_trivia.iter_mut().chain(self.trailing_trivia.iter_mut()) {
+            trivia.span.shift(delta);
+        }
+    }
+
+    /// Moves the trivia of a token the parser does not keep, such as a
+    /// separating comma, onto the end of this token so no comment is lost.
+    pub fn absorb_trivia(&mut self, token: Token) {
+        self.trailing_trivia.extend(token.leading_trivia);
+        self.trailing_trivia.extend(token.trailing_trivia);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    LineComment,
+    BlockComment,
+}
+
+/// Source text between tokens that does not affect parsing. Trivia on the
+/// same line after a token is that token's trailing trivia; everything from
+/// the next line break up to the following token is its leading trivia.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: TextSpan,
+}
+
+impl Trivia {
+    pub fn new(kind: TriviaKind, span: TextSpan) -> Self {
         Self { kind, span }
     }
 }
 
+/// Turns source text into tokens in a single pass. `current_pos` is a byte
+/// offset into `input` that always sits on a character boundary, so every
+/// span can slice `input` directly, whatever characters it contains.
 pub struct Lexer<'a> {
     input: &'a str,
+    file_id: FileId,
     current_pos: usize,
+    finished: bool,
+    diagnostics: DiagnosticsBagCell,
 }
 
 impl<'a> Lexer<'a> {
-    pub fn new(input: &'a str) -> Self {
+    pub fn new(input: &'a str, file_id: FileId, diagnostics: DiagnosticsBagCell) -> Self {
         Self {
             input,
+            file_id,
             current_pos: 0,
+            finished: false,
+            diagnostics,
         }
     }
 
+    /// Starts lexing at the byte `position` of the input instead of at its
+    /// start, which must be a token boundary. Spans stay relative to the
+    /// whole input.
+    pub fn with_start_position(mut self, position: usize) -> Self {
+        self.current_pos = position;
+        self
+    }
+
+    /// Returns the next token, then a single `Eof` at the end of the input
+    /// and `None` from then on.
     pub fn next_token(&mut self) -> Option<Token> {
-        if self.current_pos == self.input.len() {
-            let eof_char: char = '\0';
-            self.current_pos += 1;
-            return Some(Token::new(
+        if self.finished {
+            return None;
+        }
+        let leading_trivia = self.consume_trivia(false);
+        let mut token = if self.current_pos == self.input.len() {
+            let end = self.input.len();
+            self.finished = true;
+            Token::new(
                 TokenKind::Eof,
-                TextSpan::new(0, 0, eof_char.to_string()),
-            ));
+                TextSpan::new(self.file_id, end, end, String::new()),
+            )
+        } else {
+            self.consume_token()
+        };
+        token.leading_trivia = leading_trivia;
+        if token.kind != TokenKind::Eof {
+            token.trailing_trivia = self.consume_trivia(true);
         }
-        let c = self.current_char();
-        return c.map(|c| {
+        Some(token)
+    }
+
+    fn consume_token(&mut self) -> Token {
+        let c = self.current_char().unwrap();
+        let start = self.current_pos;
+        let kind = if Self::is_number_start(&c) && self.is_float_start() {
+            self.consume_float();
+            TokenKind::Float
+        } else if Self::is_number_start(&c) {
+            let number: i64 = self.consume_number();
+            TokenKind::Number(number)
+        } else if c == '"' {
+            self.consume_string();
+            TokenKind::String
+        } else if Self::is_identifier_start(&c) {
+            let identifier = self.consume_identifier();
+            match identifier {
+                "let" => TokenKind::Let,
+                "function" => TokenKind::Function,
+                "return" => TokenKind::Return,
+                "import" => TokenKind::Import,
+                "use" => TokenKind::Use,
+                _ => TokenKind::Identifier,
+            }
+        } else {
+            self.consume_punctuation()
+        };
+
+        let end = self.current_pos;
+        let literal = self.input[start..end].to_string();
+        let span = TextSpan::new(self.file_id, start, end, literal);
+        Token::new(kind, span)
+    }
+
+    /// Consumes whitespace and comments. Trailing trivia stops at the first
+    /// line break, which is left for the leading trivia of the next token.
+    fn consume_trivia(&mut self, trailing: bool) -> Vec<Trivia> {
+        let mut trivia = vec![];
+        while let Some(c) = self.current_char() {
             let start = self.current_pos;
-            let mut kind = TokenKind::Bad;
-            if Self::is_number_start(&c) {
-                let number: i64 = self.consume_number();
-                kind = TokenKind::Number(number);
-            } else if Self::is_whitespace(&c) {
-                self.consume();
-                kind = TokenKind::Whitespace;
-            } else if Self::is_identifier_start(&c) {
-                let identifier = self.consume_identifier();
-                kind = match identifier.as_str() {
-                    "let" => TokenKind::Let,
-                    "function" => TokenKind::Function,
-                    _ => TokenKind::Identifier,
+            let kind = if Self::is_whitespace(&c) {
+                while let Some(c) = self.current_char() {
+                    if !Self::is_whitespace(&c) || (trailing && c == '\n') {
+                        break;
+                    }
+                    self.consume();
+                }
+                if self.current_pos == start {
+                    break;
                 }
+                TriviaKind::Whitespace
+            } else if c == '/' && self.peek_char(1) == Some('/') {
+                while let Some(c) = self.current_char() {
+                    if c == '\n' {
+                        break;
+                    }
+                    self.consume();
+                }
+                TriviaKind::LineComment
+            } else if c == '/' && self.peek_char(1) == Some('*') {
+                self.consume_block_comment();
+                TriviaKind::BlockComment
             } else {
-                kind = self.consume_punctuation();
+                break;
+            };
+            let literal = self.input[start..self.current_pos].to_string();
+            trivia.push(Trivia::new(kind, TextSpan::new(self.file_id, start, self.current_pos, literal)));
+            if trailing && self.current_char() == Some('\n') {
+                break;
             }
+        }
+        trivia
+    }
 
-            let end = self.current_pos;
-            let literal = self.input[start..end].to_string();
-            let span = TextSpan::new(start, end, literal);
-            Token::new(kind, span)
-        });
+    /// Consumes a `/* */` comment. Block comments nest, so
+    /// `/* a /* b */ c */` is a single comment.
+    fn consume_block_comment(&mut self) {
+        let start = self.current_pos;
+        let mut depth = 0;
+        while let Some(c) = self.current_char() {
+            if c == '/' && self.peek_char(1) == Some('*') {
+                self.consume();
+                self.consume();
+                depth += 1;
+            } else if c == '*' && self.peek_char(1) == Some('/') {
+                self.consume();
+                self.consume();
+                depth -= 1;
+                if depth == 0 {
+                    return;
+                }
+            } else {
+                self.consume();
+            }
+        }
+        let span = TextSpan::new(self.file_id, start, start + 2, String::from("/*"));
+        self.diagnostics.borrow_mut().report_unterminated_block_comment(span);
     }
 
     fn consume_punctuation(&mut self) -> TokenKind {
         let c = self.consume().unwrap();
         match c {
             '+' => TokenKind::Plus,
+            '-' if self.current_char() == Some('>') => {
+                self.consume();
+                TokenKind::Arrow
+            }
             '-' => TokenKind::Minus,
             '*'=> TokenKind::Asterisk,     
             '/' => TokenKind::Slash,
@@ -128,6 +309,11 @@ impl<'a> Lexer<'a> {
             '(' => TokenKind::OpenParen,
             ')'=>TokenKind::CloseParen,
             ','=>TokenKind::Comma,
+            ':' if self.current_char() == Some(':') => {
+                self.consume();
+                TokenKind::ColonColon
+            }
+            ':'=>TokenKind::Colon,
             '{'=>TokenKind::OpenBrace,
             '}' => TokenKind::CloseBrace,
             _ => TokenKind::Bad,
@@ -135,54 +321,235 @@ impl<'a> Lexer<'a> {
     }
 
     fn is_number_start(c: &char) -> bool {
-        c.is_digit(10)
+        c.is_ascii_digit()
     }
 
     fn is_identifier_start(c: &char) -> bool {
         c.is_alphabetic() || c == &'_'
     }
 
+    fn is_identifier_continue(c: &char) -> bool {
+        c.is_alphanumeric() || c == &'_'
+    }
+
     fn is_whitespace(c: &char) -> bool {
         c.is_whitespace()
     }
 
+    fn remaining(&self) -> &'a str {
+        self.input.get(self.current_pos..).unwrap_or_default()
+    }
+
     fn current_char(&self) -> Option<char> {
-        self.input.chars().nth(self.current_pos)
+        self.remaining().chars().next()
     }
 
-    fn consume(&mut self) -> Option<char> {
-        if self.current_pos >= self.input.len() {
-            return None;
-        }
-        let c = self.current_char();
-        self.current_pos += 1;
+    fn peek_char(&self, offset: usize) -> Option<char> {
+        self.remaining().chars().nth(offset)
+    }
 
-        c
+    fn consume(&mut self) -> Option<char> {
+        let c = self.current_char()?;
+        self.current_pos += c.len_utf8();
+        Some(c)
     }
 
-    fn consume_identifier(&mut self) -> String {
-        let mut identifier = String::new();
+    fn consume_identifier(&mut self) -> &'a str {
+        let start = self.current_pos;
         while let Some(c) = self.current_char() {
-            if Self::is_identifier_start(&c) {
-                self.consume().unwrap();
-                identifier.push(c);
-            } else {
+            if !Self::is_identifier_continue(&c) {
                 break;
             }
+            self.consume();
+        }
+        &self.input[start..self.current_pos]
+    }
+
+    /// Consumes a double quoted string literal, reporting bad escapes and a
+    /// missing closing quote. A string ends at the end of its line at the
+    /// latest; the escapes are decoded by `unescape`.
+    fn consume_string(&mut self) {
+        let start = self.current_pos;
+        self.consume();
+        loop {
+            match self.current_char() {
+                None | Some('\n') => {
+                    let literal = self.input[start..self.current_pos].to_string();
+                    let span = TextSpan::new(self.file_id, start, self.current_pos, literal);
+                    self.diagnostics.borrow_mut().report_unterminated_string(span);
+                    return;
+                }
+                Some('"') => {
+                    self.consume();
+                    return;
+                }
+                Some('\\') => self.consume_escape(),
+                Some(_) => {
+                    self.consume();
+                }
+            }
+        }
+    }
+
+    fn consume_escape(&mut self) {
+        let start = self.current_pos;
+        self.consume();
+        let valid = match self.current_char() {
+            Some('n' | 't' | 'r' | '0' | '\\' | '"') => {
+                self.consume();
+                true
+            }
+            Some('u') => {
+                self.consume();
+                self.consume_unicode_escape()
+            }
+            // Leave the line break for `consume_string` to report.
+            None | Some('\n') => true,
+            Some(_) => {
+                self.consume();
+                false
+            }
+        };
+        if !valid {
+            let literal = self.input[start..self.current_pos].to_string();
+            let span = TextSpan::new(self.file_id, start, self.current_pos, literal);
+            self.diagnostics.borrow_mut().report_invalid_escape(span);
+        }
+    }
+
+    /// Consumes the `{...}` of a `\u{...}` escape and checks that it holds one
+    /// to six hex digits naming a valid character.
+    fn consume_unicode_escape(&mut self) -> bool {
+        if self.current_char() != Some('{') {
+            return false;
+        }
+        self.consume();
+        let digits_start = self.current_pos;
+        while self.current_char().is_some_and(|c| c.is_ascii_hexdigit()) {
+            self.consume();
+        }
+        let digits = &self.input[digits_start..self.current_pos];
+        if self.current_char() != Some('}') {
+            return false;
+        }
+        self.consume();
+        digits.len() <= 6
+            && u32::from_str_radix(digits, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .is_some()
+    }
+
+    /// Whether the decimal digits at the cursor are followed by a `.` and
+    /// another digit, as in `2.5`.
+    fn is_float_start(&self) -> bool {
+        let integer_part = self.remaining().trim_start_matches(|c: char| c.is_ascii_digit() || c == '_');
+        let mut rest = integer_part.chars();
+        rest.next() == Some('.') && rest.next().is_some_and(|c| c.is_ascii_digit())
+    }
+
+    /// Consumes a decimal literal with a fractional part, such as `1_000.25`.
+    /// Its value is read from the literal by the parser.
+    fn consume_float(&mut self) {
+        while self.current_char().is_some_and(|c| c.is_ascii_digit() || c == '_') {
+            self.consume();
+        }
+        self.consume();
+        while self.current_char().is_some_and(|c| c.is_ascii_digit() || c == '_') {
+            self.consume();
         }
-        identifier
     }
 
+    /// Consumes a decimal, `0x` hexadecimal, `0b` binary or `0o` octal
+    /// literal. Digits may be separated by `_`, as in `1_000_000`. Literals
+    /// that do not fit an `i64` or contain digits outside their radix are
+    /// reported and lexed as `0`.
     fn consume_number(&mut self) -> i64 {
-        let mut number: i64 = 0;
+        let start = self.current_pos;
+        let radix = match (self.current_char(), self.peek_char(1)) {
+            (Some('0'), Some('x' | 'X')) => 16,
+            (Some('0'), Some('b' | 'B')) => 2,
+            (Some('0'), Some('o' | 'O')) => 8,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.consume();
+            self.consume();
+        }
+        let digits_start = self.current_pos;
         while let Some(c) = self.current_char() {
-            if c.is_digit(10) {
-                self.consume().unwrap();
-                number = number * 10 + c.to_digit(10).unwrap() as i64;
-            } else {
+            if !Self::is_identifier_continue(&c) {
                 break;
             }
+            self.consume();
+        }
+        let literal = &self.input[start..self.current_pos];
+        let span = TextSpan::new(self.file_id, start, self.current_pos, literal.to_string());
+        let digits = &self.input[digits_start..self.current_pos];
+
+        let mut number: i64 = 0;
+        let mut has_digits = false;
+        for c in digits.chars().filter(|c| *c != '_') {
+            let Some(digit) = c.to_digit(radix) else {
+                self.diagnostics.borrow_mut().report_invalid_digit(c, radix, span);
+                return 0;
+            };
+            has_digits = true;
+            match number.checked_mul(radix as i64).and_then(|number| number.checked_add(digit as i64)) {
+                Some(next) => number = next,
+                None => {
+                    self.diagnostics.borrow_mut().report_integer_overflow(span);
+                    return 0;
+                }
+            }
+        }
+        if !has_digits {
+            self.diagnostics.borrow_mut().report_missing_digits(span);
+            return 0;
         }
         number
     }
 }
+
+/// Decodes the escapes of a string literal as lexed by `Lexer`, without its
+/// quotes. Escapes the lexer reported as invalid are dropped.
+pub fn unescape(literal: &str) -> String {
+    let literal = literal.strip_prefix('"').unwrap_or(literal);
+    let literal = literal.strip_suffix('"').unwrap_or(literal);
+    let mut value = String::with_capacity(literal.len());
+    let mut chars = literal.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('t') => value.push('\t'),
+            Some('r') => value.push('\r'),
+            Some('0') => value.push('\0'),
+            Some('u') => {
+                let escape: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let decoded = u32::from_str_radix(escape.trim_start_matches('{'), 16)
+                    .ok()
+                    .and_then(char::from_u32);
+                if let Some(decoded) = decoded {
+                    value.push(decoded);
+                }
+            }
+            Some(c @ ('\\' | '"')) => value.push(c),
+            Some(_) | None => {}
+        }
+    }
+    value
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.next_token()
+    }
+}
+
+impl FusedIterator for Lexer<'_> {}
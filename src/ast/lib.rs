@@ -1,4 +1,4 @@
-use std::{marker::PhantomData, process::Output};
+use std::marker::PhantomData;
 
 
 #[macro_export]
@@ -8,7 +8,7 @@ macro_rules! id_gen {
         #[derive(Debug, Clone, Eq, PartialEq, Hash, Copy, Ord, PartialOrd)]
          pub struct $name(usize);
 
-      impl crate::ast::lib::Id for $name{
+      impl $crate::ast::lib::Id for $name{
         fn new(id:usize)->Self{
             Self(id)
         }
@@ -42,6 +42,12 @@ impl<IdType:Id,T> IdVec<IdType,T>{
         Self { data: vec![], _marker: PhantomData }
     }
 
+    /// A vector holding `len` copies of `item`, for side tables indexed by
+    /// the ids of another arena.
+    pub fn filled(item:T,len:usize)->Self where T:Clone{
+        Self { data: vec![item; len], _marker: PhantomData }
+    }
+
     pub fn get(&self,id:IdType)->&T{
       return &self.data[id.to_usize()];
     }
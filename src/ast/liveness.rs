@@ -0,0 +1,148 @@
+use crate::diagnostics::DiagnosticsBagCell;
+
+use super::{
+    lib::IdVec, visitor::Visitor, Ast, CallExpr, FloatExpr, FunctionId, ItemKind, LetStatement, NumberExpr, StatementKind,
+    StringExpr, VariableExpr, VariableId,
+};
+
+/// Which variables of an `Ast` are read and which functions can be called.
+///
+/// A variable is used if any expression reads it. A function is called if
+/// the top-level statements of some file call it, or a function that is
+/// called does, so functions that only call each other are not. Names that
+/// start with `_` are never reported, as in Rust, and neither are the
+/// top-level `let`s of the main file, whose values `run` prints.
+pub struct Liveness {
+    pub reads: IdVec<VariableId, usize>,
+    pub called: IdVec<FunctionId, bool>,
+}
+
+impl Liveness {
+    pub fn new(ast: &Ast) -> Self {
+        let mut collector = UseCollector {
+            reads: IdVec::filled(0, ast.variables.data.len()),
+            calls: IdVec::filled(Vec::new(), ast.functions.data.len()),
+            roots: Vec::new(),
+            function: None,
+        };
+        ast.visit(&mut collector);
+        let mut called = IdVec::filled(false, ast.functions.data.len());
+        let mut pending = collector.roots;
+        while let Some(function_id) = pending.pop() {
+            if std::mem::replace(called.get_mut(function_id), true) {
+                continue;
+            }
+            pending.extend(collector.calls.get(function_id).iter().copied());
+        }
+        Self {
+            reads: collector.reads,
+            called,
+        }
+    }
+
+    pub fn is_used(&self, variable_id: VariableId) -> bool {
+        *self.reads.get(variable_id) > 0
+    }
+
+    pub fn is_called(&self, function_id: FunctionId) -> bool {
+        *self.called.get(function_id)
+    }
+
+    /// Warns about every variable, parameter and function of `ast` that is
+    /// not used, in source order, leaving out the `let`s whose value is one
+    /// of the results of the program.
+    pub fn report(&self, ast: &Ast, diagnostics: DiagnosticsBagCell) {
+        let mut diagnostics = diagnostics.borrow_mut();
+        let reported = |name: &str| !name.is_empty() && !name.starts_with('_');
+        let unused_lets = |statements: &[super::StmtId]| -> Vec<VariableId> {
+            statements
+                .iter()
+                .filter_map(|stmt_id| match &ast.query_stmt(*stmt_id).stmt_kind {
+                    StatementKind::Let(let_stmt) if !self.is_used(let_stmt.variable_id) => Some(let_stmt.variable_id),
+                    _ => None,
+                })
+                .collect()
+        };
+        for item in ast.top_level_items() {
+            match item.kind {
+                ItemKind::Statement(stmt_id) => {
+                    if ast.is_result(stmt_id) && ast.is_main_file(ast.query_stmt(stmt_id).span(ast).file_id) {
+                        continue;
+                    }
+                    for variable_id in unused_lets(&[stmt_id]) {
+                        let variable = ast.query_variable(variable_id);
+                        if reported(&variable.name) {
+                            diagnostics.report_unused_variable(&variable.identifier.span);
+                        }
+                    }
+                }
+                ItemKind::Function(function_id) => {
+                    let function = ast.query_function(function_id);
+                    if !self.is_called(function_id) && reported(&function.name) {
+                        diagnostics.report_unused_function(&function.identifier.span);
+                    }
+                    for parameter in function.parameters.iter() {
+                        if !self.is_used(parameter.variable_id) && reported(&parameter.identifier.span.literal) {
+                            diagnostics.report_unused_parameter(&parameter.identifier.span);
+                        }
+                    }
+                    for variable_id in unused_lets(&function.body.statements) {
+                        let variable = ast.query_variable(variable_id);
+                        if reported(&variable.name) {
+                            diagnostics.report_unused_variable(&variable.identifier.span);
+                        }
+                    }
+                }
+                ItemKind::Import(_) => {}
+            }
+        }
+    }
+}
+
+/// Counts the reads of every variable and records which functions each
+/// function calls, and which the top-level statements call in `roots`.
+struct UseCollector {
+    reads: IdVec<VariableId, usize>,
+    calls: IdVec<FunctionId, Vec<FunctionId>>,
+    roots: Vec<FunctionId>,
+    function: Option<FunctionId>,
+}
+
+impl Visitor for UseCollector {
+    fn visit_function(&mut self, ast: &Ast, function_id: FunctionId) {
+        self.function = Some(function_id);
+        for stmt_id in ast.query_function(function_id).body.statements.iter() {
+            self.visit_statement(ast, *stmt_id);
+        }
+        self.function = None;
+    }
+
+    fn visit_let_statement(&mut self, ast: &Ast, stmt: &LetStatement) {
+        self.visit_expression(ast, stmt.initializer);
+    }
+
+    fn visit_number(&mut self, _ast: &Ast, _number: &NumberExpr) {}
+
+    fn visit_float(&mut self, _ast: &Ast, _number: &FloatExpr) {}
+
+    fn visit_string(&mut self, _ast: &Ast, _string: &StringExpr) {}
+
+    fn visit_variable_expression(&mut self, _ast: &Ast, variable_expr: &VariableExpr) {
+        if let Some(variable_id) = variable_expr.variable_id {
+            *self.reads.get_mut(variable_id) += 1;
+        }
+    }
+
+    fn visit_call_expression(&mut self, ast: &Ast, call_expr: &CallExpr) {
+        for argument in call_expr.arguments.iter() {
+            self.visit_expression(ast, *argument);
+        }
+        let Some(callee) = ast.resolve_call(call_expr) else {
+            return;
+        };
+        match self.function {
+            Some(caller) => self.calls.get_mut(caller).push(callee),
+            None => self.roots.push(callee),
+        }
+    }
+}
@@ -1,13 +1,22 @@
-use std::{borrow::{Borrow, BorrowMut}, vec};
-
-use evaluator::ExpressionEvaluator;
-use lexer::{Token, TokenKind};
+use std::{fmt::{Display, Formatter}, sync::Arc};
+
+use evaluator::{Evaluation, ExpressionEvaluator};
+use dot_printer::DotPrinter;
+use formatter::SourceFormatter;
+use serializer::{JsonSerializer, SexprSerializer};
+use global_scope::GlobalScope;
+use lexer::{unescape, Lexer, Token};
+use liveness::Liveness;
+use parser::Parser;
+use text::{FileId, SourceFile, TextSpan};
 use lib::{Id, IdVec};
 use printer::Printer;
-use text::TextSpan;
+use subexpressions::ExpressionTable;
+use syntax::{GreenNode, SyntaxKind, SyntaxNode};
+use type_checker::TypeChecker;
 use visitor::Visitor;
 
-use crate::id_gen;
+use crate::{diagnostics::DiagnosticsBagCell, id_gen};
 
 pub mod lexer;
 pub mod text;
@@ -17,6 +26,23 @@ pub mod visitor;
 pub mod printer;
 pub mod evaluator;
 pub mod global_scope;
+pub mod formatter;
+pub mod serializer;
+pub mod dot_printer;
+pub mod value;
+pub mod builtins;
+pub mod types;
+pub mod type_checker;
+pub mod symbols;
+pub mod incremental;
+pub mod syntax;
+pub mod syntax_view;
+pub mod rename;
+pub mod completion;
+pub mod modules;
+pub mod image;
+pub mod liveness;
+pub mod subexpressions;
 
 
 id_gen!(ItemId);
@@ -24,19 +50,36 @@ id_gen!(StmtId);
 id_gen!(ExprId);
 id_gen!(VariableId);
 id_gen!(FunctionId);
-
+id_gen!(ImportId);
+
+/// The nodes of a parsed file, each kind in its own arena. `top_level`
+/// lists the items of the file in source order; after an incremental
+/// reparse the arenas also hold the replaced nodes, which nothing refers to.
+///
+/// A program of several files is parsed into one `Ast`, each file after
+/// the modules it imports, so `top_level` runs through the files in that
+/// order. `modules` then has an entry per file; an `Ast` of a single file
+/// parsed on its own has none, and leaves its imports unresolved.
 #[derive(Debug)]
 pub struct Ast{
    pub items: IdVec<ItemId,Item>,
+   pub top_level: Vec<ItemId>,
    pub statements: IdVec<StmtId,Statement>,
    pub expressions: IdVec<ExprId,Expression>,
-   pub functions: IdVec<FunctionId,Function>
+   pub functions: IdVec<FunctionId,Function>,
+   pub variables: IdVec<VariableId,Variable>,
+   pub imports: IdVec<ImportId,Import>,
+   pub modules: Vec<Module>,
+   pub eof: Option<Token>,
+   /// The lossless syntax tree of the file, every token and all trivia.
+   pub syntax_tree: Arc<GreenNode>
 }
 
 #[derive(Debug,Clone,Copy)]
 pub enum ItemKind{
     Statement(StmtId),
-    Function(FunctionId)
+    Function(FunctionId),
+    Import(ImportId)
 }
 #[derive(Debug,Clone,Copy)]
 pub struct Item{
@@ -52,51 +95,178 @@ impl Item{
 }
 #[derive(Debug)]
 pub struct Function{
+   pub function_keyword:Token,
+   pub identifier:Token,
    pub name:String,
    pub open_paren:Token,
    pub parameters:Vec<Parameter>,
    pub close_paren:Token,
-   //pub body:Body,
+   pub return_type:Option<TypeAnnotation>,
+   pub body:Body,
 }
 impl Function{
-    pub fn new( name:String,open_paren:Token,close_paren:Token,parameters:Vec<Parameter>)->Self{
+    pub fn new(function_keyword:Token,identifier:Token,open_paren:Token,close_paren:Token,parameters:Vec<Parameter>,return_type:Option<TypeAnnotation>,body:Body)->Self{
         Self {
-             name,
+             function_keyword,
+             name: identifier.span.literal.clone(),
+             identifier,
               open_paren,
-               parameters: parameters,
+               parameters,
                close_paren,
-                // body: body,
+               return_type,
+                body,
              }
 }
+
+    pub fn span(&self)->TextSpan{
+        TextSpan::combine(vec![self.function_keyword.span.clone(),self.body.close_brace.span.clone()])
+    }
+}
+/// `import "geometry.calc"`, or `use geometry::area`, which imports the
+/// module and brings one of its names into scope. `module` is the file the
+/// import loaded, if any.
+#[derive(Debug,Clone)]
+pub struct Import{
+    pub keyword:Token,
+    pub kind:ImportKind,
+    pub module:Option<FileId>
+}
+
+#[derive(Debug,Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum ImportKind{
+    File{path:Token},
+    Name{module:Token,colons:Token,name:Token}
+}
+
+impl Import{
+    /// The path of the imported file, relative to the importing one.
+    pub fn path(&self)->String{
+        match &self.kind{
+            ImportKind::File{path}=>unescape(&path.span.literal),
+            ImportKind::Name{module,..}=>modules::module_path(&module.span.literal),
+        }
+    }
+
+    /// The import as written, without comments: `import "geometry.calc"`
+    /// or `use geometry::area`.
+    pub fn text(&self)->String{
+        match &self.kind{
+            ImportKind::File{path}=>format!("import {}",path.span.literal),
+            ImportKind::Name{module,name,..}=>format!("use {}::{}",module.span.literal,name.span.literal),
+        }
+    }
+
+    pub fn span(&self)->TextSpan{
+        let last = match &self.kind{
+            ImportKind::File{path}=>path,
+            ImportKind::Name{name,..}=>name,
+        };
+        TextSpan::combine(vec![self.keyword.span.clone(),last.span.clone()])
+    }
+}
+
+/// A source file of a program. `name` is the file name without its
+/// extension, which qualifies the names of the module where it is imported.
+#[derive(Debug,Clone)]
+pub struct Module{
+    pub name:String,
+    pub file_id:FileId,
+    pub imports:Vec<ModuleImport>,
+    /// The functions of other modules that `use` brought into scope.
+    pub used_functions:Vec<(String,FunctionId)>
+}
+
+/// A module that a file imports. `file_id` is `None` when it could not be
+/// loaded or would close an import cycle, which has been reported.
+#[derive(Debug,Clone)]
+pub struct ModuleImport{
+    pub path:String,
+    pub name:String,
+    pub file_id:Option<FileId>
 }
+
+/// The `geometry::` in front of a name from another module.
+#[derive(Debug,Clone)]
+pub struct Qualifier{
+    pub module:Token,
+    pub colons:Token
+}
+
+impl Qualifier{
+    pub fn name(&self)->&str{
+        &self.module.span.literal
+    }
+}
+
 #[derive(Debug,Clone)]
 pub struct Parameter{
     pub identifier:Token,
-    pub value:Option<i64>
+    pub type_annotation:Option<TypeAnnotation>,
+    pub variable_id:VariableId
 
 }
 
 impl Parameter{
-    pub fn new(identifier:Token)->Self{
-        Self {identifier,value:None }
+    pub fn new(identifier:Token,variable_id:VariableId)->Self{
+        Self {identifier,type_annotation:None,variable_id }
     }
 }
+
+/// A written type: `: int` after a `let` name or a parameter, or `-> float`
+/// after the parameters of a function. `separator` is the `:` or `->`.
+#[derive(Debug,Clone)]
+pub struct TypeAnnotation{
+    pub separator:Token,
+    pub name:Token
+}
+
+impl TypeAnnotation{
+    pub fn new(separator:Token,name:Token)->Self{
+        Self { separator, name }
+    }
+
+    pub fn type_name(&self)->&str{
+        &self.name.span.literal
+    }
+
+    pub fn span(&self)->TextSpan{
+        TextSpan::combine(vec![self.separator.span.clone(),self.name.span.clone()])
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum VariableKind{
+    Global,
+    Local,
+    Parameter
+}
+
+/// A declared variable: a `let` binding or a function parameter.
+#[derive(Debug,Clone)]
+pub struct Variable{
+    pub id:VariableId,
+    pub name:String,
+    pub identifier:Token,
+    pub kind:VariableKind
+}
 #[derive(Debug,Clone)]
 pub struct Body{
    pub  open_brace:Token,
    pub  statements:Vec<StmtId>,
-   pub return_value: Option<i64>,
    pub close_brace:Token
 }
 
 impl Body{
     pub fn new(open_brace:Token,statements:Vec<StmtId>,close_brace:Token)->Self{
-       Self { open_brace, statements, return_value: None, close_brace }
+       Self { open_brace, statements, close_brace }
     }
 }
 #[derive(Debug,Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum StatementKind{
     Let(LetStatement),
+    Return(ReturnStatement),
     Expression(ExprId)
    
 }
@@ -111,14 +281,36 @@ impl Statement{
     Self { stmt_kind: kind, id }
   }
 
+  pub fn span(&self,ast:&Ast)->TextSpan{
+    match &self.stmt_kind{
+        StatementKind::Let(let_stmt)=>TextSpan::combine(vec![
+            let_stmt.let_keyword.span.clone(),
+            ast.query_expr(let_stmt.initializer).span(ast),
+        ]),
+        StatementKind::Return(return_stmt)=>TextSpan::combine(vec![
+            return_stmt.return_keyword.span.clone(),
+            ast.query_expr(return_stmt.value).span(ast),
+        ]),
+        StatementKind::Expression(expr_id)=>ast.query_expr(*expr_id).span(ast),
+    }
+  }
+
 }
 
 #[derive(Debug,Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum ExpressionKind{
     Number(NumberExpr),
+    Float(FloatExpr),
+    String(StringExpr),
+    Variable(VariableExpr),
+    Call(CallExpr),
     Binary(BinaryExpr),
     Parenthesized(ParenthesizedExpr),
-    Assignment(AssignExpr)
+    Assignment(AssignExpr),
+    /// Stands in for an expression the parser could not make out. `token`
+    /// is the token it skipped, or an empty one when it skipped none.
+    Error(Token)
 }
 #[derive(Debug,Clone)]
 pub struct NumberExpr{
@@ -127,6 +319,67 @@ pub struct NumberExpr{
 
 }
 #[derive(Debug,Clone)]
+pub struct FloatExpr{
+    pub number:f64,
+    pub token:Token
+}
+#[derive(Debug,Clone)]
+pub struct StringExpr{
+    pub value:String,
+    pub token:Token
+}
+/// A use of a variable. `variable_id` is `None` when no variable of that
+/// name is in scope, which the parser reports.
+#[derive(Debug,Clone)]
+pub struct VariableExpr{
+    pub qualifier:Option<Qualifier>,
+    pub identifier:Token,
+    pub variable_id:Option<VariableId>
+}
+impl VariableExpr{
+    pub fn name(&self)->&str{
+        &self.identifier.span.literal
+    }
+
+    /// The name with its module, as `geometry::pi`.
+    pub fn qualified_name(&self)->String{
+        qualified_name(&self.qualifier, self.name())
+    }
+}
+
+fn qualified_name(qualifier:&Option<Qualifier>,name:&str)->String{
+    match qualifier{
+        Some(qualifier)=>format!("{}::{}",qualifier.name(),name),
+        None=>name.to_string(),
+    }
+}
+#[derive(Debug,Clone)]
+pub struct CallExpr{
+    pub qualifier:Option<Qualifier>,
+    pub callee:Token,
+    pub open_paren:Token,
+    pub arguments:Vec<ExprId>,
+    pub commas:Vec<Token>,
+    pub close_paren:Token
+}
+impl CallExpr{
+    pub fn name(&self)->&str{
+        &self.callee.span.literal
+    }
+
+    pub fn qualified_name(&self)->String{
+        qualified_name(&self.qualifier, self.name())
+    }
+
+    /// The module name of a qualified call, the callee otherwise.
+    pub fn first_token(&self)->&Token{
+        match &self.qualifier{
+            Some(qualifier)=>&qualifier.module,
+            None=>&self.callee,
+        }
+    }
+}
+#[derive(Debug,Clone)]
 pub struct Expression{
     pub kind:ExpressionKind,
     pub id: ExprId
@@ -136,6 +389,40 @@ impl Expression{
     pub fn new(kind:ExpressionKind,id:ExprId)->Self{
         Self { kind , id }
     }
+
+    pub fn span(&self,ast:&Ast)->TextSpan{
+        match &self.kind{
+            ExpressionKind::Number(number)=>number.token.span.clone(),
+            ExpressionKind::Float(number)=>number.token.span.clone(),
+            ExpressionKind::String(string)=>string.token.span.clone(),
+            ExpressionKind::Variable(variable_expr)=>match &variable_expr.qualifier{
+                Some(qualifier)=>TextSpan::combine(vec![
+                    qualifier.module.span.clone(),
+                    variable_expr.identifier.span.clone(),
+                ]),
+                None=>variable_expr.identifier.span.clone(),
+            },
+            ExpressionKind::Call(call_expr)=>TextSpan::combine(vec![
+                call_expr.first_token().span.clone(),
+                call_expr.close_paren.span.clone(),
+            ]),
+            ExpressionKind::Binary(bin_expr)=>TextSpan::combine(vec![
+                ast.query_expr(bin_expr.left).span(ast),
+                bin_expr.operator.token.span.clone(),
+                ast.query_expr(bin_expr.right).span(ast),
+            ]),
+            ExpressionKind::Parenthesized(parenthesized_expr)=>TextSpan::combine(vec![
+                parenthesized_expr.open_paren.span.clone(),
+                ast.query_expr(parenthesized_expr.expr).span(ast),
+                parenthesized_expr.close_paren.span.clone(),
+            ]),
+            ExpressionKind::Error(token)=>token.span.clone(),
+            ExpressionKind::Assignment(assign_expr)=>TextSpan::combine(vec![
+                assign_expr.let_keyword.span.clone(),
+                ast.query_expr(assign_expr.expr).span(ast),
+            ]),
+        }
+    }
 }
 
 #[derive(Debug,Clone)]
@@ -191,7 +478,7 @@ impl BinOperator{
         Self { kind, token }
     }
 
-    pub fn precedence(&mut self)->u8{
+    pub fn precedence(&self)->u8{
         return match self.kind{
             BinOperatorKind::Multiply => 19,
             BinOperatorKind::Divide => 19,
@@ -206,34 +493,82 @@ impl BinOperator{
         return BinOperatorAssiciativity::Left
     }
 }
-#[derive(Debug,Clone,Copy,PartialEq, Eq)]
+#[derive(Debug,Clone,Copy,PartialEq, Eq, Hash)]
 pub enum BinOperatorKind{
     Plus,
     Minus,
     Multiply,
     Divide
 }
+
+impl Display for BinOperatorKind{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BinOperatorKind::Plus => write!(f, "+"),
+            BinOperatorKind::Minus => write!(f, "-"),
+            BinOperatorKind::Multiply => write!(f, "*"),
+            BinOperatorKind::Divide => write!(f, "/"),
+        }
+    }
+}
 #[derive(Debug,Clone)]
 pub struct LetStatement{
-    pub let_keyword:TokenKind,
+    pub let_keyword:Token,
     pub identifier:Token,
+    pub type_annotation:Option<TypeAnnotation>,
+    pub equals:Token,
     pub initializer:ExprId,
     pub  variable_id:VariableId
 
 }
 
+#[derive(Debug,Clone)]
+pub struct ReturnStatement{
+    pub return_keyword:Token,
+    pub value:ExprId
+}
+
+impl Default for Ast{
+    fn default()->Self{
+        Self::new()
+    }
+}
+
 impl Ast{
 
+    /// Lexes and parses `source`, reporting syntax errors to `diagnostics`.
+    pub fn parse(source:&SourceFile,diagnostics:DiagnosticsBagCell)->Self{
+        let lexer = Lexer::new(&source.text, source.id, diagnostics.clone());
+        let mut global_scope = GlobalScope::new();
+        let mut ast = Ast::new();
+        let mut parser = Parser::new(lexer, &mut ast, &mut global_scope, diagnostics);
+        parser.parse();
+        return ast;
+    }
+
     pub fn new()->Self{
         Self { 
              items: IdVec::new(),
+             top_level: Vec::new(),
              statements: IdVec::new(), 
              expressions: IdVec::new(),
-             functions:IdVec::new()
+             functions:IdVec::new(),
+             variables:IdVec::new(),
+             imports:IdVec::new(),
+             modules:Vec::new(),
+             eof: None,
+             syntax_tree: Arc::new(GreenNode::new(SyntaxKind::File, Vec::new()))
             }
     }
+    /// The root of the syntax tree, whose text is the source text.
+    pub fn syntax(&self)->SyntaxNode{
+        return SyntaxNode::new_root(self.syntax_tree.clone());
+    }
     pub fn query_item(&self,item_id:ItemId)->&Item{
         return self.items.get(item_id);
+    }
+    pub fn top_level_items(&self)->impl Iterator<Item = &Item>{
+        return self.top_level.iter().map(|item_id| self.items.get(*item_id));
     }
      pub fn query_stmt(&self,stmt_id: StmtId)->&Statement{
         return self.statements.get(stmt_id);
@@ -248,6 +583,7 @@ impl Ast{
         let item = Item::new(kind, ItemId::new(0));
         let id = self.items.push(item);
          self.items.get_mut(id).id = id;
+         self.top_level.push(id);
 
          return self.items.get(id);
 
@@ -257,6 +593,14 @@ impl Ast{
         let new_item = Item::new(item_kind, ItemId::new(0));
         let item_id = self.items.push(new_item);
         self.items.get_mut(item_id).id = item_id;
+        self.top_level.push(item_id);
+        return self.items.get(item_id);
+    }
+    pub fn item_from_import(&mut self,import:Import)->&Item{
+        let import_id = self.imports.push(import);
+        let item_id = self.items.push(Item::new(ItemKind::Import(import_id), ItemId::new(0)));
+        self.items.get_mut(item_id).id = item_id;
+        self.top_level.push(item_id);
         return self.items.get(item_id);
     }
     pub fn stmt_from_stmt_kind(&mut self,kind:StatementKind)->&Statement{
@@ -266,15 +610,96 @@ impl Ast{
 
     return self.statements.get(id);
     }
-    pub fn save_function(&mut self,name:String,open_paren:Token,close_paren:Token,parameters:Vec<Parameter>)->FunctionId{
-        let function = Function::new(name, open_paren, close_paren, parameters);
-        let function_id = self.functions.push(function);
-        print!("function id:{:?}",function_id);
-        return function_id;
+    pub fn save_function(&mut self,function:Function)->FunctionId{
+        return self.functions.push(function);
+    }
 
-        
-        
+    pub fn query_function(&self,function_id:FunctionId)->&Function{
+        return self.functions.get(function_id);
+    }
 
+    pub fn query_variable(&self,variable_id:VariableId)->&Variable{
+        return self.variables.get(variable_id);
+    }
+
+    pub fn function_by_name(&self,name:&str)->Option<FunctionId>{
+        return self.top_level_items().find_map(|item| match item.kind{
+            ItemKind::Function(function_id) if self.functions.get(function_id).name == name=>Some(function_id),
+            _=>None,
+        });
+    }
+
+    pub fn query_import(&self,import_id:ImportId)->&Import{
+        return self.imports.get(import_id);
+    }
+
+    pub fn module(&self,file_id:FileId)->Option<&Module>{
+        return self.modules.iter().find(|module| module.file_id == file_id);
+    }
+
+    /// The file that `name` stands for in qualified names of `file_id`.
+    pub fn imported_module(&self,file_id:FileId,name:&str)->Option<FileId>{
+        return self.module(file_id)?.imports.iter().find(|import| import.name == name)?.file_id;
+    }
+
+    /// The function `name` declared in the file `file_id`.
+    pub fn function_in(&self,file_id:FileId,name:&str)->Option<FunctionId>{
+        return self.top_level_items().find_map(|item| match item.kind{
+            ItemKind::Function(function_id) => {
+                let function = self.functions.get(function_id);
+                (function.name == name && function.function_keyword.span.file_id == file_id).then_some(function_id)
+            }
+            _=>None,
+        });
+    }
+
+    /// The last global `let` of `name` in the file `file_id`, which holds
+    /// its value once the file has run.
+    /// Whether `file_id` is the main file of the program: the one loaded
+    /// last, or the only one.
+    pub fn is_main_file(&self,file_id:FileId)->bool{
+        return self.modules.last().is_none_or(|module| module.file_id == file_id);
+    }
+
+    /// Whether the value of the top-level statement `stmt_id` is one of the
+    /// results of the program, which `run` prints. A `let` of a name that
+    /// starts with `_`, such as a temporary of `cse`, is not.
+    pub fn is_result(&self,stmt_id:StmtId)->bool{
+        return match &self.statements.get(stmt_id).stmt_kind{
+            StatementKind::Let(let_stmt) => !let_stmt.identifier.span.literal.starts_with('_'),
+            _=>true,
+        };
+    }
+
+    pub fn global_in(&self,file_id:FileId,name:&str)->Option<VariableId>{
+        return self.top_level_items().filter_map(|item| match item.kind{
+            ItemKind::Statement(stmt_id) => match &self.statements.get(stmt_id).stmt_kind{
+                StatementKind::Let(let_stmt) if let_stmt.identifier.span.literal == name && let_stmt.let_keyword.span.file_id == file_id => Some(let_stmt.variable_id),
+                _=>None,
+            },
+            _=>None,
+        }).last();
+    }
+
+    /// The function a call refers to: for `geometry::area(..)` the `area`
+    /// of the imported module, otherwise the function of that name in the
+    /// calling file or one that file brought in with `use`.
+    pub fn resolve_call(&self,call_expr:&CallExpr)->Option<FunctionId>{
+        let file_id = call_expr.callee.span.file_id;
+        if let Some(qualifier) = &call_expr.qualifier{
+            let module = self.imported_module(file_id, qualifier.name())?;
+            return self.function_in(module, call_expr.name());
+        }
+        return self.function_in(file_id, call_expr.name()).or_else(|| {
+            self.module(file_id)?.used_functions.iter().find(|(name,_)| name == call_expr.name()).map(|(_,function_id)| *function_id)
+        });
+    }
+
+    pub fn save_variable(&mut self,identifier:Token,kind:VariableKind)->VariableId{
+        let variable = Variable{ id:VariableId::new(0), name:identifier.span.literal.clone(), identifier, kind };
+        let variable_id = self.variables.push(variable);
+        self.variables.get_mut(variable_id).id = variable_id;
+        return variable_id;
     }
 
     pub fn save_expression_statement(&mut self,expr_id:ExprId)->&Statement{
@@ -291,21 +716,31 @@ impl Ast{
 
     pub fn save_let_statement(
         &mut self,
+    let_keyword:Token,
     identifier:Token,
-    initializer:ExprId
+    type_annotation:Option<TypeAnnotation>,
+    equals:Token,
+    initializer:ExprId,
+    variable_id:VariableId
     )->&Statement{
        self.stmt_from_stmt_kind(StatementKind::Let(LetStatement {
-         let_keyword: TokenKind::Let,
+         let_keyword,
           identifier,
+          type_annotation,
+          equals,
           initializer,
-           variable_id:VariableId::new(0)}))
+           variable_id}))
+    }
+
+    pub fn save_return_statement(&mut self,return_keyword:Token,value:ExprId)->&Statement{
+       self.stmt_from_stmt_kind(StatementKind::Return(ReturnStatement { return_keyword, value }))
     }
 
     pub fn expr_from_kind(&mut self,kind:ExpressionKind)->&Expression{
         let expression = Expression::new(kind, ExprId::new(0));
         let expr_id = self.expressions.push(expression);
         self.expressions.get_mut(expr_id).id = expr_id;
-        return &self.expressions.get(expr_id);
+        return self.expressions.get(expr_id);
     }
 
     pub fn save_number_expression(&mut self,token:Token,number:i64)->&Expression{
@@ -313,6 +748,26 @@ impl Ast{
         
     }
 
+    pub fn save_variable_expression(&mut self,qualifier:Option<Qualifier>,identifier:Token,variable_id:Option<VariableId>)->&Expression{
+       return self.expr_from_kind(ExpressionKind::Variable(VariableExpr{ qualifier, identifier, variable_id }));
+    }
+
+    pub fn save_float_expression(&mut self,token:Token,number:f64)->&Expression{
+       return self.expr_from_kind(ExpressionKind::Float(FloatExpr{ number,token}));
+    }
+
+    pub fn save_string_expression(&mut self,token:Token,value:String)->&Expression{
+       return self.expr_from_kind(ExpressionKind::String(StringExpr{ value,token}));
+    }
+
+    pub fn save_call_expression(&mut self,qualifier:Option<Qualifier>,callee:Token,open_paren:Token,arguments:Vec<ExprId>,commas:Vec<Token>,close_paren:Token)->&Expression{
+        return self.expr_from_kind(ExpressionKind::Call(CallExpr{ qualifier, callee, open_paren, arguments, commas, close_paren }));
+    }
+
+    pub fn save_error_expression(&mut self,token:Token)->&Expression{
+        return self.expr_from_kind(ExpressionKind::Error(token));
+    }
+
     pub fn save_binary_expression(&mut self,operator:BinOperator,left:ExprId,right:ExprId)->&Expression{
         return self.expr_from_kind(ExpressionKind::Binary(BinaryExpr { left, operator, right }))
     }
@@ -323,34 +778,63 @@ impl Ast{
 
     }
 
-    pub fn visit(&mut self,visitor:&mut dyn Visitor){
-       for item in self.items.iter(){
-        visitor.visit_item(self,item.id);
+    pub fn visit(&self,visitor:&mut dyn Visitor){
+       for item_id in self.top_level.iter(){
+        visitor.visit_item(self,*item_id);
        }
      
     }
-    pub fn visualize(&mut self){
+    pub fn visualize(&self){
         let mut printer = Printer::new(0);
         self.visit(&mut printer);
 
     }
-    pub fn evaluate(&mut self){
-        let mut evaluator = ExpressionEvaluator::new();
-        self.visit(&mut evaluator);
-        if let Some(result) = evaluator.result{
-        println!("{}{}"," ".repeat(10),".".repeat(90));
-        println!("{}"," ".repeat(20));
-
-
-        println!("{} Answer: {:?}"," ".repeat(50),result);
-        
-        println!("{}"," ".repeat(20));
-
-        println!("{}{}"," ".repeat(10),".".repeat(90));
+    pub fn format(&self)->String{
+        let mut formatter = SourceFormatter::new();
+        self.visit(&mut formatter);
+        formatter.finish(self);
+        return formatter.output;
+    }
+    pub fn to_dot(&self)->String{
+        let mut printer = DotPrinter::new();
+        self.visit(&mut printer);
+        return printer.finish();
+    }
+    pub fn to_json(&self)->String{
+        let mut serializer = JsonSerializer::new();
+        self.visit(&mut serializer);
+        return serializer.to_json().to_string();
+    }
+    pub fn to_sexpr(&self)->String{
+        let mut serializer = SexprSerializer::new();
+        self.visit(&mut serializer);
+        return serializer.output;
+    }
+    pub fn type_check(&self,diagnostics:DiagnosticsBagCell)->TypeChecker{
+        let mut checker = TypeChecker::new(self, diagnostics);
+        self.visit(&mut checker);
+        checker.finish();
+        return checker;
+    }
+    /// Warns about the variables, parameters and functions nothing uses.
+    /// A program with errors gets no warnings: what did not resolve would
+    /// leave its names looking unused.
+    pub fn check_liveness(&self,diagnostics:DiagnosticsBagCell)->Liveness{
+        let liveness = Liveness::new(self);
+        if !diagnostics.borrow().has_errors(){
+            liveness.report(self, diagnostics);
         }
-       
-       
-
-
+        return liveness;
     }
-}
\ No newline at end of file
+    /// Groups the expressions by structure; see `ExpressionTable`.
+    pub fn hash_expressions(&self)->ExpressionTable{
+        return ExpressionTable::new(self);
+    }
+    /// Runs the program and returns the value of each top-level statement.
+    pub fn evaluate(&self,evaluator:&mut ExpressionEvaluator)->Evaluation{
+        for item_id in self.top_level.iter(){
+            evaluator.evaluate_item(self,*item_id);
+        }
+        return evaluator.take_evaluation();
+    }
+}
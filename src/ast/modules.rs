@@ -0,0 +1,310 @@
+//! Programs spread over several files. A file imports another with
+//! `import "geometry.calc"` and then names what it declares as
+//! `geometry::area`, or brings a single name into scope with
+//! `use geometry::area`, which imports `geometry.calc` as well.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::diagnostics::{DiagnosticsBag, DiagnosticsBagCell};
+
+use super::{
+    global_scope::GlobalScope,
+    lexer::{unescape, Lexer, TokenKind},
+    parser::Parser,
+    lib::Id,
+    text::{FileId, SourceFile, SourceMap, TextSpan},
+    Ast, Module, ModuleImport,
+};
+
+/// The extension of source files, which `use geometry::area` adds to the
+/// module name to find its file.
+pub const MODULE_EXTENSION: &str = "calc";
+
+/// The file that `use name::..` imports.
+pub fn module_path(name: &str) -> String {
+    format!("{}.{}", name, MODULE_EXTENSION)
+}
+
+/// The name a module is qualified by: its file name without the extension.
+pub fn module_name(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// The directory that the imports of the program whose main file is at
+/// `main` are confined to: the directory of that file.
+pub fn import_root(main: &str) -> String {
+    normalize(Path::new(main).parent().unwrap_or(Path::new(""))).to_string_lossy().into_owned()
+}
+
+/// `path` as imported from the file `from`: relative to the directory of
+/// `from`, with `.` and `..` worked out, so that every import of a file
+/// ends up with the same name. Fails if `path` is absolute or leads out of
+/// `root`, so a program only reads the files under its `import_root`.
+pub fn resolve_path(root: &str, from: &str, path: &str) -> Result<String, String> {
+    if Path::new(path).has_root() || Path::new(path).is_absolute() {
+        return Err(String::from("imports must be relative paths"));
+    }
+    let resolved = normalize(&Path::new(from).parent().unwrap_or(Path::new("")).join(path));
+    match resolved.strip_prefix(root) {
+        Ok(inside) if inside.components().all(|component| matches!(component, Component::Normal(_))) => {
+            Ok(resolved.to_string_lossy().into_owned())
+        }
+        _ => Err(String::from("it is outside the directory of the main file")),
+    }
+}
+
+/// `path` with `.` and `..` worked out as far as they go.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Reads the text of modules for a `Program`.
+pub trait ModuleLoader {
+    /// The text of the file at `path`, or why it cannot be read.
+    fn read(&mut self, path: &str) -> Result<String, String>;
+}
+
+/// Reads modules from the file system.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileLoader;
+
+impl ModuleLoader for FileLoader {
+    fn read(&mut self, path: &str) -> Result<String, String> {
+        fs::read_to_string(path).map_err(|error| error.to_string())
+    }
+}
+
+/// Modules held in memory, by path.
+impl ModuleLoader for HashMap<String, String> {
+    fn read(&mut self, path: &str) -> Result<String, String> {
+        self.get(path).cloned().ok_or_else(|| String::from("no such file"))
+    }
+}
+
+/// The files of a program as they were when it was read: each path with
+/// its text, or why it could not be read. Reading the imports only takes
+/// lexing, so a snapshot is cheap to make before deciding whether to
+/// compile, and loading the program from it compiles the same text.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceSnapshot {
+    pub files: Vec<(String, Result<String, String>)>,
+}
+
+impl SourceSnapshot {
+    /// Reads the file at `path` and everything it imports, in the order
+    /// they are found, leaving out the imports that `resolve_path` refuses.
+    /// Fails only if the main file cannot be read.
+    pub fn read(path: &str, loader: &mut dyn ModuleLoader) -> Result<Self, String> {
+        let text = loader.read(path)?;
+        let root = import_root(path);
+        let mut snapshot = Self {
+            files: vec![(path.to_string(), Ok(text))],
+        };
+        let mut next = 0;
+        while let Some((from, text)) = snapshot.files.get(next).cloned() {
+            next += 1;
+            let Ok(text) = text else {
+                continue;
+            };
+            let source = SourceFile::new(FileId::new(0), from.clone(), text);
+            for (path, _, _) in scan_imports(&source) {
+                let Ok(resolved) = resolve_path(&root, &from, &path) else {
+                    continue;
+                };
+                if snapshot.files.iter().all(|(known, _)| *known != resolved) {
+                    let text = loader.read(&resolved);
+                    snapshot.files.push((resolved, text));
+                }
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// Replaces the text of the file at `path`, as if it had been read so.
+    pub fn set_text(&mut self, path: &str, text: String) {
+        match self.files.iter_mut().find(|(known, _)| known == path) {
+            Some((_, known_text)) => *known_text = Ok(text),
+            None => self.files.push((path.to_string(), Ok(text))),
+        }
+    }
+}
+
+impl ModuleLoader for SourceSnapshot {
+    fn read(&mut self, path: &str) -> Result<String, String> {
+        match self.files.iter().find(|(known, _)| known == path) {
+            Some((_, text)) => text.clone(),
+            None => Err(String::from("no such file")),
+        }
+    }
+}
+
+/// A main file and every module it imports, directly or not, parsed into
+/// one `Ast`. Each module is parsed after the modules it imports, with its
+/// own global scope, so a module runs after its imports and only sees
+/// their names through `use` and qualified names.
+pub struct Program {
+    pub sources: SourceMap,
+    pub ast: Ast,
+    pub main: FileId,
+}
+
+impl Program {
+    /// Loads the file at `path` and its imports through `loader`. Imports
+    /// that cannot be loaded, that lead out of the directory of the main
+    /// file or that would close a cycle are reported to `diagnostics` at
+    /// the import. Fails only if the main file cannot be read.
+    pub fn load(path: &str, loader: &mut dyn ModuleLoader, diagnostics: DiagnosticsBagCell) -> Result<Self, String> {
+        let text = loader.read(path)?;
+        let mut sources = SourceMap::new();
+        let main = sources.add_file(path.to_string(), text);
+        let mut loading = Loading {
+            loader,
+            root: import_root(path),
+            sources,
+            ast: Ast::new(),
+            stack: Vec::new(),
+            diagnostics,
+        };
+        loading.load(main);
+        Ok(Self {
+            sources: loading.sources,
+            ast: loading.ast,
+            main,
+        })
+    }
+
+    pub fn main_source(&self) -> &SourceFile {
+        self.sources.get(self.main)
+    }
+}
+
+/// The state of `Program::load`. `stack` holds the files being loaded, each
+/// imported by the one before it.
+struct Loading<'a> {
+    loader: &'a mut dyn ModuleLoader,
+    root: String,
+    sources: SourceMap,
+    ast: Ast,
+    stack: Vec<FileId>,
+    diagnostics: DiagnosticsBagCell,
+}
+
+impl Loading<'_> {
+    fn load(&mut self, file_id: FileId) {
+        self.stack.push(file_id);
+        let source = self.sources.get(file_id);
+        let from = source.name.clone();
+        let mut module = Module {
+            name: module_name(&from),
+            file_id,
+            imports: Vec::new(),
+            used_functions: Vec::new(),
+        };
+        let imports = scan_imports(source);
+        for (path, span, is_use) in imports.iter().cloned() {
+            let name = module_name(&path);
+            // `use geometry::area` names a module imported under that name
+            // already, if there is one, before it loads `geometry.calc`.
+            let imported_by_name = |(other, _, other_is_use): &(String, TextSpan, bool)| !other_is_use && module_name(other) == name;
+            let known = if is_use {
+                imports.iter().any(imported_by_name) || module.imports.iter().any(|module_import| module_import.name == name)
+            } else {
+                module.imports.iter().any(|module_import| module_import.path == path)
+            };
+            if known {
+                continue;
+            }
+            let resolved = match resolve_path(&self.root, &from, &path) {
+                Ok(resolved) => resolved,
+                Err(reason) => {
+                    self.diagnostics.borrow_mut().report_module_not_loaded(&path, &reason, span);
+                    module.imports.push(ModuleImport { name, path, file_id: None });
+                    continue;
+                }
+            };
+            let loaded = self.sources.files.iter().find(|file| file.name == resolved).map(|file| file.id);
+            let imported = match loaded {
+                Some(loaded) if self.stack.contains(&loaded) => {
+                    let start = self.stack.iter().position(|file_id| *file_id == loaded).unwrap_or(0);
+                    let cycle: Vec<String> = self.stack[start..]
+                        .iter()
+                        .chain([&loaded])
+                        .map(|file_id| self.sources.get(*file_id).name.clone())
+                        .collect();
+                    self.diagnostics.borrow_mut().report_import_cycle(&cycle, span);
+                    None
+                }
+                Some(loaded) => Some(loaded),
+                None => match self.loader.read(&resolved) {
+                    Ok(text) => {
+                        let imported = self.sources.add_file(resolved, text);
+                        self.load(imported);
+                        Some(imported)
+                    }
+                    Err(reason) => {
+                        self.diagnostics.borrow_mut().report_module_not_loaded(&path, &reason, span);
+                        None
+                    }
+                },
+            };
+            module.imports.push(ModuleImport {
+                name,
+                path,
+                file_id: imported,
+            });
+        }
+        self.ast.modules.push(module);
+
+        let source = self.sources.get(file_id);
+        let lexer = Lexer::new(&source.text, source.id, self.diagnostics.clone());
+        let mut scope = GlobalScope::new();
+        Parser::new(lexer, &mut self.ast, &mut scope, self.diagnostics.clone()).parse();
+        self.stack.pop();
+    }
+}
+
+/// The paths a file imports at its top level, each with the span of the
+/// token naming it and whether it is the module of a `use`. The file is
+/// lexed without reporting errors; parsing it reports them.
+fn scan_imports(source: &SourceFile) -> Vec<(String, TextSpan, bool)> {
+    let tokens: Vec<_> = Lexer::new(&source.text, source.id, DiagnosticsBag::new_cell()).collect();
+    let mut imports = Vec::new();
+    let mut depth = 0usize;
+    for (index, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::OpenBrace => depth += 1,
+            TokenKind::CloseBrace => depth = depth.saturating_sub(1),
+            TokenKind::Import | TokenKind::Use if depth == 0 => {
+                let (Some(next), after) = (tokens.get(index + 1), tokens.get(index + 2)) else {
+                    continue;
+                };
+                match (token.kind, next.kind, after.map(|token| token.kind)) {
+                    (TokenKind::Import, TokenKind::String, _) => imports.push((unescape(&next.span.literal), next.span.clone(), false)),
+                    (TokenKind::Use, TokenKind::Identifier, Some(TokenKind::ColonColon)) => {
+                        imports.push((module_path(&next.span.literal), next.span.clone(), true))
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    imports
+}
@@ -1,143 +1,346 @@
-use std::{borrow::Borrow, vec};
+use std::collections::{HashMap, VecDeque};
 
-use crate::ast::lib::Id;
+use crate::diagnostics::DiagnosticsBagCell;
 
 use super::{
-    global_scope::GlobalScope, lexer::{Token, TokenKind}, Ast, BinOperator, BinOperatorAssiciativity, BinOperatorKind, Body, ExprId, Function, FunctionId, Item, Parameter, Statement, StatementKind, StmtId
+    global_scope::GlobalScope, syntax::{Checkpoint, GreenBuilder, SyntaxKind}, lexer::{unescape, Token, TokenKind}, lib::Id, text::{FileId, TextSpan}, Ast, BinOperator, BinOperatorAssiciativity, BinOperatorKind, Body, ExprId, ExpressionKind, Function, FunctionId, Import, ImportKind, ItemId, Parameter, Qualifier, StatementKind, StmtId, TypeAnnotation, VariableKind
 };
 
-pub struct Parser<'a> {
-    pub tokens: Vec<Token>,
-    pub current: usize,
+/// How deeply expressions may nest, counting every operator, parenthesis
+/// and call between the outermost expression and its innermost operand.
+pub const MAX_EXPRESSION_DEPTH: usize = 256;
+
+/// Pulls tokens from `tokens` as it goes instead of collecting them first,
+/// so only the tokens in `lookahead` are held in memory. `lookahead` always
+/// holds at least the current token, and once the token stream has ended
+/// its last token is an `Eof` that is never consumed.
+///
+/// Syntax errors are reported to `diagnostics` and parsing carries on: a
+/// missing token is made up with an empty span and a missing expression
+/// becomes an `ExpressionKind::Error`, so editors get a tree for half
+/// typed input. `consumed` counts the tokens taken so far, which lets the
+/// loops over items and statements skip a token they cannot parse. Only the
+/// first syntax error at a given token is reported, so one missing token
+/// does not cascade into a list of errors.
+///
+/// Every consumed token also goes into `syntax` with its trivia, so the
+/// syntax tree holds the whole text even where the `Ast` made up or
+/// skipped tokens.
+///
+/// Expressions nested deeper than `MAX_EXPRESSION_DEPTH` are reported and
+/// the rest of them is skipped, so that the passes walking the tree
+/// recursively never meet one deep enough to overflow the stack. `nesting`
+/// counts the expressions being parsed around the current token and
+/// `depths` the height of each expression built so far.
+///
+/// When the file is a module of a program, the modules it imports have
+/// been parsed into `ast` before it, so `use` and qualified names such as
+/// `geometry::area` are resolved as they are parsed. A file parsed on its
+/// own leaves them unresolved without reporting them.
+pub struct Parser<'a, T: Iterator<Item = Token>> {
+    tokens: T,
+    lookahead: VecDeque<Token>,
+    consumed: usize,
+    last_error_at: Option<usize>,
+    nesting: usize,
+    depths: HashMap<ExprId, usize>,
     pub ast: &'a mut Ast,
     pub scope: &'a mut GlobalScope,
+    pub syntax: GreenBuilder,
+    diagnostics: DiagnosticsBagCell,
 }
 
-impl<'a> Parser<'a> {
-    pub fn new(tokens: Vec<Token>, ast: &'a mut Ast, scope: &'a mut GlobalScope) -> Self {
-        Self { 
-            tokens: tokens.into_iter().filter(|token| token.kind != TokenKind::Whitespace).collect(),
-            current: 0,
+impl<'a, T: Iterator<Item = Token>> Parser<'a, T> {
+    pub fn new(tokens: T, ast: &'a mut Ast, scope: &'a mut GlobalScope, diagnostics: DiagnosticsBagCell) -> Self {
+        let mut parser = Self {
+            tokens,
+            lookahead: VecDeque::new(),
+            consumed: 0,
+            last_error_at: None,
+            nesting: 0,
+            depths: HashMap::new(),
             ast,
             scope,
-        }
+            syntax: GreenBuilder::new(),
+            diagnostics,
+        };
+        parser.syntax.start_node(SyntaxKind::File);
+        parser.fill_lookahead(1);
+        parser
+    }
+
+    /// Seeds the position of the last syntax error, for a parser that
+    /// continues after items parsed before.
+    pub fn with_last_error_at(mut self, last_error_at: Option<usize>) -> Self {
+        self.last_error_at = last_error_at;
+        self
+    }
+
+    pub fn last_error_at(&self) -> Option<usize> {
+        self.last_error_at
     }
 
     pub fn parse(&mut self) {
-        self.parse_items();
+        while !self.is_at_end() {
+            self.parse_next_item();
+        }
+        self.finish();
+    }
+
+    /// Records the `Eof` token, which holds the trivia at the end of the
+    /// file, and the syntax tree.
+    pub fn finish(&mut self) {
+        let eof = self.current_token().clone();
+        self.syntax.trivia(&eof.leading_trivia);
+        self.ast.syntax_tree = self.syntax.finish();
+        self.ast.eof = Some(eof);
     }
 
-    fn is_at_end(&self) -> bool {
+    pub fn is_at_end(&self) -> bool {
         self.current_token().kind == TokenKind::Eof
     }
 
-    fn parse_items(&mut self) {
-        while !self.is_at_end() {
-            self.parse_statement();
+    /// Parses one top-level item, skipping a token that cannot start one.
+    pub fn parse_next_item(&mut self) -> ItemId {
+        let consumed = self.consumed;
+        let item_id = self.parse_item();
+        self.skip_if_stuck(consumed);
+        item_id
+    }
+
+    /// Skips the current token when nothing was consumed since `consumed`,
+    /// which happens when a statement starts with a token that cannot begin
+    /// one, such as a stray `)`.
+    fn skip_if_stuck(&mut self, consumed: usize) {
+        if self.consumed == consumed && !self.is_at_end() {
+            self.consume();
         }
     }
 
-    fn parse_statement(&mut self)->Option<StmtId> {
-        let current_token = self.current_token();
-        match current_token.kind {
-            TokenKind::Let => {
-                let stmt_id = self.parse_let_statement();
-                self.ast.item_from_stmt_id(stmt_id);
-                Some(stmt_id)
-            }
+    fn parse_item(&mut self) -> ItemId {
+        match self.current_token().kind {
             TokenKind::Function => {
-                if let Some(function_id) = self.parse_function() {
-                    self.ast.item_from_function_id(function_id);
-                    None
-                } else {
-                    panic!("Failed to parse function statement");
-                }
+                let checkpoint = self.syntax.checkpoint();
+                let function_id = self.parse_function();
+                self.syntax.wrap(checkpoint, SyntaxKind::Function);
+                self.ast.item_from_function_id(function_id).id
+            }
+            TokenKind::Import | TokenKind::Use => {
+                let checkpoint = self.syntax.checkpoint();
+                let import = self.parse_import();
+                self.syntax.wrap(checkpoint, SyntaxKind::Import);
+                self.ast.item_from_import(import).id
             }
             _ => {
-                let expr_id = self.parse_expression();
-                let stmt_id = self.ast.stmt_from_stmt_kind(StatementKind::Expression(expr_id)).id;
-                Some(stmt_id)
+                let stmt_id = self.parse_statement();
+                self.ast.item_from_stmt_id(stmt_id).id
             }
         }
     }
 
+    /// Parses `import "path"` or `use module::name`. A `use` of a global
+    /// variable declares it in this file; a `use` of a function is recorded
+    /// on the module, since calls are resolved after parsing.
+    fn parse_import(&mut self) -> Import {
+        let keyword = self.consume();
+        let kind = if keyword.kind == TokenKind::Import {
+            ImportKind::File { path: self.consume_and_verify_token(TokenKind::String) }
+        } else {
+            let module = self.consume_and_verify_token(TokenKind::Identifier);
+            let colons = self.consume_and_verify_token(TokenKind::ColonColon);
+            let name = self.consume_and_verify_token(TokenKind::Identifier);
+            ImportKind::Name { module, colons, name }
+        };
+        let file_id = keyword.span.file_id;
+        let mut import = Import { keyword, kind, module: None };
+        import.module = match &import.kind {
+            ImportKind::File { .. } => {
+                let path = import.path();
+                let module = self.ast.module(file_id);
+                module.and_then(|module| module.imports.iter().find(|module_import| module_import.path == path))
+            }
+            ImportKind::Name { module, .. } => {
+                let name = &module.span.literal;
+                let importing = self.ast.module(file_id);
+                importing.and_then(|importing| importing.imports.iter().find(|module_import| module_import.name == *name))
+            }
+        }
+        .and_then(|module_import| module_import.file_id);
+        let (ImportKind::Name { module, name, .. }, Some(module_file)) = (&import.kind, import.module) else {
+            return import;
+        };
+        let used = &name.span.literal;
+        if used.is_empty() {
+            return import;
+        }
+        if let Some(variable_id) = self.ast.global_in(module_file, used) {
+            self.scope.declare_variable(used.clone(), variable_id);
+        } else if let Some(function_id) = self.ast.function_in(module_file, used) {
+            if let Some(module) = self.ast.modules.iter_mut().find(|module| module.file_id == file_id) {
+                module.used_functions.push((used.clone(), function_id));
+            }
+        } else {
+            self.diagnostics.borrow_mut().report_not_in_module(&module.span.literal, &name.span);
+        }
+        import
+    }
+
+    fn parse_statement(&mut self)->StmtId {
+        let checkpoint = self.syntax.checkpoint();
+        let current_token = self.current_token();
+        let (stmt_id, kind) = match current_token.kind {
+            TokenKind::Let => (self.parse_let_statement(), SyntaxKind::LetStatement),
+            TokenKind::Return => {
+                let return_keyword = self.consume_and_verify_token(TokenKind::Return);
+                let value = self.parse_expression();
+                (self.ast.save_return_statement(return_keyword, value).id, SyntaxKind::ReturnStatement)
+            }
+            _ => {
+                let expr_id = self.parse_expression();
+                (self.ast.stmt_from_stmt_kind(StatementKind::Expression(expr_id)).id, SyntaxKind::ExpressionStatement)
+            }
+        };
+        self.syntax.wrap(checkpoint, kind);
+        stmt_id
+    }
+
     fn parse_let_statement(&mut self) -> StmtId {
-        self.consume_and_verify_token(TokenKind::Let);
+        let let_keyword = self.consume_and_verify_token(TokenKind::Let);
         let identifier = self.consume_and_verify_token(TokenKind::Identifier);
-        self.consume_and_verify_token(TokenKind::Equals);
+        let type_annotation = self.parse_type_annotation(TokenKind::Colon);
+        let equals = self.consume_and_verify_token(TokenKind::Equals);
         let expr_id = self.parse_expression();
-         let value = self.current_token().span.literal.clone();
-        self.scope.add_global_variable(identifier.span.literal.clone(), value);
-        let stmt = self.ast.save_let_statement(identifier, expr_id);
+        // Declared after the initializer so `let x = x + 1` reads the outer `x`.
+        let kind = if self.scope.is_global() { VariableKind::Global } else { VariableKind::Local };
+        let variable_id = self.ast.save_variable(identifier.clone(), kind);
+        self.scope.declare_variable(identifier.span.literal.clone(), variable_id);
+        let stmt = self.ast.save_let_statement(let_keyword, identifier, type_annotation, equals, expr_id, variable_id);
         stmt.id
     }
 
-    fn parse_function(&mut self) -> Option<FunctionId> {
-        self.consume_and_verify_token(TokenKind::Function);
+    fn parse_function(&mut self) -> FunctionId {
+        let function_keyword = self.consume_and_verify_token(TokenKind::Function);
         let function_name_token = self.consume_and_verify_token(TokenKind::Identifier);
         let open_paren = self.consume_and_verify_token(TokenKind::OpenParen);
 
         let parameters_vec = self.parse_function_parameters().unwrap_or_default();
         let close_paren = self.consume_and_verify_token(TokenKind::CloseParen);
+        let return_type = self.parse_type_annotation(TokenKind::Arrow);
+        let body_checkpoint = self.syntax.checkpoint();
         let open_brace = self.consume_and_verify_token(TokenKind::OpenBrace);
 
+        self.scope.enter_scope();
+        for parameter in parameters_vec.iter() {
+            self.scope.declare_variable(parameter.identifier.span.literal.clone(), parameter.variable_id);
+        }
         let body_vec = self.parse_function_body().unwrap_or_default();
+        self.scope.exit_scope();
         let close_brace = self.consume_and_verify_token(TokenKind::CloseBrace);
+        self.syntax.wrap(body_checkpoint, SyntaxKind::Body);
+
+        // A missing name has been reported already.
+        let name = &function_name_token.span.literal;
+        if !name.is_empty() && self.ast.function_in(function_name_token.span.file_id, name).is_some() {
+            self.diagnostics.borrow_mut().report_function_already_declared(&function_name_token.span);
+        }
 
         let function_body = Body::new(open_brace,body_vec,close_brace);
-        print!("{:?}",function_body);
 
-        let func = self.ast.save_function(function_name_token.span.literal, open_paren,close_paren,parameters_vec);
-        Some(func)
+        let function = Function::new(function_keyword, function_name_token, open_paren,close_paren,parameters_vec,return_type,function_body);
+        self.ast.save_function(function)
     }
 
     fn parse_function_parameters(&mut self) -> Option<Vec<Parameter>> {
-        let mut parameters = Vec::new();
-        while self.current_token().kind != TokenKind::CloseParen {
-            if self.current_token().kind == TokenKind::Comma {
-                self.consume_and_verify_token(TokenKind::Comma);
+        let mut parameters: Vec<Parameter> = Vec::new();
+        while !matches!(self.current_token().kind, TokenKind::CloseParen | TokenKind::OpenBrace | TokenKind::Eof) {
+            if !parameters.is_empty() {
+                let comma = self.consume_and_verify_token(TokenKind::Comma);
+                if let Some(parameter) = parameters.last_mut() {
+                    match &mut parameter.type_annotation {
+                        Some(type_annotation) => type_annotation.name.absorb_trivia(comma),
+                        None => parameter.identifier.absorb_trivia(comma),
+                    }
+                }
             }
+            if self.current_token().kind != TokenKind::Identifier {
+                self.consume_and_verify_token(TokenKind::Identifier);
+                break;
+            }
+            let checkpoint = self.syntax.checkpoint();
             let parameter_token = self.consume_and_verify_token(TokenKind::Identifier);
-            parameters.push(Parameter::new(parameter_token));
+            let variable_id = self.ast.save_variable(parameter_token.clone(), VariableKind::Parameter);
+            let mut parameter = Parameter::new(parameter_token, variable_id);
+            parameter.type_annotation = self.parse_type_annotation(TokenKind::Colon);
+            self.syntax.wrap(checkpoint, SyntaxKind::Parameter);
+            parameters.push(parameter);
         }
         Some(parameters)
     }
 
+    /// Parses `: type` or `-> type` when the current token is `separator`.
+    fn parse_type_annotation(&mut self, separator: TokenKind) -> Option<TypeAnnotation> {
+        if self.current_token().kind != separator {
+            return None;
+        }
+        let checkpoint = self.syntax.checkpoint();
+        let separator = self.consume();
+        let name = self.consume_and_verify_token(TokenKind::Identifier);
+        self.syntax.wrap(checkpoint, SyntaxKind::TypeAnnotation);
+        Some(TypeAnnotation::new(separator, name))
+    }
+
     fn parse_function_body(&mut self) -> Option<Vec<StmtId>> {
         let mut body_vec:Vec<StmtId> = Vec::new();
-        while self.current_token().kind != TokenKind::CloseBrace {
-            let stmt_id = self.parse_statement().unwrap();
+        while self.current_token().kind != TokenKind::CloseBrace && !self.is_at_end() {
+            let consumed = self.consumed;
+            let stmt_id = self.parse_statement();
             body_vec.push(stmt_id);
+            self.skip_if_stuck(consumed);
         }
         Some(body_vec)
     }
 
     fn parse_expression(&mut self) -> ExprId {
-        self.parse_binary_expression()
+        if self.nesting == MAX_EXPRESSION_DEPTH {
+            let token = self.current_token().clone();
+            return self.skip_too_deep_expression(&token);
+        }
+        self.nesting += 1;
+        let expr_id = self.parse_binary_expression();
+        self.nesting -= 1;
+        expr_id
     }
 
     fn parse_binary_expression(&mut self) -> ExprId {
+        let checkpoint = self.syntax.checkpoint();
         let left = self.parse_primary();
-        self.parse_binary_expression_recursive(left, 0)
+        self.parse_binary_expression_recursive(left, checkpoint, 0)
     }
 
-    fn parse_binary_expression_recursive(&mut self, mut left: ExprId, precedence: u8) -> ExprId {
-        while let Some(mut operator) = self.parse_binary_operator() {
+    /// `left_start` is where the syntax of `left` starts, so the binary
+    /// expressions it is the left operand of can be wrapped around it.
+    fn parse_binary_expression_recursive(&mut self, mut left: ExprId, left_start: Checkpoint, precedence: u8) -> ExprId {
+        while let Some(operator) = self.parse_binary_operator() {
             let operator_precedence = operator.precedence();
             if operator_precedence < precedence {
                 break;
             }
-            self.consume();
+            let operator_token = self.consume();
+            let right_start = self.syntax.checkpoint();
             let mut right = self.parse_primary();
-            while let Some(mut inner_operator) = self.parse_binary_operator() {
+            while let Some(inner_operator) = self.parse_binary_operator() {
                 let higher_precedence = inner_operator.precedence() > operator_precedence;
                 let equal_precedence = inner_operator.precedence() == operator_precedence;
-                if !higher_precedence && !(equal_precedence && inner_operator.assicativity() == BinOperatorAssiciativity::Right) {
+                if !(higher_precedence || (equal_precedence && inner_operator.assicativity() == BinOperatorAssiciativity::Right)) {
                     break;
                 }
-                right = self.parse_binary_expression_recursive(right, inner_operator.precedence());
+                right = self.parse_binary_expression_recursive(right, right_start, inner_operator.precedence());
             }
             left = self.ast.save_binary_expression(operator, left, right).id;
+            if !self.within_depth_limit(left) {
+                return self.skip_too_deep_expression(&operator_token);
+            }
+            self.syntax.wrap(left_start, SyntaxKind::BinaryExpr);
         }
         left
     }
@@ -155,41 +358,221 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_primary(&mut self) -> ExprId {
-        let current_token = self.consume().clone();
+        let checkpoint = self.syntax.checkpoint();
+        let (expr_id, kind) = self.parse_primary_kind();
+        self.syntax.wrap(checkpoint, kind);
+        if !self.within_depth_limit(expr_id) {
+            let token = self.current_token().clone();
+            return self.skip_too_deep_expression(&token);
+        }
+        expr_id
+    }
+
+    /// Records the height of `expr_id`, one more than that of its deepest
+    /// operand, and tells whether it is within `MAX_EXPRESSION_DEPTH`.
+    fn within_depth_limit(&mut self, expr_id: ExprId) -> bool {
+        let operands = match &self.ast.query_expr(expr_id).kind {
+            ExpressionKind::Binary(bin_expr) => vec![bin_expr.left, bin_expr.right],
+            ExpressionKind::Parenthesized(paren_expr) => vec![paren_expr.expr],
+            ExpressionKind::Call(call_expr) => call_expr.arguments.clone(),
+            _ => vec![],
+        };
+        let depth = 1 + operands.iter().map(|operand| self.depths.get(operand).copied().unwrap_or(1)).max().unwrap_or(0);
+        self.depths.insert(expr_id, depth);
+        depth <= MAX_EXPRESSION_DEPTH
+    }
+
+    /// Reports an expression nested too deeply at `token` and skips what is
+    /// left of it: everything up to the next token that starts a statement
+    /// or closes a parenthesis or brace opened before it. The expression
+    /// becomes an `ExpressionKind::Error`.
+    fn skip_too_deep_expression(&mut self, token: &Token) -> ExprId {
+        if self.should_report_error_at(token) {
+            self.diagnostics.borrow_mut().report_expression_too_deep(MAX_EXPRESSION_DEPTH, &token.span);
+        }
+        let checkpoint = self.syntax.checkpoint();
+        let mut open_parens = 0;
+        loop {
+            match self.current_token().kind {
+                TokenKind::Eof | TokenKind::CloseBrace | TokenKind::Let | TokenKind::Function | TokenKind::Return | TokenKind::Import | TokenKind::Use => break,
+                TokenKind::CloseParen if open_parens == 0 => break,
+                TokenKind::CloseParen => open_parens -= 1,
+                TokenKind::OpenParen => open_parens += 1,
+                _ => {}
+            }
+            self.consume();
+        }
+        self.syntax.wrap(checkpoint, SyntaxKind::ErrorExpr);
+        let expr_id = self.ast.save_error_expression(token.clone()).id;
+        // It stands in for what was skipped and adds no depth, so the
+        // expressions around it are not reported again.
+        self.depths.insert(expr_id, 0);
+        expr_id
+    }
+
+    /// Parses a primary expression and tells which syntax node it makes.
+    fn parse_primary_kind(&mut self) -> (ExprId, SyntaxKind) {
+        // Tokens that end or start a statement are left for the caller.
+        if matches!(
+            self.current_token().kind,
+            TokenKind::Eof
+                | TokenKind::CloseBrace
+                | TokenKind::CloseParen
+                | TokenKind::Let
+                | TokenKind::Function
+                | TokenKind::Return
+                | TokenKind::Import
+                | TokenKind::Use
+        ) {
+            let token = self.current_token().clone();
+            if self.should_report_error_at(&token) {
+                self.diagnostics.borrow_mut().report_expected_expression(&token);
+            }
+            return (self.ast.save_error_expression(Self::missing_token(&token, TokenKind::Bad)).id, SyntaxKind::ErrorExpr);
+        }
+        let current_token = self.consume();
         match current_token.kind {
-            TokenKind::Number(number) => self.ast.save_number_expression(current_token, number).id,
+            TokenKind::Number(number) => (self.ast.save_number_expression(current_token, number).id, SyntaxKind::NumberExpr),
+            TokenKind::Float => {
+                let number = current_token.span.literal.replace('_', "").parse().unwrap_or_default();
+                (self.ast.save_float_expression(current_token, number).id, SyntaxKind::FloatExpr)
+            }
+            TokenKind::String => {
+                let value = unescape(&current_token.span.literal);
+                (self.ast.save_string_expression(current_token, value).id, SyntaxKind::StringExpr)
+            }
+            TokenKind::Identifier if self.current_token().kind == TokenKind::ColonColon => self.parse_qualified_name(current_token),
+            TokenKind::Identifier if self.current_token().kind == TokenKind::OpenParen => (self.parse_call_expression(None, current_token), SyntaxKind::CallExpr),
+            TokenKind::Identifier => {
+                let variable_id = self.scope.lookup_variable(&current_token.span.literal);
+                if variable_id.is_none() {
+                    self.diagnostics.borrow_mut().report_undefined_variable(&current_token.span);
+                }
+                (self.ast.save_variable_expression(None, current_token, variable_id).id, SyntaxKind::VariableExpr)
+            }
             TokenKind::OpenParen => {
                 let expr_id = self.parse_expression();
-                self.consume_and_verify_token(TokenKind::CloseParen);
-                expr_id
+                let close_paren = self.consume_and_verify_token(TokenKind::CloseParen);
+                (self.ast.save_parenthesized_expression(expr_id, current_token, close_paren).id, SyntaxKind::ParenthesizedExpr)
             },
-            _ => panic!("Unexpected token:{:?} : {:?}", current_token.span.literal,current_token.kind),
+            _ => {
+                if self.should_report_error_at(&current_token) {
+                    self.diagnostics.borrow_mut().report_expected_expression(&current_token);
+                }
+                (self.ast.save_error_expression(current_token).id, SyntaxKind::ErrorExpr)
+            }
+        }
+    }
+
+    /// Parses `module::name` or `module::name(..)`, with the current token
+    /// on the `::`.
+    fn parse_qualified_name(&mut self, module: Token) -> (ExprId, SyntaxKind) {
+        let colons = self.consume();
+        let identifier = self.consume_and_verify_token(TokenKind::Identifier);
+        let qualifier = Qualifier { module, colons };
+        let module_file = self.qualified_module(&qualifier);
+        let name = &identifier.span.literal;
+        let is_call = self.current_token().kind == TokenKind::OpenParen;
+        let variable_id = module_file.and_then(|module_file| self.ast.global_in(module_file, name));
+        let found = is_call && module_file.is_some_and(|module_file| self.ast.function_in(module_file, name).is_some());
+        if module_file.is_some() && !name.is_empty() && !found && variable_id.is_none() {
+            self.diagnostics.borrow_mut().report_not_in_module(qualifier.name(), &identifier.span);
+        }
+        if is_call {
+            return (self.parse_call_expression(Some(qualifier), identifier), SyntaxKind::CallExpr);
+        }
+        (self.ast.save_variable_expression(Some(qualifier), identifier, variable_id).id, SyntaxKind::VariableExpr)
+    }
+
+    /// The file that a qualifier names. A module that is not imported is
+    /// reported, one that failed to load has been already.
+    fn qualified_module(&mut self, qualifier: &Qualifier) -> Option<FileId> {
+        let module = self.ast.module(qualifier.module.span.file_id)?;
+        match module.imports.iter().find(|module_import| module_import.name == qualifier.name()) {
+            Some(module_import) => module_import.file_id,
+            None => {
+                self.diagnostics.borrow_mut().report_unknown_module(&qualifier.module.span);
+                None
+            }
+        }
+    }
+
+    fn parse_call_expression(&mut self, qualifier: Option<Qualifier>, callee: Token) -> ExprId {
+        let open_paren = self.consume_and_verify_token(TokenKind::OpenParen);
+        let mut arguments = Vec::new();
+        let mut commas = Vec::new();
+        while self.current_token().kind != TokenKind::CloseParen && !self.is_at_end() {
+            arguments.push(self.parse_expression());
+            if self.current_token().kind != TokenKind::Comma {
+                break;
+            }
+            commas.push(self.consume_and_verify_token(TokenKind::Comma));
         }
+        let close_paren = self.consume_and_verify_token(TokenKind::CloseParen);
+        self.ast.save_call_expression(qualifier, callee, open_paren, arguments, commas, close_paren).id
     }
 
+    /// Consumes the current token if it is a `token_kind`. Otherwise reports
+    /// it and returns a made up `token_kind` token in front of it.
     fn consume_and_verify_token(&mut self, token_kind: TokenKind) -> Token {
         let current_token = self.current_token().clone();
         if current_token.kind != token_kind {
-            panic!("Found {:?}, expected {:?}", current_token.kind, token_kind);
+            if self.should_report_error_at(&current_token) {
+                self.diagnostics.borrow_mut().report_unexpected_token(token_kind, &current_token);
+            }
+            return Self::missing_token(&current_token, token_kind);
         }
         self.consume();
         current_token
     }
 
-    fn consume(&mut self) -> &Token {
-        let token = self.tokens[self.current].borrow();
-        if token.kind != TokenKind::Eof {
-            self.current += 1;
+    fn should_report_error_at(&mut self, token: &Token) -> bool {
+        let first_error_here = self.last_error_at != Some(token.span.start);
+        self.last_error_at = Some(token.span.start);
+        first_error_here
+    }
+
+    /// An empty `kind` token right before `next`, standing in for a token
+    /// missing from the source.
+    fn missing_token(next: &Token, kind: TokenKind) -> Token {
+        Token::new(kind, TextSpan::new(next.span.file_id, next.span.start, next.span.start, String::new()))
+    }
+
+    fn consume(&mut self) -> Token {
+        if self.current_token().kind == TokenKind::Eof {
+            return self.current_token().clone();
         }
+        self.consumed += 1;
+        // Pull the next token first, so an `Eof` made up for a stream that
+        // ends here can still be placed after the token being consumed.
+        self.fill_lookahead(2);
+        let token = self.lookahead.pop_front().unwrap();
+        self.syntax.token(&token);
         token
     }
 
-    fn peek(&self, offset: usize) -> &Token {
-        let index = self.current + offset;
-        &self.tokens[index]
+    /// Pulls tokens until `lookahead` holds `count` of them or ends with
+    /// `Eof`. A stream that stops without an `Eof` gets one appended at the
+    /// end of its last token.
+    fn fill_lookahead(&mut self, count: usize) {
+        while self.lookahead.len() < count {
+            if self.lookahead.back().is_some_and(|token| token.kind == TokenKind::Eof) {
+                return;
+            }
+            match self.tokens.next() {
+                Some(token) => self.lookahead.push_back(token),
+                None => {
+                    let span = match self.lookahead.back() {
+                        Some(last) => TextSpan::new(last.span.file_id, last.span.end, last.span.end, String::new()),
+                        None => TextSpan::new(FileId::new(0), 0, 0, String::new()),
+                    };
+                    self.lookahead.push_back(Token::new(TokenKind::Eof, span));
+                }
+            }
+        }
     }
 
-    fn current_token(&self) -> &Token {
-        self.peek(0)
+    pub fn current_token(&self) -> &Token {
+        &self.lookahead[0]
     }
 }
@@ -1,5 +1,3 @@
-use std::any::{Any};
-
 use super::visitor::Visitor;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -31,7 +29,10 @@ impl Printer {
 }
 
 impl Visitor for Printer {
-    
+    fn visit_import(&mut self, ast: &super::Ast, import_id: super::ImportId) {
+        self.print_with_indent(&ast.query_import(import_id).text());
+    }
+
     fn visit_statement(&mut self, ast: &super::Ast, stmt_id: super::StmtId) {
         self.print_same_line("Statement_start >>>");
         self.enter_scope();
@@ -88,6 +89,40 @@ impl Visitor for Printer {
 
    }
 
+    fn visit_return_statement(&mut self, ast: &super::Ast, stmt: &super::ReturnStatement) {
+        self.print_same_line("Return_statement_start >> ");
+        self.visit_expression(ast, stmt.value);
+        self.print_same_line("Return_statement_end << ");
+    }
+
+    fn visit_float(&mut self, _ast: &super::Ast, number: &super::FloatExpr) {
+        self.enter_scope();
+        self.print_with_indent(&number.token.span.literal);
+        self.exit_scope();
+    }
+
+    fn visit_string(&mut self, _ast: &super::Ast, string: &super::StringExpr) {
+        self.enter_scope();
+        self.print_with_indent(&string.token.span.literal);
+        self.exit_scope();
+    }
+
+    fn visit_variable_expression(&mut self, _ast: &super::Ast, variable_expr: &super::VariableExpr) {
+        self.enter_scope();
+        self.print_with_indent(&variable_expr.qualified_name());
+        self.exit_scope();
+    }
+
+    fn visit_call_expression(&mut self, ast: &super::Ast, call_expr: &super::CallExpr) {
+        self.print_with_indent(&format!("call_expression_start {} {{", call_expr.qualified_name()));
+        self.enter_scope();
+        for argument in call_expr.arguments.iter() {
+            self.visit_expression(ast, *argument);
+        }
+        self.exit_scope();
+        self.print_with_indent("call_expression_end }");
+    }
+
     fn visit_number(&mut self, _ast: &super::Ast, number: &super::NumberExpr) {
         // self.print_with_indent("Number:");
         self.enter_scope();
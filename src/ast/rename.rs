@@ -0,0 +1,229 @@
+use std::fmt::{Display, Formatter};
+
+use crate::diagnostics::DiagnosticsBag;
+
+use super::{
+    builtins::Builtin,
+    incremental::TextEdit,
+    lexer::{Lexer, TokenKind},
+    symbols::{Reference, Symbol, SymbolIndex},
+    lib::Id,
+    text::{FileId, SourceFile, TextSpan},
+    Ast, ItemKind, StatementKind, VariableId,
+};
+
+/// Why a rename was refused. Spans are in the text before the rename.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenameError {
+    /// There is no variable or function name at the offset.
+    NoSymbol,
+    /// The new name is not an identifier, as `2x` or `let`.
+    InvalidName(String),
+    /// The name is declared in the same scope already, at `declaration`,
+    /// or is a built-in function when `declaration` is `None`.
+    Collision { name: String, declaration: Option<TextSpan> },
+    /// A use of the renamed symbol would refer to the declaration of the
+    /// same name at `declaration` instead.
+    Shadowed { name: String, declaration: TextSpan },
+    /// The existing use of the name at `span` would refer to the renamed
+    /// symbol.
+    Captures { name: String, span: TextSpan },
+}
+
+impl Display for RenameError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenameError::NoSymbol => write!(f, "There is no variable or function to rename here"),
+            RenameError::InvalidName(name) => write!(f, "`{}` is not a valid name", name),
+            RenameError::Collision { name, declaration: Some(_) } => write!(f, "`{}` is already declared in this scope", name),
+            RenameError::Collision { name, declaration: None } => write!(f, "`{}` is a built-in function", name),
+            RenameError::Shadowed { name, .. } => write!(f, "Uses of the renamed symbol would refer to another `{}`", name),
+            RenameError::Captures { name, .. } => write!(f, "An existing use of `{}` would refer to the renamed symbol", name),
+        }
+    }
+}
+
+/// The declaration and every use of the variable or function whose name is
+/// under `offset`, in source order.
+pub fn find_references(ast: &Ast, offset: usize) -> Vec<Reference> {
+    let index = SymbolIndex::new(ast);
+    let Some(symbol) = index.reference_at(offset).map(|reference| reference.symbol) else {
+        return Vec::new();
+    };
+    index.references_to(symbol).cloned().collect()
+}
+
+/// The edits that rename the variable or function whose name is under
+/// `offset` to `new_name`, one per declaration or use, in source order.
+///
+/// A rename is refused if the new name is declared in the same scope
+/// already. It is also refused if it would change what any name in the
+/// file refers to: the renamed text is resolved again and every name must
+/// still refer to the same symbol as before, so a use of the symbol cannot
+/// end up shadowed by a closer declaration and an unrelated use of the new
+/// name cannot end up referring to the symbol.
+pub fn rename(source: &SourceFile, ast: &Ast, offset: usize, new_name: &str) -> Result<Vec<TextEdit>, RenameError> {
+    let index = SymbolIndex::new(ast);
+    let symbol = index.reference_at(offset).ok_or(RenameError::NoSymbol)?.symbol;
+    if !is_identifier(new_name) {
+        return Err(RenameError::InvalidName(new_name.to_string()));
+    }
+    let edits: Vec<TextEdit> = index
+        .references_to(symbol)
+        .filter(|reference| reference.span.literal != new_name)
+        .map(|reference| TextEdit::new(reference.span.start, reference.span.end, new_name.to_string()))
+        .collect();
+    if edits.is_empty() {
+        return Ok(edits);
+    }
+    check_scope(ast, symbol, new_name)?;
+
+    let renamed = SourceFile::new(source.id, source.name.clone(), apply_edits(&source.text, &edits));
+    let renamed_ast = Ast::parse(&renamed, DiagnosticsBag::new_cell());
+    check_resolution(&index, &SymbolIndex::new(&renamed_ast), symbol, new_name, &edits)?;
+    Ok(edits)
+}
+
+/// `text` with `edits` applied. The edits must not overlap.
+pub fn apply_edits(text: &str, edits: &[TextEdit]) -> String {
+    let mut edits: Vec<&TextEdit> = edits.iter().collect();
+    edits.sort_by_key(|edit| edit.start);
+    let mut output = String::with_capacity(text.len());
+    let mut copied = 0;
+    for edit in edits {
+        output.push_str(&text[copied..edit.start]);
+        output.push_str(&edit.replacement);
+        copied = edit.end;
+    }
+    output.push_str(&text[copied..]);
+    output
+}
+
+/// Whether `name` lexes as one identifier and nothing else.
+fn is_identifier(name: &str) -> bool {
+    let diagnostics = DiagnosticsBag::new_cell();
+    let tokens: Vec<_> = Lexer::new(name, FileId::new(0), diagnostics.clone())
+        .filter(|token| token.kind != TokenKind::Eof)
+        .collect();
+    let [token] = tokens.as_slice() else {
+        return false;
+    };
+    token.kind == TokenKind::Identifier
+        && token.span.literal == name
+        && token.leading_trivia.is_empty()
+        && token.trailing_trivia.is_empty()
+        && diagnostics.borrow().diagnostics.is_empty()
+}
+
+/// Refuses a name that is declared in the scope of `symbol` already:
+/// functions share one scope with the built-ins, global `let`s share the
+/// top level and locals share the parameters and `let`s of their function.
+fn check_scope(ast: &Ast, symbol: Symbol, new_name: &str) -> Result<(), RenameError> {
+    let collision = |declaration| RenameError::Collision {
+        name: new_name.to_string(),
+        declaration,
+    };
+    match symbol {
+        Symbol::Function(_) => {
+            if let Some(function_id) = ast.function_by_name(new_name) {
+                return Err(collision(Some(ast.query_function(function_id).identifier.span.clone())));
+            }
+            if Builtin::lookup(new_name).is_some() {
+                return Err(collision(None));
+            }
+        }
+        Symbol::Variable(variable_id) => {
+            let existing = scope_declarations(ast, variable_id)
+                .into_iter()
+                .map(|variable_id| ast.variables.get(variable_id))
+                .find(|variable| variable.name == new_name);
+            if let Some(variable) = existing {
+                return Err(collision(Some(variable.identifier.span.clone())));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The variables declared in the same scope as `variable_id`.
+fn scope_declarations(ast: &Ast, variable_id: VariableId) -> Vec<VariableId> {
+    let let_variable = |stmt_id| match &ast.query_stmt(stmt_id).stmt_kind {
+        StatementKind::Let(let_stmt) => Some(let_stmt.variable_id),
+        _ => None,
+    };
+    let mut globals = Vec::new();
+    for item in ast.top_level_items() {
+        match item.kind {
+            ItemKind::Statement(stmt_id) => globals.extend(let_variable(stmt_id)),
+            ItemKind::Import(_) => {}
+            ItemKind::Function(function_id) => {
+                let function = ast.query_function(function_id);
+                let locals: Vec<VariableId> = function
+                    .parameters
+                    .iter()
+                    .map(|parameter| parameter.variable_id)
+                    .chain(function.body.statements.iter().filter_map(|stmt_id| let_variable(*stmt_id)))
+                    .collect();
+                if locals.contains(&variable_id) {
+                    return locals;
+                }
+            }
+        }
+    }
+    globals
+}
+
+/// Checks that every name of `renamed` refers to the same symbol as the
+/// name it was in `index`, and that no name refers to a symbol it did not
+/// refer to before.
+fn check_resolution(index: &SymbolIndex, renamed: &SymbolIndex, symbol: Symbol, new_name: &str, edits: &[TextEdit]) -> Result<(), RenameError> {
+    let delta = edits[0].replacement.len() as isize - (edits[0].end - edits[0].start) as isize;
+    let edits_before = |offset: usize| edits.partition_point(|edit| edit.start < offset) as isize;
+    let new_start = |old_start: usize| old_start.wrapping_add_signed(delta * edits_before(old_start));
+    // The symbols of the renamed text, by what they were before.
+    let old_symbol = |new_symbol: Symbol| {
+        let declaration = renamed.declaration(new_symbol)?;
+        index
+            .references
+            .iter()
+            .find(|reference| reference.is_declaration && new_start(reference.span.start) == declaration.span.start)
+            .map(|reference| reference.symbol)
+    };
+    for reference in index.references.iter() {
+        let start = new_start(reference.span.start);
+        let now = renamed.references.iter().find(|renamed| renamed.span.start == start);
+        let now_symbol = now.and_then(|now| old_symbol(now.symbol));
+        if now_symbol == Some(reference.symbol) {
+            continue;
+        }
+        if reference.symbol != symbol {
+            return Err(RenameError::Captures {
+                name: new_name.to_string(),
+                span: reference.span.clone(),
+            });
+        }
+        let declaration = now_symbol.and_then(|now_symbol| index.declaration(now_symbol));
+        return Err(RenameError::Shadowed {
+            name: new_name.to_string(),
+            declaration: declaration.unwrap_or(reference).span.clone(),
+        });
+    }
+    // A name that did not resolve before, as a use of an undeclared
+    // variable or a call of a built-in, now does.
+    if renamed.references.len() > index.references.len() {
+        let new_starts: Vec<usize> = index.references.iter().map(|reference| new_start(reference.span.start)).collect();
+        if let Some(captured) = renamed.references.iter().find(|renamed| !new_starts.contains(&renamed.span.start)) {
+            let old_start = edits
+                .iter()
+                .filter(|edit| new_start(edit.start) < captured.span.start)
+                .fold(captured.span.start as isize, |start, _| start - delta) as usize;
+            let length = captured.span.length();
+            let span = TextSpan::new(captured.span.file_id, old_start, old_start + length, captured.span.literal.clone());
+            return Err(RenameError::Captures {
+                name: new_name.to_string(),
+                span,
+            });
+        }
+    }
+    Ok(())
+}
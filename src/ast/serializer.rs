@@ -0,0 +1,293 @@
+use crate::json::Json;
+
+use super::{
+    lexer::Token,
+    lib::Id, text::TextSpan, visitor::Visitor, Ast, ExprId, ExpressionKind, FunctionId, ImportId, ImportKind, ItemId, Qualifier, StatementKind, StmtId, TypeAnnotation,
+};
+
+/// Bumped whenever the shape of the JSON export changes in a way that
+/// consumers have to know about.
+pub const JSON_FORMAT_VERSION: i64 = 2;
+
+/// Exports the `Ast` as JSON. Every node is an object with a `kind`, the
+/// `id` of the node in the arena for that kind (`function`, statement or
+/// expression ids), its `span` and its `children`, plus a few kind specific
+/// fields such as `operator` or `name`. A qualified variable or call also
+/// has the `module` it names.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JsonSerializer {
+    items: Vec<Json>,
+    children: Vec<Vec<Json>>,
+}
+
+impl JsonSerializer {
+    pub fn new() -> Self {
+        Self {
+            items: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("format_version", Json::Number(JSON_FORMAT_VERSION)),
+            ("items", Json::Array(self.items.clone())),
+        ])
+    }
+
+    fn span(span: &TextSpan) -> Json {
+        Json::object(vec![
+            ("start", Json::Number(span.start as i64)),
+            ("end", Json::Number(span.end as i64)),
+        ])
+    }
+
+    fn node(kind: &str, id: usize, span: &TextSpan, mut fields: Vec<(&str, Json)>, children: Vec<Json>) -> Json {
+        let mut node = vec![
+            ("kind", Json::string(kind)),
+            ("id", Json::Number(id as i64)),
+            ("span", Self::span(span)),
+        ];
+        node.append(&mut fields);
+        node.push(("children", Json::Array(children)));
+        Json::object(node)
+    }
+
+    /// The `type` field of an annotated `let` or parameter: the type name
+    /// and the span of the annotation.
+    fn type_annotation(type_annotation: &Option<TypeAnnotation>) -> Option<(&'static str, Json)> {
+        type_annotation.as_ref().map(|type_annotation| {
+            let annotation = Json::object(vec![
+                ("name", Json::string(type_annotation.type_name())),
+                ("span", Self::span(&type_annotation.span())),
+            ]);
+            ("type", annotation)
+        })
+    }
+
+    /// The `module` field of a qualified name.
+    fn module(qualifier: &Option<Qualifier>) -> Option<(&'static str, Json)> {
+        qualifier.as_ref().map(|qualifier| ("module", Json::string(qualifier.name())))
+    }
+
+    fn collect_children(&mut self, visit: impl FnOnce(&mut Self)) -> Vec<Json> {
+        self.children.push(Vec::new());
+        visit(self);
+        self.children.pop().unwrap_or_default()
+    }
+
+    fn emit(&mut self, node: Json) {
+        match self.children.last_mut() {
+            Some(children) => children.push(node),
+            None => self.items.push(node),
+        }
+    }
+}
+
+impl Visitor for JsonSerializer {
+    fn visit_item(&mut self, ast: &Ast, item_id: ItemId) {
+        self.do_visit_item(ast, item_id);
+    }
+
+    fn visit_import(&mut self, ast: &Ast, import_id: ImportId) {
+        let import = ast.query_import(import_id);
+        let mut fields = vec![("path", Json::String(import.path()))];
+        if let ImportKind::Name { name, .. } = &import.kind {
+            fields.push(("name", Json::string(&name.span.literal)));
+        }
+        let node = Self::node("import", import_id.to_usize(), &import.span(), fields, Vec::new());
+        self.emit(node);
+    }
+
+    fn visit_function(&mut self, ast: &Ast, function_id: FunctionId) {
+        let function = ast.query_function(function_id);
+        let children = self.collect_children(|serializer| {
+            for stmt_id in function.body.statements.iter() {
+                serializer.visit_statement(ast, *stmt_id);
+            }
+        });
+        let parameters = function
+            .parameters
+            .iter()
+            .map(|parameter| {
+                let mut fields = vec![
+                    ("name", Json::string(&parameter.identifier.span.literal)),
+                    ("span", Self::span(&parameter.identifier.span)),
+                ];
+                fields.extend(Self::type_annotation(&parameter.type_annotation));
+                Json::object(fields)
+            })
+            .collect();
+        let mut fields = vec![("name", Json::string(&function.name)), ("parameters", Json::Array(parameters))];
+        fields.extend(Self::type_annotation(&function.return_type).map(|(_, annotation)| ("return_type", annotation)));
+        let node = Self::node("function", function_id.to_usize(), &function.span(), fields, children);
+        self.emit(node);
+    }
+
+    fn visit_statement(&mut self, ast: &Ast, stmt_id: StmtId) {
+        let children = self.collect_children(|serializer| serializer.do_visit_statement(ast, stmt_id));
+        let stmt = ast.query_stmt(stmt_id);
+        let (kind, fields) = match &stmt.stmt_kind {
+            StatementKind::Let(let_stmt) => {
+                let mut fields = vec![("name", Json::string(&let_stmt.identifier.span.literal))];
+                fields.extend(Self::type_annotation(&let_stmt.type_annotation));
+                ("let", fields)
+            }
+            StatementKind::Return(_) => ("return", vec![]),
+            StatementKind::Expression(_) => ("expression_statement", vec![]),
+        };
+        let node = Self::node(kind, stmt_id.to_usize(), &stmt.span(ast), fields, children);
+        self.emit(node);
+    }
+
+    fn visit_expression(&mut self, ast: &Ast, expr_id: ExprId) {
+        let children = self.collect_children(|serializer| serializer.do_visit_expression(ast, expr_id));
+        let expr = ast.query_expr(expr_id);
+        let (kind, fields) = match &expr.kind {
+            ExpressionKind::Number(number) => ("number", vec![("value", Json::Number(number.number))]),
+            ExpressionKind::Float(number) => ("float", vec![("value", Json::Float(number.number))]),
+            ExpressionKind::String(string) => ("string", vec![("value", Json::string(&string.value))]),
+            ExpressionKind::Variable(variable_expr) => {
+                let mut fields = vec![("name", Json::string(variable_expr.name()))];
+                fields.extend(Self::module(&variable_expr.qualifier));
+                ("variable", fields)
+            }
+            ExpressionKind::Call(call_expr) => {
+                let mut fields = vec![("callee", Json::string(call_expr.name()))];
+                fields.extend(Self::module(&call_expr.qualifier));
+                ("call", fields)
+            }
+            ExpressionKind::Binary(bin_expr) => (
+                "binary",
+                vec![("operator", Json::String(bin_expr.operator.kind.to_string()))],
+            ),
+            ExpressionKind::Parenthesized(_) => ("parenthesized", vec![]),
+            ExpressionKind::Assignment(_) => ("assignment", vec![]),
+            ExpressionKind::Error(_) => ("error", vec![]),
+        };
+        let node = Self::node(kind, expr_id.to_usize(), &expr.span(ast), fields, children);
+        self.emit(node);
+    }
+
+    fn visit_let_statement(&mut self, ast: &Ast, stmt: &super::LetStatement) {
+        self.visit_expression(ast, stmt.initializer);
+    }
+
+    fn visit_number(&mut self, _ast: &Ast, _number: &super::NumberExpr) {}
+
+    fn visit_float(&mut self, _ast: &Ast, _number: &super::FloatExpr) {}
+
+    fn visit_string(&mut self, _ast: &Ast, _string: &super::StringExpr) {}
+
+    fn visit_variable_expression(&mut self, _ast: &Ast, _variable_expr: &super::VariableExpr) {}
+}
+
+/// Exports the `Ast` as S-expressions, one top-level item per line, e.g.
+/// `200 * 7 + 5` becomes `(+ (* 200 7) 5)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SexprSerializer {
+    pub output: String,
+}
+
+impl SexprSerializer {
+    pub fn new() -> Self {
+        Self { output: String::new() }
+    }
+
+    /// A name followed by its type annotation, if any, as in `w:float`.
+    fn annotated(name: &str, type_annotation: &Option<TypeAnnotation>) -> String {
+        match type_annotation {
+            Some(type_annotation) => format!("{}:{}", name, type_annotation.type_name()),
+            None => name.to_string(),
+        }
+    }
+}
+
+impl Visitor for SexprSerializer {
+    fn visit_item(&mut self, ast: &Ast, item_id: ItemId) {
+        self.do_visit_item(ast, item_id);
+        self.output.push('\n');
+    }
+
+    fn visit_import(&mut self, ast: &Ast, import_id: ImportId) {
+        let import = ast.query_import(import_id);
+        match &import.kind {
+            ImportKind::File { path } => self.output.push_str(&format!("(import {})", path.span.literal)),
+            ImportKind::Name { module, name, .. } => self.output.push_str(&format!("(use {}::{})", module.span.literal, name.span.literal)),
+        }
+    }
+
+    fn visit_function(&mut self, ast: &Ast, function_id: FunctionId) {
+        let function = ast.query_function(function_id);
+        let parameters = function
+            .parameters
+            .iter()
+            .map(|parameter| Self::annotated(&parameter.identifier.span.literal, &parameter.type_annotation))
+            .collect::<Vec<String>>()
+            .join(" ");
+        self.output.push_str(&format!("(function {} ({})", function.name, parameters));
+        if let Some(return_type) = &function.return_type {
+            self.output.push_str(&format!(" -> {}", return_type.type_name()));
+        }
+        for stmt_id in function.body.statements.iter() {
+            self.output.push(' ');
+            self.visit_statement(ast, *stmt_id);
+        }
+        self.output.push(')');
+    }
+
+    fn visit_let_statement(&mut self, ast: &Ast, stmt: &super::LetStatement) {
+        self.output.push_str(&format!("(let {} ", Self::annotated(&stmt.identifier.span.literal, &stmt.type_annotation)));
+        self.visit_expression(ast, stmt.initializer);
+        self.output.push(')');
+    }
+
+    fn visit_return_statement(&mut self, ast: &Ast, stmt: &super::ReturnStatement) {
+        self.output.push_str("(return ");
+        self.visit_expression(ast, stmt.value);
+        self.output.push(')');
+    }
+
+    fn visit_number(&mut self, _ast: &Ast, number: &super::NumberExpr) {
+        self.output.push_str(&number.number.to_string());
+    }
+
+    fn visit_float(&mut self, _ast: &Ast, number: &super::FloatExpr) {
+        self.output.push_str(&format!("{:?}", number.number));
+    }
+
+    fn visit_string(&mut self, _ast: &Ast, string: &super::StringExpr) {
+        self.output.push_str(&string.token.span.literal);
+    }
+
+    fn visit_variable_expression(&mut self, _ast: &Ast, variable_expr: &super::VariableExpr) {
+        self.output.push_str(&variable_expr.qualified_name());
+    }
+
+    fn visit_call_expression(&mut self, ast: &Ast, call_expr: &super::CallExpr) {
+        self.output.push_str(&format!("({}", call_expr.qualified_name()));
+        for argument in call_expr.arguments.iter() {
+            self.output.push(' ');
+            self.visit_expression(ast, *argument);
+        }
+        self.output.push(')');
+    }
+
+    fn visit_binary_expression(&mut self, ast: &Ast, bin_expr: &super::BinaryExpr) {
+        self.output.push_str(&format!("({} ", bin_expr.operator.kind));
+        self.visit_expression(ast, bin_expr.left);
+        self.output.push(' ');
+        self.visit_expression(ast, bin_expr.right);
+        self.output.push(')');
+    }
+
+    fn visit_error_expression(&mut self, _ast: &Ast, _token: &Token) {
+        self.output.push_str("error");
+    }
+
+    fn visit_assignment_expression(&mut self, ast: &Ast, assign_expr: &super::AssignExpr) {
+        self.output.push_str("(= ");
+        self.visit_expression(ast, assign_expr.expr);
+        self.output.push(')');
+    }
+}
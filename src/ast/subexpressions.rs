@@ -0,0 +1,414 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Display, Formatter},
+};
+
+use crate::diagnostics::DiagnosticsBag;
+
+use super::{
+    builtins::Builtin,
+    incremental::TextEdit,
+    lib::{Id, IdVec},
+    modules::{Program, SourceSnapshot},
+    rename::apply_edits,
+    text::{SourceFile, TextSpan},
+    Ast, BinOperatorKind, ExprId, ExpressionKind, FunctionId, ItemKind, StatementKind, StmtId, VariableId,
+};
+
+/// What an expression computes: its kind, with each child standing in by
+/// the class of its structure.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ExpressionKey {
+    Number(i64),
+    /// The bits of the float, so `0.0` and `-0.0` stay apart.
+    Float(u64),
+    String(String),
+    Variable(VariableId),
+    Call(Callee, Vec<ExprId>),
+    Binary(BinOperatorKind, ExprId, ExprId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Callee {
+    Function(FunctionId),
+    Builtin(Builtin),
+}
+
+/// The expressions of an `Ast` grouped by structure through hash-consing.
+/// Every expression is keyed on its kind and the classes of its children,
+/// and the first expression hashed with a key stands for the class of all
+/// expressions with that key, so two expressions compute the same value
+/// exactly when their classes are the same expression.
+///
+/// Parentheses are looked through: `(a * b)` is in the class of `a * b`.
+/// Variables are keyed on the variable they refer to, not on their name, and
+/// calls on the function they call. Expressions that did not resolve, and
+/// the ones containing them, have no class.
+pub struct ExpressionTable {
+    classes: IdVec<ExprId, Option<ExprId>>,
+    sizes: IdVec<ExprId, usize>,
+}
+
+impl ExpressionTable {
+    pub fn new(ast: &Ast) -> Self {
+        let count = ast.expressions.data.len();
+        let mut hashing = Hashing {
+            ast,
+            keys: HashMap::new(),
+            classes: IdVec::filled(None, count),
+            sizes: IdVec::filled(0, count),
+            hashed: IdVec::filled(false, count),
+        };
+        for index in 0..count {
+            hashing.hash(ExprId::new(index));
+        }
+        Self {
+            classes: hashing.classes,
+            sizes: hashing.sizes,
+        }
+    }
+
+    /// The expression that stands for every expression with the structure
+    /// of `expr_id`.
+    pub fn class(&self, expr_id: ExprId) -> Option<ExprId> {
+        *self.classes.get(expr_id)
+    }
+
+    /// Whether `left` and `right` have the same structure, and so the same
+    /// value.
+    pub fn same(&self, left: ExprId, right: ExprId) -> bool {
+        self.class(left).is_some() && self.class(left) == self.class(right)
+    }
+
+    /// The number of nodes of `expr_id`, parentheses not counted.
+    pub fn size(&self, expr_id: ExprId) -> usize {
+        *self.sizes.get(expr_id)
+    }
+}
+
+struct Hashing<'a> {
+    ast: &'a Ast,
+    keys: HashMap<ExpressionKey, ExprId>,
+    classes: IdVec<ExprId, Option<ExprId>>,
+    sizes: IdVec<ExprId, usize>,
+    hashed: IdVec<ExprId, bool>,
+}
+
+impl Hashing<'_> {
+    /// Hashes `expr_id` after its children and returns its class.
+    fn hash(&mut self, expr_id: ExprId) -> Option<ExprId> {
+        if *self.hashed.get(expr_id) {
+            return self.class(expr_id);
+        }
+        let ast = self.ast;
+        let (key, size) = match &ast.query_expr(expr_id).kind {
+            ExpressionKind::Number(number) => (Some(ExpressionKey::Number(number.number)), 1),
+            ExpressionKind::Float(number) => (Some(ExpressionKey::Float(number.number.to_bits())), 1),
+            ExpressionKind::String(string) => (Some(ExpressionKey::String(string.value.clone())), 1),
+            ExpressionKind::Variable(variable_expr) => (variable_expr.variable_id.map(ExpressionKey::Variable), 1),
+            ExpressionKind::Call(call_expr) => {
+                let arguments: Vec<Option<ExprId>> = call_expr.arguments.iter().map(|argument| self.hash(*argument)).collect();
+                let size = 1 + call_expr.arguments.iter().map(|argument| *self.sizes.get(*argument)).sum::<usize>();
+                let builtin = call_expr.qualifier.is_none().then(|| Builtin::lookup(call_expr.name())).flatten();
+                let callee = match ast.resolve_call(call_expr) {
+                    Some(function_id) => Some(Callee::Function(function_id)),
+                    None => builtin.map(Callee::Builtin),
+                };
+                let arguments: Option<Vec<ExprId>> = arguments.into_iter().collect();
+                (callee.zip(arguments).map(|(callee, arguments)| ExpressionKey::Call(callee, arguments)), size)
+            }
+            ExpressionKind::Binary(bin_expr) => {
+                let (left, right) = (self.hash(bin_expr.left), self.hash(bin_expr.right));
+                let size = 1 + *self.sizes.get(bin_expr.left) + *self.sizes.get(bin_expr.right);
+                let key = left.zip(right).map(|(left, right)| ExpressionKey::Binary(bin_expr.operator.kind, left, right));
+                (key, size)
+            }
+            ExpressionKind::Parenthesized(parenthesized_expr) => {
+                let class = self.hash(parenthesized_expr.expr);
+                let size = *self.sizes.get(parenthesized_expr.expr);
+                self.record(expr_id, class, size);
+                return class;
+            }
+            ExpressionKind::Assignment(_) | ExpressionKind::Error(_) => (None, 1),
+        };
+        let class = key.map(|key| *self.keys.entry(key).or_insert(expr_id));
+        self.record(expr_id, class, size);
+        class
+    }
+
+    fn class(&self, expr_id: ExprId) -> Option<ExprId> {
+        *self.classes.get(expr_id)
+    }
+
+    fn record(&mut self, expr_id: ExprId, class: Option<ExprId>, size: usize) {
+        *self.classes.get_mut(expr_id) = class;
+        *self.sizes.get_mut(expr_id) = size;
+        *self.hashed.get_mut(expr_id) = true;
+    }
+}
+
+/// An expression that is now computed once, into the variable `name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Temporary {
+    pub name: String,
+    /// The expression as written where it was computed first.
+    pub expression: String,
+    /// How many times the expression was computed before.
+    pub occurrences: usize,
+}
+
+impl Display for Temporary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {} ({} occurrences)", self.name, self.expression, self.occurrences)
+    }
+}
+
+/// The edits of one round of common subexpression elimination and the
+/// temporaries they compute.
+#[derive(Debug, Clone, Default)]
+pub struct Subexpressions {
+    pub edits: Vec<TextEdit>,
+    pub temporaries: Vec<Temporary>,
+}
+
+/// The edits that compute each operation or call that `source` repeats
+/// once, into a temporary, and use the temporary everywhere else.
+///
+/// Repeats are only looked for within one function body, or among the
+/// top-level statements of the file, where everything runs in order and
+/// every variable keeps its value, so computing an expression once before
+/// the statement it first appears in gives the same value. Only where a
+/// program that traps stops can differ. Larger expressions are taken first,
+/// and the expressions inside a repeat are left for the next round, since
+/// they now appear in the temporary only once.
+///
+/// An expression that first appears as the whole initializer of a `let`
+/// reuses its variable, if no other `let` of that name comes before the
+/// last occurrence. Otherwise the temporary is a new `let _cseN` just above
+/// that statement; the `_` keeps it from being reported as unused and, at
+/// the top level, from adding a line to the results that `run` prints.
+pub fn find_common_subexpressions(ast: &Ast, source: &SourceFile) -> Subexpressions {
+    let table = ast.hash_expressions();
+    let mut names: HashSet<String> = ast
+        .variables
+        .iter()
+        .map(|variable| variable.name.clone())
+        .chain(ast.functions.iter().map(|function| function.name.clone()))
+        .collect();
+    let mut found = Subexpressions::default();
+    for body in bodies(ast, source) {
+        let elimination = BodyElimination {
+            ast,
+            table: &table,
+            source,
+            body: &body,
+        };
+        elimination.eliminate(&mut names, &mut found);
+    }
+    found
+}
+
+/// The outcome of `eliminate_common_subexpressions`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Elimination {
+    /// The rewritten text of the main file.
+    pub text: String,
+    /// The temporaries of every round, in order.
+    pub temporaries: Vec<Temporary>,
+}
+
+/// Rewrites the main file of the program at `path`, read into `snapshot`,
+/// with `find_common_subexpressions` until no operation or call in it is
+/// computed twice. Each round loads the program again from the text the
+/// last one left, so the imports stay resolved. Fails only if the main file
+/// cannot be read.
+pub fn eliminate_common_subexpressions(path: &str, mut snapshot: SourceSnapshot) -> Result<Elimination, String> {
+    let mut temporaries = Vec::new();
+    loop {
+        let program = Program::load(path, &mut snapshot, DiagnosticsBag::new_cell())?;
+        let source = program.main_source();
+        let round = find_common_subexpressions(&program.ast, source);
+        if round.temporaries.is_empty() {
+            return Ok(Elimination {
+                text: source.text.clone(),
+                temporaries,
+            });
+        }
+        temporaries.extend(round.temporaries);
+        snapshot.set_text(path, apply_edits(&source.text, &round.edits));
+    }
+}
+
+/// The top-level statements of `source` and the statements of each of its
+/// functions, in the order they start.
+fn bodies(ast: &Ast, source: &SourceFile) -> Vec<Vec<StmtId>> {
+    let mut bodies: Vec<Vec<StmtId>> = Vec::new();
+    let mut top_level = None;
+    for item in ast.top_level_items() {
+        match item.kind {
+            ItemKind::Statement(stmt_id) if ast.query_stmt(stmt_id).span(ast).file_id == source.id => {
+                let index = *top_level.get_or_insert_with(|| {
+                    bodies.push(Vec::new());
+                    bodies.len() - 1
+                });
+                bodies[index].push(stmt_id);
+            }
+            ItemKind::Function(function_id) => {
+                let function = ast.query_function(function_id);
+                if function.function_keyword.span.file_id == source.id {
+                    bodies.push(function.body.statements.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+    bodies
+}
+
+/// An operation or call of a body, possibly in parentheses. `expr_id` is
+/// the outermost parentheses, which a use of the temporary replaces, and
+/// `inner` the expression inside them.
+struct Occurrence {
+    class: ExprId,
+    expr_id: ExprId,
+    inner: ExprId,
+    statement: usize,
+    span: TextSpan,
+}
+
+struct BodyElimination<'a> {
+    ast: &'a Ast,
+    table: &'a ExpressionTable,
+    source: &'a SourceFile,
+    body: &'a [StmtId],
+}
+
+impl BodyElimination<'_> {
+    fn eliminate(&self, names: &mut HashSet<String>, found: &mut Subexpressions) {
+        let mut occurrences = Vec::new();
+        for (statement, stmt_id) in self.body.iter().enumerate() {
+            let expr_id = match &self.ast.query_stmt(*stmt_id).stmt_kind {
+                StatementKind::Let(let_stmt) => let_stmt.initializer,
+                StatementKind::Return(return_stmt) => return_stmt.value,
+                StatementKind::Expression(expr_id) => *expr_id,
+            };
+            self.collect(expr_id, statement, &mut occurrences);
+        }
+        let mut classes: HashMap<ExprId, Vec<&Occurrence>> = HashMap::new();
+        for occurrence in occurrences.iter() {
+            classes.entry(occurrence.class).or_default().push(occurrence);
+        }
+        let mut candidates: Vec<Vec<&Occurrence>> = classes.into_values().filter(|class| class.len() > 1).collect();
+        candidates.sort_by_key(|class| (std::cmp::Reverse(self.table.size(class[0].inner)), class[0].span.start));
+        // The occurrences taken, none of which may overlap another.
+        let mut taken: Vec<&TextSpan> = Vec::new();
+        let mut repeats: Vec<Vec<&Occurrence>> = Vec::new();
+        for candidate in candidates {
+            let free: Vec<&Occurrence> = candidate
+                .into_iter()
+                .filter(|occurrence| !taken.iter().any(|span| overlaps(span, &occurrence.span)))
+                .collect();
+            if free.len() > 1 {
+                taken.extend(free.iter().map(|occurrence| &occurrence.span));
+                repeats.push(free);
+            }
+        }
+        repeats.sort_by_key(|repeat| repeat[0].span.start);
+        // Inserting before a statement that starts with a replaced
+        // expression only works with the insertion first.
+        let mut replacements = Vec::new();
+        for repeat in repeats {
+            let (name, replaced) = match self.reusable_variable(&repeat) {
+                Some(name) => (name, &repeat[1..]),
+                None => {
+                    let name = fresh_name(names);
+                    found.edits.push(self.insertion(&name, repeat[0]));
+                    (name, &repeat[..])
+                }
+            };
+            for occurrence in replaced {
+                replacements.push(TextEdit::new(occurrence.span.start, occurrence.span.end, name.clone()));
+            }
+            found.temporaries.push(Temporary {
+                name,
+                expression: self.text(&self.ast.query_expr(repeat[0].inner).span(self.ast)).to_string(),
+                occurrences: repeat.len(),
+            });
+        }
+        found.edits.extend(replacements);
+    }
+
+    /// Collects the operations and calls of `expr_id` that have a class,
+    /// outermost first.
+    fn collect(&self, expr_id: ExprId, statement: usize, occurrences: &mut Vec<Occurrence>) {
+        let mut inner = expr_id;
+        while let ExpressionKind::Parenthesized(parenthesized_expr) = &self.ast.query_expr(inner).kind {
+            inner = parenthesized_expr.expr;
+        }
+        let children = match &self.ast.query_expr(inner).kind {
+            ExpressionKind::Binary(bin_expr) => vec![bin_expr.left, bin_expr.right],
+            ExpressionKind::Call(call_expr) => call_expr.arguments.clone(),
+            _ => return,
+        };
+        if let Some(class) = self.table.class(inner) {
+            occurrences.push(Occurrence {
+                class,
+                expr_id,
+                inner,
+                statement,
+                span: self.ast.query_expr(expr_id).span(self.ast),
+            });
+        }
+        for child in children {
+            self.collect(child, statement, occurrences);
+        }
+    }
+
+    /// The variable of the `let` whose initializer is the first occurrence
+    /// of `repeat`, unless a `let` of the same name follows it up to the
+    /// statement of the last occurrence, that one included. Past such a
+    /// `let` the name is a new variable, and in its own initializer it
+    /// still reads the old one, which a reader would take for the new.
+    fn reusable_variable(&self, repeat: &[&Occurrence]) -> Option<String> {
+        let first = repeat[0];
+        let StatementKind::Let(let_stmt) = &self.ast.query_stmt(self.body[first.statement]).stmt_kind else {
+            return None;
+        };
+        if let_stmt.initializer != first.expr_id {
+            return None;
+        }
+        let name = &self.ast.query_variable(let_stmt.variable_id).name;
+        let last = repeat.iter().map(|occurrence| occurrence.statement).max()?;
+        let rebound = self.body[first.statement + 1..=last].iter().any(|stmt_id| match &self.ast.query_stmt(*stmt_id).stmt_kind {
+            StatementKind::Let(other) => self.ast.query_variable(other.variable_id).name == *name,
+            _ => false,
+        });
+        (!rebound).then(|| name.clone())
+    }
+
+    /// The `let` of `name` for `occurrence`, on a line of its own above the
+    /// statement of the occurrence, indented the same.
+    fn insertion(&self, name: &str, occurrence: &Occurrence) -> TextEdit {
+        let start = self.ast.query_stmt(self.body[occurrence.statement]).span(self.ast).start;
+        let line_start = self.source.text[..start].rfind('\n').map_or(0, |newline| newline + 1);
+        let line = &self.source.text[line_start..];
+        let indent = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+        let expression = self.text(&self.ast.query_expr(occurrence.inner).span(self.ast));
+        TextEdit::new(start, start, format!("let {} = {}\n{}", name, expression, indent))
+    }
+
+    fn text(&self, span: &TextSpan) -> &str {
+        &self.source.text[span.start..span.end]
+    }
+}
+
+fn overlaps(left: &TextSpan, right: &TextSpan) -> bool {
+    left.start < right.end && right.start < left.end
+}
+
+/// The first name `_cseN` that nothing is called yet, which it then is.
+fn fresh_name(names: &mut HashSet<String>) -> String {
+    let name = (0..).map(|index| format!("_cse{}", index)).find(|name| !names.contains(name)).unwrap();
+    names.insert(name.clone());
+    name
+}
@@ -0,0 +1,102 @@
+use super::{text::TextSpan, visitor::Visitor, Ast, FunctionId, VariableId};
+
+/// Something a name in the source can stand for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symbol {
+    Variable(VariableId),
+    Function(FunctionId),
+}
+
+/// One occurrence of a name: where a symbol is declared or used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub symbol: Symbol,
+    pub span: TextSpan,
+    pub is_declaration: bool,
+}
+
+/// Every name in an `Ast` together with the symbol it resolves to, in
+/// source order. Names the parser made up for missing tokens and names
+/// that resolve to nothing are left out.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolIndex {
+    pub references: Vec<Reference>,
+}
+
+impl SymbolIndex {
+    pub fn new(ast: &Ast) -> Self {
+        let mut index = Self { references: Vec::new() };
+        ast.visit(&mut index);
+        index.references.sort_by_key(|reference| reference.span.start);
+        index
+    }
+
+    /// The name under `offset`. An offset right after a name still counts,
+    /// since that is where the cursor sits after typing it.
+    pub fn reference_at(&self, offset: usize) -> Option<&Reference> {
+        self.references
+            .iter()
+            .find(|reference| reference.span.start <= offset && offset <= reference.span.end)
+    }
+
+    pub fn declaration(&self, symbol: Symbol) -> Option<&Reference> {
+        self.references
+            .iter()
+            .find(|reference| reference.symbol == symbol && reference.is_declaration)
+    }
+
+    /// The declaration and every use of `symbol`.
+    pub fn references_to(&self, symbol: Symbol) -> impl Iterator<Item = &Reference> {
+        self.references.iter().filter(move |reference| reference.symbol == symbol)
+    }
+
+    fn add(&mut self, symbol: Symbol, span: &TextSpan, is_declaration: bool) {
+        if span.length() == 0 {
+            return;
+        }
+        self.references.push(Reference {
+            symbol,
+            span: span.clone(),
+            is_declaration,
+        });
+    }
+}
+
+impl Visitor for SymbolIndex {
+    fn visit_function(&mut self, ast: &Ast, function_id: FunctionId) {
+        let function = ast.query_function(function_id);
+        self.add(Symbol::Function(function_id), &function.identifier.span, true);
+        for parameter in function.parameters.iter() {
+            self.add(Symbol::Variable(parameter.variable_id), &parameter.identifier.span, true);
+        }
+        for stmt_id in function.body.statements.iter() {
+            self.visit_statement(ast, *stmt_id);
+        }
+    }
+
+    fn visit_let_statement(&mut self, ast: &Ast, stmt: &super::LetStatement) {
+        self.add(Symbol::Variable(stmt.variable_id), &stmt.identifier.span, true);
+        self.visit_expression(ast, stmt.initializer);
+    }
+
+    fn visit_number(&mut self, _ast: &Ast, _number: &super::NumberExpr) {}
+
+    fn visit_float(&mut self, _ast: &Ast, _number: &super::FloatExpr) {}
+
+    fn visit_string(&mut self, _ast: &Ast, _string: &super::StringExpr) {}
+
+    fn visit_variable_expression(&mut self, _ast: &Ast, variable_expr: &super::VariableExpr) {
+        if let Some(variable_id) = variable_expr.variable_id {
+            self.add(Symbol::Variable(variable_id), &variable_expr.identifier.span, false);
+        }
+    }
+
+    fn visit_call_expression(&mut self, ast: &Ast, call_expr: &super::CallExpr) {
+        if let Some(function_id) = ast.resolve_call(call_expr) {
+            self.add(Symbol::Function(function_id), &call_expr.callee.span, false);
+        }
+        for argument in call_expr.arguments.iter() {
+            self.visit_expression(ast, *argument);
+        }
+    }
+}
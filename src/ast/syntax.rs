@@ -0,0 +1,461 @@
+use std::{
+    fmt::{Display, Formatter},
+    ops::Range,
+    rc::Rc,
+    sync::Arc,
+};
+
+use super::lexer::{Token, TokenKind, Trivia, TriviaKind};
+
+/// The kind of a node or token of the concrete syntax tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    File,
+    Import,
+    Function,
+    Parameter,
+    TypeAnnotation,
+    Body,
+    LetStatement,
+    ReturnStatement,
+    ExpressionStatement,
+    NumberExpr,
+    FloatExpr,
+    StringExpr,
+    VariableExpr,
+    CallExpr,
+    BinaryExpr,
+    ParenthesizedExpr,
+    /// An expression the parser could not make out, holding the token it
+    /// skipped, if any.
+    ErrorExpr,
+    Token(TokenKind),
+    Trivia(TriviaKind),
+}
+
+impl SyntaxKind {
+    pub fn is_expression(&self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::NumberExpr
+                | SyntaxKind::FloatExpr
+                | SyntaxKind::StringExpr
+                | SyntaxKind::VariableExpr
+                | SyntaxKind::CallExpr
+                | SyntaxKind::BinaryExpr
+                | SyntaxKind::ParenthesizedExpr
+                | SyntaxKind::ErrorExpr
+        )
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, SyntaxKind::Trivia(_))
+    }
+}
+
+impl Display for SyntaxKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyntaxKind::Token(kind) => write!(f, "{:?}", kind),
+            SyntaxKind::Trivia(kind) => write!(f, "{:?}", kind),
+            kind => write!(f, "{:?}", kind),
+        }
+    }
+}
+
+/// A token of the green tree: its kind and its text, without a position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GreenToken {
+    pub kind: SyntaxKind,
+    pub text: String,
+}
+
+impl GreenToken {
+    pub fn new(kind: SyntaxKind, text: String) -> Self {
+        Self { kind, text }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GreenElement {
+    Node(Arc<GreenNode>),
+    Token(Arc<GreenToken>),
+}
+
+impl GreenElement {
+    pub fn kind(&self) -> SyntaxKind {
+        match self {
+            GreenElement::Node(node) => node.kind,
+            GreenElement::Token(token) => token.kind,
+        }
+    }
+
+    pub fn text_len(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.text_len,
+            GreenElement::Token(token) => token.text.len(),
+        }
+    }
+
+    fn write_text(&self, output: &mut String) {
+        match self {
+            GreenElement::Node(node) => node.write_text(output),
+            GreenElement::Token(token) => output.push_str(&token.text),
+        }
+    }
+}
+
+/// A node of the green tree. Green nodes know their kind, their children
+/// and the length of their text but not where they are, so an unchanged
+/// subtree can be shared between two versions of a file. Every token and
+/// every piece of trivia of the source is in the tree, in order, so the
+/// text of the root is the source text byte for byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GreenNode {
+    pub kind: SyntaxKind,
+    text_len: usize,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub fn new(kind: SyntaxKind, children: Vec<GreenElement>) -> Self {
+        let text_len = children.iter().map(GreenElement::text_len).sum();
+        Self { kind, text_len, children }
+    }
+
+    pub fn text_len(&self) -> usize {
+        self.text_len
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+
+    /// A copy of this node with child `index` replaced by `element`.
+    pub fn replace_child(&self, index: usize, element: GreenElement) -> GreenNode {
+        let mut children = self.children.clone();
+        children[index] = element;
+        GreenNode::new(self.kind, children)
+    }
+
+    fn write_text(&self, output: &mut String) {
+        for child in self.children.iter() {
+            child.write_text(output);
+        }
+    }
+}
+
+impl Display for GreenNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut text = String::with_capacity(self.text_len);
+        self.write_text(&mut text);
+        write!(f, "{}", text)
+    }
+}
+
+/// A position in a `GreenBuilder` to wrap what follows into a node once it
+/// is known which node it starts, as the left operand of a binary
+/// expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint(usize);
+
+/// Builds a green tree from the tokens the parser consumes, in order.
+#[derive(Debug, Default)]
+pub struct GreenBuilder {
+    /// The nodes being built and the index in `children` of their first
+    /// child.
+    parents: Vec<(SyntaxKind, usize)>,
+    children: Vec<GreenElement>,
+}
+
+impl GreenBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start_node(&mut self, kind: SyntaxKind) {
+        self.parents.push((kind, self.children.len()));
+    }
+
+    pub fn finish_node(&mut self) {
+        let (kind, first_child) = self.parents.pop().expect("finish_node without start_node");
+        let children = self.children.split_off(first_child);
+        self.children.push(GreenElement::Node(Arc::new(GreenNode::new(kind, children))));
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.children.len())
+    }
+
+    /// Makes everything added since `checkpoint` the children of a new
+    /// `kind` node.
+    pub fn wrap(&mut self, checkpoint: Checkpoint, kind: SyntaxKind) {
+        let children = self.children.split_off(checkpoint.0);
+        self.children.push(GreenElement::Node(Arc::new(GreenNode::new(kind, children))));
+    }
+
+    /// Adds `token` with its leading and trailing trivia.
+    pub fn token(&mut self, token: &Token) {
+        self.trivia(&token.leading_trivia);
+        let kind = SyntaxKind::Token(token.kind);
+        self.children.push(GreenElement::Token(Arc::new(GreenToken::new(kind, token.span.literal.clone()))));
+        self.trivia(&token.trailing_trivia);
+    }
+
+    pub fn trivia(&mut self, trivia: &[Trivia]) {
+        for trivia in trivia.iter() {
+            let kind = SyntaxKind::Trivia(trivia.kind);
+            self.children.push(GreenElement::Token(Arc::new(GreenToken::new(kind, trivia.span.literal.clone()))));
+        }
+    }
+
+    /// Adds elements built before, such as the syntax of reused items.
+    pub fn elements(&mut self, elements: impl IntoIterator<Item = GreenElement>) {
+        self.children.extend(elements);
+    }
+
+    /// What was added since `checkpoint` at the current level.
+    pub fn children_since(&self, checkpoint: Checkpoint) -> Vec<GreenElement> {
+        self.children[checkpoint.0..].to_vec()
+    }
+
+    /// Finishes the outermost node and returns it.
+    pub fn finish(&mut self) -> Arc<GreenNode> {
+        while !self.parents.is_empty() {
+            self.finish_node();
+        }
+        match self.children.pop() {
+            Some(GreenElement::Node(node)) if self.children.is_empty() => node,
+            _ => panic!("a green tree must have a single root node"),
+        }
+    }
+}
+
+/// A node of the red tree: a green node at a position, with a link to its
+/// parent. Red nodes are made on the fly while walking down from the root.
+#[derive(Debug, Clone)]
+pub struct SyntaxNode(Rc<SyntaxNodeData>);
+
+#[derive(Debug)]
+struct SyntaxNodeData {
+    green: Arc<GreenNode>,
+    offset: usize,
+    parent: Option<SyntaxNode>,
+    /// The index of the node among the children of its parent.
+    index: usize,
+}
+
+impl PartialEq for SyntaxNode {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0.green, &other.0.green) && self.0.offset == other.0.offset
+    }
+}
+
+impl Eq for SyntaxNode {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxToken {
+    green: Arc<GreenToken>,
+    offset: usize,
+    parent: SyntaxNode,
+    index: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: Arc<GreenNode>) -> Self {
+        Self(Rc::new(SyntaxNodeData {
+            green,
+            offset: 0,
+            parent: None,
+            index: 0,
+        }))
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.0.green.kind
+    }
+
+    pub fn green(&self) -> &Arc<GreenNode> {
+        &self.0.green
+    }
+
+    pub fn text_range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.text_len()
+    }
+
+    pub fn text(&self) -> String {
+        self.0.green.to_string()
+    }
+
+    pub fn parent(&self) -> Option<SyntaxNode> {
+        self.0.parent.clone()
+    }
+
+    pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+        let mut children = Vec::with_capacity(self.0.green.children().len());
+        for (index, child) in self.0.green.children().iter().enumerate() {
+            children.push(match child {
+                GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(SyntaxNodeData {
+                    green: green.clone(),
+                    offset,
+                    parent: Some(self.clone()),
+                    index,
+                }))),
+                GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                    green: green.clone(),
+                    offset,
+                    parent: self.clone(),
+                    index,
+                }),
+            });
+            offset += child.text_len();
+        }
+        children
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = SyntaxNode> {
+        self.children_with_tokens().into_iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// The child tokens of the node, trivia left out.
+    pub fn child_tokens(&self) -> impl Iterator<Item = SyntaxToken> {
+        self.children_with_tokens().into_iter().filter_map(|child| match child {
+            SyntaxElement::Token(token) if !token.kind().is_trivia() => Some(token),
+            _ => None,
+        })
+    }
+
+    pub fn child_token(&self, kind: TokenKind) -> Option<SyntaxToken> {
+        self.child_tokens().find(|token| token.kind() == SyntaxKind::Token(kind))
+    }
+
+    pub fn child_node(&self, kind: SyntaxKind) -> Option<SyntaxNode> {
+        self.children().find(|child| child.kind() == kind)
+    }
+
+    /// The node and all nodes below it, parents before their children.
+    pub fn descendants(&self) -> Vec<SyntaxNode> {
+        let mut descendants = vec![self.clone()];
+        let mut index = 0;
+        while index < descendants.len() {
+            let children: Vec<SyntaxNode> = descendants[index].children().collect();
+            descendants.splice(index + 1..index + 1, children);
+            index += 1;
+        }
+        descendants
+    }
+
+    /// Every token below the node, trivia included, in source order.
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        let mut tokens = Vec::new();
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+
+    /// The token that contains the byte `offset`.
+    pub fn token_at_offset(&self, offset: usize) -> Option<SyntaxToken> {
+        let mut node = self.clone();
+        loop {
+            let child = node
+                .children_with_tokens()
+                .into_iter()
+                .find(|child| match child {
+                    SyntaxElement::Node(child) => child.text_range().contains(&offset),
+                    SyntaxElement::Token(child) => child.text_range().contains(&offset),
+                })?;
+            match child {
+                SyntaxElement::Node(child) => node = child,
+                SyntaxElement::Token(token) => return Some(token),
+            }
+        }
+    }
+
+    /// The root of a tree in which this node is replaced by `green`. The
+    /// rest of the tree is shared with this one.
+    pub fn replace_with(&self, green: GreenNode) -> Arc<GreenNode> {
+        replace_in_parent(self.parent(), self.0.index, GreenElement::Node(Arc::new(green)))
+    }
+}
+
+impl Display for SyntaxNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.green)
+    }
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> SyntaxKind {
+        self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    pub fn text_range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.text.len()
+    }
+
+    pub fn parent(&self) -> SyntaxNode {
+        self.parent.clone()
+    }
+
+    /// The root of a tree in which this token is replaced by `green`, as in
+    /// renaming an identifier.
+    pub fn replace_with(&self, green: GreenToken) -> Arc<GreenNode> {
+        replace_in_parent(Some(self.parent()), self.index, GreenElement::Token(Arc::new(green)))
+    }
+}
+
+/// Replaces child `index` of `parent` by `element` and rebuilds the nodes
+/// above it up to the root.
+fn replace_in_parent(parent: Option<SyntaxNode>, index: usize, element: GreenElement) -> Arc<GreenNode> {
+    match (parent, element) {
+        (Some(parent), element) => parent.replace_with(parent.green().replace_child(index, element)),
+        (None, GreenElement::Node(root)) => root,
+        (None, GreenElement::Token(_)) => panic!("a token cannot be the root of a tree"),
+    }
+}
+
+/// Prints the tree with one node or token per line, indented by depth, as
+/// `LetStatement@0..12` or `Identifier@4..5 "x"`.
+pub fn debug_tree(node: &SyntaxNode) -> String {
+    fn print(node: &SyntaxNode, depth: usize, output: &mut String) {
+        let range = node.text_range();
+        output.push_str(&format!("{}{}@{}..{}\n", "  ".repeat(depth), node.kind(), range.start, range.end));
+        for child in node.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(child) => print(&child, depth + 1, output),
+                SyntaxElement::Token(token) => {
+                    let range = token.text_range();
+                    output.push_str(&format!(
+                        "{}{}@{}..{} {:?}\n",
+                        "  ".repeat(depth + 1),
+                        token.kind(),
+                        range.start,
+                        range.end,
+                        token.text()
+                    ));
+                }
+            }
+        }
+    }
+    let mut output = String::new();
+    print(node, 0, &mut output);
+    output
+}
@@ -0,0 +1,323 @@
+//! Typed views over the syntax tree. Each view wraps a `SyntaxNode` of one
+//! kind and finds its parts among the children, so a part that is missing
+//! from broken source comes back as `None` instead of a made up token.
+
+use super::{
+    lexer::TokenKind,
+    syntax::{SyntaxKind, SyntaxNode, SyntaxToken},
+};
+
+pub trait SyntaxView: Sized {
+    /// Wraps `node` if it has the kind of the view.
+    fn cast(node: SyntaxNode) -> Option<Self>;
+
+    fn syntax(&self) -> &SyntaxNode;
+}
+
+macro_rules! syntax_view {
+    ($name:ident, $kind:ident) => {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct $name(SyntaxNode);
+
+        impl SyntaxView for $name {
+            fn cast(node: SyntaxNode) -> Option<Self> {
+                (node.kind() == SyntaxKind::$kind).then_some(Self(node))
+            }
+
+            fn syntax(&self) -> &SyntaxNode {
+                &self.0
+            }
+        }
+    };
+}
+
+syntax_view!(FileSyntax, File);
+syntax_view!(ImportSyntax, Import);
+syntax_view!(FunctionSyntax, Function);
+syntax_view!(ParameterSyntax, Parameter);
+syntax_view!(TypeAnnotationSyntax, TypeAnnotation);
+syntax_view!(BodySyntax, Body);
+syntax_view!(LetStatementSyntax, LetStatement);
+syntax_view!(ReturnStatementSyntax, ReturnStatement);
+syntax_view!(ExpressionStatementSyntax, ExpressionStatement);
+syntax_view!(NumberExprSyntax, NumberExpr);
+syntax_view!(FloatExprSyntax, FloatExpr);
+syntax_view!(StringExprSyntax, StringExpr);
+syntax_view!(VariableExprSyntax, VariableExpr);
+syntax_view!(CallExprSyntax, CallExpr);
+syntax_view!(BinaryExprSyntax, BinaryExpr);
+syntax_view!(ParenthesizedExprSyntax, ParenthesizedExpr);
+syntax_view!(ErrorExprSyntax, ErrorExpr);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatementSyntax {
+    Let(LetStatementSyntax),
+    Return(ReturnStatementSyntax),
+    Expression(ExpressionStatementSyntax),
+}
+
+impl SyntaxView for StatementSyntax {
+    fn cast(node: SyntaxNode) -> Option<Self> {
+        match node.kind() {
+            SyntaxKind::LetStatement => Some(StatementSyntax::Let(LetStatementSyntax(node))),
+            SyntaxKind::ReturnStatement => Some(StatementSyntax::Return(ReturnStatementSyntax(node))),
+            SyntaxKind::ExpressionStatement => Some(StatementSyntax::Expression(ExpressionStatementSyntax(node))),
+            _ => None,
+        }
+    }
+
+    fn syntax(&self) -> &SyntaxNode {
+        match self {
+            StatementSyntax::Let(statement) => statement.syntax(),
+            StatementSyntax::Return(statement) => statement.syntax(),
+            StatementSyntax::Expression(statement) => statement.syntax(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpressionSyntax {
+    Number(NumberExprSyntax),
+    Float(FloatExprSyntax),
+    String(StringExprSyntax),
+    Variable(VariableExprSyntax),
+    Call(CallExprSyntax),
+    Binary(BinaryExprSyntax),
+    Parenthesized(ParenthesizedExprSyntax),
+    Error(ErrorExprSyntax),
+}
+
+impl SyntaxView for ExpressionSyntax {
+    fn cast(node: SyntaxNode) -> Option<Self> {
+        match node.kind() {
+            SyntaxKind::NumberExpr => Some(ExpressionSyntax::Number(NumberExprSyntax(node))),
+            SyntaxKind::FloatExpr => Some(ExpressionSyntax::Float(FloatExprSyntax(node))),
+            SyntaxKind::StringExpr => Some(ExpressionSyntax::String(StringExprSyntax(node))),
+            SyntaxKind::VariableExpr => Some(ExpressionSyntax::Variable(VariableExprSyntax(node))),
+            SyntaxKind::CallExpr => Some(ExpressionSyntax::Call(CallExprSyntax(node))),
+            SyntaxKind::BinaryExpr => Some(ExpressionSyntax::Binary(BinaryExprSyntax(node))),
+            SyntaxKind::ParenthesizedExpr => Some(ExpressionSyntax::Parenthesized(ParenthesizedExprSyntax(node))),
+            SyntaxKind::ErrorExpr => Some(ExpressionSyntax::Error(ErrorExprSyntax(node))),
+            _ => None,
+        }
+    }
+
+    fn syntax(&self) -> &SyntaxNode {
+        match self {
+            ExpressionSyntax::Number(expression) => expression.syntax(),
+            ExpressionSyntax::Float(expression) => expression.syntax(),
+            ExpressionSyntax::String(expression) => expression.syntax(),
+            ExpressionSyntax::Variable(expression) => expression.syntax(),
+            ExpressionSyntax::Call(expression) => expression.syntax(),
+            ExpressionSyntax::Binary(expression) => expression.syntax(),
+            ExpressionSyntax::Parenthesized(expression) => expression.syntax(),
+            ExpressionSyntax::Error(expression) => expression.syntax(),
+        }
+    }
+}
+
+/// The children of `node` that `V` views, in order.
+fn children<V: SyntaxView>(node: &SyntaxNode) -> Vec<V> {
+    node.children().filter_map(V::cast).collect()
+}
+
+fn child<V: SyntaxView>(node: &SyntaxNode) -> Option<V> {
+    node.children().find_map(V::cast)
+}
+
+impl FileSyntax {
+    pub fn imports(&self) -> Vec<ImportSyntax> {
+        children(&self.0)
+    }
+
+    pub fn functions(&self) -> Vec<FunctionSyntax> {
+        children(&self.0)
+    }
+
+    /// The top-level statements, without the ones in function bodies.
+    pub fn statements(&self) -> Vec<StatementSyntax> {
+        children(&self.0)
+    }
+}
+
+impl ImportSyntax {
+    /// The quoted path of `import "geometry.calc"`.
+    pub fn path(&self) -> Option<SyntaxToken> {
+        self.0.child_token(TokenKind::String)
+    }
+
+    /// The module of `use geometry::area`.
+    pub fn module(&self) -> Option<SyntaxToken> {
+        qualifier(&self.0)
+    }
+
+    /// The name of `use geometry::area`.
+    pub fn name(&self) -> Option<SyntaxToken> {
+        qualifier(&self.0).and(qualified_name(&self.0))
+    }
+}
+
+/// The module name in front of a `::`.
+fn qualifier(node: &SyntaxNode) -> Option<SyntaxToken> {
+    let tokens: Vec<SyntaxToken> = node.child_tokens().collect();
+    let colons = tokens.iter().position(|token| token.kind() == SyntaxKind::Token(TokenKind::ColonColon))?;
+    identifier(tokens.get(colons.checked_sub(1)?))
+}
+
+/// The name after the `::` if there is one, else the first identifier.
+fn qualified_name(node: &SyntaxNode) -> Option<SyntaxToken> {
+    let tokens: Vec<SyntaxToken> = node.child_tokens().collect();
+    match tokens.iter().position(|token| token.kind() == SyntaxKind::Token(TokenKind::ColonColon)) {
+        Some(colons) => identifier(tokens.get(colons + 1)),
+        None => node.child_token(TokenKind::Identifier),
+    }
+}
+
+fn identifier(token: Option<&SyntaxToken>) -> Option<SyntaxToken> {
+    token.filter(|token| token.kind() == SyntaxKind::Token(TokenKind::Identifier)).cloned()
+}
+
+impl FunctionSyntax {
+    pub fn name(&self) -> Option<SyntaxToken> {
+        self.0.child_token(TokenKind::Identifier)
+    }
+
+    pub fn parameters(&self) -> Vec<ParameterSyntax> {
+        children(&self.0)
+    }
+
+    pub fn return_type(&self) -> Option<TypeAnnotationSyntax> {
+        child(&self.0)
+    }
+
+    pub fn body(&self) -> Option<BodySyntax> {
+        child(&self.0)
+    }
+}
+
+impl ParameterSyntax {
+    pub fn name(&self) -> Option<SyntaxToken> {
+        self.0.child_token(TokenKind::Identifier)
+    }
+
+    pub fn type_annotation(&self) -> Option<TypeAnnotationSyntax> {
+        child(&self.0)
+    }
+}
+
+impl TypeAnnotationSyntax {
+    /// The `:` or `->` in front of the type.
+    pub fn separator(&self) -> Option<SyntaxToken> {
+        self.0.child_tokens().next()
+    }
+
+    pub fn type_name(&self) -> Option<SyntaxToken> {
+        self.0.child_token(TokenKind::Identifier)
+    }
+}
+
+impl BodySyntax {
+    pub fn statements(&self) -> Vec<StatementSyntax> {
+        children(&self.0)
+    }
+}
+
+impl LetStatementSyntax {
+    pub fn name(&self) -> Option<SyntaxToken> {
+        self.0.child_token(TokenKind::Identifier)
+    }
+
+    pub fn type_annotation(&self) -> Option<TypeAnnotationSyntax> {
+        child(&self.0)
+    }
+
+    pub fn initializer(&self) -> Option<ExpressionSyntax> {
+        child(&self.0)
+    }
+}
+
+impl ReturnStatementSyntax {
+    pub fn value(&self) -> Option<ExpressionSyntax> {
+        child(&self.0)
+    }
+}
+
+impl ExpressionStatementSyntax {
+    pub fn expression(&self) -> Option<ExpressionSyntax> {
+        child(&self.0)
+    }
+}
+
+impl NumberExprSyntax {
+    pub fn value(&self) -> Option<i64> {
+        self.0.child_tokens().find_map(|token| match token.kind() {
+            SyntaxKind::Token(TokenKind::Number(value)) => Some(value),
+            _ => None,
+        })
+    }
+}
+
+impl FloatExprSyntax {
+    pub fn token(&self) -> Option<SyntaxToken> {
+        self.0.child_token(TokenKind::Float)
+    }
+}
+
+impl StringExprSyntax {
+    /// The string literal, quotes included.
+    pub fn token(&self) -> Option<SyntaxToken> {
+        self.0.child_token(TokenKind::String)
+    }
+}
+
+impl VariableExprSyntax {
+    /// The module of a qualified name, as the `geometry` of `geometry::pi`.
+    pub fn qualifier(&self) -> Option<SyntaxToken> {
+        qualifier(&self.0)
+    }
+
+    pub fn name(&self) -> Option<SyntaxToken> {
+        qualified_name(&self.0)
+    }
+}
+
+impl CallExprSyntax {
+    pub fn qualifier(&self) -> Option<SyntaxToken> {
+        qualifier(&self.0)
+    }
+
+    pub fn callee(&self) -> Option<SyntaxToken> {
+        qualified_name(&self.0)
+    }
+
+    pub fn arguments(&self) -> Vec<ExpressionSyntax> {
+        children(&self.0)
+    }
+}
+
+impl BinaryExprSyntax {
+    pub fn left(&self) -> Option<ExpressionSyntax> {
+        child(&self.0)
+    }
+
+    pub fn operator(&self) -> Option<SyntaxToken> {
+        self.0.child_tokens().next()
+    }
+
+    pub fn right(&self) -> Option<ExpressionSyntax> {
+        children(&self.0).into_iter().nth(1)
+    }
+}
+
+impl ParenthesizedExprSyntax {
+    pub fn expression(&self) -> Option<ExpressionSyntax> {
+        child(&self.0)
+    }
+}
+
+impl ErrorExprSyntax {
+    /// The token the parser skipped, if the expression is not just missing.
+    pub fn token(&self) -> Option<SyntaxToken> {
+        self.0.child_tokens().next()
+    }
+}
@@ -1,14 +1,23 @@
+use std::fmt::{Display, Formatter};
+
+use crate::id_gen;
+
+use super::lib::{Id, IdVec};
+
+id_gen!(FileId);
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TextSpan {
+    pub file_id: FileId,
     pub start: usize,
     pub end: usize,
     pub literal: String,
 }
 
 impl TextSpan {
-    pub fn new(start: usize, end: usize, literal: String) -> Self {
+    pub fn new(file_id: FileId, start: usize, end: usize, literal: String) -> Self {
         Self {
+            file_id,
             start,
             end,
             literal,
@@ -19,6 +28,132 @@ impl TextSpan {
         self.end - self.start
     }
 
-  
+    /// Moves the span by `delta` bytes, for text inserted or removed before it.
+    pub fn shift(&mut self, delta: isize) {
+        self.start = self.start.wrapping_add_signed(delta);
+        self.end = self.end.wrapping_add_signed(delta);
+    }
+
+    /// Builds the smallest span covering all of `spans`. The literal is left
+    /// empty: the text between the pieces, whitespace and comments, is only
+    /// in the source, so use `SourceFile::slice` for the text of the span.
+    pub fn combine(mut spans: Vec<TextSpan>) -> TextSpan {
+        spans.sort_by_key(|span| span.start);
+        let file_id = spans.first().map(|span| span.file_id).unwrap_or(FileId::new(0));
+        let start = spans.first().map(|span| span.start).unwrap_or(0);
+        let end = spans.iter().map(|span| span.end).max().unwrap_or(start);
+        TextSpan::new(file_id, start, end, String::new())
+    }
+}
+
+/// A zero based line and column. The column counts characters, not bytes.
+/// `Display` prints both one based, the way editors show them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for LineCol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line + 1, self.column + 1)
+    }
+}
+
+/// The text of one input file together with the byte offset at which each
+/// of its lines starts, so offsets can be turned into lines and columns
+/// without rescanning the text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    pub id: FileId,
+    pub name: String,
+    pub text: String,
+    line_starts: Vec<usize>,
 }
 
+impl SourceFile {
+    pub fn new(id: FileId, name: String, text: String) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        Self {
+            id,
+            name,
+            text,
+            line_starts,
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Byte offset at which line `line` starts.
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.line_starts.get(line).copied()
+    }
+
+    /// Index of the line containing the byte `offset`. Offsets past the end
+    /// of the text belong to the last line.
+    pub fn line_index(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        }
+    }
+
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.line_index(offset);
+        let line_start = self.line_starts[line];
+        let column = self.text[line_start..offset].chars().count();
+        LineCol { line, column }
+    }
+
+    /// The text of line `line` without its line break.
+    pub fn line_text(&self, line: usize) -> &str {
+        let start = self.line_starts[line];
+        let end = self.line_starts.get(line + 1).copied().unwrap_or(self.text.len());
+        self.text[start..end].trim_end_matches(['\n', '\r'])
+    }
+
+    /// The source text `span` covers, or nothing if it is out of range.
+    pub fn slice(&self, span: &TextSpan) -> &str {
+        self.text.get(span.start..span.end).unwrap_or("")
+    }
+
+    /// `name:line:column` of the start of `span`, e.g. `area.calc:3:14`.
+    pub fn location(&self, span: &TextSpan) -> String {
+        format!("{}:{}", self.name, self.line_col(span.start))
+    }
+}
+
+/// Owns every `SourceFile` of a compilation and hands out their `FileId`s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMap {
+    pub files: IdVec<FileId, SourceFile>,
+}
+
+impl Default for SourceMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self { files: IdVec::new() }
+    }
+
+    pub fn add_file(&mut self, name: String, text: String) -> FileId {
+        let id = FileId::new(self.files.data.len());
+        self.files.push(SourceFile::new(id, name, text))
+    }
+
+    pub fn get(&self, file_id: FileId) -> &SourceFile {
+        self.files.get(file_id)
+    }
+}
@@ -0,0 +1,365 @@
+use crate::diagnostics::DiagnosticsBagCell;
+
+use super::{
+    builtins::Builtin,
+    lib::IdVec,
+    text::TextSpan,
+    types::{FunctionType, Type},
+    visitor::Visitor,
+    Ast, BinOperatorKind, ExprId, FunctionId, ItemKind, StatementKind, StmtId, TypeAnnotation, VariableId,
+};
+
+/// Assigns a `Type` to every expression, variable and function of an `Ast`.
+///
+/// Types that are not known yet, such as those of parameters and function
+/// results, start as fresh `Type::Var`s and are worked out by unification as
+/// the checker meets their uses. Every function gets its signature before
+/// any body is checked, so a call may come before the function it calls.
+/// Whatever is still unknown at the end is taken to be `int`. Type
+/// annotations fix a type up front and are enforced like any other use.
+///
+/// The checker also validates control flow: `return` only inside a
+/// function, no statements after a `return`, and a value returned on every
+/// path of a function that has a result.
+pub struct TypeChecker {
+    pub types: IdVec<ExprId, Type>,
+    pub variable_types: IdVec<VariableId, Type>,
+    pub function_types: IdVec<FunctionId, FunctionType>,
+    substitutions: Vec<Option<Type>>,
+    /// Operators whose operand type was still unknown when they were
+    /// checked, verified again once every type is known.
+    deferred_operators: Vec<(BinOperatorKind, Type, TextSpan)>,
+    function: Option<FunctionId>,
+    ty: Type,
+    diagnostics: DiagnosticsBagCell,
+}
+
+impl TypeChecker {
+    pub fn new(ast: &Ast, diagnostics: DiagnosticsBagCell) -> Self {
+        // The side tables are indexed by the arenas, but only the nodes that
+        // `top_level` reaches get types: an incremental reparse leaves the
+        // nodes it replaced in the arenas.
+        let mut checker = Self {
+            types: IdVec::filled(Type::Error, ast.expressions.data.len()),
+            variable_types: IdVec::filled(Type::Error, ast.variables.data.len()),
+            function_types: IdVec::filled(FunctionType::new(Vec::new(), Type::Error), ast.functions.data.len()),
+            substitutions: Vec::new(),
+            deferred_operators: Vec::new(),
+            function: None,
+            ty: Type::Error,
+            diagnostics,
+        };
+        for item in ast.top_level_items() {
+            match item.kind {
+                ItemKind::Statement(stmt_id) => checker.declare_let(ast, stmt_id),
+                ItemKind::Function(function_id) => checker.declare_function(ast, function_id),
+                ItemKind::Import(_) => {}
+            }
+        }
+        checker
+    }
+
+    /// Gives the variable of a `let` a type to be worked out.
+    fn declare_let(&mut self, ast: &Ast, stmt_id: StmtId) {
+        if let StatementKind::Let(let_stmt) = &ast.query_stmt(stmt_id).stmt_kind {
+            let ty = self.fresh();
+            *self.variable_types.get_mut(let_stmt.variable_id) = ty;
+        }
+    }
+
+    /// Gives a function its signature, with the types its annotations name,
+    /// and its locals types to be worked out.
+    fn declare_function(&mut self, ast: &Ast, function_id: FunctionId) {
+        let function = ast.query_function(function_id);
+        let mut parameters = Vec::new();
+        for parameter in function.parameters.iter() {
+            let ty = self.fresh();
+            if let Some(type_annotation) = &parameter.type_annotation {
+                let annotated = self.annotation_type(type_annotation);
+                self.bind(ty, annotated);
+            }
+            *self.variable_types.get_mut(parameter.variable_id) = ty;
+            parameters.push(ty);
+        }
+        let return_type = self.fresh();
+        if let Some(type_annotation) = &function.return_type {
+            let annotated = self.annotation_type(type_annotation);
+            self.bind(return_type, annotated);
+        }
+        *self.function_types.get_mut(function_id) = FunctionType::new(parameters, return_type);
+        for stmt_id in function.body.statements.iter() {
+            self.declare_let(ast, *stmt_id);
+        }
+    }
+
+    /// The declaration of a function with the types it was given, as
+    /// `function area(width: int, height: int) -> int`.
+    pub fn function_signature(&self, ast: &Ast, function_id: FunctionId) -> String {
+        let function = ast.query_function(function_id);
+        let function_type = self.function_types.get(function_id);
+        let parameters: Vec<String> = function
+            .parameters
+            .iter()
+            .zip(function_type.parameters.iter())
+            .map(|(parameter, ty)| format!("{}: {}", parameter.identifier.span.literal, ty))
+            .collect();
+        format!("function {}({}) -> {}", function.name, parameters.join(", "), function_type.return_type)
+    }
+
+    /// Defaults the types that are still unknown to `int` and replaces every
+    /// type variable in the side tables by the type it stands for.
+    pub fn finish(&mut self) {
+        for index in 0..self.substitutions.len() {
+            if let Type::Var(unbound) = self.resolve(Type::Var(index)) {
+                self.substitutions[unbound] = Some(Type::Int);
+            }
+        }
+        for index in 0..self.types.data.len() {
+            self.types.data[index] = self.resolve(self.types.data[index]);
+        }
+        for index in 0..self.variable_types.data.len() {
+            self.variable_types.data[index] = self.resolve(self.variable_types.data[index]);
+        }
+        for index in 0..self.function_types.data.len() {
+            let function_type = &self.function_types.data[index];
+            let parameters = function_type.parameters.iter().map(|ty| self.resolve(*ty)).collect();
+            let return_type = self.resolve(function_type.return_type);
+            self.function_types.data[index] = FunctionType::new(parameters, return_type);
+        }
+        for (operator, operand, span) in std::mem::take(&mut self.deferred_operators) {
+            let operand = self.resolve(operand);
+            if !Self::accepts(operator, operand) {
+                self.diagnostics.borrow_mut().report_invalid_operands(operator, &operand, &operand, span);
+            }
+        }
+    }
+
+    /// Whether `operator` applies to two operands of type `operand`: `+`
+    /// adds numbers and concatenates strings, the other operators only take
+    /// numbers.
+    fn accepts(operator: BinOperatorKind, operand: Type) -> bool {
+        match operand {
+            Type::Int | Type::Float | Type::Error | Type::Var(_) => true,
+            Type::String => operator == BinOperatorKind::Plus,
+            Type::Void => false,
+        }
+    }
+
+    fn annotation_type(&mut self, type_annotation: &TypeAnnotation) -> Type {
+        match Type::from_name(type_annotation.type_name()) {
+            Some(ty) => ty,
+            // The parser has reported the missing name.
+            None if type_annotation.type_name().is_empty() => Type::Error,
+            None => {
+                self.diagnostics.borrow_mut().report_unknown_type(&type_annotation.name.span);
+                Type::Error
+            }
+        }
+    }
+
+    /// Fixes the type of a variable that has not been constrained yet, even
+    /// to `Type::Error`, which `unify` would leave unbound.
+    fn bind(&mut self, variable_type: Type, ty: Type) {
+        if let Type::Var(index) = self.resolve(variable_type) {
+            self.substitutions[index] = Some(ty);
+        }
+    }
+
+    fn fresh(&mut self) -> Type {
+        self.substitutions.push(None);
+        Type::Var(self.substitutions.len() - 1)
+    }
+
+    fn resolve(&self, mut ty: Type) -> Type {
+        while let Type::Var(index) = ty {
+            match self.substitutions[index] {
+                Some(bound) => ty = bound,
+                None => break,
+            }
+        }
+        ty
+    }
+
+    /// Makes `left` and `right` the same type. Returns `false` when they are
+    /// two different known types.
+    fn unify(&mut self, left: Type, right: Type) -> bool {
+        match (self.resolve(left), self.resolve(right)) {
+            (Type::Error, _) | (_, Type::Error) => true,
+            (Type::Var(left), Type::Var(right)) if left == right => true,
+            (Type::Var(index), other) | (other, Type::Var(index)) => {
+                self.substitutions[index] = Some(other);
+                true
+            }
+            (left, right) => left == right,
+        }
+    }
+
+    fn expect(&mut self, ast: &Ast, expr_id: ExprId, expected: Type) {
+        let found = *self.types.get(expr_id);
+        if !self.unify(expected, found) {
+            let span = ast.query_expr(expr_id).span(ast);
+            self.report_mismatch(expected, found, span);
+        }
+    }
+
+    fn report_mismatch(&mut self, expected: Type, found: Type, span: TextSpan) {
+        let (expected, found) = (self.resolve(expected), self.resolve(found));
+        self.diagnostics.borrow_mut().report_type_mismatch(&expected, &found, span);
+    }
+
+    fn check_call(&mut self, ast: &Ast, call_expr: &super::CallExpr, signature: FunctionType) -> Type {
+        if call_expr.arguments.len() != signature.parameters.len() {
+            let span = TextSpan::combine(vec![call_expr.callee.span.clone(), call_expr.close_paren.span.clone()]);
+            self.diagnostics.borrow_mut().report_wrong_argument_count(
+                call_expr.name(),
+                signature.parameters.len(),
+                call_expr.arguments.len(),
+                span,
+            );
+            return Type::Error;
+        }
+        for (argument, parameter) in call_expr.arguments.iter().zip(signature.parameters.iter()) {
+            self.expect(ast, *argument, *parameter);
+        }
+        signature.return_type
+    }
+}
+
+impl Visitor for TypeChecker {
+    fn visit_function(&mut self, ast: &Ast, function_id: FunctionId) {
+        let function = ast.query_function(function_id);
+        self.function = Some(function_id);
+        let mut returned = false;
+        let mut reported_unreachable = false;
+        for stmt_id in function.body.statements.iter() {
+            let stmt = ast.query_stmt(*stmt_id);
+            // Only the first unreachable statement is reported.
+            if returned && !reported_unreachable {
+                self.diagnostics.borrow_mut().report_unreachable_code(stmt.span(ast));
+                reported_unreachable = true;
+            }
+            self.visit_statement(ast, *stmt_id);
+            returned = returned || matches!(stmt.stmt_kind, StatementKind::Return(_));
+        }
+        self.function = None;
+        // A function that can reach the end of its body returns nothing,
+        // which is only fine if nothing expects a value from it.
+        let return_type = self.function_types.get(function_id).return_type;
+        if !returned && !self.unify(return_type, Type::Void) {
+            let return_type = self.resolve(return_type);
+            self.diagnostics.borrow_mut().report_missing_return(&function.name, &return_type, function.body.close_brace.span.clone());
+        }
+    }
+
+    fn visit_expression(&mut self, ast: &Ast, expr_id: ExprId) {
+        self.ty = Type::Error;
+        self.do_visit_expression(ast, expr_id);
+        *self.types.get_mut(expr_id) = self.ty;
+    }
+
+    fn visit_let_statement(&mut self, ast: &Ast, stmt: &super::LetStatement) {
+        self.visit_expression(ast, stmt.initializer);
+        let variable_type = *self.variable_types.get(stmt.variable_id);
+        if let Some(type_annotation) = &stmt.type_annotation {
+            let annotated = self.annotation_type(type_annotation);
+            self.bind(variable_type, annotated);
+            self.expect(ast, stmt.initializer, annotated);
+            return;
+        }
+        let initializer_type = self.resolve(*self.types.get(stmt.initializer));
+        if initializer_type == Type::Void {
+            let span = ast.query_expr(stmt.initializer).span(ast);
+            self.diagnostics.borrow_mut().report_void_value(span);
+            return;
+        }
+        self.unify(variable_type, initializer_type);
+    }
+
+    fn visit_return_statement(&mut self, ast: &Ast, stmt: &super::ReturnStatement) {
+        self.visit_expression(ast, stmt.value);
+        match self.function {
+            Some(function_id) => {
+                let return_type = self.function_types.get(function_id).return_type;
+                self.expect(ast, stmt.value, return_type);
+            }
+            None => self.diagnostics.borrow_mut().report_return_outside_function(&stmt.return_keyword.span),
+        }
+    }
+
+    fn visit_number(&mut self, _ast: &Ast, _number: &super::NumberExpr) {
+        self.ty = Type::Int;
+    }
+
+    fn visit_float(&mut self, _ast: &Ast, _number: &super::FloatExpr) {
+        self.ty = Type::Float;
+    }
+
+    fn visit_string(&mut self, _ast: &Ast, _string: &super::StringExpr) {
+        self.ty = Type::String;
+    }
+
+    fn visit_variable_expression(&mut self, _ast: &Ast, variable_expr: &super::VariableExpr) {
+        // An undefined variable has already been reported by the parser.
+        self.ty = match variable_expr.variable_id {
+            Some(variable_id) => *self.variable_types.get(variable_id),
+            None => Type::Error,
+        };
+    }
+
+    fn visit_call_expression(&mut self, ast: &Ast, call_expr: &super::CallExpr) {
+        for argument in call_expr.arguments.iter() {
+            self.visit_expression(ast, *argument);
+        }
+        // Built-ins are not in any module, and a qualified name that does
+        // not resolve has been reported by the parser.
+        let builtin = call_expr.qualifier.is_none().then(|| Builtin::lookup(call_expr.name())).flatten();
+        let signature = match (ast.resolve_call(call_expr), builtin) {
+            (Some(function_id), _) => self.function_types.get(function_id).clone(),
+            (None, Some(builtin)) => builtin.signature(),
+            (None, None) => {
+                if call_expr.qualifier.is_none() {
+                    self.diagnostics.borrow_mut().report_undefined_function(&call_expr.callee.span);
+                }
+                self.ty = Type::Error;
+                return;
+            }
+        };
+        self.ty = self.check_call(ast, call_expr, signature);
+    }
+
+    fn visit_binary_expression(&mut self, ast: &Ast, bin_expr: &super::BinaryExpr) {
+        self.visit_expression(ast, bin_expr.left);
+        self.visit_expression(ast, bin_expr.right);
+        let left = *self.types.get(bin_expr.left);
+        let right = *self.types.get(bin_expr.right);
+        let operator = bin_expr.operator.kind;
+        let valid = self.unify(left, right) && Self::accepts(operator, self.resolve(left));
+        let result = self.resolve(left);
+        if let Type::Var(_) = result {
+            self.deferred_operators.push((operator, result, bin_expr.operator.token.span.clone()));
+        }
+        if !valid {
+            let (left, right) = (self.resolve(left), self.resolve(right));
+            self.diagnostics.borrow_mut().report_invalid_operands(
+                operator,
+                &left,
+                &right,
+                bin_expr.operator.token.span.clone(),
+            );
+            self.ty = Type::Error;
+            return;
+        }
+        self.ty = result;
+    }
+
+    fn visit_parenthesized_expression(&mut self, ast: &Ast, parenthesized_expr: &super::ParenthesizedExpr) {
+        self.visit_expression(ast, parenthesized_expr.expr);
+        self.ty = *self.types.get(parenthesized_expr.expr);
+    }
+
+    fn visit_assignment_expression(&mut self, ast: &Ast, assign_expr: &super::AssignExpr) {
+        self.visit_expression(ast, assign_expr.expr);
+        self.ty = *self.types.get(assign_expr.expr);
+    }
+}
+
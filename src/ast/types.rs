@@ -0,0 +1,65 @@
+use std::fmt::{Display, Formatter};
+
+/// The static type of an expression, variable or function result.
+///
+/// `Var` is a type the checker has not worked out yet; it is resolved through
+/// the checker's substitutions. `Error` is given to expressions whose type
+/// could not be determined because of an error that was already reported,
+/// and is compatible with every type so one mistake is reported only once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Type {
+    Int,
+    Float,
+    String,
+    Void,
+    Var(usize),
+    Error,
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Void => write!(f, "void"),
+            Type::Var(_) => write!(f, "_"),
+            Type::Error => write!(f, "?"),
+        }
+    }
+}
+
+impl Type {
+    /// The types a type annotation can name.
+    pub const NAMED: [Type; 3] = [Type::Int, Type::Float, Type::String];
+
+    /// The type a type annotation names, if it names one.
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "int" => Some(Type::Int),
+            "float" => Some(Type::Float),
+            "string" => Some(Type::String),
+            _ => None,
+        }
+    }
+}
+
+/// The parameter and return types of a function or builtin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionType {
+    pub parameters: Vec<Type>,
+    pub return_type: Type,
+}
+
+impl FunctionType {
+    pub fn new(parameters: Vec<Type>, return_type: Type) -> Self {
+        Self { parameters, return_type }
+    }
+}
+
+impl Display for FunctionType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let parameters = self.parameters.iter().map(Type::to_string).collect::<Vec<String>>();
+        write!(f, "({}) -> {}", parameters.join(", "), self.return_type)
+    }
+}
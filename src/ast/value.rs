@@ -0,0 +1,43 @@
+use std::fmt::{Display, Formatter};
+
+/// A value produced by evaluating an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Float(f64),
+    String(String),
+}
+
+impl Value {
+    /// The name of the value's type as written in messages, such as `int`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Integer(_) => "int",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+        }
+    }
+}
+
+impl Value {
+    /// Roughly how many bytes the value takes up: the value itself plus the
+    /// text of a string.
+    pub fn size(&self) -> usize {
+        let heap = match self {
+            Value::Integer(_) | Value::Float(_) => 0,
+            Value::String(value) => value.len(),
+        };
+        std::mem::size_of::<Value>() + heap
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Integer(value) => write!(f, "{}", value),
+            // Debug keeps the fractional part of whole numbers: `2.0`, not `2`.
+            Value::Float(value) => write!(f, "{:?}", value),
+            Value::String(value) => write!(f, "{:?}", value),
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use super::{AssignExpr, Ast, BinaryExpr, ExprId, ExpressionKind, ItemId, ItemKind, LetStatement, NumberExpr, ParenthesizedExpr, StatementKind, StmtId};
+use super::{lexer::Token, AssignExpr, Ast, BinaryExpr, CallExpr, FloatExpr, StringExpr, VariableExpr, ExprId, ExpressionKind, FunctionId, ImportId, ItemId, ItemKind, LetStatement, NumberExpr, ReturnStatement, ParenthesizedExpr, StatementKind, StmtId};
 
 
 pub trait Visitor{
@@ -16,7 +16,20 @@ pub trait Visitor{
             ItemKind::Statement(stmt_id)=>{
                 self.visit_statement(ast, stmt_id);
             }
-            ItemKind::Function(function_id) => todo!(),
+            ItemKind::Function(function_id) => {
+                self.visit_function(ast, function_id);
+            }
+            ItemKind::Import(import_id) => {
+                self.visit_import(ast, import_id);
+            }
+        }
+    }
+    fn visit_import(&mut self,_ast:&Ast,_import_id:ImportId){
+    }
+    fn visit_function(&mut self,ast:&Ast,function_id:FunctionId){
+        let function = ast.query_function(function_id);
+        for stmt_id in function.body.statements.iter(){
+            self.visit_statement(ast, *stmt_id);
         }
     }
     fn do_visit_statement(&mut self,ast: &Ast,stmt_id:StmtId){
@@ -28,14 +41,32 @@ pub trait Visitor{
             StatementKind::Let(stmt)=>{
                 self.visit_let_statement(ast,stmt);
             }
+            StatementKind::Return(stmt)=>{
+                self.visit_return_statement(ast,stmt);
+            }
         }
     }
     fn visit_expression(&mut self,ast:&Ast, expr_id:ExprId){
+        self.do_visit_expression(ast,expr_id);
+    }
+    fn do_visit_expression(&mut self,ast:&Ast, expr_id:ExprId){
         let expr = ast.query_expr(expr_id);
         match &expr.kind{
             ExpressionKind::Number(number)=>{
                 self.visit_number(ast,number);
             },
+            ExpressionKind::Float(number)=>{
+                self.visit_float(ast,number);
+            },
+            ExpressionKind::String(string)=>{
+                self.visit_string(ast,string);
+            },
+            ExpressionKind::Variable(variable_expr)=>{
+                self.visit_variable_expression(ast,variable_expr);
+            },
+            ExpressionKind::Call(call_expr)=>{
+                self.visit_call_expression(ast,call_expr);
+            },
             ExpressionKind::Binary(bin_expr)=>{
                 self.visit_binary_expression(ast,bin_expr);
             }
@@ -45,10 +76,24 @@ pub trait Visitor{
             ExpressionKind::Assignment(assign_expr) => {
                 self.visit_assignment_expression(ast,assign_expr);
             }
+            ExpressionKind::Error(token) => {
+                self.visit_error_expression(ast,token);
+            }
         }
     }
     fn visit_let_statement(&mut self,ast:&Ast,stmt:&LetStatement);
+    fn visit_return_statement(&mut self,ast:&Ast,stmt:&ReturnStatement){
+        self.visit_expression(ast, stmt.value);
+    }
     fn visit_number(&mut self,ast: &Ast,number:&NumberExpr);
+    fn visit_float(&mut self,ast: &Ast,number:&FloatExpr);
+    fn visit_string(&mut self,ast: &Ast,string:&StringExpr);
+    fn visit_variable_expression(&mut self,ast: &Ast,variable_expr:&VariableExpr);
+    fn visit_call_expression(&mut self,ast:&Ast,call_expr:&CallExpr){
+        for argument in call_expr.arguments.iter(){
+            self.visit_expression(ast, *argument);
+        }
+    }
     fn visit_binary_expression(&mut self,ast: &Ast,bin_expr:&BinaryExpr){
         self.visit_expression(ast, bin_expr.left);
         self.visit_expression(ast, bin_expr.right);
@@ -59,4 +104,6 @@ pub trait Visitor{
     fn visit_assignment_expression(&mut self,ast:&Ast,assign_expr:&AssignExpr){
         self.visit_expression(ast, assign_expr.expr);
     }
-}
\ No newline at end of file
+    fn visit_error_expression(&mut self,_ast:&Ast,_token:&Token){
+    }
+}
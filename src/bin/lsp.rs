@@ -0,0 +1,42 @@
+use std::{io, process};
+
+use compiler::{
+    json::Json,
+    lsp::{
+        protocol::{read_message, write_message},
+        Server, PARSE_ERROR,
+    },
+};
+
+/// Speaks the Language Server Protocol over stdin and stdout. A message
+/// that cannot be read or parsed gets a parse error in answer and the
+/// server goes on with the next one; only a failure of stdin or stdout
+/// itself stops it.
+fn main() -> io::Result<()> {
+    let mut reader = io::stdin().lock();
+    let mut writer = io::stdout().lock();
+    let mut server = Server::new();
+    loop {
+        let body = match read_message(&mut reader) {
+            Ok(Some(body)) => body,
+            Ok(None) => break,
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                write_message(&mut writer, &Server::error_response(Json::Null, PARSE_ERROR, &error.to_string()))?;
+                continue;
+            }
+            Err(error) => return Err(error),
+        };
+        match Json::parse(&body) {
+            Ok(message) => {
+                for response in server.handle(&message) {
+                    write_message(&mut writer, &response)?;
+                }
+            }
+            Err(error) => write_message(&mut writer, &Server::error_response(Json::Null, PARSE_ERROR, &error))?,
+        }
+        if let Some(exit_code) = server.exit_code {
+            process::exit(exit_code);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,306 @@
+//! An on-disk cache of compiled programs. Compiling a program is a pure
+//! function of its source files, the compiler and the command, so what it
+//! produced is stored under a hash of those and taken from the cache when
+//! they come round again. A command that runs the program runs the stored
+//! one, with the options it is given this time.
+
+use std::{
+    fmt::{Display, Formatter},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::json::Json;
+
+/// Bumped whenever the layout of the cache entries, or of the program
+/// images in them, changes, which leaves the old entries unused.
+pub const CACHE_FORMAT_VERSION: i64 = 3;
+
+/// A hash of the sources the compiler was built from, made by `build.rs`,
+/// so that a compiler built from changed sources does not take the entries
+/// of another even when the package version is the same.
+pub const SOURCE_FINGERPRINT: &str = env!("COMPILER_SOURCE_FINGERPRINT");
+
+/// The directory used unless one is given.
+pub const DEFAULT_CACHE_DIR: &str = ".compiler-cache";
+
+const STATS_FILE: &str = "stats.json";
+
+/// A source file a program was compiled from: its path and a hash of its
+/// text, or of the error reading it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceHash {
+    pub path: String,
+    pub hash: CacheKey,
+}
+
+impl SourceHash {
+    /// The hash of each of `files`, as `CacheKey::new` takes them.
+    pub fn of_files(files: &[(String, Result<String, String>)]) -> Vec<Self> {
+        files
+            .iter()
+            .map(|(path, text)| {
+                let mut hash = CacheKey(CacheKey::OFFSET_BASIS);
+                hash.write_source(text);
+                Self { path: path.clone(), hash }
+            })
+            .collect()
+    }
+
+    fn to_json(&self) -> Json {
+        Json::object(vec![("path", Json::string(&self.path)), ("hash", Json::String(self.hash.to_string()))])
+    }
+
+    fn from_json(json: &Json) -> Option<Self> {
+        Some(Self {
+            path: json.get("path")?.as_str()?.to_string(),
+            hash: CacheKey(u64::from_str_radix(json.get("hash")?.as_str()?, 16).ok()?),
+        })
+    }
+}
+
+/// What compiling a program produced: the rendered diagnostics, what the
+/// command prints about the program, such as the types `check` inferred,
+/// and for a program without errors that is to be run, its image as saved
+/// by `ast::image::save`. `sources` are the files it was compiled from,
+/// which an entry must match besides its key to be used.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Artifact {
+    pub sources: Vec<SourceHash>,
+    pub diagnostics: String,
+    pub listing: String,
+    pub has_errors: bool,
+    pub program: Option<Json>,
+}
+
+impl Artifact {
+    fn to_json(&self, key: CacheKey) -> Json {
+        Json::object(vec![
+            ("format_version", Json::Number(CACHE_FORMAT_VERSION)),
+            ("key", Json::String(key.to_string())),
+            ("sources", Json::Array(self.sources.iter().map(SourceHash::to_json).collect())),
+            ("diagnostics", Json::string(&self.diagnostics)),
+            ("listing", Json::string(&self.listing)),
+            ("has_errors", Json::Bool(self.has_errors)),
+            ("program", self.program.clone().unwrap_or(Json::Null)),
+        ])
+    }
+
+    /// Reads an entry back, or `None` if it is not one written for `key` by
+    /// this version of the cache from the files `sources`. Comparing the
+    /// files as well keeps two inputs whose keys collide apart.
+    fn from_json(json: &Json, key: CacheKey, sources: &[SourceHash]) -> Option<Self> {
+        if json.get("format_version")?.as_i64()? != CACHE_FORMAT_VERSION || json.get("key")?.as_str()? != key.to_string() {
+            return None;
+        }
+        let stored = json.get("sources")?.as_array()?.iter().map(SourceHash::from_json).collect::<Option<Vec<SourceHash>>>()?;
+        if stored != sources {
+            return None;
+        }
+        let Json::Bool(has_errors) = json.get("has_errors")? else {
+            return None;
+        };
+        Some(Self {
+            sources: stored,
+            diagnostics: json.get("diagnostics")?.as_str()?.to_string(),
+            listing: json.get("listing")?.as_str()?.to_string(),
+            has_errors: *has_errors,
+            program: Some(json.get("program")?.clone()).filter(|program| *program != Json::Null),
+        })
+    }
+}
+
+/// A 64-bit FNV-1a hash of everything compiling depends on. Unlike the
+/// hasher of the standard library it is the same on every platform and in
+/// every build, so entries stay valid across runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheKey(u64);
+
+impl CacheKey {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    /// Hashes the compiler version, the command, and the path and text of
+    /// every source file, or the error reading it.
+    pub fn new(compiler_version: &str, command: &[String], files: &[(String, Result<String, String>)]) -> Self {
+        let mut key = Self(Self::OFFSET_BASIS);
+        key.write(compiler_version.as_bytes());
+        key.write_count(command.len());
+        for argument in command {
+            key.write(argument.as_bytes());
+        }
+        key.write_count(files.len());
+        for (path, text) in files {
+            key.write(path.as_bytes());
+            key.write_source(text);
+        }
+        key
+    }
+
+    fn write_source(&mut self, text: &Result<String, String>) {
+        match text {
+            Ok(text) => {
+                self.write(b"ok");
+                self.write(text.as_bytes());
+            }
+            Err(error) => {
+                self.write(b"error");
+                self.write(error.as_bytes());
+            }
+        }
+    }
+
+    /// Hashes the length of `bytes` before them, so that fields cannot run
+    /// into each other: `ab` + `c` differs from `a` + `bc`.
+    fn write(&mut self, bytes: &[u8]) {
+        self.write_count(bytes.len());
+        self.write_bytes(bytes);
+    }
+
+    fn write_count(&mut self, count: usize) {
+        self.write_bytes(&(count as u64).to_le_bytes());
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+}
+
+impl Display for CacheKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// How much a cache holds and how often it was used, over every process
+/// that shared it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl Display for CacheStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let lookups = self.hits + self.misses;
+        let hit_rate = if lookups == 0 { 0.0 } else { self.hits as f64 * 100.0 / lookups as f64 };
+        writeln!(f, "entries: {}", self.entries)?;
+        writeln!(f, "size:    {} bytes", self.bytes)?;
+        writeln!(f, "hits:    {}", self.hits)?;
+        writeln!(f, "misses:  {}", self.misses)?;
+        write!(f, "hit rate: {:.1}%", hit_rate)
+    }
+}
+
+/// A directory of entries, one `<key>.json` file each, and the hit and
+/// miss counts in `stats.json`.
+///
+/// Entries are written to a temporary file and renamed into place, so a
+/// process never reads one half written. The counts are read, bumped and
+/// written back, which can lose a count when processes race; they are for
+/// reporting only. A cache that cannot be read or written is treated as
+/// empty: a compilation never fails because of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The artifact stored for `key` and compiled from `sources`, counting
+    /// the lookup as a hit or a miss.
+    pub fn get(&self, key: CacheKey, sources: &[SourceHash]) -> Option<Artifact> {
+        let artifact = fs::read_to_string(self.entry_path(key))
+            .ok()
+            .and_then(|text| Json::parse(&text).ok())
+            .and_then(|json| Artifact::from_json(&json, key, sources));
+        self.count(artifact.is_some());
+        artifact
+    }
+
+    pub fn put(&self, key: CacheKey, artifact: &Artifact) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.entry_path(key);
+        let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&temporary, artifact.to_json(key).to_string())?;
+        fs::rename(&temporary, &path)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let mut stats = self.read_counts();
+        for (_, size) in self.entries() {
+            stats.entries += 1;
+            stats.bytes += size;
+        }
+        stats
+    }
+
+    /// Removes every entry and the counts, returning how many entries there
+    /// were.
+    pub fn clear(&self) -> io::Result<usize> {
+        let entries = self.entries();
+        for (path, _) in entries.iter() {
+            fs::remove_file(path)?;
+        }
+        match fs::remove_file(self.dir.join(STATS_FILE)) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+            _ => {}
+        }
+        Ok(entries.len())
+    }
+
+    fn entry_path(&self, key: CacheKey) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// The entry files and their sizes.
+    fn entries(&self) -> Vec<(PathBuf, u64)> {
+        let Ok(read_dir) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        read_dir
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                name.ends_with(".json") && name != STATS_FILE
+            })
+            .map(|entry| (entry.path(), entry.metadata().map_or(0, |metadata| metadata.len())))
+            .collect()
+    }
+
+    fn read_counts(&self) -> CacheStats {
+        let json = fs::read_to_string(self.dir.join(STATS_FILE)).ok().and_then(|text| Json::parse(&text).ok());
+        let count = |name| json.as_ref().and_then(|json| json.get(name)?.as_i64()).unwrap_or(0).max(0) as u64;
+        CacheStats {
+            hits: count("hits"),
+            misses: count("misses"),
+            ..CacheStats::default()
+        }
+    }
+
+    fn count(&self, hit: bool) {
+        let mut stats = self.read_counts();
+        if hit {
+            stats.hits += 1;
+        } else {
+            stats.misses += 1;
+        }
+        let json = Json::object(vec![("hits", Json::Number(stats.hits as i64)), ("misses", Json::Number(stats.misses as i64))]);
+        if fs::create_dir_all(&self.dir).is_ok() {
+            let _ = fs::write(self.dir.join(STATS_FILE), json.to_string());
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::ast::{
+    lexer::{Token, TokenKind},
+    text::TextSpan,
+    types::Type,
+    BinOperatorKind,
+};
+
+pub mod printer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: TextSpan,
+    pub kind: DiagnosticKind,
+}
+
+impl Diagnostic {
+    pub fn new(message: String, span: TextSpan, kind: DiagnosticKind) -> Self {
+        Self { message, span, kind }
+    }
+}
+
+/// Collects the diagnostics reported while compiling. The lexer, parser and
+/// later passes share one bag through a `DiagnosticsBagCell`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiagnosticsBag {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+pub type DiagnosticsBagCell = Rc<RefCell<DiagnosticsBag>>;
+
+impl DiagnosticsBag {
+    pub fn new() -> Self {
+        Self { diagnostics: vec![] }
+    }
+
+    pub fn new_cell() -> DiagnosticsBagCell {
+        Rc::new(RefCell::new(Self::new()))
+    }
+
+    pub fn report_error(&mut self, message: String, span: TextSpan) {
+        self.diagnostics.push(Diagnostic::new(message, span, DiagnosticKind::Error));
+    }
+
+    pub fn report_warning(&mut self, message: String, span: TextSpan) {
+        self.diagnostics.push(Diagnostic::new(message, span, DiagnosticKind::Warning));
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|diagnostic| diagnostic.kind == DiagnosticKind::Error)
+    }
+
+    pub fn report_unterminated_block_comment(&mut self, span: TextSpan) {
+        self.report_error(String::from("Unterminated block comment"), span);
+    }
+
+    pub fn report_integer_overflow(&mut self, span: TextSpan) {
+        self.report_error(
+            format!("Integer literal `{}` does not fit in 64 bits (max {})", span.literal, i64::MAX),
+            span,
+        );
+    }
+
+    pub fn report_invalid_digit(&mut self, digit: char, radix: u32, span: TextSpan) {
+        let radix_name = match radix {
+            2 => "binary",
+            8 => "octal",
+            16 => "hexadecimal",
+            _ => "decimal",
+        };
+        self.report_error(
+            format!("Invalid digit `{}` in {} literal `{}`", digit, radix_name, span.literal),
+            span,
+        );
+    }
+
+    pub fn report_unterminated_string(&mut self, span: TextSpan) {
+        self.report_error(String::from("Unterminated string literal"), span);
+    }
+
+    pub fn report_invalid_escape(&mut self, span: TextSpan) {
+        self.report_error(format!("Invalid escape sequence `{}`", span.literal), span);
+    }
+
+    pub fn report_unexpected_token(&mut self, expected: TokenKind, found: &Token) {
+        self.report_error(format!("Expected {}, found {}", expected, found.kind), found.span.clone());
+    }
+
+    pub fn report_expected_expression(&mut self, found: &Token) {
+        self.report_error(format!("Expected an expression, found {}", found.kind), found.span.clone());
+    }
+
+    pub fn report_expression_too_deep(&mut self, max_depth: usize, span: &TextSpan) {
+        self.report_error(format!("Expression is nested more than {} levels deep", max_depth), span.clone());
+    }
+
+    pub fn report_undefined_variable(&mut self, span: &TextSpan) {
+        self.report_error(format!("Undefined variable `{}`", span.literal), span.clone());
+    }
+
+    pub fn report_function_already_declared(&mut self, span: &TextSpan) {
+        self.report_error(format!("Function `{}` is already declared", span.literal), span.clone());
+    }
+
+    pub fn report_undefined_function(&mut self, span: &TextSpan) {
+        self.report_error(format!("Undefined function `{}`", span.literal), span.clone());
+    }
+
+    pub fn report_unknown_type(&mut self, span: &TextSpan) {
+        self.report_error(format!("Unknown type `{}`", span.literal), span.clone());
+    }
+
+    pub fn report_type_mismatch(&mut self, expected: &Type, found: &Type, span: TextSpan) {
+        self.report_error(format!("Mismatched types: expected {}, found {}", expected, found), span);
+    }
+
+    pub fn report_invalid_operands(&mut self, operator: BinOperatorKind, left: &Type, right: &Type, span: TextSpan) {
+        self.report_error(format!("Cannot apply `{}` to {} and {}", operator, left, right), span);
+    }
+
+    pub fn report_wrong_argument_count(&mut self, name: &str, expected: usize, found: usize, span: TextSpan) {
+        self.report_error(format!("`{}` takes {} argument(s) but {} were given", name, expected, found), span);
+    }
+
+    pub fn report_void_value(&mut self, span: TextSpan) {
+        self.report_error(String::from("Expression does not have a value"), span);
+    }
+
+    pub fn report_return_outside_function(&mut self, span: &TextSpan) {
+        self.report_error(String::from("`return` outside of a function"), span.clone());
+    }
+
+    pub fn report_unreachable_code(&mut self, span: TextSpan) {
+        self.report_warning(String::from("Unreachable code after `return`"), span);
+    }
+
+    pub fn report_unused_variable(&mut self, span: &TextSpan) {
+        self.report_warning(format!("Variable `{}` is never used", span.literal), span.clone());
+    }
+
+    pub fn report_unused_parameter(&mut self, span: &TextSpan) {
+        self.report_warning(format!("Parameter `{}` is never used", span.literal), span.clone());
+    }
+
+    pub fn report_unused_function(&mut self, span: &TextSpan) {
+        self.report_warning(format!("Function `{}` is never called", span.literal), span.clone());
+    }
+
+    pub fn report_missing_return(&mut self, function: &str, return_type: &Type, span: TextSpan) {
+        self.report_error(
+            format!("Function `{}` must return a value of type {} on every path", function, return_type),
+            span,
+        );
+    }
+
+    /// `cycle` lists the modules from the first one on the cycle round to
+    /// it again.
+    pub fn report_import_cycle(&mut self, cycle: &[String], span: TextSpan) {
+        self.report_error(format!("Import cycle: {}", cycle.join(" -> ")), span);
+    }
+
+    pub fn report_module_not_loaded(&mut self, path: &str, reason: &str, span: TextSpan) {
+        self.report_error(format!("Cannot load module `{}`: {}", path, reason), span);
+    }
+
+    pub fn report_unknown_module(&mut self, span: &TextSpan) {
+        self.report_error(format!("Module `{}` is not imported", span.literal), span.clone());
+    }
+
+    pub fn report_not_in_module(&mut self, module: &str, span: &TextSpan) {
+        self.report_error(format!("Module `{}` has no `{}`", module, span.literal), span.clone());
+    }
+
+    pub fn report_missing_digits(&mut self, span: TextSpan) {
+        self.report_error(format!("Integer literal `{}` has no digits", span.literal), span);
+    }
+}
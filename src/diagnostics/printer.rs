@@ -0,0 +1,60 @@
+use crate::ast::text::SourceFile;
+
+use super::{Diagnostic, DiagnosticKind};
+
+/// Renders diagnostics against the source they were reported for:
+///
+/// ```text
+/// error: Unterminated block comment
+///  --> area.calc:3:5
+///   |
+/// 3 | let a = /* 1 + 2
+///   |         ^^
+/// ```
+pub struct DiagnosticsPrinter<'a> {
+    source: &'a SourceFile,
+    diagnostics: &'a [Diagnostic],
+}
+
+impl<'a> DiagnosticsPrinter<'a> {
+    pub fn new(source: &'a SourceFile, diagnostics: &'a [Diagnostic]) -> Self {
+        Self { source, diagnostics }
+    }
+
+    pub fn stringify_diagnostic(&self, diagnostic: &Diagnostic) -> String {
+        let kind = match diagnostic.kind {
+            DiagnosticKind::Error => "error",
+            DiagnosticKind::Warning => "warning",
+        };
+        let position = self.source.line_col(diagnostic.span.start);
+        let line_number = (position.line + 1).to_string();
+        let gutter = " ".repeat(line_number.len());
+        let line_text = self.source.line_text(position.line);
+        let underline_width = self
+            .source
+            .slice(&diagnostic.span)
+            .chars()
+            .take_while(|c| *c != '\n')
+            .count()
+            .max(1);
+        format!(
+            "{}: {}\n{}--> {}\n{} |\n{} | {}\n{} | {}{}\n",
+            kind,
+            diagnostic.message,
+            gutter,
+            self.source.location(&diagnostic.span),
+            gutter,
+            line_number,
+            line_text,
+            gutter,
+            " ".repeat(position.column),
+            "^".repeat(underline_width)
+        )
+    }
+
+    pub fn print(&self) {
+        for diagnostic in self.diagnostics {
+            eprintln!("{}", self.stringify_diagnostic(diagnostic));
+        }
+    }
+}
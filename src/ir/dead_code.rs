@@ -0,0 +1,264 @@
+//! Removes the code of an `IrProgram` whose results nothing uses.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Display, Formatter},
+};
+
+use crate::ast::{
+    builtins::Builtin,
+    lib::{Id, IdVec},
+    types::Type,
+    value::Value,
+    BinOperatorKind,
+};
+
+use super::{Callee, GlobalId, Instruction, IrFunction, IrFunctionId, IrProgram, Register};
+
+/// What `eliminate_dead_code` removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeadCode {
+    pub functions: Vec<String>,
+    pub parameters: usize,
+    pub instructions: usize,
+    pub globals: Vec<String>,
+}
+
+impl DeadCode {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Display for DeadCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "removed {} function(s), {} parameter(s), {} instruction(s) and {} global(s)",
+            self.functions.len(),
+            self.parameters,
+            self.instructions,
+            self.globals.len()
+        )
+    }
+}
+
+/// Removes, until there is nothing left to remove:
+///
+/// - the functions `$main` cannot reach through calls;
+/// - the parameters a function never reads, and the arguments passed for
+///   them;
+/// - the instructions whose result nothing reads and that cannot have an
+///   effect: constants, phis, calls of built-ins, and arithmetic that
+///   cannot trap, such as on floats or strings, or on integer constants
+///   that do not overflow;
+/// - the stores to globals that no function loads, and then the globals
+///   nothing loads or stores.
+///
+/// Calls of the program's functions are kept, since they may trap, and so
+/// are loads, which trap before the global is stored, and the `output` of
+/// every statement result, with the code that computes it. The registers of each
+/// function are numbered again afterwards.
+pub fn eliminate_dead_code(program: &mut IrProgram) -> DeadCode {
+    let mut removed = DeadCode::default();
+    remove_uncalled_functions(program, &mut removed);
+    loop {
+        let before = (removed.parameters, removed.instructions);
+        for function in program.functions.data.iter_mut() {
+            remove_dead_instructions(function, &mut removed);
+        }
+        remove_dead_stores(program, &mut removed);
+        remove_unused_parameters(program, &mut removed);
+        if (removed.parameters, removed.instructions) == before {
+            break;
+        }
+    }
+    remove_unused_globals(program, &mut removed);
+    for function in program.functions.data.iter_mut() {
+        function.renumber_registers();
+    }
+    removed
+}
+
+fn instructions_mut(program: &mut IrProgram) -> impl Iterator<Item = &mut Instruction> {
+    program
+        .functions
+        .data
+        .iter_mut()
+        .flat_map(|function| function.blocks.data.iter_mut())
+        .flat_map(|block| block.instructions.iter_mut())
+}
+
+fn instructions(program: &IrProgram) -> impl Iterator<Item = &Instruction> {
+    program
+        .functions
+        .iter()
+        .flat_map(|function| function.blocks.iter())
+        .flat_map(|block| block.instructions.iter())
+}
+
+fn remove_uncalled_functions(program: &mut IrProgram, removed: &mut DeadCode) {
+    let mut called = vec![false; program.functions.data.len()];
+    let mut pending = vec![program.main];
+    while let Some(function_id) = pending.pop() {
+        if std::mem::replace(&mut called[function_id.to_usize()], true) {
+            continue;
+        }
+        let function = program.functions.get(function_id);
+        for instruction in function.blocks.iter().flat_map(|block| block.instructions.iter()) {
+            if let Instruction::Call { callee: Callee::Function(callee), .. } = instruction {
+                pending.push(*callee);
+            }
+        }
+    }
+    let mut renumbered = HashMap::new();
+    let mut functions = IdVec::new();
+    for (index, function) in std::mem::take(&mut program.functions.data).into_iter().enumerate() {
+        if called[index] {
+            renumbered.insert(IrFunctionId::new(index), functions.push(function));
+        } else {
+            removed.functions.push(function.name);
+        }
+    }
+    program.functions = functions;
+    program.main = renumbered[&program.main];
+    for instruction in instructions_mut(program) {
+        if let Instruction::Call { callee: Callee::Function(callee), .. } = instruction {
+            *callee = renumbered[callee];
+        }
+    }
+}
+
+fn remove_dead_instructions(function: &mut IrFunction, removed: &mut DeadCode) {
+    loop {
+        let uses = function.use_counts();
+        let constants: HashMap<Register, Value> = function
+            .blocks
+            .iter()
+            .flat_map(|block| block.instructions.iter())
+            .filter_map(|instruction| match instruction {
+                Instruction::Const { dest, value } => Some((*dest, value.clone())),
+                _ => None,
+            })
+            .collect();
+        let registers = &function.registers;
+        let dead = |instruction: &Instruction| {
+            instruction.dest().is_some_and(|dest| *uses.get(dest) == 0) && !has_effect(instruction, registers, &constants)
+        };
+        let mut count = 0;
+        for block in function.blocks.data.iter_mut() {
+            let before = block.instructions.len();
+            block.instructions.retain(|instruction| !dead(instruction));
+            count += before - block.instructions.len();
+        }
+        if count == 0 {
+            return;
+        }
+        removed.instructions += count;
+    }
+}
+
+/// Whether running `instruction` could do more than define its register.
+fn has_effect(instruction: &Instruction, registers: &IdVec<Register, Type>, constants: &HashMap<Register, Value>) -> bool {
+    match instruction {
+        Instruction::Const { .. } | Instruction::Phi { .. } => false,
+        Instruction::Call { callee: Callee::Builtin(Builtin::Len), .. } => false,
+        Instruction::Call { callee: Callee::Function(_), .. }
+        | Instruction::Load { .. }
+        | Instruction::Store { .. }
+        | Instruction::Output { .. } => true,
+        Instruction::Binary { dest, operator, left, right } => {
+            let (left, right) = (constants.get(left), constants.get(right));
+            match (registers.get(*dest), operator) {
+                (Type::String, _) => false,
+                (Type::Float, BinOperatorKind::Divide) => !matches!(right, Some(Value::Float(divisor)) if *divisor != 0.0),
+                (Type::Float, _) => false,
+                (Type::Int, BinOperatorKind::Divide) if matches!(right, Some(Value::Integer(divisor)) if *divisor != 0 && *divisor != -1) => false,
+                (Type::Int, _) => match (left, right) {
+                    (Some(Value::Integer(left)), Some(Value::Integer(right))) => match operator {
+                        BinOperatorKind::Plus => left.checked_add(*right).is_none(),
+                        BinOperatorKind::Minus => left.checked_sub(*right).is_none(),
+                        BinOperatorKind::Multiply => left.checked_mul(*right).is_none(),
+                        BinOperatorKind::Divide => left.checked_div(*right).is_none(),
+                    },
+                    _ => true,
+                },
+                _ => true,
+            }
+        }
+    }
+}
+
+fn remove_dead_stores(program: &mut IrProgram, removed: &mut DeadCode) {
+    let loaded: HashSet<GlobalId> = instructions(program)
+        .filter_map(|instruction| match instruction {
+            Instruction::Load { global, .. } => Some(*global),
+            _ => None,
+        })
+        .collect();
+    for block in program.functions.data.iter_mut().flat_map(|function| function.blocks.data.iter_mut()) {
+        let before = block.instructions.len();
+        block
+            .instructions
+            .retain(|instruction| !matches!(instruction, Instruction::Store { global, .. } if !loaded.contains(global)));
+        removed.instructions += before - block.instructions.len();
+    }
+}
+
+fn remove_unused_parameters(program: &mut IrProgram, removed: &mut DeadCode) {
+    let mut unused: HashMap<IrFunctionId, Vec<usize>> = HashMap::new();
+    for (index, function) in program.functions.iter().enumerate() {
+        let uses = function.use_counts();
+        let positions: Vec<usize> = (0..function.parameters.len())
+            .filter(|position| *uses.get(function.parameters[*position]) == 0)
+            .collect();
+        if !positions.is_empty() {
+            unused.insert(IrFunctionId::new(index), positions);
+        }
+    }
+    for (function_id, positions) in unused.iter() {
+        let parameters = &mut program.functions.get_mut(*function_id).parameters;
+        *parameters = keep_except(std::mem::take(parameters), positions);
+        removed.parameters += positions.len();
+    }
+    for instruction in instructions_mut(program) {
+        if let Instruction::Call { callee: Callee::Function(callee), arguments, .. } = instruction {
+            if let Some(positions) = unused.get(callee) {
+                *arguments = keep_except(std::mem::take(arguments), positions);
+            }
+        }
+    }
+}
+
+fn keep_except(registers: Vec<Register>, positions: &[usize]) -> Vec<Register> {
+    registers
+        .into_iter()
+        .enumerate()
+        .filter(|(position, _)| !positions.contains(position))
+        .map(|(_, register)| register)
+        .collect()
+}
+
+fn remove_unused_globals(program: &mut IrProgram, removed: &mut DeadCode) {
+    let used: HashSet<GlobalId> = instructions(program)
+        .filter_map(|instruction| match instruction {
+            Instruction::Load { global, .. } | Instruction::Store { global, .. } => Some(*global),
+            _ => None,
+        })
+        .collect();
+    let mut renumbered = HashMap::new();
+    let mut globals = IdVec::new();
+    for (index, global) in std::mem::take(&mut program.globals.data).into_iter().enumerate() {
+        if used.contains(&GlobalId::new(index)) {
+            renumbered.insert(GlobalId::new(index), globals.push(global));
+        } else {
+            removed.globals.push(global.name);
+        }
+    }
+    program.globals = globals;
+    for instruction in instructions_mut(program) {
+        if let Instruction::Load { global, .. } | Instruction::Store { global, .. } = instruction {
+            *global = renumbered[global];
+        }
+    }
+}
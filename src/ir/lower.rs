@@ -0,0 +1,306 @@
+//! Builds the IR of a type-checked `Ast`.
+
+use std::collections::HashMap;
+
+use crate::ast::{
+    builtins::Builtin,
+    lib::{Id, IdVec},
+    text::FileId,
+    type_checker::TypeChecker,
+    types::Type,
+    value::Value,
+    Ast, ExprId, ExpressionKind, FunctionId, ItemKind, StatementKind, StmtId, VariableId, VariableKind,
+};
+
+use super::{Block, BlockId, Callee, Global, GlobalId, Instruction, IrFunction, IrFunctionId, IrProgram, Register, Terminator, MAIN_FUNCTION};
+
+/// Lowers every function of `ast` and its top-level statements, which make
+/// up the function `$main`, using the types `types` gave them. The value
+/// of each top-level statement of the main file is reported with `output`,
+/// as `run` prints it.
+///
+/// Every variable is bound once, by its `let` or as a parameter, so each
+/// one becomes the register its value was computed into, and the code is
+/// in SSA form as built. Global variables are also stored to a global when
+/// bound, for the functions that read them. The functions and globals of
+/// imported modules are named with the module, as `@geometry::area`.
+///
+/// The program is expected to have type checked without errors. Code that
+/// did not resolve ends its block with `unreachable`, and the statements
+/// after it are not lowered.
+pub fn lower(ast: &Ast, types: &TypeChecker) -> IrProgram {
+    let mut lowering = Lowering {
+        ast,
+        types,
+        functions: HashMap::new(),
+        globals: HashMap::new(),
+        program: IrProgram {
+            globals: IdVec::new(),
+            functions: IdVec::new(),
+            main: IrFunctionId::new(0),
+        },
+    };
+    lowering.declare();
+    let mut functions = Vec::new();
+    for item in ast.top_level_items() {
+        if let ItemKind::Function(function_id) = item.kind {
+            functions.push(lowering.lower_function(function_id));
+        }
+    }
+    let statements: Vec<StmtId> = ast
+        .top_level_items()
+        .filter_map(|item| match item.kind {
+            ItemKind::Statement(stmt_id) => Some(stmt_id),
+            _ => None,
+        })
+        .collect();
+    let mut main = FunctionLowering::new(&lowering, MAIN_FUNCTION.to_string(), Type::Void);
+    main.lower_top_level(&statements);
+    functions.push(main.function);
+    lowering.program.main = IrFunctionId::new(functions.len() - 1);
+    for function in functions {
+        lowering.program.functions.push(function);
+    }
+    lowering.program
+}
+
+struct Lowering<'a> {
+    ast: &'a Ast,
+    types: &'a TypeChecker,
+    functions: HashMap<FunctionId, IrFunctionId>,
+    globals: HashMap<VariableId, GlobalId>,
+    program: IrProgram,
+}
+
+impl Lowering<'_> {
+    /// Numbers the functions in source order, with `$main` after them, and
+    /// declares a global for every global `let`. A global that shadows one
+    /// of the same name is told apart by a suffix, as `@x.1`.
+    fn declare(&mut self) {
+        let ast = self.ast;
+        let mut names: HashMap<String, usize> = HashMap::new();
+        for item in ast.top_level_items() {
+            match item.kind {
+                ItemKind::Function(function_id) => {
+                    let next = IrFunctionId::new(self.functions.len());
+                    self.functions.insert(function_id, next);
+                }
+                ItemKind::Statement(stmt_id) => {
+                    let StatementKind::Let(let_stmt) = &ast.query_stmt(stmt_id).stmt_kind else {
+                        continue;
+                    };
+                    let variable = ast.query_variable(let_stmt.variable_id);
+                    if variable.kind != VariableKind::Global {
+                        continue;
+                    }
+                    let name = self.qualified_name(&variable.name, variable.identifier.span.file_id);
+                    let count = names.entry(name.clone()).or_insert(0);
+                    let name = if *count == 0 { name } else { format!("{}.{}", name, count) };
+                    *count += 1;
+                    let ty = *self.types.variable_types.get(let_stmt.variable_id);
+                    let global_id = self.program.globals.push(Global { name, ty });
+                    self.globals.insert(let_stmt.variable_id, global_id);
+                }
+                ItemKind::Import(_) => {}
+            }
+        }
+    }
+
+    /// `name` declared in the file `file_id`, with the module in front
+    /// unless it is the main file.
+    fn qualified_name(&self, name: &str, file_id: FileId) -> String {
+        match self.ast.module(file_id) {
+            Some(module) if !self.ast.is_main_file(file_id) => format!("{}::{}", module.name, name),
+            _ => name.to_string(),
+        }
+    }
+
+    fn lower_function(&self, function_id: FunctionId) -> IrFunction {
+        let function = self.ast.query_function(function_id);
+        let function_type = self.types.function_types.get(function_id);
+        let name = self.qualified_name(&function.name, function.function_keyword.span.file_id);
+        let mut lowering = FunctionLowering::new(self, name, function_type.return_type);
+        for (parameter, ty) in function.parameters.iter().zip(function_type.parameters.iter()) {
+            let register = lowering.new_register(*ty);
+            lowering.function.parameters.push(register);
+            lowering.locals.insert(parameter.variable_id, register);
+        }
+        lowering.lower_body(&function.body.statements);
+        lowering.function
+    }
+}
+
+/// The state of lowering one function: the block instructions go to and
+/// the register each variable of the function was bound to.
+struct FunctionLowering<'a, 'b> {
+    lowering: &'b Lowering<'a>,
+    function: IrFunction,
+    current: BlockId,
+    locals: HashMap<VariableId, Register>,
+    terminated: bool,
+}
+
+impl<'a, 'b> FunctionLowering<'a, 'b> {
+    fn new(lowering: &'b Lowering<'a>, name: String, return_type: Type) -> Self {
+        let mut blocks = IdVec::new();
+        let entry = blocks.push(Block::default());
+        Self {
+            lowering,
+            function: IrFunction {
+                name,
+                parameters: Vec::new(),
+                return_type,
+                registers: IdVec::new(),
+                blocks,
+            },
+            current: entry,
+            locals: HashMap::new(),
+            terminated: false,
+        }
+    }
+
+    fn new_register(&mut self, ty: Type) -> Register {
+        self.function.registers.push(ty)
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.function.blocks.get_mut(self.current).instructions.push(instruction);
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        self.function.blocks.get_mut(self.current).terminator = terminator;
+        self.terminated = true;
+    }
+
+    /// Lowers `statements` up to the first that ends the block, and returns
+    /// nothing if control reaches the end.
+    fn lower_body(&mut self, statements: &[StmtId]) {
+        for stmt_id in statements {
+            self.lower_statement(*stmt_id);
+            if self.terminated {
+                return;
+            }
+        }
+        self.terminate(Terminator::Return(None));
+    }
+
+    /// Lowers the top-level statements like `lower_body`, and outputs the
+    /// value of each statement of the main file that is one of the results
+    /// of the program.
+    fn lower_top_level(&mut self, statements: &[StmtId]) {
+        let ast = self.lowering.ast;
+        for stmt_id in statements {
+            let value = self.lower_statement(*stmt_id);
+            let reported = ast.is_result(*stmt_id) && ast.is_main_file(ast.query_stmt(*stmt_id).span(ast).file_id);
+            if let Some(value) = value.filter(|_| reported) {
+                self.emit(Instruction::Output { value });
+            }
+            if self.terminated {
+                return;
+            }
+        }
+        self.terminate(Terminator::Return(None));
+    }
+
+    /// Lowers a statement and returns the register holding its value: the
+    /// value bound by a `let` or that of an expression.
+    fn lower_statement(&mut self, stmt_id: StmtId) -> Option<Register> {
+        let ast = self.lowering.ast;
+        match &ast.query_stmt(stmt_id).stmt_kind {
+            StatementKind::Let(let_stmt) => {
+                let value = self.lower_value(let_stmt.initializer)?;
+                self.locals.insert(let_stmt.variable_id, value);
+                if let Some(global) = self.lowering.globals.get(&let_stmt.variable_id) {
+                    self.emit(Instruction::Store { global: *global, value });
+                }
+                Some(value)
+            }
+            StatementKind::Return(return_stmt) => {
+                if let Some(value) = self.lower_value(return_stmt.value) {
+                    self.terminate(Terminator::Return(Some(value)));
+                }
+                None
+            }
+            StatementKind::Expression(expr_id) => self.lower_expression(*expr_id),
+        }
+    }
+
+    /// Lowers an expression whose value is needed. A call of a function
+    /// without a result ends the block with `unreachable`.
+    fn lower_value(&mut self, expr_id: ExprId) -> Option<Register> {
+        let value = self.lower_expression(expr_id);
+        if value.is_none() && !self.terminated {
+            self.terminate(Terminator::Unreachable);
+        }
+        value
+    }
+
+    /// Lowers an expression and returns the register holding its value, or
+    /// nothing for a call of a function without a result and for code that
+    /// did not resolve, which ends the block.
+    fn lower_expression(&mut self, expr_id: ExprId) -> Option<Register> {
+        if self.terminated {
+            return None;
+        }
+        let ast = self.lowering.ast;
+        let ty = *self.lowering.types.types.get(expr_id);
+        match &ast.query_expr(expr_id).kind {
+            ExpressionKind::Number(number) => Some(self.constant(Value::Integer(number.number), Type::Int)),
+            ExpressionKind::Float(number) => Some(self.constant(Value::Float(number.number), Type::Float)),
+            ExpressionKind::String(string) => Some(self.constant(Value::String(string.value.clone()), Type::String)),
+            ExpressionKind::Variable(variable_expr) => {
+                let Some(variable_id) = variable_expr.variable_id else {
+                    return self.unresolved();
+                };
+                if let Some(register) = self.locals.get(&variable_id) {
+                    return Some(*register);
+                }
+                let Some(global) = self.lowering.globals.get(&variable_id).copied() else {
+                    return self.unresolved();
+                };
+                let dest = self.new_register(self.lowering.program.globals.get(global).ty);
+                self.emit(Instruction::Load { dest, global });
+                Some(dest)
+            }
+            ExpressionKind::Call(call_expr) => {
+                let mut arguments = Vec::new();
+                for argument in call_expr.arguments.iter() {
+                    arguments.push(self.lower_value(*argument)?);
+                }
+                let builtin = call_expr.qualifier.is_none().then(|| Builtin::lookup(call_expr.name())).flatten();
+                let (callee, return_type) = match (ast.resolve_call(call_expr), builtin) {
+                    (Some(function_id), _) => match self.lowering.functions.get(&function_id) {
+                        Some(callee) => (Callee::Function(*callee), self.lowering.types.function_types.get(function_id).return_type),
+                        None => return self.unresolved(),
+                    },
+                    (None, Some(builtin)) => (Callee::Builtin(builtin), builtin.signature().return_type),
+                    (None, None) => return self.unresolved(),
+                };
+                let dest = (return_type != Type::Void).then(|| self.new_register(return_type));
+                self.emit(Instruction::Call { dest, callee, arguments });
+                dest
+            }
+            ExpressionKind::Binary(bin_expr) => {
+                let left = self.lower_value(bin_expr.left)?;
+                let right = self.lower_value(bin_expr.right)?;
+                let dest = self.new_register(ty);
+                self.emit(Instruction::Binary { dest, operator: bin_expr.operator.kind, left, right });
+                Some(dest)
+            }
+            ExpressionKind::Parenthesized(parenthesized_expr) => self.lower_expression(parenthesized_expr.expr),
+            ExpressionKind::Assignment(assign_expr) => self.lower_expression(assign_expr.expr),
+            ExpressionKind::Error(_) => self.unresolved(),
+        }
+    }
+
+    fn unresolved(&mut self) -> Option<Register> {
+        self.terminate(Terminator::Unreachable);
+        None
+    }
+
+    fn constant(&mut self, value: Value, ty: Type) -> Register {
+        let dest = self.new_register(ty);
+        self.emit(Instruction::Const { dest, value });
+        dest
+    }
+}
@@ -0,0 +1,359 @@
+//! A mid-level intermediate representation between the `Ast` and the code
+//! that runs it. A program is a list of functions, each a control-flow
+//! graph of basic blocks of three-address instructions over virtual
+//! registers, in SSA form: every register is assigned by exactly one
+//! instruction, which dominates its uses. Where control flow merges, a
+//! `phi` at the head of the block picks the register of the predecessor
+//! that was taken.
+//!
+//! `lower` builds the IR from a type-checked `Ast`, `verify` checks that it
+//! is well formed, and `Display` prints it as text:
+//!
+//! ```text
+//! global @pi: float
+//!
+//! function @area(%0: int, %1: int) -> int {
+//! b0:
+//!     %2: int = mul %0, %1
+//!     ret %2
+//! }
+//! ```
+
+use std::fmt::{Display, Formatter};
+
+use crate::{
+    ast::{builtins::Builtin, lib::IdVec, types::Type, value::Value, BinOperatorKind},
+    id_gen,
+};
+
+pub mod dead_code;
+pub mod lower;
+pub mod verifier;
+
+pub use dead_code::{eliminate_dead_code, DeadCode};
+pub use lower::lower;
+pub use verifier::{verify, VerifierError};
+
+id_gen!(Register);
+id_gen!(BlockId);
+id_gen!(IrFunctionId);
+id_gen!(GlobalId);
+
+/// The name of the function that runs the top-level statements of every
+/// file, in the order the files run. `$` keeps it apart from the functions
+/// of the program.
+pub const MAIN_FUNCTION: &str = "$main";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IrProgram {
+    pub globals: IdVec<GlobalId, Global>,
+    pub functions: IdVec<IrFunctionId, IrFunction>,
+    /// The function that runs the top-level statements.
+    pub main: IrFunctionId,
+}
+
+impl IrProgram {
+    pub fn function_by_name(&self, name: &str) -> Option<&IrFunction> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+}
+
+/// A global `let`. Functions read it with `load`; the top-level code that
+/// binds it stores it once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Global {
+    pub name: String,
+    pub ty: Type,
+}
+
+/// A function: its parameters are the first registers, defined on entry,
+/// and `blocks[0]` is the entry block. `registers` holds the type of every
+/// register.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IrFunction {
+    pub name: String,
+    pub parameters: Vec<Register>,
+    pub return_type: Type,
+    pub registers: IdVec<Register, Type>,
+    pub blocks: IdVec<BlockId, Block>,
+}
+
+impl IrFunction {
+    pub fn entry(&self) -> BlockId {
+        BlockId(0)
+    }
+
+    pub fn block_ids(&self) -> impl Iterator<Item = BlockId> {
+        (0..self.blocks.data.len()).map(BlockId)
+    }
+
+    /// The blocks that jump or branch to each block, in block order.
+    pub fn predecessors(&self) -> IdVec<BlockId, Vec<BlockId>> {
+        let mut predecessors = IdVec::filled(Vec::new(), self.blocks.data.len());
+        for block_id in self.block_ids() {
+            for successor in self.blocks.get(block_id).terminator.successors() {
+                if successor.0 < self.blocks.data.len() {
+                    predecessors.get_mut(successor).push(block_id);
+                }
+            }
+        }
+        predecessors
+    }
+
+    /// How many times each register is read, by instructions and
+    /// terminators.
+    pub fn use_counts(&self) -> IdVec<Register, usize> {
+        let mut counts = IdVec::filled(0, self.registers.data.len());
+        for block in self.blocks.iter() {
+            let operands = block.instructions.iter().flat_map(Instruction::operands);
+            for operand in operands.chain(block.terminator.operands()) {
+                *counts.get_mut(operand) += 1;
+            }
+        }
+        counts
+    }
+
+    /// Numbers the registers again from `%0`, parameters first and then in
+    /// the order they are defined, dropping those no longer defined, so
+    /// that a function passes removed code from reads without gaps.
+    pub fn renumber_registers(&mut self) {
+        let mut renumbered: IdVec<Register, Option<Register>> = IdVec::filled(None, self.registers.data.len());
+        let mut registers = IdVec::new();
+        let defined = self
+            .blocks
+            .iter()
+            .flat_map(|block| block.instructions.iter().filter_map(Instruction::dest));
+        for register in self.parameters.clone().into_iter().chain(defined) {
+            *renumbered.get_mut(register) = Some(registers.push(*self.registers.get(register)));
+        }
+        let renumber = |register: &mut Register| {
+            if let Some(new) = renumbered.get(*register) {
+                *register = *new;
+            }
+        };
+        self.parameters.iter_mut().for_each(renumber);
+        for block in self.blocks.data.iter_mut() {
+            for instruction in block.instructions.iter_mut() {
+                instruction.dest_mut().into_iter().for_each(renumber);
+                instruction.operands_mut().into_iter().for_each(renumber);
+            }
+            block.terminator.operands_mut().into_iter().for_each(renumber);
+        }
+        self.registers = registers;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Block {
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+/// A function a `call` can name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Callee {
+    Function(IrFunctionId),
+    Builtin(Builtin),
+}
+
+/// An instruction of a block. All but `store`, `output` and calls of
+/// functions that return nothing define a register.
+///
+/// Instructions behave as the evaluator does: arithmetic on integers traps
+/// on overflow and division by zero, and loading a global that has not
+/// been stored yet traps.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Const { dest: Register, value: Value },
+    Binary { dest: Register, operator: BinOperatorKind, left: Register, right: Register },
+    Call { dest: Option<Register>, callee: Callee, arguments: Vec<Register> },
+    Load { dest: Register, global: GlobalId },
+    Store { global: GlobalId, value: Register },
+    /// Reports `value` as the result of a top-level statement, which `run`
+    /// prints.
+    Output { value: Register },
+    /// The register that came in from the predecessor taken, one entry per
+    /// predecessor. Phis come first in their block.
+    Phi { dest: Register, incoming: Vec<(BlockId, Register)> },
+}
+
+impl Instruction {
+    pub fn dest(&self) -> Option<Register> {
+        match self {
+            Instruction::Const { dest, .. }
+            | Instruction::Binary { dest, .. }
+            | Instruction::Load { dest, .. }
+            | Instruction::Phi { dest, .. } => Some(*dest),
+            Instruction::Call { dest, .. } => *dest,
+            Instruction::Store { .. } | Instruction::Output { .. } => None,
+        }
+    }
+
+    /// The registers the instruction reads, in order.
+    pub fn operands(&self) -> Vec<Register> {
+        match self {
+            Instruction::Const { .. } | Instruction::Load { .. } => Vec::new(),
+            Instruction::Binary { left, right, .. } => vec![*left, *right],
+            Instruction::Call { arguments, .. } => arguments.clone(),
+            Instruction::Store { value, .. } | Instruction::Output { value } => vec![*value],
+            Instruction::Phi { incoming, .. } => incoming.iter().map(|(_, register)| *register).collect(),
+        }
+    }
+
+    pub fn dest_mut(&mut self) -> Option<&mut Register> {
+        match self {
+            Instruction::Const { dest, .. }
+            | Instruction::Binary { dest, .. }
+            | Instruction::Load { dest, .. }
+            | Instruction::Phi { dest, .. } => Some(dest),
+            Instruction::Call { dest, .. } => dest.as_mut(),
+            Instruction::Store { .. } | Instruction::Output { .. } => None,
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Register> {
+        match self {
+            Instruction::Const { .. } | Instruction::Load { .. } => Vec::new(),
+            Instruction::Binary { left, right, .. } => vec![left, right],
+            Instruction::Call { arguments, .. } => arguments.iter_mut().collect(),
+            Instruction::Store { value, .. } | Instruction::Output { value } => vec![value],
+            Instruction::Phi { incoming, .. } => incoming.iter_mut().map(|(_, register)| register).collect(),
+        }
+    }
+}
+
+/// How a block ends.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Terminator {
+    Return(Option<Register>),
+    Jump(BlockId),
+    /// Goes to `then_block` if `condition`, an integer, is not zero, and to
+    /// `else_block` otherwise.
+    Branch { condition: Register, then_block: BlockId, else_block: BlockId },
+    /// Ends a block control never reaches the end of, such as one whose
+    /// code did not resolve.
+    #[default]
+    Unreachable,
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch { then_block, else_block, .. } => vec![*then_block, *else_block],
+            Terminator::Return(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn operands(&self) -> Vec<Register> {
+        match self {
+            Terminator::Return(value) => value.iter().copied().collect(),
+            Terminator::Branch { condition, .. } => vec![*condition],
+            Terminator::Jump(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Register> {
+        match self {
+            Terminator::Return(value) => value.iter_mut().collect(),
+            Terminator::Branch { condition, .. } => vec![condition],
+            Terminator::Jump(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+}
+
+/// The mnemonic of an operator in the text format.
+pub fn operator_name(operator: BinOperatorKind) -> &'static str {
+    match operator {
+        BinOperatorKind::Plus => "add",
+        BinOperatorKind::Minus => "sub",
+        BinOperatorKind::Multiply => "mul",
+        BinOperatorKind::Divide => "div",
+    }
+}
+
+impl Display for Register {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl Display for BlockId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "b{}", self.0)
+    }
+}
+
+fn join<T: Display>(items: &[T]) -> String {
+    items.iter().map(T::to_string).collect::<Vec<String>>().join(", ")
+}
+
+impl IrProgram {
+    fn write_instruction(&self, function: &IrFunction, instruction: &Instruction, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "    ")?;
+        if let Some(dest) = instruction.dest() {
+            write!(f, "{}: {} = ", dest, function.registers.get(dest))?;
+        }
+        match instruction {
+            Instruction::Const { value, .. } => write!(f, "const {}", value),
+            Instruction::Binary { operator, left, right, .. } => write!(f, "{} {}, {}", operator_name(*operator), left, right),
+            Instruction::Call { callee, arguments, .. } => {
+                let name = match callee {
+                    Callee::Function(function_id) => format!("@{}", self.functions.get(*function_id).name),
+                    Callee::Builtin(builtin) => builtin.name().to_string(),
+                };
+                write!(f, "call {}({})", name, join(arguments))
+            }
+            Instruction::Load { global, .. } => write!(f, "load @{}", self.globals.get(*global).name),
+            Instruction::Store { global, value } => write!(f, "store @{}, {}", self.globals.get(*global).name, value),
+            Instruction::Output { value } => write!(f, "output {}", value),
+            Instruction::Phi { incoming, .. } => {
+                let incoming: Vec<String> = incoming.iter().map(|(block, register)| format!("[{}: {}]", block, register)).collect();
+                write!(f, "phi {}", incoming.join(", "))
+            }
+        }
+    }
+
+    fn write_function(&self, function: &IrFunction, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let parameters: Vec<String> = function
+            .parameters
+            .iter()
+            .map(|parameter| format!("{}: {}", parameter, function.registers.get(*parameter)))
+            .collect();
+        writeln!(f, "function @{}({}) -> {} {{", function.name, parameters.join(", "), function.return_type)?;
+        for block_id in function.block_ids() {
+            let block = function.blocks.get(block_id);
+            writeln!(f, "{}:", block_id)?;
+            for instruction in block.instructions.iter() {
+                self.write_instruction(function, instruction, f)?;
+                writeln!(f)?;
+            }
+            match &block.terminator {
+                Terminator::Return(Some(value)) => writeln!(f, "    ret {}", value)?,
+                Terminator::Return(None) => writeln!(f, "    ret")?,
+                Terminator::Jump(target) => writeln!(f, "    jmp {}", target)?,
+                Terminator::Branch { condition, then_block, else_block } => {
+                    writeln!(f, "    br {}, {}, {}", condition, then_block, else_block)?
+                }
+                Terminator::Unreachable => writeln!(f, "    unreachable")?,
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+/// The text format: the globals, then each function with its blocks.
+impl Display for IrProgram {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for global in self.globals.iter() {
+            writeln!(f, "global @{}: {}", global.name, global.ty)?;
+        }
+        for (index, function) in self.functions.iter().enumerate() {
+            if index > 0 || !self.globals.is_vec_empty() {
+                writeln!(f)?;
+            }
+            self.write_function(function, f)?;
+        }
+        Ok(())
+    }
+}
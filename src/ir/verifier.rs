@@ -0,0 +1,361 @@
+//! Checks that an `IrProgram` is well formed, for the passes that build or
+//! rewrite it and the code that lowers from it.
+
+use std::fmt::{Display, Formatter};
+
+use crate::ast::{
+    lib::{Id, IdVec},
+    types::{FunctionType, Type},
+    value::Value,
+    BinOperatorKind,
+};
+
+use super::{BlockId, Callee, Instruction, IrFunction, IrProgram, Register, Terminator};
+
+/// A rule of the IR that a function breaks, at the block it breaks it in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifierError {
+    pub function: String,
+    pub block: Option<BlockId>,
+    pub message: String,
+}
+
+impl Display for VerifierError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.block {
+            Some(block) => write!(f, "@{} {}: {}", self.function, block, self.message),
+            None => write!(f, "@{}: {}", self.function, self.message),
+        }
+    }
+}
+
+/// Checks every function of `program` and returns what is wrong with them:
+///
+/// - every block ends in a terminator whose targets exist, and nothing
+///   jumps back to the entry block;
+/// - every register is defined once, parameters on entry, and its
+///   definition dominates each use; the register a phi takes from a
+///   predecessor only has to be defined at the end of that predecessor;
+/// - phis come first in their block and have one entry per predecessor;
+/// - operands, results, calls, globals and returns have matching types.
+///
+/// Uses in blocks that cannot be reached from the entry only need to be
+/// defined somewhere.
+pub fn verify(program: &IrProgram) -> Vec<VerifierError> {
+    let mut errors = Vec::new();
+    for function in program.functions.iter() {
+        FunctionVerifier::new(program, function, &mut errors).verify();
+    }
+    errors
+}
+
+/// Where a register is defined: the block and the index of the instruction,
+/// `None` for a parameter, which is defined before the entry block.
+type Definition = (BlockId, Option<usize>);
+
+struct FunctionVerifier<'a> {
+    program: &'a IrProgram,
+    function: &'a IrFunction,
+    errors: &'a mut Vec<VerifierError>,
+    predecessors: IdVec<BlockId, Vec<BlockId>>,
+    /// For each reachable block, the blocks that dominate it; `None` for
+    /// unreachable ones.
+    dominators: IdVec<BlockId, Option<Vec<bool>>>,
+    definitions: IdVec<Register, Option<Definition>>,
+}
+
+impl<'a> FunctionVerifier<'a> {
+    fn new(program: &'a IrProgram, function: &'a IrFunction, errors: &'a mut Vec<VerifierError>) -> Self {
+        Self {
+            program,
+            function,
+            errors,
+            predecessors: function.predecessors(),
+            dominators: IdVec::new(),
+            definitions: IdVec::filled(None, function.registers.data.len()),
+        }
+    }
+
+    fn report(&mut self, block: Option<BlockId>, message: String) {
+        self.errors.push(VerifierError {
+            function: self.function.name.clone(),
+            block,
+            message,
+        });
+    }
+
+    fn verify(mut self) {
+        if self.function.blocks.is_vec_empty() {
+            self.report(None, String::from("function has no blocks"));
+            return;
+        }
+        self.check_control_flow();
+        self.dominators = self.compute_dominators();
+        self.collect_definitions();
+        for block_id in self.function.block_ids() {
+            self.check_block(block_id);
+        }
+    }
+
+    fn check_control_flow(&mut self) {
+        let block_count = self.function.blocks.data.len();
+        for block_id in self.function.block_ids() {
+            for target in self.function.blocks.get(block_id).terminator.successors() {
+                if target.to_usize() >= block_count {
+                    self.report(Some(block_id), format!("jumps to {}, which does not exist", target));
+                } else if target == self.function.entry() {
+                    self.report(Some(block_id), format!("jumps to the entry block {}", target));
+                }
+            }
+        }
+    }
+
+    /// The classic iterative data-flow solution: a block is dominated by
+    /// itself and by whatever dominates all of its reachable predecessors.
+    fn compute_dominators(&self) -> IdVec<BlockId, Option<Vec<bool>>> {
+        let block_count = self.function.blocks.data.len();
+        let mut reachable = vec![false; block_count];
+        let mut stack = vec![self.function.entry()];
+        while let Some(block_id) = stack.pop() {
+            if std::mem::replace(&mut reachable[block_id.to_usize()], true) {
+                continue;
+            }
+            for successor in self.function.blocks.get(block_id).terminator.successors() {
+                if successor.to_usize() < block_count {
+                    stack.push(successor);
+                }
+            }
+        }
+        let mut dominators: IdVec<BlockId, Option<Vec<bool>>> = IdVec::new();
+        for (index, reachable) in reachable.iter().enumerate() {
+            let dominated_by = match (*reachable, index) {
+                (false, _) => None,
+                (true, 0) => Some((0..block_count).map(|other| other == 0).collect()),
+                (true, _) => Some(vec![true; block_count]),
+            };
+            dominators.push(dominated_by);
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block_id in self.function.block_ids().skip(1) {
+                if dominators.get(block_id).is_none() {
+                    continue;
+                }
+                let mut dominated_by = vec![true; block_count];
+                for predecessor in self.predecessors.get(block_id) {
+                    if let Some(predecessor_dominators) = dominators.get(*predecessor) {
+                        for (dominates, predecessor_dominates) in dominated_by.iter_mut().zip(predecessor_dominators) {
+                            *dominates &= *predecessor_dominates;
+                        }
+                    }
+                }
+                dominated_by[block_id.to_usize()] = true;
+                if dominators.get(block_id).as_ref() != Some(&dominated_by) {
+                    *dominators.get_mut(block_id) = Some(dominated_by);
+                    changed = true;
+                }
+            }
+        }
+        dominators
+    }
+
+    fn collect_definitions(&mut self) {
+        let entry = self.function.entry();
+        for parameter in self.function.parameters.iter() {
+            self.define(*parameter, (entry, None));
+        }
+        for block_id in self.function.block_ids() {
+            for (index, instruction) in self.function.blocks.get(block_id).instructions.iter().enumerate() {
+                if let Some(dest) = instruction.dest() {
+                    self.define(dest, (block_id, Some(index)));
+                }
+            }
+        }
+    }
+
+    fn define(&mut self, register: Register, definition: Definition) {
+        let block = Some(definition.0);
+        if register.to_usize() >= self.definitions.data.len() {
+            self.report(block, format!("{} has no type", register));
+            return;
+        }
+        if self.definitions.get(register).is_some() {
+            self.report(block, format!("{} is defined more than once", register));
+            return;
+        }
+        *self.definitions.get_mut(register) = Some(definition);
+    }
+
+    /// Whether `block` is dominated by `dominator`. Unreachable blocks are
+    /// dominated by everything.
+    fn dominates(&self, dominator: BlockId, block: BlockId) -> bool {
+        match self.dominators.get(block) {
+            Some(dominated_by) => dominated_by[dominator.to_usize()],
+            None => true,
+        }
+    }
+
+    /// Checks that `register` is defined before the instruction at `index`
+    /// of `block`, or before the end of the block if `index` is `None`.
+    fn check_use(&mut self, register: Register, block: BlockId, index: Option<usize>) {
+        let definition = if register.to_usize() < self.definitions.data.len() {
+            *self.definitions.get(register)
+        } else {
+            None
+        };
+        let Some((defining_block, defining_index)) = definition else {
+            self.report(Some(block), format!("{} is used but never defined", register));
+            return;
+        };
+        let defined_before = if defining_block == block {
+            match (defining_index, index) {
+                (None, _) => true,
+                (Some(_), None) => true,
+                (Some(defining_index), Some(index)) => defining_index < index,
+            }
+        } else {
+            self.dominates(defining_block, block)
+        };
+        if !defined_before {
+            self.report(Some(block), format!("{} is used where its definition does not dominate", register));
+        }
+    }
+
+    fn register_type(&self, register: Register) -> Option<Type> {
+        self.function.registers.data.get(register.to_usize()).copied()
+    }
+
+    /// Reports `register` unless it has type `expected`.
+    fn expect_type(&mut self, block: BlockId, register: Register, expected: Type, what: &str) {
+        match self.register_type(register) {
+            Some(found) if found != expected => {
+                self.report(Some(block), format!("{} is {} of type {}, expected {}", register, what, found, expected))
+            }
+            _ => {}
+        }
+    }
+
+    fn check_block(&mut self, block_id: BlockId) {
+        let block = self.function.blocks.get(block_id);
+        let mut phis_allowed = true;
+        for (index, instruction) in block.instructions.iter().enumerate() {
+            let is_phi = matches!(instruction, Instruction::Phi { .. });
+            if is_phi && !phis_allowed {
+                self.report(Some(block_id), String::from("a phi comes after other instructions"));
+            }
+            phis_allowed = phis_allowed && is_phi;
+            if !is_phi {
+                for operand in instruction.operands() {
+                    self.check_use(operand, block_id, Some(index));
+                }
+            }
+            self.check_instruction(block_id, instruction);
+        }
+        let terminator = &block.terminator;
+        for operand in terminator.operands() {
+            self.check_use(operand, block_id, None);
+        }
+        match terminator {
+            Terminator::Return(value) => match (value, self.function.return_type) {
+                (None, Type::Void) => {}
+                (None, return_type) => self.report(Some(block_id), format!("returns nothing from a function returning {}", return_type)),
+                (Some(value), Type::Void) => self.report(Some(block_id), format!("returns {} from a function returning void", value)),
+                (Some(value), return_type) => self.expect_type(block_id, *value, return_type, "returned"),
+            },
+            Terminator::Branch { condition, .. } => self.expect_type(block_id, *condition, Type::Int, "the condition"),
+            Terminator::Jump(_) | Terminator::Unreachable => {}
+        }
+    }
+
+    fn check_instruction(&mut self, block_id: BlockId, instruction: &Instruction) {
+        match instruction {
+            Instruction::Const { dest, value } => {
+                let ty = match value {
+                    Value::Integer(_) => Type::Int,
+                    Value::Float(_) => Type::Float,
+                    Value::String(_) => Type::String,
+                };
+                self.expect_type(block_id, *dest, ty, "a constant");
+            }
+            Instruction::Binary { dest, operator, left, right } => {
+                let Some(ty) = self.register_type(*dest) else {
+                    return;
+                };
+                let accepted = match ty {
+                    Type::Int | Type::Float => true,
+                    Type::String => *operator == BinOperatorKind::Plus,
+                    Type::Void | Type::Var(_) | Type::Error => false,
+                };
+                if !accepted {
+                    self.report(Some(block_id), format!("{} cannot be applied to {}", super::operator_name(*operator), ty));
+                }
+                self.expect_type(block_id, *left, ty, "an operand");
+                self.expect_type(block_id, *right, ty, "an operand");
+            }
+            Instruction::Call { dest, callee, arguments } => {
+                let (name, signature) = match callee {
+                    Callee::Function(function_id) => {
+                        let Some(function) = self.program.functions.data.get(function_id.to_usize()) else {
+                            self.report(Some(block_id), String::from("calls a function that does not exist"));
+                            return;
+                        };
+                        let parameters = function.parameters.iter().map(|parameter| *function.registers.get(*parameter)).collect();
+                        (format!("@{}", function.name), FunctionType::new(parameters, function.return_type))
+                    }
+                    Callee::Builtin(builtin) => (builtin.name().to_string(), builtin.signature()),
+                };
+                if arguments.len() != signature.parameters.len() {
+                    self.report(
+                        Some(block_id),
+                        format!("{} takes {} argument(s) but {} were given", name, signature.parameters.len(), arguments.len()),
+                    );
+                } else {
+                    for (argument, parameter) in arguments.iter().zip(signature.parameters.iter()) {
+                        self.expect_type(block_id, *argument, *parameter, "an argument");
+                    }
+                }
+                match (dest, signature.return_type) {
+                    // A result nothing uses may be dropped.
+                    (None, _) => {}
+                    (Some(dest), Type::Void) => self.report(Some(block_id), format!("{} is the result of {}, which returns nothing", dest, name)),
+                    (Some(dest), return_type) => self.expect_type(block_id, *dest, return_type, "a result"),
+                }
+            }
+            Instruction::Load { dest, global } => match self.program.globals.data.get(global.to_usize()) {
+                Some(global) => self.expect_type(block_id, *dest, global.ty, "loaded"),
+                None => self.report(Some(block_id), String::from("loads a global that does not exist")),
+            },
+            Instruction::Store { global, value } => match self.program.globals.data.get(global.to_usize()) {
+                Some(global) => self.expect_type(block_id, *value, global.ty, "stored"),
+                None => self.report(Some(block_id), String::from("stores a global that does not exist")),
+            },
+            Instruction::Output { value } => {
+                if self.register_type(*value) == Some(Type::Void) {
+                    self.report(Some(block_id), format!("outputs {}, which is void", value));
+                }
+            }
+            Instruction::Phi { dest, incoming } => {
+                let predecessors = self.predecessors.get(block_id).clone();
+                let mut sources: Vec<BlockId> = incoming.iter().map(|(block, _)| *block).collect();
+                sources.sort();
+                if sources != predecessors {
+                    let names = |blocks: &[BlockId]| blocks.iter().map(BlockId::to_string).collect::<Vec<String>>().join(", ");
+                    self.report(
+                        Some(block_id),
+                        format!("phi {} comes in from [{}], but the predecessors are [{}]", dest, names(&sources), names(&predecessors)),
+                    );
+                }
+                let Some(ty) = self.register_type(*dest) else {
+                    return;
+                };
+                for (predecessor, register) in incoming {
+                    if predecessors.contains(predecessor) {
+                        self.check_use(*register, *predecessor, None);
+                    }
+                    self.expect_type(block_id, *register, ty, "an incoming value");
+                }
+            }
+        }
+    }
+}
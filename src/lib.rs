@@ -0,0 +1,8 @@
+#![allow(clippy::needless_return)]
+
+pub mod ast;
+pub mod cache;
+pub mod diagnostics;
+pub mod ir;
+pub mod json;
+pub mod lsp;
@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{
+        completion::{self, CompletionKind},
+        evaluator::ExpressionEvaluator,
+        lib::Id,
+        rename::{self, RenameError},
+        symbols::{Symbol, SymbolIndex},
+        incremental::{IncrementalParser, TextEdit},
+        text::{FileId, SourceFile, TextSpan},
+        type_checker::TypeChecker,
+        value::Value,
+        FunctionId, ItemKind, StatementKind, VariableId, VariableKind,
+    },
+    diagnostics::{Diagnostic, DiagnosticKind, DiagnosticsBag},
+    json::Json,
+};
+
+pub mod protocol;
+
+/// Limits for evaluating a document to show the values of its `let`s, so a
+/// runaway formula cannot hang the editor.
+const HOVER_FUEL: usize = 100_000;
+const HOVER_MEMORY_LIMIT: usize = 1024 * 1024;
+const HOVER_MAX_CALL_DEPTH: usize = 64;
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const REQUEST_FAILED: i64 = -32803;
+pub const PARSE_ERROR: i64 = -32700;
+
+/// `SymbolKind`s of the LSP specification.
+const SYMBOL_KIND_MODULE: i64 = 2;
+const SYMBOL_KIND_FUNCTION: i64 = 12;
+const SYMBOL_KIND_VARIABLE: i64 = 13;
+
+/// `CompletionItemKind`s of the LSP specification.
+const COMPLETION_KIND_FUNCTION: i64 = 3;
+const COMPLETION_KIND_VARIABLE: i64 = 6;
+const COMPLETION_KIND_KEYWORD: i64 = 14;
+const COMPLETION_KIND_TYPE_PARAMETER: i64 = 25;
+
+/// An open file and everything known about its current text. The file is
+/// reparsed incrementally as it changes; the later passes run on the whole
+/// tree.
+struct Document {
+    parser: IncrementalParser,
+    version: Option<i64>,
+    checker: TypeChecker,
+    symbols: SymbolIndex,
+    diagnostics: Vec<Diagnostic>,
+    /// Values of the global variables, when the file has no errors and
+    /// evaluates within the hover limits.
+    values: HashMap<VariableId, Value>,
+}
+
+impl Document {
+    fn open(uri: &str, text: String, version: Option<i64>) -> Self {
+        let source = SourceFile::new(FileId::new(0), uri.to_string(), text);
+        Self::analyze(IncrementalParser::new(source), version)
+    }
+
+    /// Applies the `contentChanges` of a `didChange` notification. A
+    /// change with a `range` replaces that range, one without replaces the
+    /// whole text.
+    fn change(self, changes: &[Json], version: Option<i64>) -> Self {
+        let mut parser = self.parser;
+        for change in changes.iter() {
+            let Some(text) = change.get("text").and_then(Json::as_str) else {
+                continue;
+            };
+            let (start, end) = match change.get("range") {
+                Some(range) => {
+                    let start = range.get("start").map_or(0, |start| protocol::offset(&parser.source, start));
+                    let end = range.get("end").map_or(0, |end| protocol::offset(&parser.source, end));
+                    (start.min(end), end.max(start))
+                }
+                None => (0, parser.source.text.len()),
+            };
+            parser.edit(&TextEdit::new(start, end, text.to_string()));
+        }
+        Self::analyze(parser, version)
+    }
+
+    fn analyze(parser: IncrementalParser, version: Option<i64>) -> Self {
+        let diagnostics = DiagnosticsBag::new_cell();
+        diagnostics.borrow_mut().diagnostics = parser.diagnostics();
+        let ast = &parser.ast;
+        let checker = ast.type_check(diagnostics.clone());
+        ast.check_liveness(diagnostics.clone());
+        let values = if diagnostics.borrow().has_errors() {
+            HashMap::new()
+        } else {
+            let mut evaluator = ExpressionEvaluator::new()
+                .with_max_call_depth(HOVER_MAX_CALL_DEPTH)
+                .with_fuel(HOVER_FUEL)
+                .with_memory_limit(HOVER_MEMORY_LIMIT);
+            ast.evaluate(&mut evaluator).environment
+        };
+        let symbols = SymbolIndex::new(ast);
+        let diagnostics = diagnostics.borrow().diagnostics.clone();
+        Self {
+            parser,
+            version,
+            checker,
+            symbols,
+            diagnostics,
+            values,
+        }
+    }
+
+    fn range(&self, span: &TextSpan) -> Json {
+        protocol::range(&self.parser.source, span.start, span.end)
+    }
+
+    fn signature(&self, function_id: FunctionId) -> String {
+        self.checker.function_signature(&self.parser.ast, function_id)
+    }
+
+    /// What hovering over `symbol` shows: the declaration with its type,
+    /// and the value of a global variable when it is known.
+    fn describe(&self, symbol: Symbol) -> String {
+        match symbol {
+            Symbol::Function(function_id) => self.signature(function_id),
+            Symbol::Variable(variable_id) => {
+                let variable = self.parser.ast.query_variable(variable_id);
+                let ty = self.checker.variable_types.get(variable_id);
+                let declaration = match variable.kind {
+                    VariableKind::Parameter => format!("(parameter) {}: {}", variable.name, ty),
+                    VariableKind::Global | VariableKind::Local => format!("let {}: {}", variable.name, ty),
+                };
+                match self.values.get(&variable_id) {
+                    Some(value) => format!("{} = {}", declaration, value),
+                    None => declaration,
+                }
+            }
+        }
+    }
+
+    fn lsp_diagnostics(&self) -> Json {
+        let diagnostics = self
+            .diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let severity = match diagnostic.kind {
+                    DiagnosticKind::Error => 1,
+                    DiagnosticKind::Warning => 2,
+                };
+                Json::object(vec![
+                    ("range", self.range(&diagnostic.span)),
+                    ("severity", Json::Number(severity)),
+                    ("source", Json::string("calc")),
+                    ("message", Json::string(&diagnostic.message)),
+                ])
+            })
+            .collect();
+        Json::Array(diagnostics)
+    }
+
+    fn document_symbols(&self) -> Json {
+        let mut symbols = Vec::new();
+        for item in self.parser.ast.top_level_items() {
+            match item.kind {
+                ItemKind::Function(function_id) => {
+                    let function = self.parser.ast.query_function(function_id);
+                    symbols.push(Json::object(vec![
+                        ("name", Json::string(&function.name)),
+                        ("detail", Json::String(self.signature(function_id))),
+                        ("kind", Json::Number(SYMBOL_KIND_FUNCTION)),
+                        ("range", self.range(&function.span())),
+                        ("selectionRange", self.range(&function.identifier.span)),
+                    ]));
+                }
+                ItemKind::Import(import_id) => {
+                    let import = self.parser.ast.query_import(import_id);
+                    symbols.push(Json::object(vec![
+                        ("name", Json::String(import.path())),
+                        ("kind", Json::Number(SYMBOL_KIND_MODULE)),
+                        ("range", self.range(&import.span())),
+                        ("selectionRange", self.range(&import.span())),
+                    ]));
+                }
+                ItemKind::Statement(stmt_id) => {
+                    let stmt = self.parser.ast.query_stmt(stmt_id);
+                    if let StatementKind::Let(let_stmt) = &stmt.stmt_kind {
+                        let ty = self.checker.variable_types.get(let_stmt.variable_id);
+                        symbols.push(Json::object(vec![
+                            ("name", Json::string(&let_stmt.identifier.span.literal)),
+                            ("detail", Json::String(ty.to_string())),
+                            ("kind", Json::Number(SYMBOL_KIND_VARIABLE)),
+                            ("range", self.range(&stmt.span(&self.parser.ast))),
+                            ("selectionRange", self.range(&let_stmt.identifier.span)),
+                        ]));
+                    }
+                }
+            }
+        }
+        Json::Array(symbols)
+    }
+}
+
+/// A language server for formula files. It keeps every open document
+/// analyzed: parsed, type checked and, when it has no errors, evaluated.
+/// `handle` takes one JSON-RPC message and returns the messages to send
+/// back, so the server can be driven by any transport.
+///
+/// Documents are synchronized incrementally: each change carries the
+/// edited range and its new text.
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    shutdown_requested: bool,
+    /// Set once the client sent `exit`: 0 after a `shutdown` request, 1
+    /// otherwise.
+    pub exit_code: Option<i32>,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or_default();
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let Some(id) = message.get("id") else {
+            return self.handle_notification(method, params);
+        };
+        let result = match method {
+            "initialize" => Ok(Self::capabilities()),
+            "shutdown" => {
+                self.shutdown_requested = true;
+                Ok(Json::Null)
+            }
+            "textDocument/hover" => self.with_document(params, Self::hover),
+            "textDocument/definition" => self.with_document(params, Self::definition),
+            "textDocument/documentSymbol" => self.with_document(params, |document, _| document.document_symbols()),
+            "textDocument/references" => self.with_document(params, |document, offset| Self::references(document, offset, params)),
+            "textDocument/rename" => self.rename(params),
+            "textDocument/completion" => self.with_document(params, Self::completion),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method {}", method))),
+        };
+        vec![match result {
+            Ok(result) => Self::response(id.clone(), result),
+            Err((code, message)) => Self::error_response(id.clone(), code, &message),
+        }]
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let text_document = params.get("textDocument").unwrap_or(&Json::Null);
+        let Some(uri) = text_document.get("uri").and_then(Json::as_str) else {
+            if method == "exit" {
+                self.exit_code = Some(if self.shutdown_requested { 0 } else { 1 });
+            }
+            return vec![];
+        };
+        let version = text_document.get("version").and_then(Json::as_i64);
+        let document = match method {
+            "textDocument/didOpen" => match text_document.get("text").and_then(Json::as_str) {
+                Some(text) => Document::open(uri, text.to_string(), version),
+                None => return vec![],
+            },
+            "textDocument/didChange" => {
+                let changes = params.get("contentChanges").and_then(Json::as_array).unwrap_or_default();
+                match self.documents.remove(uri) {
+                    Some(document) => document.change(changes, version),
+                    None => return vec![],
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![Self::publish_diagnostics(uri, None, Json::Array(vec![]))];
+            }
+            _ => return vec![],
+        };
+        let notification = Self::publish_diagnostics(uri, document.version, document.lsp_diagnostics());
+        self.documents.insert(uri.to_string(), document);
+        vec![notification]
+    }
+
+    fn capabilities() -> Json {
+        Json::object(vec![
+            (
+                "capabilities",
+                Json::object(vec![
+                    ("textDocumentSync", Json::Number(2)),
+                    ("hoverProvider", Json::Bool(true)),
+                    ("definitionProvider", Json::Bool(true)),
+                    ("documentSymbolProvider", Json::Bool(true)),
+                    ("referencesProvider", Json::Bool(true)),
+                    ("renameProvider", Json::Bool(true)),
+                    ("completionProvider", Json::object(vec![])),
+                ]),
+            ),
+            ("serverInfo", Json::object(vec![("name", Json::string("calc-lsp"))])),
+        ])
+    }
+
+    /// Runs `request` on the document named by `params.textDocument.uri`
+    /// with the byte offset of `params.position`, if there is one.
+    fn with_document(
+        &self,
+        params: &Json,
+        request: impl Fn(&Document, usize) -> Json,
+    ) -> Result<Json, (i64, String)> {
+        let (document, offset) = self.document_at(params)?;
+        Ok(request(document, offset))
+    }
+
+    /// The document named by `params.textDocument.uri` and the byte offset
+    /// of `params.position` in it.
+    fn document_at(&self, params: &Json) -> Result<(&Document, usize), (i64, String)> {
+        let uri = params
+            .get("textDocument")
+            .and_then(|text_document| text_document.get("uri"))
+            .and_then(Json::as_str)
+            .ok_or((INVALID_PARAMS, "missing textDocument.uri".to_string()))?;
+        let document = self
+            .documents
+            .get(uri)
+            .ok_or((INVALID_PARAMS, format!("{} is not open", uri)))?;
+        let offset = params
+            .get("position")
+            .map(|position| protocol::offset(&document.parser.source, position))
+            .unwrap_or(0);
+        Ok((document, offset))
+    }
+
+    fn hover(document: &Document, offset: usize) -> Json {
+        let Some(reference) = document.symbols.reference_at(offset) else {
+            return Json::Null;
+        };
+        let contents = format!("```calc\n{}\n```", document.describe(reference.symbol));
+        Json::object(vec![
+            (
+                "contents",
+                Json::object(vec![("kind", Json::string("markdown")), ("value", Json::String(contents))]),
+            ),
+            ("range", document.range(&reference.span)),
+        ])
+    }
+
+    fn definition(document: &Document, offset: usize) -> Json {
+        let declaration = document
+            .symbols
+            .reference_at(offset)
+            .and_then(|reference| document.symbols.declaration(reference.symbol));
+        match declaration {
+            Some(declaration) => Json::object(vec![
+                ("uri", Json::string(&document.parser.source.name)),
+                ("range", document.range(&declaration.span)),
+            ]),
+            None => Json::Null,
+        }
+    }
+
+    fn references(document: &Document, offset: usize, params: &Json) -> Json {
+        let include_declaration = params
+            .get("context")
+            .and_then(|context| context.get("includeDeclaration"))
+            .is_none_or(|include| *include == Json::Bool(true));
+        let Some(reference) = document.symbols.reference_at(offset) else {
+            return Json::Null;
+        };
+        let locations = document
+            .symbols
+            .references_to(reference.symbol)
+            .filter(|reference| include_declaration || !reference.is_declaration)
+            .map(|reference| {
+                Json::object(vec![
+                    ("uri", Json::string(&document.parser.source.name)),
+                    ("range", document.range(&reference.span)),
+                ])
+            })
+            .collect();
+        Json::Array(locations)
+    }
+
+    fn completion(document: &Document, offset: usize) -> Json {
+        let items = completion::completions(&document.parser.ast, &document.checker, offset)
+            .into_iter()
+            .map(|completion| {
+                let kind = match completion.kind {
+                    CompletionKind::Variable => COMPLETION_KIND_VARIABLE,
+                    CompletionKind::Function | CompletionKind::Builtin => COMPLETION_KIND_FUNCTION,
+                    CompletionKind::Keyword => COMPLETION_KIND_KEYWORD,
+                    CompletionKind::Type => COMPLETION_KIND_TYPE_PARAMETER,
+                };
+                Json::object(vec![
+                    ("label", Json::String(completion.label)),
+                    ("kind", Json::Number(kind)),
+                    ("detail", Json::String(completion.detail)),
+                ])
+            })
+            .collect();
+        Json::Array(items)
+    }
+
+    /// Answers with a `WorkspaceEdit` that renames the symbol at the
+    /// position, or with an error saying why it cannot be renamed.
+    fn rename(&self, params: &Json) -> Result<Json, (i64, String)> {
+        let (document, offset) = self.document_at(params)?;
+        let new_name = params
+            .get("newName")
+            .and_then(Json::as_str)
+            .ok_or((INVALID_PARAMS, "missing newName".to_string()))?;
+        let source = &document.parser.source;
+        let edits = rename::rename(source, &document.parser.ast, offset, new_name).map_err(|error| {
+            let message = match &error {
+                RenameError::Collision { declaration: Some(span), .. } | RenameError::Shadowed { declaration: span, .. } => {
+                    format!("{} (declared on line {})", error, source.line_index(span.start) + 1)
+                }
+                RenameError::Captures { span, .. } => format!("{} (used on line {})", error, source.line_index(span.start) + 1),
+                _ => error.to_string(),
+            };
+            (REQUEST_FAILED, message)
+        })?;
+        let text_edits = edits
+            .iter()
+            .map(|edit| {
+                Json::object(vec![
+                    ("range", protocol::range(source, edit.start, edit.end)),
+                    ("newText", Json::string(&edit.replacement)),
+                ])
+            })
+            .collect();
+        let changes = Json::Object(vec![(source.name.clone(), Json::Array(text_edits))]);
+        Ok(Json::object(vec![("changes", changes)]))
+    }
+
+    fn publish_diagnostics(uri: &str, version: Option<i64>, diagnostics: Json) -> Json {
+        let mut params = vec![("uri", Json::string(uri))];
+        if let Some(version) = version {
+            params.push(("version", Json::Number(version)));
+        }
+        params.push(("diagnostics", diagnostics));
+        Json::object(vec![
+            ("jsonrpc", Json::string("2.0")),
+            ("method", Json::string("textDocument/publishDiagnostics")),
+            ("params", Json::object(params)),
+        ])
+    }
+
+    fn response(id: Json, result: Json) -> Json {
+        Json::object(vec![("jsonrpc", Json::string("2.0")), ("id", id), ("result", result)])
+    }
+
+    pub fn error_response(id: Json, code: i64, message: &str) -> Json {
+        Json::object(vec![
+            ("jsonrpc", Json::string("2.0")),
+            ("id", id),
+            (
+                "error",
+                Json::object(vec![("code", Json::Number(code)), ("message", Json::string(message))]),
+            ),
+        ])
+    }
+}
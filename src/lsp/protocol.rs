@@ -0,0 +1,94 @@
+use std::io::{self, BufRead, Read, Write};
+
+use crate::{ast::text::SourceFile, json::Json};
+
+/// The largest message body `read_message` accepts. The length comes from
+/// the client, so it is checked before anything is allocated for it.
+pub const MAX_CONTENT_LENGTH: usize = 64 * 1024 * 1024;
+
+/// Reads the body of the next message, which is preceded by a
+/// `Content-Length` header. Returns `None` once the input has ended.
+///
+/// A message without a `Content-Length`, with a body over
+/// `MAX_CONTENT_LENGTH` or with a body that is not UTF-8 is an
+/// `InvalidData` error. Its body is read and dropped first, without being
+/// stored, so the next message can still be read.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let Some(content_length) = content_length else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header"));
+    };
+    if content_length > MAX_CONTENT_LENGTH {
+        io::copy(&mut reader.take(content_length as u64), &mut io::sink())?;
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Content-Length of {} bytes is over the limit of {}", content_length, MAX_CONTENT_LENGTH),
+        ));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// The LSP position of the byte `offset`. LSP counts columns in UTF-16
+/// code units.
+pub fn position(source: &SourceFile, offset: usize) -> Json {
+    let mut offset = offset.min(source.text.len());
+    while !source.text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let line = source.line_index(offset);
+    let line_start = source.line_start(line).unwrap_or(0);
+    let character = source.text[line_start..offset].encode_utf16().count();
+    Json::object(vec![
+        ("line", Json::Number(line as i64)),
+        ("character", Json::Number(character as i64)),
+    ])
+}
+
+pub fn range(source: &SourceFile, start: usize, end: usize) -> Json {
+    Json::object(vec![("start", position(source, start)), ("end", position(source, end))])
+}
+
+/// The byte offset of an LSP position. Positions past the end of a line
+/// are clamped to it, and positions past the last line to the end of the
+/// text.
+pub fn offset(source: &SourceFile, position: &Json) -> usize {
+    let line = position.get("line").and_then(Json::as_i64).unwrap_or(0).max(0) as usize;
+    let character = position.get("character").and_then(Json::as_i64).unwrap_or(0).max(0) as usize;
+    let Some(line_start) = source.line_start(line) else {
+        return source.text.len();
+    };
+    let line_text = source.line_text(line);
+    let mut units = 0;
+    for (index, c) in line_text.char_indices() {
+        if units >= character {
+            return line_start + index;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line_text.len()
+}
@@ -1,32 +1,415 @@
-use ast::{ global_scope::{self, GlobalScope}, lexer::{Lexer, Token}, parser::Parser, Ast};
+use std::{env, fs, process};
 
+use compiler::{ir, cache::{Artifact, Cache, CacheKey, SourceHash, CACHE_FORMAT_VERSION, DEFAULT_CACHE_DIR, SOURCE_FINGERPRINT}, ast::{ evaluator::{ExpressionEvaluator, RuntimeError, StatementResult, DEFAULT_MAX_CALL_DEPTH}, image, lib::Id, modules::{FileLoader, Program, SourceSnapshot}, subexpressions::eliminate_common_subexpressions, syntax::debug_tree, text::{FileId, SourceFile, SourceMap}, Ast, VariableKind}, diagnostics::{printer::DiagnosticsPrinter, Diagnostic, DiagnosticKind, DiagnosticsBag}};
 
-mod ast;
+/// `fmt [--check] <files...>` rewrites each file in the canonical style.
+/// With `--check` nothing is written; files that differ are reported and
+/// the process exits with status 1.
+fn format_files(args:&[String]){
+    let check = args.iter().any(|arg| arg == "--check");
+    let paths:Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if paths.is_empty(){
+        eprintln!("usage: compiler fmt [--check] <files...>");
+        process::exit(2);
+    }
+    let mut unformatted = 0;
+    for path in paths{
+        let source = fs::read_to_string(path).unwrap_or_else(|error| {
+            eprintln!("{}: {}",path,error);
+            process::exit(2);
+        });
+        let source = SourceFile::new(FileId::new(0), path.clone(), source);
+        let diagnostics = DiagnosticsBag::new_cell();
+        let ast = Ast::parse(&source,diagnostics.clone());
+        if diagnostics.borrow().has_errors(){
+            DiagnosticsPrinter::new(&source, &diagnostics.borrow().diagnostics).print();
+            process::exit(2);
+        }
+        let formatted = ast.format();
+        if formatted == source.text{
+            continue;
+        }
+        if check{
+            println!("{}: not formatted",path);
+            unformatted += 1;
+        }else if let Err(error) = fs::write(path, formatted){
+            eprintln!("{}: {}",path,error);
+            process::exit(2);
+        }
+    }
+    if unformatted > 0{
+        process::exit(1);
+    }
+}
+
+/// `ast [--json|--sexpr|--dot|--cst] <file>` prints the parsed tree of a file, either
+/// as the indented debug view or in one of the machine-readable formats.
+/// `--cst` prints the lossless syntax tree, trivia included.
+fn print_ast(args:&[String]){
+    let Some(path) = args.iter().find(|arg| !arg.starts_with("--")) else {
+        eprintln!("usage: compiler ast [--json|--sexpr|--dot|--cst] <file>");
+        process::exit(2);
+    };
+    let source = fs::read_to_string(path).unwrap_or_else(|error| {
+        eprintln!("{}: {}",path,error);
+        process::exit(2);
+    });
+    let source = SourceFile::new(FileId::new(0), path.clone(), source);
+    let diagnostics = DiagnosticsBag::new_cell();
+    let ast = Ast::parse(&source,diagnostics.clone());
+    DiagnosticsPrinter::new(&source, &diagnostics.borrow().diagnostics).print();
+    if args.iter().any(|arg| arg == "--json"){
+        println!("{}",ast.to_json());
+    }else if args.iter().any(|arg| arg == "--sexpr"){
+        print!("{}",ast.to_sexpr());
+    }else if args.iter().any(|arg| arg == "--dot"){
+        print!("{}",ast.to_dot());
+    }else if args.iter().any(|arg| arg == "--cst"){
+        print!("{}",debug_tree(&ast.syntax()));
+    }else{
+        ast.visualize();
+    }
+}
+
+/// Where the outcomes of `run` and `check` are cached: `--cache-dir <dir>`,
+/// else `$COMPILER_CACHE_DIR`, else `.compiler-cache`. Returns the cache,
+/// whether to use it, which `--no-cache` turns off, and the arguments left.
+fn cache_options(args:&[String])->(Cache,bool,Vec<String>){
+    let mut enabled = true;
+    let mut dir = env::var("COMPILER_CACHE_DIR").unwrap_or_else(|_| String::from(DEFAULT_CACHE_DIR));
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next(){
+        match arg.as_str(){
+            "--no-cache"=>enabled = false,
+            "--cache-dir"=>match args.next(){
+                Some(value)=>dir = value.clone(),
+                None=>{
+                    eprintln!("--cache-dir expects a directory");
+                    process::exit(2);
+                }
+            },
+            _=>rest.push(arg.clone()),
+        }
+    }
+    (Cache::new(dir),enabled,rest)
+}
+
+/// The version the cache keys are made with: the package version, the
+/// fingerprint of the sources the compiler was built from, and the format
+/// of the entries, which changes with the compiled form of programs.
+fn compiler_version()->String{
+    format!("{} {} ({}) cache format {}",env!("CARGO_PKG_NAME"),env!("CARGO_PKG_VERSION"),SOURCE_FINGERPRINT,CACHE_FORMAT_VERSION)
+}
+
+/// The exit status when the compiler itself failed, for instance to read
+/// the main file.
+const COMPILER_FAILURE:i32 = 2;
+
+/// Reads the file at `path` with its imports and compiles them with
+/// `compile`, unless `cache` holds what compiling them for `command`
+/// produced already. Prints the diagnostics and the listing, and exits if
+/// there are errors. Otherwise `open` turns the artifact into what the
+/// command goes on with; a cached one it cannot open is compiled again.
+fn compile_cached<T>(cache:Option<Cache>,command:Vec<String>,path:&str,compile:impl FnOnce(SourceSnapshot)->Result<Artifact,String>,open:impl Fn(&Artifact,&SourceSnapshot)->Option<T>)->T{
+    let snapshot = SourceSnapshot::read(path, &mut FileLoader).unwrap_or_else(|error| {
+        eprintln!("{}: {}",path,error);
+        process::exit(COMPILER_FAILURE);
+    });
+    let compile = |snapshot:&SourceSnapshot| compile(snapshot.clone()).unwrap_or_else(|error| {
+        eprintln!("{}: {}",path,error);
+        process::exit(COMPILER_FAILURE);
+    });
+    let key = CacheKey::new(&compiler_version(), &command, &snapshot.files);
+    let sources = SourceHash::of_files(&snapshot.files);
+    let cached = cache.as_ref().and_then(|cache| cache.get(key, &sources)).and_then(|artifact| {
+        let opened = if artifact.has_errors { None } else { Some(open(&artifact, &snapshot)?) };
+        Some((artifact,opened))
+    });
+    let (artifact,opened) = cached.unwrap_or_else(|| {
+        let artifact = Artifact{ sources, ..compile(&snapshot) };
+        // A cache that cannot be written only costs the next run the time.
+        if let Some(cache) = &cache{
+            if cache.put(key, &artifact).is_err(){
+                eprintln!("warning: cannot write to the cache in {}",cache.dir().display());
+            }
+        }
+        let opened = (!artifact.has_errors).then(|| open(&artifact, &snapshot).expect("a program just compiled opens"));
+        (artifact,opened)
+    });
+    print!("{}",artifact.listing);
+    eprint!("{}",artifact.diagnostics);
+    opened.unwrap_or_else(|| process::exit(1))
+}
+
+/// Renders each diagnostic against the file it was reported in.
+fn render_diagnostics(sources:&SourceMap,diagnostics:&[Diagnostic],out:&mut String){
+    for diagnostic in diagnostics{
+        out.push_str(&DiagnosticsPrinter::new(sources.get(diagnostic.span.file_id), &[]).stringify_diagnostic(diagnostic));
+        out.push('\n');
+    }
+}
+
+/// Reads the number that follows a flag such as `--fuel`.
+fn flag_value(flag:&str,value:Option<&String>)->usize{
+    value.and_then(|value| value.parse().ok()).unwrap_or_else(|| {
+        eprintln!("{} expects a number",flag);
+        process::exit(2);
+    })
+}
+
+/// `run [--max-depth <calls>] [--fuel <steps>] [--memory-limit <bytes>] [--no-cache] [--cache-dir <dir>] <file>`
+/// evaluates a file, after the modules it imports, and prints the answer.
+/// The compiled program is cached; see `compile_cached`.
+fn run_file(args:&[String]){
+    let (cache,enabled,args) = cache_options(args);
+    let cache = enabled.then_some(cache);
+    let mut max_call_depth = DEFAULT_MAX_CALL_DEPTH;
+    let mut fuel = None;
+    let mut memory_limit = None;
+    let mut path = None;
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next(){
+        match arg.as_str(){
+            "--max-depth"=>max_call_depth = flag_value(arg, rest.next()),
+            "--fuel"=>fuel = Some(flag_value(arg, rest.next())),
+            "--memory-limit"=>memory_limit = Some(flag_value(arg, rest.next())),
+            _=>path = Some(arg),
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("usage: compiler run [--max-depth <calls>] [--fuel <steps>] [--memory-limit <bytes>] [--no-cache] [--cache-dir <dir>] <file>");
+        process::exit(2);
+    };
+    // The options only matter once the program runs, so the compiled
+    // program is the same for all of them.
+    let command = vec![String::from("run"),path.clone()];
+    let program = compile_cached(cache, command, path, |mut snapshot| {
+        let mut artifact = Artifact::default();
+        let diagnostics = DiagnosticsBag::new_cell();
+        let program = Program::load(path, &mut snapshot, diagnostics.clone())?;
+        program.ast.type_check(diagnostics.clone());
+        program.ast.check_liveness(diagnostics.clone());
+        render_diagnostics(&program.sources, &diagnostics.borrow().diagnostics, &mut artifact.diagnostics);
+        artifact.has_errors = diagnostics.borrow().has_errors();
+        if !artifact.has_errors{
+            artifact.program = Some(image::save(&program));
+        }
+        Ok(artifact)
+    }, |artifact,snapshot| image::load(artifact.program.as_ref()?, snapshot));
+    let mut evaluator = ExpressionEvaluator::new().with_max_call_depth(max_call_depth);
+    if let Some(fuel) = fuel{
+        evaluator = evaluator.with_fuel(fuel);
+    }
+    if let Some(memory_limit) = memory_limit{
+        evaluator = evaluator.with_memory_limit(memory_limit);
+    }
+    let evaluation = program.ast.evaluate(&mut evaluator);
+    // The statements of the imported modules ran first, unprinted.
+    let results:Vec<StatementResult> = evaluation.results.into_iter().filter(|result| result.span.file_id == program.main).collect();
+    let mut out = String::new();
+    render_results(program.main_source(), &results, &mut out);
+    print!("{}",out);
+    if let Some(error) = evaluation.error{
+        let mut out = String::new();
+        render_runtime_error(&program.sources, &error, &mut out);
+        eprint!("{}",out);
+        process::exit(1);
+    }
+}
+
+/// Renders the value of every top-level statement next to its first line,
+/// like the cells of a notebook.
+fn render_results(source:&SourceFile,results:&[StatementResult],out:&mut String){
+    let width = source.line_count().to_string().len();
+    for result in results{
+        let line = source.line_col(result.span.start).line;
+        out.push_str(&format!("{:>width$} | {}  => {}\n",line + 1,source.line_text(line).trim(),result.value,width = width));
+    }
+}
+
+/// Renders a runtime error like a diagnostic. A stack overflow also lists
+/// the calls that were in progress, innermost first.
+fn render_runtime_error(sources:&SourceMap,error:&RuntimeError,out:&mut String){
+    const SHOWN_CALLS:usize = 10;
+    let diagnostic = Diagnostic::new(error.to_string(), error.span().clone(), DiagnosticKind::Error);
+    render_diagnostics(sources, &[diagnostic], out);
+    if let RuntimeError::StackOverflow { trace, .. } = error{
+        out.push_str("call trace, innermost first:\n");
+        for (index, call_site) in trace.iter().enumerate(){
+            // Long traces are cut in the middle, keeping both ends.
+            let shown = index < SHOWN_CALLS / 2 || index >= trace.len().saturating_sub(SHOWN_CALLS / 2);
+            if shown{
+                out.push_str(&format!("    {} called at {}\n",call_site.function,sources.get(call_site.span.file_id).location(&call_site.span)));
+            }else if index == SHOWN_CALLS / 2{
+                out.push_str(&format!("    ... {} more\n",trace.len() - SHOWN_CALLS));
+            }
+        }
+    }
+}
+
+/// `check [--no-cache] [--cache-dir <dir>] <file>` type checks a file and
+/// the modules it imports, and prints the inferred type of every `let` and
+/// the signature of every function of the file. What it prints is cached;
+/// see `compile_cached`.
+fn check_file(args:&[String]){
+    let (cache,enabled,args) = cache_options(args);
+    let cache = enabled.then_some(cache);
+    let Some(path) = args.first() else {
+        eprintln!("usage: compiler check [--no-cache] [--cache-dir <dir>] <file>");
+        process::exit(2);
+    };
+    let command = vec![String::from("check"),path.clone()];
+    compile_cached(cache, command, path, |mut snapshot| {
+        let mut artifact = Artifact::default();
+        let diagnostics = DiagnosticsBag::new_cell();
+        let program = Program::load(path, &mut snapshot, diagnostics.clone())?;
+        let ast = &program.ast;
+        let checker = ast.type_check(diagnostics.clone());
+        ast.check_liveness(diagnostics.clone());
+        render_diagnostics(&program.sources, &diagnostics.borrow().diagnostics, &mut artifact.diagnostics);
+        for (function, function_type) in ast.functions.iter().zip(checker.function_types.iter()){
+            if function.function_keyword.span.file_id != program.main{
+                continue;
+            }
+            let parameters:Vec<String> = function.parameters.iter()
+                .zip(function_type.parameters.iter())
+                .map(|(parameter,ty)| format!("{}: {}",parameter.identifier.span.literal,ty))
+                .collect();
+            artifact.listing.push_str(&format!("function {}({}) -> {}\n",function.name,parameters.join(", "),function_type.return_type));
+        }
+        for (variable, ty) in ast.variables.iter().zip(checker.variable_types.iter()){
+            if variable.kind != VariableKind::Parameter && variable.identifier.span.file_id == program.main{
+                artifact.listing.push_str(&format!("let {}: {}\n",variable.name,ty));
+            }
+        }
+        artifact.has_errors = diagnostics.borrow().has_errors();
+        Ok(artifact)
+    }, |_,_| Some(()));
+}
+
+/// `ir [--unoptimized] <file>` lowers a file and the modules it imports to
+/// the intermediate representation, removes the code nothing uses unless
+/// `--unoptimized` is given, verifies it and prints it.
+fn print_ir(args:&[String]){
+    let optimize = !args.iter().any(|arg| arg == "--unoptimized");
+    let Some(path) = args.iter().find(|arg| !arg.starts_with("--")) else {
+        eprintln!("usage: compiler ir [--unoptimized] <file>");
+        process::exit(2);
+    };
+    let diagnostics = DiagnosticsBag::new_cell();
+    let program = Program::load(path, &mut FileLoader, diagnostics.clone()).unwrap_or_else(|error| {
+        eprintln!("{}: {}",path,error);
+        process::exit(2);
+    });
+    let checker = program.ast.type_check(diagnostics.clone());
+    program.ast.check_liveness(diagnostics.clone());
+    let mut rendered = String::new();
+    render_diagnostics(&program.sources, &diagnostics.borrow().diagnostics, &mut rendered);
+    eprint!("{}",rendered);
+    if diagnostics.borrow().has_errors(){
+        process::exit(1);
+    }
+    let mut ir = ir::lower(&program.ast, &checker);
+    if optimize{
+        let removed = ir::eliminate_dead_code(&mut ir);
+        eprintln!("dead code: {}",removed);
+    }
+    print!("{}",ir);
+    let errors = ir::verify(&ir);
+    if !errors.is_empty(){
+        for error in errors{
+            eprintln!("invalid IR: {}",error);
+        }
+        process::exit(2);
+    }
+}
+
+/// `cse [--count] <file>` computes every operation or call that a file
+/// repeats once, into a temporary, and prints the rewritten file. The
+/// temporaries go to stderr. With `--count` the file is also run before and
+/// after, and the number of nodes the evaluator visited is reported.
+fn eliminate_subexpressions(args:&[String]){
+    let count = args.iter().any(|arg| arg == "--count");
+    let Some(path) = args.iter().find(|arg| !arg.starts_with("--")) else {
+        eprintln!("usage: compiler cse [--count] <file>");
+        process::exit(2);
+    };
+    let mut snapshot = SourceSnapshot::read(path, &mut FileLoader).unwrap_or_else(|error| {
+        eprintln!("{}: {}",path,error);
+        process::exit(2);
+    });
+    let diagnostics = DiagnosticsBag::new_cell();
+    let Ok(program) = Program::load(path, &mut snapshot, diagnostics.clone()) else {
+        unreachable!("the snapshot holds the main file");
+    };
+    program.ast.type_check(diagnostics.clone());
+    let mut rendered = String::new();
+    render_diagnostics(&program.sources, &diagnostics.borrow().diagnostics, &mut rendered);
+    eprint!("{}",rendered);
+    if diagnostics.borrow().has_errors(){
+        process::exit(1);
+    }
+    let Ok(elimination) = eliminate_common_subexpressions(path, snapshot.clone()) else {
+        unreachable!("the snapshot holds the main file");
+    };
+    print!("{}",elimination.text);
+    for temporary in elimination.temporaries.iter(){
+        eprintln!("temporary: {}",temporary);
+    }
+    if count{
+        snapshot.set_text(path, elimination.text);
+        let Ok(rewritten) = Program::load(path, &mut snapshot, DiagnosticsBag::new_cell()) else {
+            unreachable!("the snapshot holds the main file");
+        };
+        eprintln!("evaluated nodes: {} before, {} after",evaluated_nodes(&program.ast),evaluated_nodes(&rewritten.ast));
+    }
+}
+
+/// How many statements and expressions running `ast` evaluates.
+fn evaluated_nodes(ast:&Ast)->usize{
+    let mut evaluator = ExpressionEvaluator::new();
+    ast.evaluate(&mut evaluator);
+    evaluator.fuel_used()
+}
+
+/// `cache stats|clear [--cache-dir <dir>]` reports how much the cache of
+/// `run` and `check` holds and how often it was hit, or empties it.
+fn manage_cache(args:&[String]){
+    // `--no-cache` only matters to `run` and `check`.
+    let (cache,_,args) = cache_options(args);
+    match args.first().map(String::as_str){
+        Some("stats")=>{
+            println!("cache:   {}",cache.dir().display());
+            println!("{}",cache.stats());
+        }
+        Some("clear")=>match cache.clear(){
+            Ok(removed)=>println!("removed {} entries from {}",removed,cache.dir().display()),
+            Err(error)=>{
+                eprintln!("{}: {}",cache.dir().display(),error);
+                process::exit(2);
+            }
+        },
+        _=>{
+            eprintln!("usage: compiler cache stats|clear [--cache-dir <dir>]");
+            process::exit(2);
+        }
+    }
+}
 
 fn main() {
-    // global_scope.add_global_variable(String::from("a"), String::from("5"));
-    
-        
-        let input:&str = "
-        function multiply(){
-          let b = 6
-          let a = 7
-        }";
-        
-        let mut lexer = Lexer::new(input);
-        let mut tokens:Vec<Token> = Vec::new(); 
-        while let Some(token) = lexer.next_token(){
-            tokens.push(token);
-        }
-    let global_scope = &mut GlobalScope::new();
-    let ast = &mut Ast::new();
-    let mut parser:Parser<'_> = Parser::new(tokens, ast,global_scope);
-    parser.parse();
-    // println!("parser tokens: {:?}",parser.tokens);
-    println!("global variables: {:?}",global_scope.get_all_global_variables());
-    // // ast.visualize();
-
-    // // ast.evaluate();
-
-   
+    let args:Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str){
+        Some("fmt")=>format_files(&args[1..]),
+        Some("ast")=>print_ast(&args[1..]),
+        Some("run")=>run_file(&args[1..]),
+        Some("check")=>check_file(&args[1..]),
+        Some("ir")=>print_ir(&args[1..]),
+        Some("cse")=>eliminate_subexpressions(&args[1..]),
+        Some("cache")=>manage_cache(&args[1..]),
+        _=>{
+            eprintln!("usage: compiler <fmt|ast|run|check|ir|cse|cache> [options] <files...>");
+            process::exit(2);
+        }
+    }
 }
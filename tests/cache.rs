@@ -0,0 +1,50 @@
+//! The compilation cache of `run` and `check` through the `compiler`
+//! binary: entries record the files they were compiled from, an entry that
+//! does not match them is compiled again, and `cache stats` reports the
+//! directory it is given.
+
+mod common;
+
+use std::{fs, path::Path};
+
+use compiler::json::Json;
+
+fn stats(cache_dir: &Path) -> String {
+    let output = common::compiler(["cache", "stats", "--no-cache", "--cache-dir", cache_dir.to_str().unwrap()]);
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn entries_are_checked_against_their_sources() {
+    let dir = common::temp_dir("cache-sources");
+    let cache_dir = dir.join("cache");
+    let main = dir.join("main.calc");
+    fs::write(&main, "let width = 6\nwidth * 7\n").unwrap();
+    let run = || common::compiler(["run", "--cache-dir", cache_dir.to_str().unwrap(), main.to_str().unwrap()]);
+
+    let first = run();
+    assert!(first.status.success());
+    assert_eq!(String::from_utf8_lossy(&first.stdout), "1 | let width = 6  => 6\n2 | width * 7  => 42\n");
+    assert_eq!(run().stdout, first.stdout);
+    let report = stats(&cache_dir);
+    assert!(report.starts_with(&format!("cache:   {}\n", cache_dir.display())), "{}", report);
+    assert!(report.contains("entries: 1\n") && report.contains("hits:    1\n") && report.contains("misses:  1\n"), "{}", report);
+
+    // An entry naming other sources is not taken, even under its key.
+    let entry = fs::read_dir(&cache_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.file_name().unwrap() != "stats.json")
+        .unwrap();
+    let json = Json::parse(&fs::read_to_string(&entry).unwrap()).unwrap();
+    let sources = json.get("sources").and_then(Json::as_array).unwrap();
+    assert_eq!(sources.len(), 1);
+    assert_eq!(sources[0].get("path").and_then(Json::as_str), main.to_str());
+    let text = fs::read_to_string(&entry).unwrap();
+    let hash = sources[0].get("hash").and_then(Json::as_str).unwrap();
+    fs::write(&entry, text.replace(hash, "0000000000000000")).unwrap();
+    assert_eq!(run().stdout, first.stdout);
+    assert!(stats(&cache_dir).contains("hits:    1\n"));
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,87 @@
+//! Helpers shared by the integration tests. Each test file uses some of
+//! them, so the others look unused from there.
+#![allow(dead_code)]
+
+use std::{
+    env,
+    ffi::OsStr,
+    fs,
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+use compiler::{
+    ast::{
+        lib::Id,
+        text::{FileId, SourceFile},
+        type_checker::TypeChecker,
+        Ast,
+    },
+    diagnostics::{DiagnosticKind, DiagnosticsBag, DiagnosticsBagCell},
+};
+
+/// A file parsed on its own, type checked unless it has syntax errors, with
+/// the diagnostics both reported.
+pub struct Checked {
+    pub source: SourceFile,
+    pub ast: Ast,
+    pub checker: Option<TypeChecker>,
+    pub diagnostics: Vec<(DiagnosticKind, String, String)>,
+}
+
+impl Checked {
+    /// The diagnostics as `(message, location)`.
+    pub fn messages(&self) -> Vec<(String, String)> {
+        self.diagnostics.iter().map(|(_, message, location)| (message.clone(), location.clone())).collect()
+    }
+
+    pub fn checker(&self) -> &TypeChecker {
+        self.checker.as_ref().expect("the file has no syntax errors")
+    }
+}
+
+/// `text` as the file `name`, with the id of the first file.
+pub fn source(name: &str, text: &str) -> SourceFile {
+    SourceFile::new(FileId::new(0), name.to_string(), text.to_string())
+}
+
+/// Parses `text` as the file `name` and type checks it if it parsed.
+pub fn check(name: &str, text: &str) -> Checked {
+    let source = source(name, text);
+    let diagnostics = DiagnosticsBag::new_cell();
+    let ast = Ast::parse(&source, diagnostics.clone());
+    let parsed = !diagnostics.borrow().has_errors();
+    let checker = parsed.then(|| ast.type_check(diagnostics.clone()));
+    let diagnostics = located(&source, &diagnostics);
+    Checked { source, ast, checker, diagnostics }
+}
+
+/// The diagnostics of `diagnostics` as `(kind, message, location)`, with
+/// the location in `source`.
+pub fn located(source: &SourceFile, diagnostics: &DiagnosticsBagCell) -> Vec<(DiagnosticKind, String, String)> {
+    diagnostics
+        .borrow()
+        .diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.kind, diagnostic.message.clone(), source.location(&diagnostic.span)))
+        .collect()
+}
+
+/// `(message, location)` pairs from string slices, to compare against
+/// `Checked::messages`.
+pub fn messages(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+    expected.iter().map(|(message, location)| (message.to_string(), location.to_string())).collect()
+}
+
+/// Runs the `compiler` binary with `arguments`.
+pub fn compiler<I: AsRef<OsStr>>(arguments: impl IntoIterator<Item = I>) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_compiler")).args(arguments).output().unwrap()
+}
+
+/// A new empty directory for the files of one test, named after it.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
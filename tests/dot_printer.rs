@@ -0,0 +1,77 @@
+//! The Graphviz export: every function is drawn in its own cluster holding
+//! the nodes of its body, top-level statements stay outside the clusters,
+//! and labels are escaped.
+
+mod common;
+
+use compiler::{ast::Ast, diagnostics::DiagnosticsBag};
+
+fn dot(text: &str) -> String {
+    let diagnostics = DiagnosticsBag::new_cell();
+    let ast = Ast::parse(&common::source("main.calc", text), diagnostics.clone());
+    assert!(!diagnostics.borrow().has_errors());
+    ast.to_dot()
+}
+
+/// The lines between `subgraph cluster_<name> {` and its closing brace.
+fn cluster<'a>(dot: &'a str, name: &str) -> Vec<&'a str> {
+    let header = format!("  subgraph cluster_{} {{", name);
+    dot.lines().skip_while(|line| *line != header).skip(1).take_while(|line| *line != "  }").collect()
+}
+
+#[test]
+fn draws_each_function_in_its_own_cluster() {
+    let expected = r#"digraph ast {
+  node [fontname="monospace"];
+  item0 [label="item 0", shape=box];
+  subgraph cluster_function0 {
+    label="area";
+    function0 [label="function area(w, h)", shape=box];
+    item0 -> function0;
+    stmt0 [label="return", shape=box];
+    function0 -> stmt0;
+    expr2 [label="*", shape=circle];
+    expr0 [label="w", shape=plaintext];
+    expr2 -> expr0 [label="left"];
+    expr1 [label="h", shape=plaintext];
+    expr2 -> expr1 [label="right"];
+    stmt0 -> expr2 [label="value"];
+  }
+  item1 [label="item 1", shape=box];
+  stmt1 [label="let x", shape=box];
+  item1 -> stmt1;
+  expr5 [label="area()", shape=box];
+  expr3 [label="2", shape=plaintext];
+  expr5 -> expr3 [label="arg0"];
+  expr4 [label="3", shape=plaintext];
+  expr5 -> expr4 [label="arg1"];
+  stmt1 -> expr5 [label="initializer"];
+}
+"#;
+    assert_eq!(dot("function area(w, h) { return w * h }\nlet x = area(2, 3)\n"), expected);
+}
+
+#[test]
+fn keeps_the_bodies_of_two_functions_apart() {
+    let dot = dot("function f(a: int) -> int { let b = a + 1 return b }\nfunction g() { return 2 }\nf(g())\n");
+
+    let f = cluster(&dot, "function0");
+    assert_eq!(f[0], "    label=\"f\";");
+    assert!(f.contains(&"    function0 [label=\"function f(a: int) -> int\", shape=box];"));
+    assert_eq!(f.iter().filter(|line| line.contains("[label=\"let b\"")).count(), 1);
+    assert!(f.iter().all(|line| !line.contains("function1")));
+
+    let g = cluster(&dot, "function1");
+    assert_eq!(g[0], "    label=\"g\";");
+    assert!(g.iter().all(|line| !line.contains("let b") && !line.contains("function0")));
+
+    let top_level: Vec<&str> = dot.lines().filter(|line| line.starts_with("  ") && !line.starts_with("    ")).collect();
+    assert!(top_level.contains(&"  expr6 [label=\"f()\", shape=box];"));
+    assert_eq!(top_level.iter().filter(|line| line.starts_with("  subgraph")).count(), 2);
+}
+
+#[test]
+fn escapes_quotes_and_backslashes_in_labels() {
+    let dot = dot("\"a\\\"b\"\n");
+    assert!(dot.contains(r#"expr0 [label="\"a\\\"b\"", shape=plaintext];"#), "{}", dot);
+}
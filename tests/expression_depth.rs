@@ -0,0 +1,76 @@
+//! Expressions nested deeper than `MAX_EXPRESSION_DEPTH` are reported by the
+//! parser and skipped, so the passes that recurse over expressions only
+//! ever see shallow ones. The tests run on the 2 MB stack of a test thread.
+
+mod common;
+
+use compiler::{
+    ast::{evaluator::ExpressionEvaluator, parser::MAX_EXPRESSION_DEPTH, subexpressions::find_common_subexpressions, value::Value},
+    diagnostics::DiagnosticsBag,
+    ir::lower::lower,
+};
+
+/// `1 - 1 - ...` with `operators` subtractions.
+fn chain(operators: usize) -> String {
+    format!("1{}", " - 1".repeat(operators))
+}
+
+fn parentheses(levels: usize) -> String {
+    format!("{}1{}", "(".repeat(levels), ")".repeat(levels))
+}
+
+fn calls(levels: usize) -> String {
+    format!("{}1{}", "f(".repeat(levels), ")".repeat(levels))
+}
+
+fn program(expression: &str) -> String {
+    format!("function f(a) {{ return a }}\nlet x = {}\nlet y = x + 1\ny\n", expression)
+}
+
+#[test]
+fn the_deepest_allowed_expressions_go_through_every_pass() {
+    let deepest = [chain(MAX_EXPRESSION_DEPTH - 1), parentheses(MAX_EXPRESSION_DEPTH - 1), calls(MAX_EXPRESSION_DEPTH - 1)];
+    for (expression, x) in deepest.iter().zip([2 - MAX_EXPRESSION_DEPTH as i64, 1, 1]) {
+        let checked = common::check("main.calc", &program(expression));
+        assert_eq!(checked.diagnostics, []);
+        let ast = &checked.ast;
+        let diagnostics = DiagnosticsBag::new_cell();
+        ast.check_liveness(diagnostics.clone());
+        assert_eq!(common::check("main.calc", &ast.format()).diagnostics, []);
+        assert!(!ast.to_sexpr().is_empty() && !ast.to_json().is_empty() && !ast.to_dot().is_empty());
+        find_common_subexpressions(ast, &checked.source);
+        lower(ast, checked.checker());
+        let evaluation = ast.evaluate(&mut ExpressionEvaluator::new());
+        assert_eq!(evaluation.results.last().map(|result| result.value.clone()), Some(Value::Integer(x + 1)));
+    }
+}
+
+#[test]
+fn one_level_deeper_is_reported_once_and_skipped() {
+    let cases = [
+        (chain(MAX_EXPRESSION_DEPTH), "main.calc:2:1031"),
+        (parentheses(MAX_EXPRESSION_DEPTH), "main.calc:2:265"),
+        (calls(MAX_EXPRESSION_DEPTH), "main.calc:2:521"),
+    ];
+    for (expression, location) in cases {
+        let checked = common::check("main.calc", &program(&expression));
+        assert_eq!(checked.messages(), common::messages(&[("Expression is nested more than 256 levels deep", location)]));
+        assert_eq!(checked.ast.to_sexpr().lines().last(), Some("y"));
+    }
+}
+
+#[test]
+fn a_hundred_thousand_levels_do_not_overflow_the_stack() {
+    for expression in [chain(100_000), parentheses(100_000), calls(100_000), format!("({}) - 1", chain(100_000))] {
+        let text = format!("{}\nlet z = {}\nz\n", program(&expression), chain(300));
+        let checked = common::check("main.calc", &text);
+        let messages = checked.messages();
+        assert_eq!(messages.len(), 2, "{:?}", messages);
+        assert!(messages.iter().all(|(message, _)| message == "Expression is nested more than 256 levels deep"));
+        let ast = &checked.ast;
+        assert!(!ast.format().is_empty() && !ast.to_sexpr().is_empty() && !ast.to_json().is_empty() && !ast.to_dot().is_empty());
+        ast.type_check(DiagnosticsBag::new_cell());
+        ast.check_liveness(DiagnosticsBag::new_cell());
+        assert_eq!(ast.syntax().text(), text);
+    }
+}
@@ -0,0 +1,117 @@
+//! The source formatter: formatting keeps the meaning and the comments of a
+//! file, formatting twice changes nothing, parentheses are kept only where
+//! they matter, and `fmt --check` reports unformatted files through its
+//! exit status.
+
+mod common;
+
+use std::{ffi::OsStr, fs};
+
+use compiler::{
+    ast::{evaluator::ExpressionEvaluator, value::Value, Ast},
+    diagnostics::DiagnosticsBag,
+};
+
+const UNFORMATTED: &str = "// header comment
+function   area(w,h){return w*h // area
+}
+let x=((1+2))*3 /* keep */
+let y = 1 + (2 * 3)
+let z = x - (y - 1)
+let q = (x - y) - 1
+let r = (x)
+area(x,  z)
+// trailing
+";
+
+const FORMATTED: &str = "// header comment
+function area(w, h) {
+    return w * h // area
+}
+
+let x = (1 + 2) * 3 /* keep */
+let y = 1 + 2 * 3
+let z = x - (y - 1)
+let q = x - y - 1
+let r = x
+area(x, z)
+// trailing
+";
+
+/// Parses `text`, which must have no syntax errors.
+fn parse(text: &str) -> Ast {
+    let diagnostics = DiagnosticsBag::new_cell();
+    let ast = Ast::parse(&common::source("format.calc", text), diagnostics.clone());
+    assert!(!diagnostics.borrow().has_errors(), "{:?}", diagnostics.borrow().diagnostics);
+    ast
+}
+
+fn values(ast: &Ast) -> Vec<Value> {
+    let evaluation = ast.evaluate(&mut ExpressionEvaluator::new());
+    assert_eq!(evaluation.error, None);
+    evaluation.results.into_iter().map(|result| result.value).collect()
+}
+
+#[test]
+fn formats_in_the_house_style() {
+    assert_eq!(parse(UNFORMATTED).format(), FORMATTED);
+}
+
+#[test]
+fn round_trips_and_is_idempotent() {
+    let original = parse(UNFORMATTED);
+    let formatted = parse(&original.format());
+    assert_eq!(values(&formatted), values(&original));
+    assert_eq!(formatted.format(), FORMATTED);
+}
+
+#[test]
+fn keeps_every_comment() {
+    let formatted = parse(UNFORMATTED).format();
+    for comment in ["// header comment", "// area", "/* keep */", "// trailing"] {
+        assert!(formatted.contains(comment), "{} is lost", comment);
+    }
+}
+
+#[test]
+fn keeps_only_the_parentheses_that_matter() {
+    let cases = [
+        ("((1 + 2)) * 3", "(1 + 2) * 3"),
+        ("1 + (2 * 3)", "1 + 2 * 3"),
+        ("(1 - 2) - 3", "1 - 2 - 3"),
+        ("1 - (2 - 3)", "1 - (2 - 3)"),
+        ("8 / (4 / 2)", "8 / (4 / 2)"),
+        ("(8 / 4) * 2", "8 / 4 * 2"),
+        ("((7))", "7"),
+    ];
+    for (text, expected) in cases {
+        let formatted = parse(text).format();
+        assert_eq!(formatted.trim_end(), expected, "{}", text);
+        assert_eq!(values(&parse(&formatted)), values(&parse(text)), "{}", text);
+    }
+}
+
+#[test]
+fn fmt_check_reports_through_its_exit_status() {
+    let dir = common::temp_dir("formatter-test");
+    let unformatted = dir.join("unformatted.calc");
+    let formatted = dir.join("formatted.calc");
+    let broken = dir.join("broken.calc");
+    fs::write(&unformatted, UNFORMATTED).unwrap();
+    fs::write(&formatted, FORMATTED).unwrap();
+    fs::write(&broken, "let = 1\n").unwrap();
+
+    let output = common::compiler([OsStr::new("fmt"), OsStr::new("--check"), formatted.as_os_str()]);
+    assert_eq!(output.status.code(), Some(0));
+    let output = common::compiler([OsStr::new("fmt"), OsStr::new("--check"), formatted.as_os_str(), unformatted.as_os_str()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), format!("{}: not formatted\n", unformatted.display()));
+    // `--check` leaves the file as it was; without it the file is rewritten.
+    assert_eq!(fs::read_to_string(&unformatted).unwrap(), UNFORMATTED);
+    assert_eq!(common::compiler([OsStr::new("fmt"), unformatted.as_os_str()]).status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&unformatted).unwrap(), FORMATTED);
+    // A file with syntax errors is not formatted at all.
+    let output = common::compiler([OsStr::new("fmt"), OsStr::new("--check"), broken.as_os_str()]);
+    assert_eq!(output.status.code(), Some(2));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,89 @@
+//! Imports confined to the directory of the main file: absolute paths and
+//! paths that lead out of it are reported at the import and never read.
+
+mod common;
+
+use std::{collections::HashMap, fs};
+
+use compiler::{
+    ast::modules::{resolve_path, ModuleLoader, Program, SourceSnapshot},
+    diagnostics::DiagnosticsBag,
+};
+
+/// Files in memory that remember which paths were read.
+#[derive(Default)]
+struct Recorded {
+    files: HashMap<String, String>,
+    read: Vec<String>,
+}
+
+impl ModuleLoader for Recorded {
+    fn read(&mut self, path: &str) -> Result<String, String> {
+        self.read.push(path.to_string());
+        self.files.read(path)
+    }
+}
+
+fn files(files: &[(&str, &str)]) -> Recorded {
+    Recorded {
+        files: files.iter().map(|(path, text)| (path.to_string(), text.to_string())).collect(),
+        read: Vec::new(),
+    }
+}
+
+#[test]
+fn paths_resolve_inside_the_root_only() {
+    assert_eq!(resolve_path("", "main.calc", "units.calc"), Ok(String::from("units.calc")));
+    assert_eq!(resolve_path("", "shapes/geometry.calc", "../units.calc"), Ok(String::from("units.calc")));
+    assert_eq!(resolve_path("app", "app/main.calc", "./lib/../units.calc"), Ok(String::from("app/units.calc")));
+    let outside = Err(String::from("it is outside the directory of the main file"));
+    assert_eq!(resolve_path("", "main.calc", "../secret.calc"), outside);
+    assert_eq!(resolve_path("app", "app/main.calc", "lib/../../secret.calc"), outside);
+    assert_eq!(resolve_path("", "main.calc", "/etc/passwd"), Err(String::from("imports must be relative paths")));
+}
+
+#[test]
+fn imports_out_of_the_root_are_reported_and_not_read() {
+    let mut loader = files(&[
+        ("app/main.calc", "import \"/etc/passwd\"\nimport \"../secret.calc\"\nimport \"lib/units.calc\"\nunits::metre\n"),
+        ("app/lib/units.calc", "import \"../../secret.calc\"\nlet metre = 1\n"),
+        ("secret.calc", "let leaked = 12345\n"),
+    ]);
+    let diagnostics = DiagnosticsBag::new_cell();
+    let program = Program::load("app/main.calc", &mut loader, diagnostics.clone()).unwrap();
+    assert_eq!(loader.read, ["app/main.calc", "app/lib/units.calc"]);
+    let messages: Vec<String> = diagnostics.borrow().diagnostics.iter().map(|diagnostic| diagnostic.message.clone()).collect();
+    assert_eq!(
+        messages,
+        [
+            "Cannot load module `/etc/passwd`: imports must be relative paths",
+            "Cannot load module `../secret.calc`: it is outside the directory of the main file",
+            "Cannot load module `../../secret.calc`: it is outside the directory of the main file",
+        ]
+    );
+    let names: Vec<&str> = program.sources.files.iter().map(|file| file.name.as_str()).collect();
+    assert_eq!(names, ["app/main.calc", "app/lib/units.calc"]);
+
+    let mut loader = files(&[("app/main.calc", "import \"../secret.calc\"\n"), ("secret.calc", "let leaked = 12345\n")]);
+    let snapshot = SourceSnapshot::read("app/main.calc", &mut loader).unwrap();
+    assert_eq!(loader.read, ["app/main.calc"]);
+    assert_eq!(snapshot.files.len(), 1);
+}
+
+#[test]
+fn run_does_not_show_files_outside_the_directory() {
+    let dir = common::temp_dir("imports-outside");
+    let secret = dir.join("secret.calc");
+    fs::write(&secret, "let leaked = 12345\nleaked\n").unwrap();
+    fs::create_dir(dir.join("app")).unwrap();
+    let main = dir.join("app").join("main.calc");
+    fs::write(&main, format!("import \"{}\"\nimport \"../secret.calc\"\n1\n", secret.display())).unwrap();
+
+    let output = common::compiler([String::from("run"), String::from("--no-cache"), main.display().to_string()]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("imports must be relative paths"), "{}", stderr);
+    assert!(stderr.contains("Cannot load module `../secret.calc`: it is outside the directory of the main file"), "{}", stderr);
+    assert!(!stdout.contains("12345") && !stderr.contains("12345") && !stderr.contains("leaked"), "{}{}", stdout, stderr);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,64 @@
+//! `IncrementalParser` keeping the `ItemId`s of the items an edit does not
+//! touch, also when it compacts its arenas, and reusing the slots of the
+//! items it replaces.
+
+mod common;
+
+use compiler::{
+    ast::{
+        incremental::{IncrementalParser, TextEdit, MIN_GARBAGE},
+        Ast,
+    },
+    diagnostics::DiagnosticsBag,
+};
+
+const PROGRAM: &str = "let width = 4
+function area(w, h) { let result = w * h return result }
+let total = area(width, 2) + 1 + 2 + 3
+total
+";
+
+fn parser() -> IncrementalParser {
+    IncrementalParser::new(common::source("main.calc", PROGRAM))
+}
+
+#[test]
+fn the_replacing_item_takes_over_the_slot() {
+    let mut parser = parser();
+    let before = parser.ast.top_level.clone();
+    let start = PROGRAM.find("+ 1").unwrap();
+    let reparse = parser.edit(&TextEdit::new(start, start + 3, "- 7".to_string()));
+    // The function before the edited `let` is parsed again as well.
+    assert_eq!(reparse.removed, before[1..3]);
+    assert_eq!(reparse.added, before[1..3]);
+    assert_eq!(parser.ast.top_level, before);
+    assert_eq!(parser.ast.items.data.len(), before.len());
+}
+
+#[test]
+fn compacting_keeps_the_item_ids() {
+    let mut parser = parser();
+    let before = parser.ast.top_level.clone();
+    let start = PROGRAM.find("1 + 2").unwrap();
+    let mut compactions = 0;
+    for index in 0..MIN_GARBAGE {
+        let expressions = parser.ast.expressions.data.len();
+        let digit = (index % 10).to_string();
+        parser.edit(&TextEdit::new(start, start + 1, digit));
+        if parser.ast.expressions.data.len() < expressions {
+            compactions += 1;
+        }
+        assert_eq!(parser.ast.top_level, before);
+    }
+    assert!(compactions > 0);
+    assert!(parser.ast.expressions.data.len() < MIN_GARBAGE);
+
+    let start = parser.source.text.find("let result").unwrap();
+    parser.edit(&TextEdit::new(start, start, "let unused = width ".to_string()));
+    let text = parser.source.text.clone();
+    let diagnostics = DiagnosticsBag::new_cell();
+    let full = Ast::parse(&parser.source, diagnostics.clone());
+    assert_eq!(parser.ast.format(), full.format());
+    assert_eq!(parser.ast.syntax().text(), text);
+    assert_eq!(parser.diagnostics(), diagnostics.borrow().diagnostics);
+}
@@ -0,0 +1,75 @@
+//! The `output` of top-level statement results in the IR: lowered for the
+//! statements of the main file and kept by dead code elimination with the
+//! code that computes them.
+
+mod common;
+
+use std::collections::HashMap;
+
+use compiler::{
+    ast::modules::Program,
+    diagnostics::DiagnosticsBag,
+    ir::{self, Instruction},
+};
+
+fn optimized(text: &str) -> String {
+    let checked = common::check("main.calc", text);
+    assert_eq!(checked.messages(), []);
+    let mut program = ir::lower(&checked.ast, checked.checker());
+    ir::eliminate_dead_code(&mut program);
+    assert_eq!(ir::verify(&program), []);
+    program.to_string()
+}
+
+#[test]
+fn statement_results_survive_dead_code_elimination() {
+    let text = "function area(w, h) { return w * h }\nlet a = area(2, 3)\na\n";
+    let expected = "function @area(%0: int, %1: int) -> int {
+b0:
+    %2: int = mul %0, %1
+    ret %2
+}
+
+function @$main() -> void {
+b0:
+    %0: int = const 2
+    %1: int = const 3
+    %2: int = call @area(%0, %1)
+    output %2
+    output %2
+    ret
+}
+";
+    assert_eq!(optimized(text), expected);
+}
+
+#[test]
+fn pure_results_are_output_too() {
+    let text = "let x = 1.5 * 2.0\n\"label\"\nlen(\"abc\")\n";
+    let checked = common::check("main.calc", text);
+    let mut program = ir::lower(&checked.ast, checked.checker());
+    let removed = ir::eliminate_dead_code(&mut program);
+    let main = program.functions.get(program.main);
+    let outputs = main
+        .blocks
+        .iter()
+        .flat_map(|block| block.instructions.iter())
+        .filter(|instruction| matches!(instruction, Instruction::Output { .. }))
+        .count();
+    assert_eq!(outputs, 3);
+    assert_eq!(removed.instructions, 1, "only the store of `x` goes: {}", program);
+}
+
+#[test]
+fn only_the_main_file_outputs() {
+    let mut files: HashMap<String, String> = HashMap::new();
+    files.insert(String::from("main.calc"), String::from("import \"units.calc\"\nunits::pi\n"));
+    files.insert(String::from("units.calc"), String::from("let pi = 3\n4\n"));
+    let diagnostics = DiagnosticsBag::new_cell();
+    let program = Program::load("main.calc", &mut files, diagnostics.clone()).unwrap();
+    let checker = program.ast.type_check(diagnostics.clone());
+    assert_eq!(diagnostics.borrow().diagnostics, []);
+    let ir = ir::lower(&program.ast, &checker);
+    let main = ir.to_string();
+    assert!(main.ends_with("    store @units::pi, %0\n    %1: int = const 4\n    output %0\n    ret\n}\n"), "{}", main);
+}
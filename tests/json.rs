@@ -0,0 +1,38 @@
+//! The JSON parser behind the language server and the compilation cache:
+//! documents round trip through `Display`, and malformed or too deeply
+//! nested text is an error instead of a crash.
+
+use compiler::json::{Json, MAX_NESTING};
+
+#[test]
+fn round_trips_through_display() {
+    let text = r#"{"a":[1,-2,2.5,"x\ny\u00e9\"",true,false,null],"b":{},"c":[]}"#;
+    let json = Json::parse(text).unwrap();
+    assert_eq!(json.get("a").and_then(Json::as_array).map(<[Json]>::len), Some(7));
+    assert_eq!(Json::parse(&json.to_string()).unwrap(), json);
+    assert_eq!(Json::parse(r#""\ud83d\ude00""#).unwrap(), Json::string("😀"));
+}
+
+#[test]
+fn reports_malformed_documents() {
+    for text in ["", "[1,", "{\"a\" 1}", "[1] 2", "tru", "\"open", "\"\\ud83d\"", "{1:2}"] {
+        assert!(Json::parse(text).is_err(), "{:?}", text);
+    }
+}
+
+#[test]
+fn accepts_nesting_up_to_the_limit() {
+    let text = format!("{}{}", "[".repeat(MAX_NESTING), "]".repeat(MAX_NESTING));
+    assert!(Json::parse(&text).is_ok());
+    let text = format!("{}1{}", "{\"a\":".repeat(MAX_NESTING), "}".repeat(MAX_NESTING));
+    assert!(Json::parse(&text).is_ok());
+}
+
+#[test]
+fn rejects_deeper_nesting_without_overflowing_the_stack() {
+    let error = Json::parse(&"[".repeat(MAX_NESTING + 1)).unwrap_err();
+    assert_eq!(error, format!("nested more than {} levels deep at {}", MAX_NESTING, MAX_NESTING));
+    for text in ["[".repeat(200_000), "{\"a\":".repeat(200_000), "[{\"a\":".repeat(100_000)] {
+        assert!(Json::parse(&text).unwrap_err().starts_with("nested more than"));
+    }
+}
@@ -0,0 +1,71 @@
+//! The lexer walks the input by bytes: spans are byte offsets that slice the
+//! input on character boundaries whatever characters it contains, and large
+//! inputs are lexed in one pass.
+
+mod common;
+
+use compiler::{
+    ast::{
+        evaluator::ExpressionEvaluator,
+        lexer::{Lexer, Token, TokenKind},
+        lib::Id,
+        text::FileId,
+        value::Value,
+    },
+    diagnostics::DiagnosticsBag,
+};
+
+fn lex(text: &str) -> Vec<Token> {
+    Lexer::new(text, FileId::new(0), DiagnosticsBag::new_cell()).collect()
+}
+
+#[test]
+fn spans_are_byte_offsets_into_the_input() {
+    let text = "let café = \"日本\" // période\nnaïve + 1";
+    let tokens = lex(text);
+    let literals: Vec<&str> = tokens.iter().map(|token| token.span.literal.as_str()).collect();
+    assert_eq!(literals, ["let", "café", "=", "\"日本\"", "naïve", "+", "1", ""]);
+    for token in tokens.iter() {
+        assert_eq!(&text[token.span.start..token.span.end], token.span.literal);
+    }
+    assert_eq!((tokens[1].span.start, tokens[1].span.end), (4, 9));
+    assert_eq!((tokens[3].span.start, tokens[3].span.end), (12, 20));
+    assert_eq!(tokens[2].trailing_trivia.len(), 1);
+    assert_eq!(tokens[3].trailing_trivia[1].span.literal, "// période");
+}
+
+#[test]
+fn non_ascii_names_evaluate() {
+    let checked = common::check("main.calc", "let café = 2\nlet größe = café * 21\ngröße\n");
+    assert_eq!(checked.diagnostics, []);
+    let evaluation = checked.ast.evaluate(&mut ExpressionEvaluator::new());
+    let values: Vec<Value> = evaluation.results.into_iter().map(|result| result.value).collect();
+    assert_eq!(values.last(), Some(&Value::Integer(42)));
+}
+
+#[test]
+fn a_character_that_starts_no_token_is_one_bad_token() {
+    let text = "1 € 2";
+    let tokens = lex(text);
+    assert_eq!(tokens.iter().map(|token| token.kind).collect::<Vec<_>>(), [
+        TokenKind::Number(1),
+        TokenKind::Bad,
+        TokenKind::Number(2),
+        TokenKind::Eof,
+    ]);
+    assert_eq!(tokens[1].span.literal, "€");
+
+    let checked = common::check("main.calc", "let café = 1\ncafé + 1 € 2\n");
+    assert_eq!(checked.messages(), common::messages(&[("Expected an expression, found Bad", "main.calc:2:10")]));
+}
+
+#[test]
+fn lexes_a_large_input_in_one_pass() {
+    let line = "let café_1 = (200 * 1 + 5) - 6 / 2 // période\n/* note */ 1 * 7\n";
+    let text = line.repeat(40_000);
+    assert!(text.len() > 2_000_000);
+    let tokens = lex(&text);
+    assert_eq!(tokens.len(), 40_000 * 17 + 1);
+    let last = &tokens[tokens.len() - 2];
+    assert_eq!((last.span.start, last.span.literal.as_str()), (text.len() - 2, "7"));
+}
@@ -0,0 +1,37 @@
+//! Unused variable warnings: the top-level `let`s of the main file are
+//! results of the program and not reported, while the `let`s of function
+//! bodies and of imported modules are.
+
+mod common;
+
+use std::collections::HashMap;
+
+use compiler::{ast::modules::Program, diagnostics::DiagnosticsBag};
+
+#[test]
+fn top_level_lets_of_the_main_file_are_not_reported() {
+    let checked = common::check("main.calc", "function area(w, h) {\n    let unused = w\n    return w * h\n}\nlet a = area(2, 3)\nlet b = a + 1\n");
+    let diagnostics = DiagnosticsBag::new_cell();
+    checked.ast.check_liveness(diagnostics.clone());
+    let warnings = common::located(&checked.source, &diagnostics);
+    let warnings: Vec<(&str, &str)> = warnings.iter().map(|(_, message, location)| (message.as_str(), location.as_str())).collect();
+    assert_eq!(warnings, [("Variable `unused` is never used", "main.calc:2:9")]);
+}
+
+#[test]
+fn top_level_lets_of_imported_modules_are_reported() {
+    let mut files: HashMap<String, String> = HashMap::new();
+    files.insert(String::from("main.calc"), String::from("import \"units.calc\"\nlet area = units::metre * 2\n"));
+    files.insert(String::from("units.calc"), String::from("let metre = 1\nlet inch = 2\n"));
+    let diagnostics = DiagnosticsBag::new_cell();
+    let program = Program::load("main.calc", &mut files, diagnostics.clone()).unwrap();
+    program.ast.type_check(diagnostics.clone());
+    program.ast.check_liveness(diagnostics.clone());
+    let warnings: Vec<(String, String)> = diagnostics
+        .borrow()
+        .diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.message.clone(), program.sources.get(diagnostic.span.file_id).location(&diagnostic.span)))
+        .collect();
+    assert_eq!(warnings, [(String::from("Variable `inch` is never used"), String::from("units.calc:2:5"))]);
+}
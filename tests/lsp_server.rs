@@ -0,0 +1,76 @@
+//! The `lsp` binary answers a message it cannot read or parse with a parse
+//! error and keeps serving the ones after it.
+
+use std::{
+    io::{BufReader, ErrorKind, Write},
+    process::{Command, Stdio},
+};
+
+use compiler::{
+    json::Json,
+    lsp::{
+        protocol::{read_message, write_message, MAX_CONTENT_LENGTH},
+        PARSE_ERROR,
+    },
+};
+
+fn framed(body: &str) -> Vec<u8> {
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body).into_bytes()
+}
+
+fn request(id: i64, method: &str) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::string("2.0")),
+        ("id", Json::Number(id)),
+        ("method", Json::string(method)),
+        ("params", Json::object(vec![])),
+    ])
+}
+
+fn error_code(response: &Json) -> Option<i64> {
+    response.get("error").and_then(|error| error.get("code")).and_then(Json::as_i64)
+}
+
+#[test]
+fn an_oversized_body_is_skipped_without_being_stored() {
+    let mut input = format!("Content-Length: {}\r\n\r\n", MAX_CONTENT_LENGTH + 1).into_bytes();
+    input.resize(input.len() + MAX_CONTENT_LENGTH + 1, b' ');
+    input.extend(framed("{}"));
+    let mut reader = input.as_slice();
+    assert_eq!(read_message(&mut reader).unwrap_err().kind(), ErrorKind::InvalidData);
+    assert_eq!(read_message(&mut reader).unwrap(), Some("{}".to_string()));
+    assert_eq!(read_message(&mut reader).unwrap(), None);
+}
+
+#[test]
+fn keeps_serving_after_malformed_messages() {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_lsp")).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+    let mut input = server.stdin.take().unwrap();
+    let mut output = BufReader::new(server.stdout.take().unwrap());
+
+    let mut oversized = format!("Content-Length: {}\r\n\r\n", MAX_CONTENT_LENGTH + 1).into_bytes();
+    oversized.resize(oversized.len() + MAX_CONTENT_LENGTH + 1, b'[');
+    let malformed = [
+        framed(&"[".repeat(200_000)),
+        framed("{\"jsonrpc\": \"2.0\", \"id\": 1,"),
+        b"Content-Type: application/json\r\n\r\n".to_vec(),
+        b"Content-Length: 1\r\n\r\n\xff".to_vec(),
+        oversized,
+    ];
+    for message in malformed.iter() {
+        input.write_all(message).unwrap();
+        let response = Json::parse(&read_message(&mut output).unwrap().unwrap()).unwrap();
+        assert_eq!(error_code(&response), Some(PARSE_ERROR), "{}", response);
+        assert_eq!(response.get("id"), Some(&Json::Null));
+    }
+
+    write_message(&mut input, &request(2, "initialize")).unwrap();
+    let initialize = Json::parse(&read_message(&mut output).unwrap().unwrap()).unwrap();
+    assert_eq!(initialize.get("id"), Some(&Json::Number(2)));
+    assert!(initialize.get("result").and_then(|result| result.get("capabilities")).is_some());
+
+    write_message(&mut input, &request(3, "shutdown")).unwrap();
+    read_message(&mut output).unwrap().unwrap();
+    input.write_all(&framed(r#"{"jsonrpc":"2.0","method":"exit"}"#)).unwrap();
+    assert!(server.wait().unwrap().success());
+}
@@ -0,0 +1,62 @@
+//! Integer literals in every radix, with digit separators, and the
+//! diagnostics for literals that overflow, have digits outside their radix
+//! or have no digits at all.
+
+mod common;
+
+use compiler::{
+    ast::lexer::{Lexer, TokenKind},
+    diagnostics::DiagnosticsBag,
+};
+
+/// The integer values of the tokens of `text`, with the diagnostics the
+/// lexer reported as `(message, location)`.
+fn lex(text: &str) -> (Vec<i64>, Vec<(String, String)>) {
+    let source = common::source("numbers.calc", text);
+    let diagnostics = DiagnosticsBag::new_cell();
+    let numbers = Lexer::new(text, source.id, diagnostics.clone())
+        .filter_map(|token| match token.kind {
+            TokenKind::Number(number) => Some(number),
+            _ => None,
+        })
+        .collect();
+    let diagnostics = common::located(&source, &diagnostics).into_iter().map(|(_, message, location)| (message, location)).collect();
+    (numbers, diagnostics)
+}
+
+#[test]
+fn lexes_every_radix() {
+    let (numbers, diagnostics) = lex("0xFF 0b1010 0o17 1_000_000 0x_dead_BEEF 9223372036854775807 0");
+    assert_eq!(diagnostics, []);
+    assert_eq!(numbers, [255, 10, 15, 1_000_000, 0xdead_beef, i64::MAX, 0]);
+}
+
+#[test]
+fn reports_invalid_literals() {
+    let cases = [
+        (
+            "99999999999999999999",
+            "Integer literal `99999999999999999999` does not fit in 64 bits (max 9223372036854775807)",
+        ),
+        (
+            "9223372036854775808",
+            "Integer literal `9223372036854775808` does not fit in 64 bits (max 9223372036854775807)",
+        ),
+        (
+            "0x1_0000_0000_0000_0000",
+            "Integer literal `0x1_0000_0000_0000_0000` does not fit in 64 bits (max 9223372036854775807)",
+        ),
+        ("0b1021", "Invalid digit `2` in binary literal `0b1021`"),
+        ("0o78", "Invalid digit `8` in octal literal `0o78`"),
+        ("0xFG", "Invalid digit `G` in hexadecimal literal `0xFG`"),
+        ("12a4", "Invalid digit `a` in decimal literal `12a4`"),
+        ("0x", "Integer literal `0x` has no digits"),
+        ("0b__", "Integer literal `0b__` has no digits"),
+    ];
+    for (text, message) in cases {
+        let (numbers, diagnostics) = lex(&format!("let x = {}", text));
+        // The literal stays one token, which evaluates as zero.
+        assert_eq!(numbers, [0], "{}", text);
+        assert_eq!(diagnostics, common::messages(&[(message, "numbers.calc:1:9")]), "{}", text);
+    }
+}
@@ -0,0 +1,88 @@
+//! The parser pulls tokens from the lexer as it goes, holding only a small
+//! lookahead, and ends every token stream with a single `Eof`.
+
+mod common;
+
+use std::{cell::Cell, rc::Rc};
+
+use compiler::{
+    ast::{
+        global_scope::GlobalScope,
+        lexer::{Lexer, Token, TokenKind},
+        lib::Id,
+        parser::Parser,
+        text::FileId,
+        Ast,
+    },
+    diagnostics::DiagnosticsBag,
+};
+
+/// Yields the tokens of `tokens` and counts how many were taken.
+struct Counted<T> {
+    tokens: T,
+    taken: Rc<Cell<usize>>,
+}
+
+impl<T: Iterator<Item = Token>> Iterator for Counted<T> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.next()?;
+        self.taken.set(self.taken.get() + 1);
+        Some(token)
+    }
+}
+
+#[test]
+fn pulls_tokens_only_as_far_as_it_has_parsed() {
+    let text = "let x = 1 + 2\n".repeat(10_000);
+    let diagnostics = DiagnosticsBag::new_cell();
+    let taken = Rc::new(Cell::new(0));
+    let tokens = Counted { tokens: Lexer::new(&text, FileId::new(0), diagnostics.clone()), taken: taken.clone() };
+    let (mut ast, mut scope) = (Ast::new(), GlobalScope::new());
+    let mut parser = Parser::new(tokens, &mut ast, &mut scope, diagnostics.clone());
+
+    for items in 1..=100 {
+        parser.parse_next_item();
+        // Six tokens per item, plus the lookahead into the next one.
+        assert!(taken.get() <= items * 6 + 2, "{} tokens taken after {} items", taken.get(), items);
+    }
+    parser.parse();
+    assert_eq!(taken.get(), 10_000 * 6 + 1);
+    assert_eq!(ast.top_level.len(), 10_000);
+    assert!(!diagnostics.borrow().has_errors());
+}
+
+#[test]
+fn a_stream_without_eof_gets_one_at_its_end() {
+    let text = "let x = 1\nx";
+    let diagnostics = DiagnosticsBag::new_cell();
+    let tokens: Vec<Token> = Lexer::new(text, FileId::new(0), diagnostics.clone())
+        .filter(|token| token.kind != TokenKind::Eof)
+        .collect();
+    let (mut ast, mut scope) = (Ast::new(), GlobalScope::new());
+    Parser::new(tokens.into_iter(), &mut ast, &mut scope, diagnostics.clone()).parse();
+
+    assert!(!diagnostics.borrow().has_errors());
+    assert_eq!(ast.to_sexpr(), "(let x 1)\nx\n");
+    let eof = ast.eof.as_ref().unwrap();
+    assert_eq!((eof.kind, eof.span.start, eof.span.end), (TokenKind::Eof, text.len(), text.len()));
+}
+
+#[test]
+fn an_empty_stream_parses_to_an_empty_file() {
+    let diagnostics = DiagnosticsBag::new_cell();
+    let (mut ast, mut scope) = (Ast::new(), GlobalScope::new());
+    let mut parser = Parser::new(std::iter::empty(), &mut ast, &mut scope, diagnostics.clone());
+    assert!(parser.is_at_end());
+    parser.parse();
+    assert!(ast.top_level.is_empty());
+    assert_eq!(ast.eof.as_ref().map(|eof| eof.kind), Some(TokenKind::Eof));
+}
+
+#[test]
+fn a_missing_token_at_the_end_is_reported_once() {
+    let checked = common::check("main.calc", "function f(a, b");
+    assert_eq!(checked.messages().len(), 1, "{:?}", checked.messages());
+    assert_eq!(checked.messages()[0].1, "main.calc:1:16");
+}
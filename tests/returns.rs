@@ -0,0 +1,69 @@
+//! The control flow diagnostics for `return`, and that a `return` ends its
+//! call with the value it returns.
+
+mod common;
+
+use compiler::{
+    ast::{evaluator::ExpressionEvaluator, value::Value},
+    diagnostics::DiagnosticKind,
+};
+
+const PROGRAM: &str = "function first(a, b) {
+    return a
+    let ignored = b * 2
+    return b
+}
+function inner(x) {
+    let y = x + 1
+    return y * 2
+}
+function outer(x) {
+    return inner(x) + inner(x + 1)
+}
+first(1, 2)
+outer(3)
+";
+
+/// `(kind, message, location)` triples from string slices.
+fn diagnostics(expected: &[(DiagnosticKind, &str, &str)]) -> Vec<(DiagnosticKind, String, String)> {
+    expected.iter().map(|(kind, message, location)| (*kind, message.to_string(), location.to_string())).collect()
+}
+
+#[test]
+fn return_ends_the_call() {
+    let checked = common::check("returns.calc", PROGRAM);
+    // Only the first statement after the `return` is reported.
+    assert_eq!(
+        checked.diagnostics,
+        diagnostics(&[(DiagnosticKind::Warning, "Unreachable code after `return`", "returns.calc:3:5")])
+    );
+    let evaluation = checked.ast.evaluate(&mut ExpressionEvaluator::new());
+    assert_eq!(evaluation.error, None);
+    let values: Vec<Value> = evaluation.results.into_iter().map(|result| result.value).collect();
+    // `first` stops at its first `return`; each call of `inner` returns to
+    // the addition in `outer`.
+    assert_eq!(values, [Value::Integer(1), Value::Integer(18)]);
+}
+
+#[test]
+fn reports_misplaced_and_missing_returns() {
+    let cases = [
+        ("return 1", vec![(DiagnosticKind::Error, "`return` outside of a function", "returns.calc:1:1")]),
+        (
+            "function total(a) -> int {\n    let b = a + 1\n}",
+            vec![(DiagnosticKind::Error, "Function `total` must return a value of type int on every path", "returns.calc:3:1")],
+        ),
+        (
+            "function log(a) {\n    let b = a + 1\n}\nlet c = log(1)",
+            vec![(DiagnosticKind::Error, "Expression does not have a value", "returns.calc:4:9")],
+        ),
+        (
+            "function twice(a) {\n    return a * 2\n    return a\n    return 0\n}",
+            vec![(DiagnosticKind::Warning, "Unreachable code after `return`", "returns.calc:3:5")],
+        ),
+    ];
+    for (text, expected) in cases {
+        let checked = common::check("returns.calc", text);
+        assert_eq!(checked.diagnostics, diagnostics(&expected), "{:?}", text);
+    }
+}
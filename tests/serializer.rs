@@ -0,0 +1,83 @@
+//! The JSON and S-expression exports: every node kind and field of the JSON
+//! schema, the format version, and the S-expression form of each construct.
+
+mod common;
+
+use compiler::{
+    ast::{serializer::JSON_FORMAT_VERSION, Ast},
+    diagnostics::DiagnosticsBag,
+    json::Json,
+};
+
+const SOURCE: &str = "import \"m.calc\"
+use m::f
+function g(x: int) -> int { return x }
+let a: float = m::f(1.5) + f(2)
+(a)
+\"s\"
+";
+
+fn parse(text: &str) -> Ast {
+    let diagnostics = DiagnosticsBag::new_cell();
+    let ast = Ast::parse(&common::source("main.calc", text), diagnostics.clone());
+    assert!(!diagnostics.borrow().has_errors());
+    ast
+}
+
+#[test]
+fn json_export_follows_the_schema() {
+    let expected = concat!(
+        r#"{"format_version":2,"items":["#,
+        r#"{"kind":"import","id":0,"span":{"start":0,"end":15},"path":"m.calc","children":[]},"#,
+        r#"{"kind":"import","id":1,"span":{"start":16,"end":24},"path":"m.calc","name":"f","children":[]},"#,
+        r#"{"kind":"function","id":0,"span":{"start":25,"end":63},"name":"g","#,
+        r#""parameters":[{"name":"x","span":{"start":36,"end":37},"type":{"name":"int","span":{"start":37,"end":42}}}],"#,
+        r#""return_type":{"name":"int","span":{"start":44,"end":50}},"#,
+        r#""children":[{"kind":"return","id":0,"span":{"start":53,"end":61},"#,
+        r#""children":[{"kind":"variable","id":0,"span":{"start":60,"end":61},"name":"x","children":[]}]}]},"#,
+        r#"{"kind":"let","id":1,"span":{"start":64,"end":95},"name":"a","type":{"name":"float","span":{"start":69,"end":76}},"#,
+        r#""children":[{"kind":"binary","id":5,"span":{"start":79,"end":95},"operator":"+","children":["#,
+        r#"{"kind":"call","id":2,"span":{"start":79,"end":88},"callee":"f","module":"m","#,
+        r#""children":[{"kind":"float","id":1,"span":{"start":84,"end":87},"value":1.5,"children":[]}]},"#,
+        r#"{"kind":"call","id":4,"span":{"start":91,"end":95},"callee":"f","#,
+        r#""children":[{"kind":"number","id":3,"span":{"start":93,"end":94},"value":2,"children":[]}]}]}]},"#,
+        r#"{"kind":"expression_statement","id":2,"span":{"start":96,"end":99},"#,
+        r#""children":[{"kind":"parenthesized","id":7,"span":{"start":96,"end":99},"#,
+        r#""children":[{"kind":"variable","id":6,"span":{"start":97,"end":98},"name":"a","children":[]}]}]},"#,
+        r#"{"kind":"expression_statement","id":3,"span":{"start":100,"end":103},"#,
+        r#""children":[{"kind":"string","id":8,"span":{"start":100,"end":103},"value":"s","children":[]}]}"#,
+        r#"]}"#,
+    );
+    assert_eq!(parse(SOURCE).to_json(), expected);
+}
+
+#[test]
+fn every_json_node_has_the_common_fields() {
+    fn check(node: &Json) {
+        assert!(node.get("kind").and_then(Json::as_str).is_some(), "{}", node);
+        assert!(node.get("id").and_then(Json::as_i64).is_some(), "{}", node);
+        let span = node.get("span").unwrap();
+        assert!(span.get("start").and_then(Json::as_i64) <= span.get("end").and_then(Json::as_i64));
+        node.get("children").and_then(Json::as_array).unwrap().iter().for_each(check);
+    }
+
+    let json = Json::parse(&parse(SOURCE).to_json()).unwrap();
+    assert_eq!(json.get("format_version").and_then(Json::as_i64), Some(JSON_FORMAT_VERSION));
+    let items = json.get("items").and_then(Json::as_array).unwrap();
+    assert_eq!(items.len(), 6);
+    items.iter().for_each(check);
+}
+
+#[test]
+fn sexpr_export_writes_one_item_per_line() {
+    let expected = "(import \"m.calc\")
+(use m::f)
+(function g (x:int) -> int (return x))
+(let a:float (+ (m::f 1.5) (f 2)))
+a
+\"s\"
+";
+    assert_eq!(parse(SOURCE).to_sexpr(), expected);
+    assert_eq!(parse("200 * 7 + 5").to_sexpr(), "(+ (* 200 7) 5)\n");
+    assert_eq!(parse("function f(w, h) { let s = w - h return s * 2 }").to_sexpr(), "(function f (w h) (let s (- w h)) (return (* s 2)))\n");
+}
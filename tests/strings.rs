@@ -0,0 +1,72 @@
+//! String literals with every escape, concatenation and `len()`, and the
+//! diagnostics for unterminated strings, bad escapes and operators strings
+//! do not support.
+
+mod common;
+
+use compiler::{
+    ast::{
+        evaluator::{ExpressionEvaluator, RuntimeError},
+        value::Value,
+        Ast, BinOperatorKind,
+    },
+    diagnostics::DiagnosticsBag,
+};
+
+#[test]
+fn evaluates_escapes_and_len() {
+    let program = "let label = \"tab\\there\\n\" + \"\\\"quoted\\\" \\\\ \\u{48}\\u{1F600}\\0\\r\"
+label
+len(label)
+len(\"\")
+";
+    let checked = common::check("strings.calc", program);
+    assert_eq!(checked.messages(), []);
+    let evaluation = checked.ast.evaluate(&mut ExpressionEvaluator::new());
+    assert_eq!(evaluation.error, None);
+    let values: Vec<Value> = evaluation.results.into_iter().map(|result| result.value).collect();
+    let label = String::from("tab\there\n\"quoted\" \\ H\u{1F600}\0\r");
+    assert_eq!(
+        values,
+        [
+            Value::String(label.clone()),
+            Value::String(label.clone()),
+            Value::Integer(label.chars().count() as i64),
+            Value::Integer(0),
+        ]
+    );
+}
+
+#[test]
+fn reports_string_errors() {
+    let cases = [
+        ("let s = \"open", "Unterminated string literal", "strings.calc:1:9"),
+        ("let s = \"line\nlet t = 1", "Unterminated string literal", "strings.calc:1:9"),
+        ("let s = \"a\\qb\"", "Invalid escape sequence `\\q`", "strings.calc:1:11"),
+        ("let s = \"\\u{110000}\"", "Invalid escape sequence `\\u{110000}`", "strings.calc:1:10"),
+        ("let s = \"\\u{}\"", "Invalid escape sequence `\\u{}`", "strings.calc:1:10"),
+        ("let s = \"\\u41\"", "Invalid escape sequence `\\u`", "strings.calc:1:10"),
+        ("let s = \"a\" * 2", "Cannot apply `*` to string and int", "strings.calc:1:13"),
+        ("let s = \"a\" - \"b\"", "Cannot apply `-` to string and string", "strings.calc:1:13"),
+        ("let s = \"a\" + 1", "Cannot apply `+` to string and int", "strings.calc:1:13"),
+        ("let n = len(5)", "Mismatched types: expected string, found int", "strings.calc:1:13"),
+        ("let n = len(\"a\", \"b\")", "`len` takes 1 argument(s) but 2 were given", "strings.calc:1:9"),
+    ];
+    for (text, message, location) in cases {
+        let checked = common::check("strings.calc", text);
+        assert_eq!(checked.messages(), common::messages(&[(message, location)]), "{:?}", text);
+    }
+}
+
+#[test]
+fn evaluator_refuses_string_arithmetic() {
+    // Without the checker the evaluator still refuses to multiply a string.
+    let source = common::source("strings.calc", "\"a\" * 2");
+    let ast = Ast::parse(&source, DiagnosticsBag::new_cell());
+    let error = ast.evaluate(&mut ExpressionEvaluator::new()).error.unwrap();
+    assert!(matches!(
+        error,
+        RuntimeError::InvalidOperands { operator: BinOperatorKind::Multiply, left: "string", right: "int", .. }
+    ));
+    assert_eq!(error.to_string(), "Cannot apply `*` to string and int");
+}
@@ -0,0 +1,41 @@
+//! Common subexpression elimination at the top level: repeats get a
+//! `_cseN` temporary there too, which adds nothing to the results `run`
+//! prints.
+
+use std::collections::HashMap;
+
+use compiler::{
+    ast::{
+        evaluator::ExpressionEvaluator,
+        modules::{Program, SourceSnapshot},
+        subexpressions::eliminate_common_subexpressions,
+        value::Value,
+    },
+    diagnostics::DiagnosticsBag,
+};
+
+fn results(text: &str) -> Vec<(String, Value)> {
+    let mut files = HashMap::from([(String::from("main.calc"), text.to_string())]);
+    let program = Program::load("main.calc", &mut files, DiagnosticsBag::new_cell()).unwrap();
+    let evaluation = program.ast.evaluate(&mut ExpressionEvaluator::new());
+    assert_eq!(evaluation.error, None);
+    let source = program.main_source();
+    evaluation.results.iter().map(|result| (source.slice(&result.span).to_string(), result.value.clone())).collect()
+}
+
+#[test]
+fn top_level_repeats_get_a_temporary() {
+    let text = "let a = 2\nlet b = 3\nlet x = (a * b + 1) * (a * b + 1)\nx\n";
+    let snapshot = SourceSnapshot {
+        files: vec![(String::from("main.calc"), Ok(text.to_string()))],
+    };
+    let elimination = eliminate_common_subexpressions("main.calc", snapshot).unwrap();
+    assert_eq!(elimination.text, "let a = 2\nlet b = 3\nlet _cse0 = a * b + 1\nlet x = _cse0 * _cse0\nx\n");
+
+    let before = results(text);
+    let after = results(&elimination.text);
+    let values = |results: &[(String, Value)]| -> Vec<Value> { results.iter().map(|(_, value)| value.clone()).collect() };
+    assert_eq!(values(&before), values(&after));
+    assert_eq!(values(&after), [Value::Integer(2), Value::Integer(3), Value::Integer(49), Value::Integer(49)]);
+    assert!(after.iter().all(|(text, _)| !text.contains("_cse0 =")));
+}
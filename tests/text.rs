@@ -0,0 +1,87 @@
+//! Source positions: `SourceFile::line_col` over lines, multi-byte
+//! characters and offsets past the end, the `FileId` in every span, and the
+//! `Eof` token sitting at the end of the input.
+
+mod common;
+
+use compiler::{
+    ast::{
+        lexer::{Lexer, TokenKind},
+        lib::Id,
+        text::{FileId, LineCol, SourceMap},
+    },
+    diagnostics::DiagnosticsBag,
+};
+
+fn line_col(line: usize, column: usize) -> LineCol {
+    LineCol { line, column }
+}
+
+#[test]
+fn line_col_finds_the_line_and_column_of_an_offset() {
+    let source = common::source("main.calc", "let x = 1\n\nlet y = x\n");
+    assert_eq!(source.line_count(), 4);
+    assert_eq!(source.line_col(0), line_col(0, 0));
+    assert_eq!(source.line_col(8), line_col(0, 8));
+    assert_eq!(source.line_col(9), line_col(0, 9));
+    assert_eq!(source.line_col(10), line_col(1, 0));
+    assert_eq!(source.line_col(11), line_col(2, 0));
+    assert_eq!(source.line_col(19), line_col(2, 8));
+    assert_eq!(source.line_col(21), line_col(3, 0));
+    assert_eq!(source.line_col(1000), line_col(3, 0));
+    assert_eq!(source.line_col(19).to_string(), "3:9");
+}
+
+#[test]
+fn columns_count_characters_not_bytes() {
+    let source = common::source("main.calc", "let café = \"日本\"\nlet z = 1");
+    let equals = source.text.find('=').unwrap();
+    assert_eq!(equals, 10);
+    assert_eq!(source.line_col(equals), line_col(0, 9));
+    // An offset inside a character belongs to that character.
+    assert_eq!(source.line_col(7), line_col(0, 7));
+    assert_eq!(source.line_col(8), line_col(0, 7));
+    assert_eq!(source.line_col(source.text.len()), line_col(1, 9));
+}
+
+#[test]
+fn line_text_drops_the_line_break() {
+    let source = common::source("main.calc", "a\r\nb\nc");
+    assert_eq!(source.line_start(1), Some(3));
+    assert_eq!(source.line_start(3), None);
+    assert_eq!((0..3).map(|line| source.line_text(line)).collect::<Vec<_>>(), ["a", "b", "c"]);
+}
+
+#[test]
+fn spans_carry_the_file_they_come_from() {
+    let mut sources = SourceMap::new();
+    let first = sources.add_file("a.calc".to_string(), "let a = 1".to_string());
+    let second = sources.add_file("b.calc".to_string(), "\n  b".to_string());
+    assert_ne!(first, second);
+
+    let source = sources.get(second);
+    let tokens: Vec<_> = Lexer::new(&source.text, second, DiagnosticsBag::new_cell()).collect();
+    assert!(tokens.iter().all(|token| token.span.file_id == second));
+    assert_eq!(source.location(&tokens[0].span), "b.calc:2:3");
+    assert_eq!(sources.get(first).name, "a.calc");
+}
+
+#[test]
+fn eof_spans_the_end_of_the_input() {
+    for text in ["", "1 + 2", "let x = 1\n// comment\n  ", "é"] {
+        let diagnostics = DiagnosticsBag::new_cell();
+        let mut lexer = Lexer::new(text, FileId::new(0), diagnostics);
+        let tokens: Vec<_> = lexer.by_ref().collect();
+        let eof = tokens.last().unwrap();
+        assert_eq!(eof.kind, TokenKind::Eof);
+        assert_eq!((eof.span.start, eof.span.end), (text.len(), text.len()), "{:?}", text);
+        assert_eq!(tokens.iter().filter(|token| token.kind == TokenKind::Eof).count(), 1);
+        assert!(lexer.next_token().is_none());
+    }
+}
+
+#[test]
+fn a_missing_expression_is_reported_at_the_end_of_the_file() {
+    let checked = common::check("main.calc", "let x = 1\nlet y =   \n");
+    assert_eq!(checked.messages(), common::messages(&[("Expected an expression, found end of file", "main.calc:3:1")]));
+}
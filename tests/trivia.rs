@@ -0,0 +1,101 @@
+//! Comments: line and nested block comments are lexed as trivia attached to
+//! the neighbouring tokens, the parser ignores them, and an unterminated
+//! block comment is reported.
+
+mod common;
+
+use compiler::{
+    ast::{
+        lexer::{Lexer, Token, TokenKind, Trivia, TriviaKind},
+        lib::Id,
+        text::FileId,
+        Ast,
+    },
+    diagnostics::DiagnosticsBag,
+};
+
+const SOURCE: &str = "let x = 1 // one
+/* a /* nested */ b */
+// two
+let y = /* inline */ 2
+";
+
+fn lex(text: &str) -> Vec<Token> {
+    let diagnostics = DiagnosticsBag::new_cell();
+    let tokens = Lexer::new(text, FileId::new(0), diagnostics.clone()).collect();
+    assert!(!diagnostics.borrow().has_errors());
+    tokens
+}
+
+/// `trivia` as `(kind, text)` pairs.
+fn trivia(trivia: &[Trivia]) -> Vec<(TriviaKind, &str)> {
+    trivia.iter().map(|trivia| (trivia.kind, trivia.span.literal.as_str())).collect()
+}
+
+#[test]
+fn comments_on_the_same_line_trail_the_token_before_them() {
+    let tokens = lex(SOURCE);
+    let one = &tokens[3];
+    assert_eq!(one.kind, TokenKind::Number(1));
+    assert_eq!(trivia(&one.trailing_trivia), [(TriviaKind::Whitespace, " "), (TriviaKind::LineComment, "// one")]);
+
+    let equals = &tokens[6];
+    assert_eq!(equals.kind, TokenKind::Equals);
+    assert_eq!(trivia(&equals.trailing_trivia), [
+        (TriviaKind::Whitespace, " "),
+        (TriviaKind::BlockComment, "/* inline */"),
+        (TriviaKind::Whitespace, " "),
+    ]);
+}
+
+#[test]
+fn comments_on_their_own_lines_lead_the_next_token() {
+    let tokens = lex(SOURCE);
+    let second_let = &tokens[4];
+    assert_eq!(second_let.kind, TokenKind::Let);
+    assert_eq!(trivia(&second_let.leading_trivia), [
+        (TriviaKind::Whitespace, "\n"),
+        (TriviaKind::BlockComment, "/* a /* nested */ b */"),
+        (TriviaKind::Whitespace, "\n"),
+        (TriviaKind::LineComment, "// two"),
+        (TriviaKind::Whitespace, "\n"),
+    ]);
+    assert_eq!(second_let.comments().count(), 2);
+
+    let eof = tokens.last().unwrap();
+    assert_eq!(eof.kind, TokenKind::Eof);
+    assert_eq!(trivia(&eof.leading_trivia), [(TriviaKind::Whitespace, "\n")]);
+}
+
+#[test]
+fn every_byte_is_in_a_token_or_its_trivia() {
+    let text: String = lex(SOURCE)
+        .iter()
+        .flat_map(|token| {
+            let leading = token.leading_trivia.iter().map(|trivia| trivia.span.literal.as_str());
+            let trailing = token.trailing_trivia.iter().map(|trivia| trivia.span.literal.as_str());
+            leading.chain(std::iter::once(token.span.literal.as_str())).chain(trailing)
+        })
+        .collect();
+    assert_eq!(text, SOURCE);
+}
+
+#[test]
+fn the_parser_ignores_comments() {
+    let diagnostics = DiagnosticsBag::new_cell();
+    let ast = Ast::parse(&common::source("main.calc", SOURCE), diagnostics.clone());
+    assert!(!diagnostics.borrow().has_errors());
+    assert_eq!(ast.to_sexpr(), "(let x 1)\n(let y 2)\n");
+}
+
+#[test]
+fn an_unterminated_block_comment_is_reported_where_it_opens() {
+    let checked = common::check("main.calc", "let x = 1\n/* open /* nested */\nlet y = 2\n");
+    assert_eq!(checked.messages(), common::messages(&[("Unterminated block comment", "main.calc:2:1")]));
+    assert_eq!(checked.ast.to_sexpr(), "(let x 1)\n");
+
+    let diagnostics = DiagnosticsBag::new_cell();
+    let tokens: Vec<Token> = Lexer::new("1 /* open", FileId::new(0), diagnostics.clone()).collect();
+    assert_eq!(tokens.iter().map(|token| token.kind).collect::<Vec<_>>(), [TokenKind::Number(1), TokenKind::Eof]);
+    assert_eq!(trivia(&tokens[0].trailing_trivia)[1], (TriviaKind::BlockComment, "/* open"));
+}
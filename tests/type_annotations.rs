@@ -0,0 +1,87 @@
+//! Type annotations on `let`s, parameters and function results: where the
+//! parser puts them and how the type checker enforces them.
+
+mod common;
+
+use compiler::ast::StatementKind;
+
+const PROGRAM: &str = "function area(w: float, h: float) -> float {
+    return w * h
+}
+function label(name: string) {
+    return name + \":\"
+}
+let floor: float = area(2.5, 4.0)
+let count: int = 3
+let title: string = label(\"floor\")
+";
+
+#[test]
+fn parses_annotations_with_their_spans() {
+    let checked = common::check("annotations.calc", PROGRAM);
+    assert_eq!(checked.messages(), []);
+    let (source, ast) = (&checked.source, &checked.ast);
+    let area = ast.query_function(ast.function_by_name("area").unwrap());
+    let parameter_annotations: Vec<(&str, String)> = area
+        .parameters
+        .iter()
+        .map(|parameter| {
+            let type_annotation = parameter.type_annotation.as_ref().unwrap();
+            (type_annotation.type_name(), source.location(&type_annotation.span()))
+        })
+        .collect();
+    assert_eq!(
+        parameter_annotations,
+        [("float", String::from("annotations.calc:1:16")), ("float", String::from("annotations.calc:1:26"))]
+    );
+    let return_type = area.return_type.as_ref().unwrap();
+    assert_eq!(return_type.type_name(), "float");
+    assert_eq!(source.slice(&return_type.span()), "-> float");
+    let let_annotations: Vec<&str> = ast
+        .statements
+        .iter()
+        .filter_map(|stmt| match &stmt.stmt_kind {
+            StatementKind::Let(let_stmt) => let_stmt.type_annotation.as_ref().map(|type_annotation| type_annotation.type_name()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(let_annotations, ["float", "int", "string"]);
+
+    let signatures: Vec<String> = ["area", "label"]
+        .iter()
+        .map(|name| checked.checker().function_signature(ast, ast.function_by_name(name).unwrap()))
+        .collect();
+    assert_eq!(
+        signatures,
+        ["function area(w: float, h: float) -> float", "function label(name: string) -> string"]
+    );
+}
+
+#[test]
+fn enforces_annotations() {
+    let cases: [(&str, &[(&str, &str)]); 7] = [
+        ("let x: int = \"five\"", &[("Mismatched types: expected int, found string", "annotations.calc:1:14")]),
+        ("let x: float = 5", &[("Mismatched types: expected float, found int", "annotations.calc:1:16")]),
+        ("let x: number = 5", &[("Unknown type `number`", "annotations.calc:1:8")]),
+        (
+            "function area(w: float, h: float) -> float { return w * h }\nlet a = area(2, 4.0)",
+            &[("Mismatched types: expected float, found int", "annotations.calc:2:14")],
+        ),
+        (
+            "function name() -> string { return 1 }",
+            &[("Mismatched types: expected string, found int", "annotations.calc:1:36")],
+        ),
+        (
+            "function double(x: int) { return x * 2 }\nlet d: float = double(2)",
+            &[("Mismatched types: expected float, found int", "annotations.calc:2:16")],
+        ),
+        (
+            "function id(x: bool) -> bool { return x }",
+            &[("Unknown type `bool`", "annotations.calc:1:16"), ("Unknown type `bool`", "annotations.calc:1:25")],
+        ),
+    ];
+    for (text, expected) in cases {
+        let checked = common::check("annotations.calc", text);
+        assert_eq!(checked.messages(), common::messages(expected), "{:?}", text);
+    }
+}
@@ -0,0 +1,84 @@
+//! The types inferred for a program without annotations, and the
+//! mismatches the type checker reports before anything runs.
+
+mod common;
+
+use compiler::ast::{types::Type, StatementKind};
+
+const PROGRAM: &str = "function greet(name) {
+    return \"hello \" + name
+}
+function scale(value, factor) {
+    return value * factor
+}
+function half(x) {
+    return x / 2
+}
+let width = len(greet(\"Ada\"))
+let area = scale(width, 3)
+let ratio = 1.5 * 2.0
+let label = greet(\"x\") + \"!\"
+";
+
+#[test]
+fn infers_signatures_and_let_types() {
+    let checked = common::check("types.calc", PROGRAM);
+    assert_eq!(checked.messages(), []);
+    let (ast, checker) = (&checked.ast, checked.checker());
+    let signatures: Vec<String> = ["greet", "scale", "half"]
+        .iter()
+        .map(|name| checker.function_signature(ast, ast.function_by_name(name).unwrap()))
+        .collect();
+    // `half` is never called, so the type of `x` defaults to `int`.
+    assert_eq!(
+        signatures,
+        [
+            "function greet(name: string) -> string",
+            "function scale(value: int, factor: int) -> int",
+            "function half(x: int) -> int",
+        ]
+    );
+    // Every `let` has the type of its initializer, which the side table
+    // holds for each expression.
+    let mut lets = Vec::new();
+    for stmt in ast.statements.iter() {
+        if let StatementKind::Let(let_stmt) = &stmt.stmt_kind {
+            let ty = *checker.types.get(let_stmt.initializer);
+            assert_eq!(ty, *checker.variable_types.get(let_stmt.variable_id));
+            lets.push((let_stmt.identifier.span.literal.as_str(), ty));
+        }
+    }
+    assert_eq!(lets, [("width", Type::Int), ("area", Type::Int), ("ratio", Type::Float), ("label", Type::String)]);
+}
+
+#[test]
+fn reports_mismatches() {
+    let cases: [(&str, &[(&str, &str)]); 7] = [
+        (
+            "function scale(value, factor) { return value * factor }\nlet a = scale(1, 2)\nlet b = scale(1.5, 2.0)",
+            &[
+                ("Mismatched types: expected int, found float", "types.calc:3:15"),
+                ("Mismatched types: expected int, found float", "types.calc:3:20"),
+            ],
+        ),
+        (
+            "function twice(x) { return x * 2 }\nlet a = twice(\"x\")",
+            &[("Mismatched types: expected int, found string", "types.calc:2:15")],
+        ),
+        (
+            "function product(a, b) { return a * b }\nlet p = product(\"x\", \"y\")",
+            &[("Cannot apply `*` to string and string", "types.calc:1:35")],
+        ),
+        ("let a = 1 + 2.5", &[("Cannot apply `+` to int and float", "types.calc:1:11")]),
+        ("let a = 1\nlet b = a + \"x\"", &[("Cannot apply `+` to int and string", "types.calc:2:11")]),
+        (
+            "function add(a, b) { return a + b }\nlet s = add(1)",
+            &[("`add` takes 2 argument(s) but 1 were given", "types.calc:2:9")],
+        ),
+        ("let a = missing(1)", &[("Undefined function `missing`", "types.calc:1:9")]),
+    ];
+    for (text, expected) in cases {
+        let checked = common::check("types.calc", text);
+        assert_eq!(checked.messages(), common::messages(expected), "{:?}", text);
+    }
+}